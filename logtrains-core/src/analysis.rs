@@ -0,0 +1,128 @@
+//! One analysis as a single call: the default path of `logtrains analyze`
+//! without the CLI's input sources, filters and output formats.
+
+use anyhow::Result;
+
+use crate::llm::{Flow, Inferencer, Prompt};
+use crate::redact::Redactor;
+use crate::rules::{self, Finding};
+use crate::severity::Severity;
+use crate::template::Vars;
+use crate::{analyzers, preprocess, truncate};
+
+/// What to analyze and how.
+#[derive(Debug, Clone)]
+pub struct AnalysisRequest {
+    /// The log as captured; escape codes and progress-bar redraws are removed.
+    pub log: String,
+    /// A custom prompt instead of the built-in one.
+    pub prompt: Option<Prompt>,
+    /// Values for the prompt's placeholders.
+    pub vars: Vars,
+    /// Answer this question about the log instead of explaining it.
+    pub question: Option<String>,
+    /// Facts appended after the log, e.g. from the machine it ran on. Never
+    /// truncated.
+    pub context: String,
+    /// Collapse repeated lines into `line (xN)`.
+    pub dedupe: bool,
+    /// Recognize the output of well-known tools (see [`analyzers`]), add a
+    /// digest of its errors and, with the built-in prompt, use one made for
+    /// the tool.
+    pub analyzers: bool,
+    /// Mask secrets and PII in the log and context before the prompt is
+    /// built.
+    pub redact: Option<Redactor>,
+}
+
+impl AnalysisRequest {
+    /// A request for `log` with the built-in prompt and this machine's
+    /// placeholder values.
+    pub fn new(log: impl Into<String>) -> Self {
+        Self {
+            log: log.into(),
+            prompt: None,
+            vars: Vars::local(None),
+            question: None,
+            context: String::new(),
+            dedupe: true,
+            analyzers: true,
+            redact: None,
+        }
+    }
+}
+
+/// What an analysis found.
+#[derive(Debug, Clone)]
+pub struct AnalysisResult {
+    /// The explanation (or answer) as Markdown.
+    pub explanation: String,
+    /// The rule-based severity, raised by the model's own judgement.
+    pub severity: Severity,
+    /// The lines that matched a known failure pattern.
+    pub findings: Vec<Finding>,
+    /// Whether the log was cut to fit the model's context.
+    pub truncated: bool,
+    /// How many items each redaction pattern masked.
+    pub redacted: Vec<(String, usize)>,
+}
+
+/// Analyzes `request.log`, passing each generated token to `callback` as it
+/// arrives. Returning [`Flow::Stop`] from the callback ends the explanation
+/// early; the result then holds what was generated so far.
+pub fn analyze<F: FnMut(String) -> Result<Flow>>(
+    engine: &mut Inferencer,
+    request: &AnalysisRequest,
+    mut callback: F,
+) -> Result<AnalysisResult> {
+    let mut log = preprocess::clean_terminal_output(&request.log);
+    if request.dedupe {
+        log = preprocess::dedupe_lines(&log);
+    }
+    let digest = if request.analyzers { analyzers::detect(&log) } else { None };
+    if let Some(scope) = digest.as_ref().and_then(|digest| digest.scope.clone()) {
+        log = scope;
+    }
+    let findings = rules::evaluate(&log);
+    let floor = Severity::from_log(&log, &findings);
+
+    let mut context = match &digest {
+        Some(digest) => digest.render() + &request.context,
+        None => request.context.clone(),
+    };
+    // Redacted before budgeting, so the measured text is what the model sees
+    let redacted = match &request.redact {
+        Some(redactor) => redactor.redact_all(&mut [&mut log, &mut context]),
+        None => Vec::new(),
+    };
+    let tool_prompt = digest.map(|digest| Prompt::System(digest.prompt.to_string()));
+    let prompt = request.prompt.as_ref().or(tool_prompt.as_ref());
+
+    let budget = match &request.question {
+        Some(question) => engine.ask_budget(question, &context),
+        None => engine.input_budget(prompt, &request.vars, &context),
+    };
+    let truncated = engine.count_tokens(&log) > budget;
+    if truncated {
+        log = truncate::smart_truncate_by(&log, budget, |s| engine.count_tokens(s));
+    }
+    log.push_str(&context);
+
+    let mut streamed = String::new();
+    let mut collect = |token: String| {
+        streamed.push_str(&token);
+        callback(token)
+    };
+    match &request.question {
+        Some(question) => engine.ask(&log, question, &mut collect)?,
+        None => engine.explain(&log, prompt, &request.vars, &mut collect)?,
+    }
+    let explanation = streamed;
+
+    let labels = Severity::ALL.map(|s| s.label());
+    let severity = match engine.classify_severity(&explanation, &labels) {
+        Ok(i) => Severity::ALL[i].max(floor),
+        Err(_) => floor,
+    };
+    Ok(AnalysisResult { explanation, severity, findings, truncated, redacted })
+}
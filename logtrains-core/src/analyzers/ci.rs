@@ -0,0 +1,179 @@
+//! CI logs of GitHub Actions: timestamps and workflow markers stripped, the
+//! log split into its steps and scoped to the step that failed. Not detected
+//! on its own; `--mode ci` asks for it.
+
+use regex::Regex;
+use std::sync::LazyLock;
+
+use super::{detect, Digest};
+
+const MAX_ERRORS: usize = 10;
+
+/// `2024-05-01T12:00:00.1234567Z ` before every line of a downloaded log.
+static TIMESTAMP: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\u{feff}?\d{4}-\d{2}-\d{2}T\d{2}:\d{2}:\d{2}(?:\.\d+)?Z ?").unwrap());
+/// `##[error]message` as the runner writes it.
+static MARKER: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^##\[(\w+)\](.*)$").unwrap());
+/// `::error file=src/app.ts,line=3::message` as a step writes it.
+static COMMAND: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^::(\w+)(?: ([^:]*))?::(.*)$").unwrap());
+
+const PROMPT: &str = "You are a {{ROLE}} and an expert in CI pipelines and GitHub Actions. The log below is the \
+    output of the one CI step that failed, followed by a digest naming the step and its errors. Explain why the \
+    step failed: the command's own error, a missing secret, tool or cache, a runner or permission problem, or a \
+    test that failed. Then give the fix, as a code change or a change to the workflow file. Be brief. Use Markdown.";
+
+#[derive(Debug, Default, PartialEq)]
+struct Step {
+    /// `Run cargo test`, or `Set up job` for the lines before the first step.
+    name: String,
+    lines: Vec<String>,
+    errors: Vec<String>,
+}
+
+/// Splits a CI log into its steps and digests the one that failed; the
+/// analysis is scoped to that step's output. When a tool's output is
+/// recognized in the step, its digest and prompt are used too.
+pub fn digest(log: &str) -> Option<Digest> {
+    let steps = steps(log)?;
+    let failing = steps.iter().position(|step| !step.errors.is_empty()).unwrap_or(steps.len() - 1);
+    let step = &steps[failing];
+
+    let mut body = format!("Failing step: {} (step {} of {})\n", step.name, failing + 1, steps.len());
+    if !step.errors.is_empty() {
+        body.push_str("Errors:\n");
+        for error in step.errors.iter().take(MAX_ERRORS) {
+            body.push_str(&format!("  {}\n", error));
+        }
+    }
+    let passed: Vec<&str> = steps[..failing].iter().map(|step| step.name.as_str()).collect();
+    if !passed.is_empty() {
+        body.push_str(&format!("Steps before it: {}\n", passed.join(", ")));
+    }
+
+    let output = format!("Step: {}\n{}\n", step.name, step.lines.join("\n"));
+    let tool = detect(&output);
+    let mut prompt = PROMPT;
+    let mut scope = output;
+    if let Some(tool) = tool {
+        body.push_str(&format!("\n{}:\n{}", tool.title, tool.body));
+        prompt = tool.prompt;
+        scope = tool.scope.unwrap_or(scope);
+    }
+
+    Some(Digest { tool: "GitHub Actions", title: "CI Failure", body, prompt, scope: Some(scope) })
+}
+
+/// The steps of a GitHub Actions log, each starting at a `##[group]Run`
+/// line, with the markers turned into plain text. None when the log has
+/// no markers.
+fn steps(log: &str) -> Option<Vec<Step>> {
+    let mut steps = vec![Step { name: "Set up job".to_string(), ..Step::default() }];
+    let mut markers = false;
+    for line in log.lines().map(str::trim_end) {
+        let line = TIMESTAMP.replace(line, "");
+        let step = steps.last_mut().expect("steps are not empty");
+        if let Some(marker) = MARKER.captures(&line) {
+            markers = true;
+            let text = marker[2].trim().to_string();
+            match &marker[1] {
+                "group" if text.starts_with("Run ") => {
+                    steps.push(Step { name: text, ..Step::default() });
+                }
+                "group" | "command" => step.lines.push(text),
+                "error" | "warning" => {
+                    step.lines.push(format!("{}: {}", &marker[1], text));
+                    if &marker[1] == "error" {
+                        step.errors.push(text);
+                    }
+                }
+                _ => {}
+            }
+        } else if let Some(command) = COMMAND.captures(&line) {
+            let message = command[3].trim();
+            match &command[1] {
+                kind @ ("error" | "warning" | "notice") => {
+                    let location = command.get(2).map(|params| location(params.as_str())).unwrap_or_default();
+                    step.lines.push(format!("{}: {}{}", kind, location, message));
+                    if kind == "error" {
+                        step.errors.push(format!("{}{}", location, message));
+                    }
+                }
+                "group" => step.lines.push(message.to_string()),
+                _ => {}
+            }
+        } else {
+            step.lines.push(line.into_owned());
+        }
+    }
+    if !markers {
+        return None;
+    }
+    // The setup lines before the first step are a step only if they say something
+    if steps.len() > 1 && steps[0].errors.is_empty() {
+        steps.remove(0);
+    }
+    Some(steps)
+}
+
+/// `src/app.ts:3: ` from `file=src/app.ts,line=3,col=7`.
+fn location(params: &str) -> String {
+    let param = |name: &str| params.split(',').find_map(|param| param.trim().strip_prefix(name)?.strip_prefix('='));
+    match (param("file"), param("line")) {
+        (Some(file), Some(line)) => format!("{}:{}: ", file, line),
+        (Some(file), None) => format!("{}: ", file),
+        _ => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOG: &str = "2024-05-01T12:00:00.0000000Z Current runner version: '2.316.0'
+2024-05-01T12:00:01.0000000Z ##[group]Run actions/checkout@v4
+2024-05-01T12:00:01.0000000Z with:
+2024-05-01T12:00:01.0000000Z ##[endgroup]
+2024-05-01T12:00:02.0000000Z Syncing repository: acme/shop
+2024-05-01T12:00:03.0000000Z ##[group]Run npm test
+2024-05-01T12:00:03.0000000Z npm test
+2024-05-01T12:00:03.0000000Z ##[endgroup]
+2024-05-01T12:00:05.0000000Z ::error file=src/cart.ts,line=3,col=7::Type 'string' is not assignable to type 'number'.
+2024-05-01T12:00:05.0000000Z ##[error]Process completed with exit code 2.
+2024-05-01T12:00:06.0000000Z Post job cleanup.
+";
+
+    #[test]
+    fn test_steps() {
+        let steps = steps(LOG).unwrap();
+        assert_eq!(steps.len(), 2);
+        assert_eq!(steps[0].name, "Run actions/checkout@v4");
+        assert_eq!(steps[0].lines, ["with:", "Syncing repository: acme/shop"]);
+        assert_eq!(
+            steps[1].errors,
+            ["src/cart.ts:3: Type 'string' is not assignable to type 'number'.", "Process completed with exit code 2."]
+        );
+        assert_eq!(location("file=app.py"), "app.py: ");
+        assert_eq!(super::steps("plain output\n"), None);
+    }
+
+    #[test]
+    fn test_digest() {
+        let digest = digest(LOG).unwrap();
+        assert_eq!(
+            digest.body,
+            "Failing step: Run npm test (step 2 of 2)\nErrors:\n\
+             \x20 src/cart.ts:3: Type 'string' is not assignable to type 'number'.\n\
+             \x20 Process completed with exit code 2.\nSteps before it: Run actions/checkout@v4\n"
+        );
+        let scope = digest.scope.unwrap();
+        assert!(scope.starts_with("Step: Run npm test\nnpm test\nerror: src/cart.ts:3: Type"), "{}", scope);
+        assert!(!scope.contains("2024-05-01T"));
+
+        // A tool recognized in the step brings its own digest and prompt
+        let rust = "##[group]Run cargo build\n##[endgroup]\nerror[E0382]: borrow of moved value: `v`\n --> src/main.rs:4:20\n\
+                    ##[error]Process completed with exit code 101.\n";
+        let digest = super::digest(rust).unwrap();
+        assert!(digest.body.contains("\nRust Compiler Errors:\n"), "{}", digest.body);
+        assert!(digest.prompt.contains("Rust"));
+    }
+}
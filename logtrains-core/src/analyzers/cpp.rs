@@ -0,0 +1,220 @@
+//! C and C++ with GCC, Clang and CMake: compiler errors grouped by
+//! translation unit with template-instantiation notes collapsed, and linker
+//! and CMake errors as printed.
+
+use regex::Regex;
+use std::sync::LazyLock;
+
+use super::{push_limited, Digest};
+
+const MAX_UNITS: usize = 5;
+const MAX_UNIT_ERRORS: usize = 5;
+const MAX_LINKER_LINES: usize = 15;
+const MAX_CMAKE_LINES: usize = 10;
+
+/// `src/main.cpp:12:5: error: ...`, only for C and C++ sources and headers.
+static DIAGNOSTIC: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(\S+\.(?:c|cc|cpp|cxx|c\+\+|h|hh|hpp|hxx|ipp|tpp|inl|m|mm|cu)):(\d+):(?:\d+:)? (fatal error|error|warning|note): (.*)$")
+        .unwrap()
+});
+/// The object a build tool is compiling: make's `Building CXX object ...o`
+/// or ninja's `FAILED: ...o`.
+static UNIT: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?:Building (?:C|CXX) object|^FAILED:) (?:\S*\.dir/)?(\S+?)\.o(?:bj)?\b").unwrap());
+static LINKER: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"undefined reference to|multiple definition of|in function `|\bld(?:\.\w+)?: |collect2: error|Undefined symbols for architecture|referenced from:|^\s+\S+ in \S+\.o$|symbol\(s\) not found|cannot find -l|LNK\d{4}",
+    )
+    .unwrap()
+});
+static CMAKE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^CMake Error(?: at (\S+))?").unwrap());
+
+const PROMPT: &str = "You are a {{ROLE}} and an expert C and C++ developer who knows GCC, Clang, CMake and linkers \
+    well. The log below is a build log, followed by a digest of it: compiler errors grouped by translation unit \
+    with template-instantiation notes collapsed, and linker and CMake errors as printed. Explain the first real \
+    error; the errors after it are often its cascade. For template errors, name the type that does not fit and \
+    why. For linker errors, name the missing symbol and whether a source file, a library (`-l`, \
+    `target_link_libraries`) or a definition is missing. For CMake errors, name the package or variable and how \
+    to provide it. Then show the smallest fix, as code or CMake. Be brief. Use Markdown.";
+
+#[derive(Debug, PartialEq)]
+struct Error {
+    /// `src/main.cpp:12:5: error: ...` as printed.
+    line: String,
+    /// Notes and instantiation context after it, left out of the digest.
+    notes: usize,
+}
+
+/// Recognizes C/C++ compiler, linker or CMake errors and digests them.
+pub fn digest(log: &str) -> Option<Digest> {
+    let lines: Vec<&str> = log.lines().map(str::trim_end).collect();
+    let units = units(&lines);
+    let linker: Vec<String> = lines.iter().filter(|line| LINKER.is_match(line)).map(|line| line.to_string()).collect();
+    let cmake = cmake_errors(&lines);
+    if units.is_empty() && linker.is_empty() && cmake.is_empty() {
+        return None;
+    }
+
+    let mut body = String::new();
+    if let Some(first) = units.first().and_then(|(_, errors)| errors.first()) {
+        body.push_str(&format!("First error: {}\n", first.line));
+    }
+    // A header's error repeats in every unit that includes it
+    let mut seen: Vec<&str> = Vec::new();
+    let mut repeated = 0;
+    for (unit, errors) in units.iter().take(MAX_UNITS) {
+        let fresh: Vec<&Error> = errors.iter().filter(|error| !seen.contains(&error.line.as_str())).collect();
+        repeated += errors.len() - fresh.len();
+        if fresh.is_empty() {
+            continue;
+        }
+        body.push_str(&format!("\n{} ({} errors):\n", unit, errors.len()));
+        for error in fresh.iter().take(MAX_UNIT_ERRORS) {
+            body.push_str(&format!("  {}", error.line));
+            if error.notes > 0 {
+                body.push_str(&format!(" ({} notes and instantiation lines left out)", error.notes));
+            }
+            body.push('\n');
+        }
+        if fresh.len() > MAX_UNIT_ERRORS {
+            body.push_str(&format!("  ... and {} more\n", fresh.len() - MAX_UNIT_ERRORS));
+        }
+        seen.extend(fresh.iter().map(|error| error.line.as_str()));
+    }
+    if repeated > 0 {
+        body.push_str(&format!("({} errors repeated from headers left out)\n", repeated));
+    }
+    if units.len() > MAX_UNITS {
+        body.push_str(&format!("... and {} more translation units\n", units.len() - MAX_UNITS));
+    }
+    if !linker.is_empty() {
+        body.push_str("\nLinker errors:\n");
+        push_limited(&mut body, &linker, MAX_LINKER_LINES);
+    }
+    if !cmake.is_empty() {
+        body.push_str("\nCMake errors:\n");
+        push_limited(&mut body, &cmake, MAX_CMAKE_LINES);
+    }
+
+    Some(Digest { tool: "C/C++", title: "C/C++ Build Errors", body, prompt: PROMPT, scope: None })
+}
+
+/// The compiler errors of each translation unit, in the order the units
+/// failed. A unit is the object the build tool names, or without one the
+/// file of the error.
+fn units(lines: &[&str]) -> Vec<(String, Vec<Error>)> {
+    let mut units: Vec<(String, Vec<Error>)> = Vec::new();
+    let mut unit: Option<String> = None;
+    // The unit of the error that notes belong to, while they follow it
+    let mut last: Option<usize> = None;
+    for line in lines {
+        let mut note = || {
+            if let Some(error) = last.and_then(|index| units[index].1.last_mut()) {
+                error.notes += 1;
+            }
+        };
+        if let Some(object) = UNIT.captures(line) {
+            unit = Some(object[1].to_string());
+            last = None;
+        } else if let Some(diagnostic) = DIAGNOSTIC.captures(line) {
+            match &diagnostic[3] {
+                "error" | "fatal error" => {
+                    let name = unit.clone().unwrap_or_else(|| diagnostic[1].to_string());
+                    let error = Error { line: line.trim().to_string(), notes: 0 };
+                    let index = match units.iter().position(|(existing, _)| *existing == name) {
+                        Some(index) => index,
+                        None => {
+                            units.push((name, Vec::new()));
+                            units.len() - 1
+                        }
+                    };
+                    units[index].1.push(error);
+                    last = Some(index);
+                }
+                "note" => note(),
+                _ => last = None,
+            }
+        } else if line.contains("required from") || line.contains("In instantiation of") {
+            note();
+        } else if !line.starts_with(char::is_whitespace) && !line.contains(": In ") {
+            last = None;
+        }
+    }
+    units
+}
+
+/// `CMake Error at CMakeLists.txt:12 (find_package):` with the indented
+/// message after it.
+fn cmake_errors(lines: &[&str]) -> Vec<String> {
+    let mut errors = Vec::new();
+    let mut in_error = false;
+    for line in lines {
+        if CMAKE.is_match(line) {
+            errors.push(line.to_string());
+            in_error = true;
+        } else if in_error && line.starts_with(char::is_whitespace) && !line.trim().is_empty() {
+            errors.push(line.to_string());
+        } else if !line.trim().is_empty() {
+            in_error = false;
+        }
+    }
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BUILD: &str = "[ 25%] Building CXX object CMakeFiles/app.dir/src/main.cpp.o
+In file included from /work/src/main.cpp:1:
+/work/include/grid.hpp:30:10: error: no matching function for call to 'fill(int&)'
+/work/include/grid.hpp: In instantiation of 'void Grid<T>::reset() [with T = int]':
+/work/src/main.cpp:8:14:   required from here
+/work/include/grid.hpp:12:6: note: candidate: 'template<class T> void fill(T*)'
+/work/include/grid.hpp:12:6: note:   template argument deduction/substitution failed:
+/work/src/main.cpp:9:3: error: 'reset_all' was not declared in this scope
+[ 50%] Building CXX object CMakeFiles/app.dir/src/util.cpp.o
+In file included from /work/src/util.cpp:2:
+/work/include/grid.hpp:30:10: error: no matching function for call to 'fill(int&)'
+make[2]: *** [CMakeFiles/app.dir/build.make:76: CMakeFiles/app.dir/src/main.cpp.o] Error 1
+";
+
+    #[test]
+    fn test_units() {
+        let lines: Vec<&str> = BUILD.lines().collect();
+        let units = units(&lines);
+        assert_eq!(units.len(), 2);
+        assert_eq!(units[0].0, "src/main.cpp");
+        assert_eq!(units[0].1.len(), 2);
+        assert_eq!(units[0].1[0].notes, 4, "instantiation context and both notes");
+        assert_eq!(units[0].1[1].notes, 0);
+        assert_eq!(units[1].0, "src/util.cpp");
+    }
+
+    #[test]
+    fn test_digest() {
+        let body = digest(BUILD).unwrap().body;
+        assert!(body.starts_with("First error: /work/include/grid.hpp:30:10: error: no matching function"), "{}", body);
+        assert!(body.contains("\nsrc/main.cpp (2 errors):\n"));
+        assert!(!body.contains("src/util.cpp (1 errors)"), "its only error repeats the header's");
+        assert!(body.contains("(1 errors repeated from headers left out)\n"));
+
+        let link = "/usr/bin/ld: CMakeFiles/app.dir/src/main.cpp.o: in function `main':
+main.cpp:(.text+0x15): undefined reference to `compute(int)'
+collect2: error: ld returned 1 exit status
+";
+        let body = digest(link).unwrap().body;
+        assert_eq!(body, format!("\nLinker errors:\n{}", link));
+
+        let cmake = "CMake Error at CMakeLists.txt:12 (find_package):
+  Could not find a package configuration file provided by \"fmt\".
+
+-- Configuring incomplete, errors occurred!
+";
+        assert_eq!(
+            digest(cmake).unwrap().body,
+            "\nCMake errors:\nCMake Error at CMakeLists.txt:12 (find_package):\n  Could not find a package configuration file provided by \"fmt\".\n"
+        );
+        assert_eq!(digest("src/app.ts:3:7: error: oops\n"), None);
+    }
+}
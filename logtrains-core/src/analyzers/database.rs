@@ -0,0 +1,311 @@
+//! PostgreSQL and MySQL: deadlock reports with the statements involved,
+//! slow queries slowest first, replication errors and other errors with
+//! their statements.
+
+use regex::Regex;
+use std::sync::LazyLock;
+
+use super::{push_limited, Digest};
+
+const MAX_DEADLOCKS: usize = 3;
+const MAX_SLOW_QUERIES: usize = 5;
+const MAX_ERRORS: usize = 5;
+const MAX_STATEMENT_CHARS: usize = 300;
+
+/// A PostgreSQL message after any `log_line_prefix`: `ERROR:  deadlock
+/// detected`, with the two spaces PostgreSQL writes and others do not.
+static PG_ENTRY: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?:^|\s)(LOG|ERROR|FATAL|PANIC|WARNING|DETAIL|HINT|CONTEXT|STATEMENT|QUERY):  (.*)$").unwrap()
+});
+static PG_DURATION: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?s)^duration: ([\d.]+) ms\s+(?:statement|execute [^:]*|parse [^:]*|bind [^:]*): (.*)$").unwrap());
+/// MySQL's error log: `2024-05-01T12:00:00.000000Z 12 [ERROR] [MY-010584] [Repl] ...`.
+static MYSQL_ENTRY: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\[(ERROR|Warning|System)\] \[MY-\d+\] (?:\[(\w+)\] )?(.*)$").unwrap());
+static MYSQL_QUERY_TIME: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^# Query_time: ([\d.]+)").unwrap());
+static MYSQL_TRANSACTION: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\*\*\* \((\d+)\) TRANSACTION:").unwrap());
+static MYSQL_ROLLBACK: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\*\*\* WE ROLL BACK TRANSACTION \((\d+)\)").unwrap());
+static REPLICATION: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)WAL|walreceiver|walsender|replication|primary server|standby|\bslave\b|\breplica\b|\bRepl\b|binlog|relay log|GTID").unwrap()
+});
+
+const PROMPT: &str = "You are a {{ROLE}} and an experienced PostgreSQL and MySQL DBA. The log below is a database \
+    server's log, followed by a digest of it: deadlocks with the statements of each transaction, the slowest \
+    queries, replication errors and other errors with their statements. For a deadlock, explain which rows or \
+    locks the transactions take in conflicting order and how to make them take them in the same order, or \
+    shorten or retry them. For a slow query, name the likely missing index or plan problem and give the index \
+    or rewrite. For replication, say whether the replica can catch up or must be rebuilt, and how. Give SQL or \
+    configuration. Be brief. Use Markdown.";
+
+/// A PostgreSQL message with the `DETAIL`, `HINT`, `CONTEXT` and
+/// `STATEMENT` lines after it.
+#[derive(Debug, Default, PartialEq)]
+struct Entry {
+    level: String,
+    message: String,
+    fields: Vec<(String, String)>,
+}
+
+impl Entry {
+    fn field(&self, name: &str) -> Option<&str> {
+        self.fields.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+    }
+}
+
+#[derive(Default)]
+struct Report {
+    deadlocks: Vec<String>,
+    /// `(milliseconds, statement)`.
+    slow: Vec<(f64, String)>,
+    replication: Vec<String>,
+    errors: Vec<String>,
+}
+
+/// Recognizes PostgreSQL or MySQL server logs and digests them.
+pub fn digest(log: &str) -> Option<Digest> {
+    let lines: Vec<&str> = log.lines().map(str::trim_end).collect();
+    let mut report = Report::default();
+    postgres(&lines, &mut report);
+    let postgres_found = !is_empty(&report);
+    mysql(&lines, &mut report);
+    if is_empty(&report) {
+        return None;
+    }
+
+    let mut body = String::new();
+    if !report.deadlocks.is_empty() {
+        body.push_str(&format!("Deadlocks: {}\n", report.deadlocks.len()));
+        for deadlock in report.deadlocks.iter().take(MAX_DEADLOCKS) {
+            body.push_str(deadlock);
+        }
+    }
+    if !report.slow.is_empty() {
+        report.slow.sort_by(|a, b| b.0.total_cmp(&a.0));
+        body.push_str(&format!("Slow queries: {}, slowest first:\n", report.slow.len()));
+        for (duration, statement) in report.slow.iter().take(MAX_SLOW_QUERIES) {
+            body.push_str(&format!("  {:.1} ms  {}\n", duration, statement));
+        }
+    }
+    if !report.replication.is_empty() {
+        body.push_str("Replication errors:\n");
+        push_limited(&mut body, &dedup(report.replication), MAX_ERRORS);
+    }
+    if !report.errors.is_empty() {
+        body.push_str("Errors:\n");
+        push_limited(&mut body, &dedup(report.errors), MAX_ERRORS);
+    }
+    let tool = if postgres_found { "PostgreSQL" } else { "MySQL" };
+    Some(Digest { tool, title: "Database Log Digest", body, prompt: PROMPT, scope: None })
+}
+
+fn is_empty(report: &Report) -> bool {
+    report.deadlocks.is_empty() && report.slow.is_empty() && report.replication.is_empty() && report.errors.is_empty()
+}
+
+fn dedup(items: Vec<String>) -> Vec<String> {
+    let mut unique: Vec<String> = Vec::new();
+    for item in items {
+        if !unique.contains(&item) {
+            unique.push(item);
+        }
+    }
+    unique
+}
+
+/// A statement on one line, shortened.
+fn statement(text: &str) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    match text.char_indices().nth(MAX_STATEMENT_CHARS) {
+        Some((end, _)) => format!("{}...", &text[..end]),
+        None => text,
+    }
+}
+
+/// PostgreSQL's messages, each with its detail lines; lines indented with a
+/// tab continue the last one.
+fn entries(lines: &[&str]) -> Vec<Entry> {
+    let mut entries: Vec<Entry> = Vec::new();
+    for line in lines {
+        if let Some(entry) = PG_ENTRY.captures(line) {
+            let (kind, text) = (&entry[1], entry[2].to_string());
+            match (kind, entries.last_mut()) {
+                ("DETAIL" | "HINT" | "CONTEXT" | "STATEMENT" | "QUERY", Some(last)) => last.fields.push((kind.to_string(), text)),
+                _ => entries.push(Entry { level: kind.to_string(), message: text, fields: Vec::new() }),
+            }
+        } else if let (Some(last), Some(continued)) = (entries.last_mut(), line.strip_prefix('\t')) {
+            match last.fields.last_mut() {
+                Some((_, value)) => value.push_str(&format!("\n{}", continued)),
+                None => last.message.push_str(&format!("\n{}", continued)),
+            }
+        }
+    }
+    entries
+}
+
+/// `deadlock detected` with its `Process N waits for ...` and `Process N:
+/// statement` lines, `duration: ... ms` logs and errors.
+fn postgres(lines: &[&str], report: &mut Report) {
+    for entry in entries(lines) {
+        if entry.message.starts_with("deadlock detected") {
+            let mut deadlock = String::new();
+            for line in entry.field("DETAIL").unwrap_or("").lines() {
+                deadlock.push_str(&format!("  {}\n", statement(line)));
+            }
+            if let Some(context) = entry.field("CONTEXT") {
+                deadlock.push_str(&format!("  Context: {}\n", statement(context)));
+            }
+            if let Some(sql) = entry.field("STATEMENT") {
+                deadlock.push_str(&format!("  Statement that failed: {}\n", statement(sql)));
+            }
+            report.deadlocks.push(deadlock);
+        } else if let Some(duration) = PG_DURATION.captures(&entry.message) {
+            report.slow.push((duration[1].parse().unwrap_or(0.0), statement(&duration[2])));
+        } else if matches!(entry.level.as_str(), "ERROR" | "FATAL" | "PANIC") {
+            let mut error = format!("{}: {}", entry.level, statement(&entry.message));
+            if let Some(sql) = entry.field("STATEMENT") {
+                error.push_str(&format!("\n    Statement: {}", statement(sql)));
+            }
+            if REPLICATION.is_match(&entry.message) {
+                report.replication.push(error);
+            } else {
+                report.errors.push(error);
+            }
+        }
+    }
+}
+
+/// InnoDB's `LATEST DETECTED DEADLOCK` report, the slow query log and the
+/// error log.
+fn mysql(lines: &[&str], report: &mut Report) {
+    if let Some(deadlock) = innodb_deadlock(lines) {
+        report.deadlocks.push(deadlock);
+    }
+    for (index, line) in lines.iter().enumerate() {
+        if let Some(time) = MYSQL_QUERY_TIME.captures(line) {
+            // After the `#` header come `use db;` and `SET timestamp=...;`, then the statement up to its `;`
+            let mut sql: Vec<&str> = Vec::new();
+            for line in lines[index + 1..].iter().take_while(|line| !line.starts_with('#')) {
+                if line.starts_with("SET timestamp=") || line.starts_with("use ") {
+                    continue;
+                }
+                sql.push(line);
+                if line.ends_with(';') {
+                    break;
+                }
+            }
+            let seconds: f64 = time[1].parse().unwrap_or(0.0);
+            report.slow.push((seconds * 1000.0, statement(&sql.join(" "))));
+        }
+    }
+    for line in lines {
+        let Some(entry) = MYSQL_ENTRY.captures(line) else { continue };
+        let message = format!("{}: {}", &entry[1], &entry[3]);
+        if entry.get(2).is_some_and(|subsystem| subsystem.as_str() == "Repl") && &entry[1] == "ERROR" {
+            report.replication.push(message);
+        } else if &entry[1] == "ERROR" {
+            report.errors.push(message);
+        }
+    }
+}
+
+/// Each transaction of an InnoDB deadlock report with its statement and the
+/// lock it waits for, and which one InnoDB rolled back.
+fn innodb_deadlock(lines: &[&str]) -> Option<String> {
+    let start = lines.iter().position(|line| MYSQL_TRANSACTION.is_match(line))?;
+    let mut deadlock = String::new();
+    let mut waiting = false;
+    let mut index = start;
+    while index < lines.len() {
+        let line = lines[index];
+        if let Some(transaction) = MYSQL_TRANSACTION.captures(line) {
+            // `TRANSACTION ...`, `mysql tables in use`, `LOCK WAIT ...`, `MySQL thread id ...`, then the statement
+            let thread = lines[index..].iter().position(|line| line.starts_with("MySQL thread id")).map(|offset| index + offset);
+            let sql: Vec<&str> = thread
+                .map(|thread| lines[thread + 1..].iter().take_while(|line| !line.starts_with("***")).copied().collect())
+                .unwrap_or_default();
+            deadlock.push_str(&format!("  Transaction {}: {}\n", &transaction[1], statement(&sql.join(" "))));
+        } else if line.contains("WAITING FOR THIS LOCK TO BE GRANTED") {
+            waiting = true;
+        } else if waiting && (line.starts_with("RECORD LOCKS") || line.starts_with("TABLE LOCK")) {
+            deadlock.push_str(&format!("    waits for {}\n", statement(line)));
+            waiting = false;
+        } else if let Some(rollback) = MYSQL_ROLLBACK.captures(line) {
+            deadlock.push_str(&format!("  InnoDB rolled back transaction {}\n", &rollback[1]));
+            break;
+        }
+        index += 1;
+    }
+    Some(deadlock)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_postgres() {
+        let log = "2024-05-01 12:00:00.123 UTC [1234] ERROR:  deadlock detected
+2024-05-01 12:00:00.123 UTC [1234] DETAIL:  Process 1234 waits for ShareLock on transaction 5678; blocked by process 4321.
+\tProcess 4321 waits for ShareLock on transaction 1234; blocked by process 1234.
+\tProcess 1234: UPDATE accounts SET balance = balance - 10 WHERE id = 1;
+\tProcess 4321: UPDATE accounts SET balance = balance + 10 WHERE id = 2;
+2024-05-01 12:00:00.123 UTC [1234] HINT:  See server log for query details.
+2024-05-01 12:00:00.123 UTC [1234] CONTEXT:  while updating tuple (0,1) in relation \"accounts\"
+2024-05-01 12:00:00.123 UTC [1234] STATEMENT:  UPDATE accounts SET balance = balance - 10 WHERE id = 1;
+2024-05-01 12:00:01.000 UTC [1240] LOG:  duration: 1523.456 ms  statement: SELECT * FROM orders
+\tWHERE customer_id = 42;
+2024-05-01 12:00:02.000 UTC [1241] LOG:  duration: 2040.000 ms  execute <unnamed>: SELECT count(*) FROM events;
+2024-05-01 12:00:03.000 UTC [99] FATAL:  could not receive data from WAL stream: ERROR:  requested WAL segment 000000010000000000000003 has already been removed
+";
+        let entries = entries(&log.lines().collect::<Vec<_>>());
+        assert_eq!(entries.len(), 4);
+        assert_eq!(entries[0].field("DETAIL").unwrap().lines().count(), 4);
+
+        let digest = digest(log).unwrap();
+        assert_eq!(digest.tool, "PostgreSQL");
+        assert!(digest.body.starts_with(
+            "Deadlocks: 1\n  Process 1234 waits for ShareLock on transaction 5678; blocked by process 4321.\n"
+        ), "{}", digest.body);
+        assert!(digest.body.contains("  Context: while updating tuple (0,1) in relation \"accounts\"\n"));
+        assert!(digest.body.contains(
+            "Slow queries: 2, slowest first:\n  2040.0 ms  SELECT count(*) FROM events;\n  1523.5 ms  SELECT * FROM orders WHERE customer_id = 42;\n"
+        ));
+        assert!(digest.body.ends_with("Replication errors:\nFATAL: could not receive data from WAL stream: ERROR: requested WAL segment 000000010000000000000003 has already been removed\n"));
+    }
+
+    #[test]
+    fn test_mysql() {
+        let log = "------------------------
+LATEST DETECTED DEADLOCK
+------------------------
+*** (1) TRANSACTION:
+TRANSACTION 5001, ACTIVE 2 sec starting index read
+MySQL thread id 12, OS thread handle 1, query id 100 localhost app updating
+UPDATE accounts SET balance = balance - 10 WHERE id = 1
+*** (1) WAITING FOR THIS LOCK TO BE GRANTED:
+RECORD LOCKS space id 58 page no 3 n bits 72 index PRIMARY of table `shop`.`accounts` trx id 5001 lock_mode X locks rec but not gap waiting
+*** (2) TRANSACTION:
+TRANSACTION 5002, ACTIVE 1 sec starting index read
+MySQL thread id 13, OS thread handle 2, query id 101 localhost app updating
+UPDATE accounts SET balance = balance + 10 WHERE id = 2
+*** (2) WAITING FOR THIS LOCK TO BE GRANTED:
+RECORD LOCKS space id 58 page no 3 n bits 72 index PRIMARY of table `shop`.`accounts` trx id 5002 lock_mode X locks rec but not gap waiting
+*** WE ROLL BACK TRANSACTION (2)
+# Time: 2024-05-01T12:00:00.000000Z
+# User@Host: app[app] @ localhost []
+# Query_time: 12.500000  Lock_time: 0.000100 Rows_sent: 1  Rows_examined: 1000000
+SET timestamp=1714564800;
+SELECT * FROM orders WHERE note LIKE '%gift%';
+2024-05-01T12:00:05.000000Z 8 [ERROR] [MY-010584] [Repl] Replica SQL for channel '': Error 'Duplicate entry '7' for key 'users.PRIMARY'' on query. Error_code: MY-001062
+";
+        let digest = digest(log).unwrap();
+        assert_eq!(digest.tool, "MySQL");
+        assert!(digest.body.starts_with(
+            "Deadlocks: 1\n  Transaction 1: UPDATE accounts SET balance = balance - 10 WHERE id = 1\n    waits for RECORD LOCKS"
+        ), "{}", digest.body);
+        assert!(digest.body.contains("  InnoDB rolled back transaction 2\n"));
+        assert!(digest.body.contains("  12500.0 ms  SELECT * FROM orders WHERE note LIKE '%gift%';\n"));
+        assert!(digest.body.contains("Replication errors:\nERROR: Replica SQL for channel ''"));
+        assert_eq!(super::digest("ERROR: something failed\n[ERROR] connection lost\n"), None);
+    }
+}
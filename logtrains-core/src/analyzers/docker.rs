@@ -0,0 +1,208 @@
+//! `docker build`: the Dockerfile instruction that failed, its stage and
+//! layer, and only that step's output, with BuildKit or the legacy builder.
+
+use regex::Regex;
+use std::sync::LazyLock;
+
+use super::Digest;
+
+/// Lines of the step's output kept when it is long.
+const MAX_STEP_LINES: usize = 60;
+
+/// BuildKit's `#5 [builder 2/4] RUN make`.
+static STEP: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^#(\d+) \[([^\]]+)\] (.+)$").unwrap());
+/// BuildKit's `#5 0.512 output` and `#5 ERROR: ...`.
+static STEP_LINE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^#(\d+) (?:\d+\.\d+ )?(.*)$").unwrap());
+static ELAPSED: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\d+\.\d+ ").unwrap());
+/// The output BuildKit repeats after a failure: ` > [2/4] RUN make:`.
+static FAILED_STEP: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^ > \[([^\]]+)\] (.+):$").unwrap());
+/// `[builder 2/4]`: the stage, if named, and the layer.
+static LAYER: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^(?:(\S+) )?(\d+)/(\d+)$").unwrap());
+/// `Dockerfile:3` before the excerpt BuildKit prints, and its `   3 | >>> RUN make` lines.
+static DOCKERFILE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^(\S*Dockerfile\S*):(\d+)$").unwrap());
+static EXCERPT: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\s*\d+ \| (>>>)?").unwrap());
+/// The legacy builder's `Step 3/5 : RUN make`.
+static LEGACY_STEP: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^Step (\d+)/(\d+) : (.+)$").unwrap());
+static LEGACY_ERROR: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^The command '.*' returned a non-zero code: \d+$").unwrap());
+
+const PROMPT: &str = "You are a {{ROLE}} and an expert in Docker and container builds. The log below is the output \
+    of the one `docker build` step that failed, followed by a digest naming its Dockerfile instruction, stage and \
+    layer. Explain why the instruction failed: a missing package or file, a COPY source outside the build context \
+    or ignored by .dockerignore, a wrong base image or platform, a missing build argument, or the command's own \
+    error. Then give the corrected instruction as a Dockerfile snippet, with any instruction it needs before it. \
+    Be brief. Use Markdown.";
+
+/// The step that failed.
+#[derive(Debug, PartialEq)]
+struct Step {
+    /// `RUN make`.
+    instruction: String,
+    /// `builder 2/4`, or `3/5` for the legacy builder.
+    layer: String,
+    output: Vec<String>,
+    error: Option<String>,
+}
+
+/// Recognizes a failed `docker build` and digests the step that failed.
+pub fn digest(log: &str) -> Option<Digest> {
+    let lines: Vec<&str> = log.lines().map(str::trim_end).collect();
+    let step = buildkit_step(&lines).or_else(|| legacy_step(&lines))?;
+
+    let mut body = format!("Failed instruction: {}\n", step.instruction);
+    if let Some(layer) = LAYER.captures(&step.layer) {
+        body.push_str(&format!("Layer {} of {}", &layer[2], &layer[3]));
+        if let Some(stage) = layer.get(1) {
+            body.push_str(&format!(" of stage `{}`", stage.as_str()));
+        }
+        body.push('\n');
+    }
+    let excerpt = dockerfile_excerpt(&lines);
+    if !excerpt.is_empty() {
+        body.push_str(&format!("{}\n", excerpt.join("\n")));
+    }
+    let failure = lines.iter().find(|line| line.starts_with("ERROR: failed to solve:") || line.starts_with("ERROR: failed to build:"));
+    if let Some(error) = failure.map(|line| line.to_string()).or(step.error) {
+        body.push_str(&format!("{}\n", error));
+    }
+
+    let shown = &step.output[step.output.len().saturating_sub(MAX_STEP_LINES)..];
+    let mut scope = format!("Output of `{}` ({}):\n", step.instruction, step.layer);
+    if shown.len() < step.output.len() {
+        scope.push_str(&format!("... {} earlier lines left out\n", step.output.len() - shown.len()));
+    }
+    for line in shown {
+        scope.push_str(&format!("{}\n", line));
+    }
+
+    Some(Digest { tool: "Docker", title: "Docker Build Failure", body, prompt: PROMPT, scope: Some(scope) })
+}
+
+/// The BuildKit step with an `#N ERROR:` line and its own `#N` lines, or
+/// without those (the TTY progress view) the block BuildKit repeats after a
+/// failure.
+fn buildkit_step(lines: &[&str]) -> Option<Step> {
+    let failed = lines.iter().find_map(|line| {
+        let line = STEP_LINE.captures(line)?;
+        line[2].starts_with("ERROR:").then(|| (line[1].to_string(), line[2].to_string()))
+    });
+    if let Some((id, error)) = failed {
+        if let Some(header) = lines.iter().filter_map(|line| STEP.captures(line)).find(|step| step[1] == id) {
+            let output = lines
+                .iter()
+                .filter(|line| !STEP.is_match(line))
+                .filter_map(|line| STEP_LINE.captures(line))
+                .filter(|line| line[1] == id)
+                .map(|line| line[2].to_string())
+                .filter(|line| !line.is_empty() && !line.starts_with("ERROR:") && !line.starts_with("DONE "))
+                .collect();
+            return Some(Step { instruction: header[3].to_string(), layer: header[2].to_string(), output, error: Some(error) });
+        }
+    }
+    let start = lines.iter().position(|line| FAILED_STEP.is_match(line))?;
+    let step = FAILED_STEP.captures(lines[start])?;
+    let output = lines[start + 1..]
+        .iter()
+        .take_while(|line| !line.starts_with("------"))
+        .map(|line| ELAPSED.replace(line, "").to_string())
+        .filter(|line| !line.trim().is_empty())
+        .collect();
+    Some(Step { instruction: step[2].to_string(), layer: step[1].to_string(), output, error: None })
+}
+
+/// The last legacy-builder step before `The command ... returned a non-zero
+/// code`, without the builder's own `--->` lines.
+fn legacy_step(lines: &[&str]) -> Option<Step> {
+    let end = lines.iter().position(|line| LEGACY_ERROR.is_match(line))?;
+    let start = lines[..end].iter().rposition(|line| LEGACY_STEP.is_match(line))?;
+    let step = LEGACY_STEP.captures(lines[start])?;
+    let output = lines[start + 1..end]
+        .iter()
+        .filter(|line| !line.trim_start().starts_with("--->") && !line.trim().is_empty())
+        .map(|line| line.to_string())
+        .collect();
+    Some(Step {
+        instruction: step[3].to_string(),
+        layer: format!("{}/{}", &step[1], &step[2]),
+        output,
+        error: Some(lines[end].to_string()),
+    })
+}
+
+/// `Dockerfile:3` and the excerpt BuildKit prints around the instruction.
+fn dockerfile_excerpt(lines: &[&str]) -> Vec<String> {
+    let Some(start) = lines.iter().rposition(|line| DOCKERFILE.is_match(line)) else {
+        return Vec::new();
+    };
+    let mut excerpt = vec![lines[start].to_string()];
+    excerpt.extend(lines[start + 1..].iter().skip_while(|line| line.starts_with("----")).take_while(|line| EXCERPT.is_match(line)).map(|line| line.to_string()));
+    excerpt
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BUILDKIT: &str = "#0 building with \"default\" instance using docker driver
+#4 [builder 1/4] FROM docker.io/library/debian:bookworm
+#4 DONE 0.0s
+#5 [builder 2/4] RUN apt-get update && apt-get install -y libfoo-dev
+#5 0.512 Reading package lists...
+#5 1.234 E: Unable to locate package libfoo-dev
+#5 ERROR: process \"/bin/sh -c apt-get update && apt-get install -y libfoo-dev\" did not complete successfully: exit code: 100
+------
+ > [builder 2/4] RUN apt-get update && apt-get install -y libfoo-dev:
+0.512 Reading package lists...
+1.234 E: Unable to locate package libfoo-dev
+------
+Dockerfile:3
+--------------------
+   2 |     WORKDIR /app
+   3 | >>> RUN apt-get update && apt-get install -y libfoo-dev
+   4 |     COPY . .
+--------------------
+ERROR: failed to solve: process \"/bin/sh -c apt-get update && apt-get install -y libfoo-dev\" did not complete successfully: exit code: 100
+";
+
+    #[test]
+    fn test_buildkit() {
+        let lines: Vec<&str> = BUILDKIT.lines().collect();
+        let step = buildkit_step(&lines).unwrap();
+        assert_eq!(step.instruction, "RUN apt-get update && apt-get install -y libfoo-dev");
+        assert_eq!(step.layer, "builder 2/4");
+        assert_eq!(step.output, ["Reading package lists...", "E: Unable to locate package libfoo-dev"]);
+
+        // The TTY view has only the repeated block
+        let tty: Vec<&str> = lines.iter().copied().filter(|line| !line.starts_with('#')).collect();
+        assert_eq!(buildkit_step(&tty), Some(Step { error: None, ..step }));
+
+        let digest = digest(BUILDKIT).unwrap();
+        assert!(digest.body.starts_with(
+            "Failed instruction: RUN apt-get update && apt-get install -y libfoo-dev\nLayer 2 of 4 of stage `builder`\n\
+             Dockerfile:3\n   2 |     WORKDIR /app\n   3 | >>> RUN"
+        ), "{}", digest.body);
+        assert!(digest.body.ends_with("exit code: 100\n"));
+        assert_eq!(
+            digest.scope.unwrap(),
+            "Output of `RUN apt-get update && apt-get install -y libfoo-dev` (builder 2/4):\n\
+             Reading package lists...\nE: Unable to locate package libfoo-dev\n"
+        );
+    }
+
+    #[test]
+    fn test_legacy() {
+        let log = "Step 2/3 : COPY target/app.jar /app.jar
+ ---> 1a2b3c4d
+Step 3/3 : RUN java -version
+ ---> Running in 5e6f7a8b
+/bin/sh: 1: java: not found
+The command '/bin/sh -c java -version' returned a non-zero code: 127
+";
+        let digest = digest(log).unwrap();
+        assert_eq!(
+            digest.body,
+            "Failed instruction: RUN java -version\nLayer 3 of 3\nThe command '/bin/sh -c java -version' returned a non-zero code: 127\n"
+        );
+        assert!(digest.scope.unwrap().ends_with("(3/3):\n/bin/sh: 1: java: not found\n"));
+        assert_eq!(super::digest("#5 [2/4] RUN make\n#5 DONE 3.1s\n"), None);
+    }
+}
@@ -0,0 +1,226 @@
+//! Go: panics with the goroutine that panicked, `go test` failures with
+//! their own output, and compile errors.
+
+use regex::Regex;
+use std::sync::LazyLock;
+
+use super::{push_limited, Digest};
+
+/// Frames kept of a goroutine, leaving out the runtime's.
+const MAX_FRAMES: usize = 8;
+/// Goroutines kept when all of them matter, as in a deadlock.
+const MAX_GOROUTINES: usize = 3;
+const MAX_FAILURES: usize = 10;
+const MAX_TEST_LINES: usize = 8;
+const MAX_BUILD_ERRORS: usize = 10;
+
+static GOROUTINE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^goroutine \d+ \[[^\]]+\]:$").unwrap());
+/// `main.process(...)` followed by `\t/app/main.go:12 +0x1d`.
+static FUNCTION: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^(?:created by )?[\w./*()\[\]-]+(?:\(.*\))?(?: in goroutine \d+)?$").unwrap());
+static LOCATION: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\s+(\S+\.go:\d+)(?: \+0x[0-9a-f]+)?$").unwrap());
+static TEST_RESULT: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\s*--- (FAIL|PASS|SKIP): (\S+)").unwrap());
+static RUN: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^=== RUN\s+(\S+)").unwrap());
+/// `FAIL\texample.com/app\t0.012s` or `FAIL\texample.com/app [build failed]`.
+static PACKAGE_FAIL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^FAIL\s+(\S+)\s+(?:\d+\.\d+s|\[[^\]]+\])$").unwrap());
+static BUILD_ERROR: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\S+\.go:\d+(?::\d+)?: .+$").unwrap());
+
+const PROMPT: &str = "You are a {{ROLE}} and an expert Go developer. The log below is output of a Go program or \
+    `go test`, followed by a digest of its failure: the panic with only the goroutine that panicked, the \
+    failing tests with their own output, or compile errors. Explain what failed and why, starting from the \
+    frame in the program's own code. For nil pointers, nil maps, out-of-range indexes or data races, name the \
+    variable involved; for a deadlock, say which goroutines wait on what. For test failures, say whether the \
+    test or the code under test is wrong. Then show the smallest code change, as a Go snippet. Be brief. \
+    Use Markdown.";
+
+/// Recognizes Go panics, test failures or compile errors and digests them.
+pub fn digest(log: &str) -> Option<Digest> {
+    let lines: Vec<&str> = log.lines().map(str::trim_end).collect();
+    let mut body = String::new();
+    panic_digest(&lines, &mut body);
+    tests_digest(&lines, &mut body);
+    build_digest(&lines, &mut body);
+    if body.is_empty() {
+        return None;
+    }
+    Some(Digest { tool: "Go", title: "Go Failure", body, prompt: PROMPT, scope: None })
+}
+
+/// The panic (or fatal error) message and the goroutine that raised it;
+/// all goroutines, up to a few, for a deadlock.
+fn panic_digest(lines: &[&str], body: &mut String) {
+    let Some(start) = lines.iter().position(|line| line.starts_with("panic: ") || line.starts_with("fatal error: ")) else {
+        return;
+    };
+    let goroutines: Vec<usize> = (start..lines.len()).filter(|&i| GOROUTINE.is_match(lines[i])).collect();
+    let Some(&first) = goroutines.first() else {
+        return;
+    };
+    // The message, with any `[recovered]` panics, runs up to the first goroutine
+    for line in lines[start..first].iter().filter(|line| !line.trim().is_empty()) {
+        body.push_str(&format!("{}\n", line.trim()));
+    }
+    let deadlock = lines[start].contains("deadlock");
+    let kept = if deadlock { MAX_GOROUTINES } else { 1 };
+    for &goroutine in goroutines.iter().take(kept) {
+        body.push_str(&format!("{}\n", lines[goroutine]));
+        let mut frames = 0;
+        let mut index = goroutine + 1;
+        while index + 1 < lines.len() && FUNCTION.is_match(lines[index]) {
+            let Some(location) = LOCATION.captures(lines[index + 1]) else { break };
+            let function = lines[index];
+            if !function.starts_with("runtime.") && !function.starts_with("testing.") && frames < MAX_FRAMES {
+                body.push_str(&format!("  {}\n      {}\n", function, &location[1]));
+                frames += 1;
+            }
+            index += 2;
+        }
+    }
+    if goroutines.len() > kept {
+        body.push_str(&format!("({} other goroutines left out)\n", goroutines.len() - kept));
+    }
+}
+
+/// The failed tests and the packages that failed.
+fn tests_digest(lines: &[&str], body: &mut String) {
+    let failures = test_failures(lines);
+    let packages: Vec<&str> = lines
+        .iter()
+        .filter_map(|line| PACKAGE_FAIL.captures(line))
+        .map(|c| c.get(1).map_or("", |m| m.as_str()))
+        .collect();
+    if failures.is_empty() && packages.is_empty() {
+        return;
+    }
+    if !body.is_empty() {
+        body.push('\n');
+    }
+    if !failures.is_empty() {
+        body.push_str("Failed tests:\n");
+        for (result, output) in failures.iter().take(MAX_FAILURES) {
+            body.push_str(&format!("{}\n", result));
+            let shown = &output[output.len().saturating_sub(MAX_TEST_LINES)..];
+            for line in shown {
+                body.push_str(&format!("    {}\n", line));
+            }
+        }
+        if failures.len() > MAX_FAILURES {
+            body.push_str(&format!("... and {} more\n", failures.len() - MAX_FAILURES));
+        }
+    }
+    if !packages.is_empty() {
+        body.push_str(&format!("Failed packages: {}\n", packages.join(", ")));
+    }
+}
+
+/// Each `--- FAIL` line with the lines its test logged. With `-v` a test's
+/// output comes before its `--- FAIL`, without after it.
+pub(super) fn test_failures(lines: &[&str]) -> Vec<(String, Vec<String>)> {
+    let mut failures: Vec<(String, Vec<String>)> = Vec::new();
+    let mut running: Vec<String> = Vec::new();
+    // Where indented output goes: the running test's or the last failure's
+    let mut after_failure = false;
+    for line in lines {
+        if RUN.is_match(line) {
+            running.clear();
+            after_failure = false;
+        } else if let Some(result) = TEST_RESULT.captures(line) {
+            if &result[1] == "FAIL" {
+                failures.push((line.trim().to_string(), std::mem::take(&mut running)));
+                after_failure = true;
+            } else {
+                running.clear();
+                after_failure = false;
+            }
+        } else if line.starts_with(char::is_whitespace) && !line.trim().is_empty() {
+            match failures.last_mut().filter(|_| after_failure) {
+                Some((_, output)) => output.push(line.trim().to_string()),
+                None => running.push(line.trim().to_string()),
+            }
+        } else if !line.starts_with("=== ") {
+            after_failure = false;
+        }
+    }
+    failures
+}
+
+/// Compile errors, which follow a `# package` line.
+fn build_digest(lines: &[&str], body: &mut String) {
+    let mut errors: Vec<String> = Vec::new();
+    let mut in_package = false;
+    for line in lines {
+        if line.starts_with("# ") {
+            in_package = true;
+        } else if in_package && BUILD_ERROR.is_match(line) {
+            errors.push(line.to_string());
+        } else if !line.starts_with(char::is_whitespace) {
+            in_package = false;
+        }
+    }
+    if errors.is_empty() {
+        return;
+    }
+    if !body.is_empty() {
+        body.push('\n');
+    }
+    body.push_str("Build errors:\n");
+    push_limited(body, &errors, MAX_BUILD_ERRORS);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_panic() {
+        let log = "starting
+panic: assignment to entry in nil map
+
+goroutine 1 [running]:
+main.(*Cache).Put(...)
+\t/app/cache.go:14
+main.main()
+\t/app/main.go:9 +0x1d
+
+goroutine 6 [chan receive]:
+main.worker()
+\t/app/main.go:30 +0x45
+exit status 2
+";
+        let body = digest(log).unwrap().body;
+        assert_eq!(
+            body,
+            "panic: assignment to entry in nil map\ngoroutine 1 [running]:\n  main.(*Cache).Put(...)\n      /app/cache.go:14\n\
+             \x20 main.main()\n      /app/main.go:9\n(1 other goroutines left out)\n"
+        );
+    }
+
+    #[test]
+    fn test_tests() {
+        let verbose = "=== RUN   TestParse
+    parse_test.go:14: got 3, want 4
+--- FAIL: TestParse (0.00s)
+=== RUN   TestFormat
+--- PASS: TestFormat (0.00s)
+FAIL
+FAIL\texample.com/app/parse\t0.012s
+";
+        let quiet = "--- FAIL: TestParse (0.00s)
+    parse_test.go:14: got 3, want 4
+FAIL
+FAIL\texample.com/app/parse\t0.012s
+";
+        let expected = "Failed tests:\n--- FAIL: TestParse (0.00s)\n    parse_test.go:14: got 3, want 4\n\
+                        Failed packages: example.com/app/parse\n";
+        assert_eq!(digest(verbose).unwrap().body, expected);
+        assert_eq!(digest(quiet).unwrap().body, expected);
+    }
+
+    #[test]
+    fn test_build_errors() {
+        let log = "# example.com/app\n./main.go:12:5: undefined: fetch\nFAIL\texample.com/app [build failed]\n";
+        let body = digest(log).unwrap().body;
+        assert!(body.ends_with("Build errors:\n./main.go:12:5: undefined: fetch\n"), "{}", body);
+        assert_eq!(digest("ok  \texample.com/app\t0.010s\n"), None);
+    }
+}
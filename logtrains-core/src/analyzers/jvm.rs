@@ -0,0 +1,208 @@
+//! Java and other JVM languages: stack traces with their `Caused by:`
+//! chains, the framework frames collapsed and the application's kept.
+
+use regex::Regex;
+use std::sync::LazyLock;
+
+use super::Digest;
+
+const MAX_TRACES: usize = 3;
+/// Application frames shown per exception; the one that threw always is.
+const MAX_APP_FRAMES: usize = 5;
+const MAX_PACKAGES: usize = 5;
+
+/// `at com.example.Foo.bar(Foo.java:12)`, also with a module
+/// (`java.base/java.lang.Thread.run`) or logback's `~[app.jar:1.0]`.
+static FRAME: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\s+at (?:[\w.$-]+(?:@[\w.-]+)?/+)?([\w$.<>]+)\(([^)]*)\)").unwrap());
+static HEADER: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"^(?:.*?Exception in thread "[^"]*" )?((?:[a-zA-Z_$][\w$]*\.)+[A-Z][\w$]*)(?::\s*(.*))?$"#).unwrap()
+});
+static CAUSE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\s*(Caused by|Suppressed): (.+)$").unwrap());
+static MORE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\s+\.\.\. \d+ (more|common frames omitted)$").unwrap());
+
+/// Packages of the JDK and common frameworks, whose frames rarely explain
+/// a failure.
+const FRAMEWORKS: &[&str] = &[
+    "java.", "javax.", "jakarta.", "jdk.", "sun.", "com.sun.", "kotlin.", "kotlinx.", "scala.", "akka.",
+    "org.springframework.", "org.apache.", "org.hibernate.", "org.eclipse.", "org.jboss.", "org.junit.",
+    "org.gradle.", "worker.org.gradle.", "org.mockito.", "org.glassfish.", "io.netty.", "io.undertow.",
+    "io.micrometer.", "io.grpc.", "reactor.", "rx.", "com.fasterxml.", "com.google.", "com.zaxxer.", "okhttp3.",
+    "feign.", "net.bytebuddy.", "groovy.", "org.codehaus.", "org.postgresql.", "com.mysql.", "ch.qos.logback.",
+];
+
+const PROMPT: &str = "You are a {{ROLE}} and an expert Java and JVM developer. The log below is output of a JVM \
+    application, followed by a digest of its stack traces: framework frames are collapsed, the application's \
+    own frames are kept and `Caused by:` chains are listed down to the root cause. Explain the root cause, \
+    usually the last `Caused by:`, and where the application's code is involved. For Spring, Hibernate or \
+    other framework errors, name the bean, entity or setting at fault. Then show the smallest fix, as code \
+    or configuration. Be brief. Use Markdown.";
+
+#[derive(Debug, PartialEq)]
+struct Exception {
+    /// `Caused by` or `Suppressed`, for all but the first.
+    relation: Option<String>,
+    message: String,
+    frames: Vec<String>,
+}
+
+fn is_framework(frame: &str) -> bool {
+    FRAMEWORKS.iter().any(|package| frame.starts_with(package)) || frame.contains("$$")
+}
+
+/// Recognizes JVM stack traces and digests them.
+pub fn digest(log: &str) -> Option<Digest> {
+    let traces = traces(log);
+    if traces.is_empty() {
+        return None;
+    }
+
+    let mut body = String::new();
+    let mut packages: Vec<String> = Vec::new();
+    for trace in traces.iter().take(MAX_TRACES) {
+        for exception in trace {
+            match &exception.relation {
+                Some(relation) => body.push_str(&format!("{}: {}\n", relation, exception.message)),
+                None => body.push_str(&format!("{}\n", exception.message)),
+            }
+            render_frames(&mut body, &exception.frames);
+            for frame in exception.frames.iter().filter(|frame| !is_framework(frame)) {
+                let package = package(frame);
+                if !package.is_empty() && !packages.contains(&package) {
+                    packages.push(package);
+                }
+            }
+        }
+        let root = trace.iter().rev().find(|exception| exception.relation.as_deref() != Some("Suppressed"));
+        if let Some(root) = root.filter(|_| trace.len() > 1) {
+            body.push_str(&format!("Root cause: {}\n", root.message));
+        }
+        body.push('\n');
+    }
+    if traces.len() > MAX_TRACES {
+        body.push_str(&format!("... and {} more stack traces\n", traces.len() - MAX_TRACES));
+    }
+    if !packages.is_empty() {
+        packages.truncate(MAX_PACKAGES);
+        body.push_str(&format!("Application packages: {}\n", packages.join(", ")));
+    }
+
+    Some(Digest { tool: "JVM", title: "JVM Stack Traces", body, prompt: PROMPT, scope: None })
+}
+
+/// The frame that threw and the application's frames, with each run of
+/// framework frames in between collapsed into a count.
+fn render_frames(out: &mut String, frames: &[String]) {
+    let mut collapsed = 0;
+    let mut shown = 0;
+    for (index, frame) in frames.iter().enumerate() {
+        if index == 0 || (!is_framework(frame) && shown < MAX_APP_FRAMES) {
+            if collapsed > 0 {
+                out.push_str(&format!("  ... {} framework frames\n", collapsed));
+                collapsed = 0;
+            }
+            out.push_str(&format!("  at {}\n", frame));
+            shown += usize::from(index > 0);
+        } else {
+            collapsed += 1;
+        }
+    }
+    if collapsed > 0 {
+        out.push_str(&format!("  ... {} framework frames\n", collapsed));
+    }
+}
+
+/// The first three parts of a frame's class name, e.g. `com.example.orders`.
+fn package(frame: &str) -> String {
+    let class = frame.split('(').next().unwrap_or(frame);
+    let parts: Vec<&str> = class.split('.').collect();
+    // The last two parts are the class and the method
+    parts[..parts.len().saturating_sub(2).min(3)].join(".")
+}
+
+/// The stack traces in the log, each the thrown exception followed by its
+/// causes. A trace starts at the line before its first frame.
+fn traces(log: &str) -> Vec<Vec<Exception>> {
+    let mut traces: Vec<Vec<Exception>> = Vec::new();
+    let mut current: Option<Vec<Exception>> = None;
+    let mut previous = "";
+    for line in log.lines().map(str::trim_end) {
+        if let Some(frame) = FRAME.captures(line) {
+            let frame = format!("{}({})", &frame[1], &frame[2]);
+            match current.as_mut().and_then(|trace| trace.last_mut()) {
+                Some(exception) => exception.frames.push(frame),
+                None => {
+                    let message = match HEADER.captures(previous.trim()) {
+                        Some(header) => match header.get(2) {
+                            Some(message) => format!("{}: {}", &header[1], message.as_str()),
+                            None => header[1].to_string(),
+                        },
+                        None => previous.trim().to_string(),
+                    };
+                    current = Some(vec![Exception { relation: None, message, frames: vec![frame] }]);
+                }
+            }
+        } else if let (Some(trace), Some(cause)) = (current.as_mut(), CAUSE.captures(line)) {
+            trace.push(Exception { relation: Some(cause[1].to_string()), message: cause[2].to_string(), frames: Vec::new() });
+        } else if current.is_some() && MORE.is_match(line) {
+            // Frames shared with the enclosing exception
+        } else if let Some(trace) = current.take() {
+            traces.push(trace);
+        }
+        previous = line;
+    }
+    traces.extend(current);
+    traces
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPRING: &str = "2024-05-01 12:00:00.000 ERROR 1 --- [main] o.s.boot.SpringApplication : Application run failed
+java.lang.IllegalStateException: Failed to execute CommandLineRunner
+\tat org.springframework.boot.SpringApplication.callRunner(SpringApplication.java:771)
+\tat org.springframework.boot.SpringApplication.run(SpringApplication.java:315)
+\tat com.example.orders.OrdersApplication.main(OrdersApplication.java:10)
+Caused by: org.springframework.dao.DataAccessResourceFailureException: Unable to acquire JDBC Connection
+\tat org.springframework.orm.jpa.vendor.HibernateJpaDialect.convertHibernateAccessException(HibernateJpaDialect.java:277)
+\tat com.example.orders.OrderLoader$$SpringCGLIB$$0.run(<generated>)
+\tat com.example.orders.OrderLoader.run(OrderLoader.java:31)
+\tat java.base/java.lang.Thread.run(Thread.java:833)
+\t... 5 common frames omitted
+Caused by: org.postgresql.util.PSQLException: Connection to localhost:5432 refused.
+\tat org.postgresql.core.v3.ConnectionFactoryImpl.openConnectionImpl(ConnectionFactoryImpl.java:319)
+\t... 12 more
+2024-05-01 12:00:01.000 INFO 1 --- [main] shutting down
+";
+
+    #[test]
+    fn test_traces() {
+        let traces = traces(SPRING);
+        assert_eq!(traces.len(), 1);
+        let trace = &traces[0];
+        assert_eq!(trace.len(), 3);
+        assert_eq!(trace[0].message, "java.lang.IllegalStateException: Failed to execute CommandLineRunner");
+        assert_eq!(trace[1].relation.as_deref(), Some("Caused by"));
+        assert_eq!(trace[1].frames[3], "java.lang.Thread.run(Thread.java:833)");
+        assert_eq!(trace[2].frames.len(), 1);
+
+        let prefixed = "Exception in thread \"main\" java.lang.NullPointerException\n\tat App.main(App.java:3)\n";
+        assert_eq!(super::traces(prefixed)[0][0].message, "java.lang.NullPointerException");
+    }
+
+    #[test]
+    fn test_digest() {
+        let body = digest(SPRING).unwrap().body;
+        assert!(body.contains(
+            "Caused by: org.springframework.dao.DataAccessResourceFailureException: Unable to acquire JDBC Connection\n\
+             \x20 at org.springframework.orm.jpa.vendor.HibernateJpaDialect.convertHibernateAccessException(HibernateJpaDialect.java:277)\n\
+             \x20 ... 1 framework frames\n\
+             \x20 at com.example.orders.OrderLoader.run(OrderLoader.java:31)\n\
+             \x20 ... 1 framework frames\n"
+        ), "{}", body);
+        assert!(body.contains("Root cause: org.postgresql.util.PSQLException: Connection to localhost:5432 refused.\n"));
+        assert!(body.ends_with("Application packages: com.example.orders\n"));
+        assert_eq!(package("App.main(App.java:3)"), "");
+    }
+}
@@ -0,0 +1,280 @@
+//! Kernel logs (`dmesg`, `journalctl -k`): oopses and BUGs, segfaults, the
+//! OOM killer and hung tasks grouped into blocks, and kernel error codes
+//! translated in the log. Not detected on its own; `--mode kernel` asks for
+//! it.
+
+use regex::{Captures, Regex};
+use std::sync::LazyLock;
+
+use super::Digest;
+
+const MAX_BLOCKS: usize = 5;
+const MAX_CALL_FRAMES: usize = 8;
+/// Lines an oops can span before its end marker is given up on.
+const MAX_OOPS_LINES: usize = 60;
+
+/// `[12345.678901] `, `<3>` or journalctl's `May 01 12:00:00 host kernel: `.
+static PREFIX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(?:<\d>)?(?:\[\s*\d+\.\d+\]\s?|\w{3} [ \d]\d \d{2}:\d{2}:\d{2} \S+ kernel: )").unwrap()
+});
+static OOPS: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(?:BUG: |Oops: |kernel BUG at |general protection fault|WARNING: CPU: |Kernel panic - |Unable to handle kernel )").unwrap()
+});
+/// `  mydrv_irq+0x1c/0x80 [mydrv]` and `  ? mydrv_irq+0x1c/0x80`.
+static CALL_FRAME: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\s*(\? )?([\w.]+\+0x[0-9a-f]+/0x[0-9a-f]+(?: \[\w+\])?)").unwrap());
+static SEGFAULT: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(\S+)\[(\d+)\]: segfault at ([0-9a-f]+) ip [0-9a-f]+ sp [0-9a-f]+ error ([0-9a-f]+)(?: in ([^\[\s]+))?").unwrap()
+});
+static OOM_KILLED: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?:Out of memory|Memory cgroup out of memory): Killed process (\d+) \(([^)]+)\).*?anon-rss:(\d+kB)").unwrap()
+});
+static OOM_INVOKED: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^(\S+) invoked oom-killer").unwrap());
+static OOM_CONSTRAINT: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"oom-kill:constraint=(\w+)").unwrap());
+static OOM_MEMCG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"task_memcg=([^,\s]+)").unwrap());
+static HUNG_TASK: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^INFO: task \S+ blocked for more than \d+ seconds").unwrap());
+/// `error -5`, `err -110`, `returned -12`, `ret=-22`, `status -71`.
+static ERROR_CODE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\b(error|err|returned|ret|status|rc|result)([ =:]+)-(\d+)\b").unwrap());
+
+/// Negative errno values as the kernel logs them.
+const ERROR_CODES: &[(u32, &str, &str)] = &[
+    (1, "EPERM", "operation not permitted"),
+    (2, "ENOENT", "no such file or directory"),
+    (5, "EIO", "input/output error"),
+    (6, "ENXIO", "no such device or address"),
+    (11, "EAGAIN", "resource temporarily unavailable"),
+    (12, "ENOMEM", "out of memory"),
+    (13, "EACCES", "permission denied"),
+    (16, "EBUSY", "device or resource busy"),
+    (19, "ENODEV", "no such device"),
+    (22, "EINVAL", "invalid argument"),
+    (28, "ENOSPC", "no space left on device"),
+    (30, "EROFS", "read-only file system"),
+    (32, "EPIPE", "broken pipe"),
+    (71, "EPROTO", "protocol error"),
+    (95, "EOPNOTSUPP", "operation not supported"),
+    (104, "ECONNRESET", "connection reset by peer"),
+    (110, "ETIMEDOUT", "timed out"),
+    (117, "EUCLEAN", "structure needs cleaning (file system corruption)"),
+    (121, "EREMOTEIO", "remote I/O error"),
+];
+
+const PROMPT: &str = "You are a {{ROLE}} and an experienced Linux kernel developer. The log below is kernel \
+    output (dmesg) with error codes translated, followed by a digest grouping its oopses and BUGs, segfaults, \
+    OOM kills and hung tasks. For an oops, explain the faulting function from RIP and the call trace, the module \
+    it is in and whether a driver, the hardware or a tainting module is the likely culprit. For segfaults, \
+    explain what the error code says about the access. For OOM kills, say what used the memory and whether a \
+    cgroup limit or the whole machine ran out. Then give the next debugging steps and the fix: a kernel, driver \
+    or firmware update, a module option, a sysctl or a limit. Be brief. Use Markdown.";
+
+/// Groups the kernel log's failures into blocks and translates its error
+/// codes; the analysis reads the translated log.
+pub fn digest(log: &str) -> Option<Digest> {
+    let lines: Vec<String> = log.lines().map(|line| PREFIX.replace(line.trim_end(), "").into_owned()).collect();
+    let mut body = String::new();
+    oopses(&lines, &mut body);
+    segfaults(&lines, &mut body);
+    oom_kills(&lines, &mut body);
+    section(&mut body, "Hung tasks", lines.iter().filter(|line| HUNG_TASK.is_match(line)).cloned().collect());
+    let (translated, codes) = translate(log);
+    section(&mut body, "Error codes", codes);
+    if body.is_empty() {
+        return None;
+    }
+    Some(Digest { tool: "kernel", title: "Kernel Log Digest", body, prompt: PROMPT, scope: Some(translated) })
+}
+
+fn section(body: &mut String, heading: &str, items: Vec<String>) {
+    if items.is_empty() {
+        return;
+    }
+    body.push_str(&format!("{} ({}):\n", heading, items.len()));
+    for item in items.iter().take(MAX_BLOCKS) {
+        body.push_str(&format!("  {}\n", item));
+    }
+}
+
+/// Each oops, BUG or warning with its header, the process it hit, RIP and
+/// the call trace without the unreliable `?` frames, up to its end marker.
+fn oopses(lines: &[String], body: &mut String) {
+    let mut blocks = Vec::new();
+    let mut index = 0;
+    while index < lines.len() {
+        if !OOPS.is_match(&lines[index]) {
+            index += 1;
+            continue;
+        }
+        let end = lines[index + 1..]
+            .iter()
+            .take(MAX_OOPS_LINES)
+            // A BUG is often followed by its `Oops:` line, which belongs to it
+            .position(|line| line.starts_with("---[ end") || (OOPS.is_match(line) && !line.starts_with("Oops: ")))
+            .map_or((index + 1 + MAX_OOPS_LINES).min(lines.len()), |offset| index + 1 + offset);
+        let block = &lines[index..end];
+        let mut text = block[0].clone();
+        for line in &block[1..] {
+            if line.starts_with("Oops: ") || line.starts_with("CPU: ") || line.starts_with("RIP: ") || line.starts_with("pc : ") {
+                text.push_str(&format!("\n    {}", line));
+            }
+        }
+        let frames: Vec<&str> = block
+            .iter()
+            .filter_map(|line| CALL_FRAME.captures(line))
+            .filter(|frame| frame.get(1).is_none())
+            .map(|frame| frame.get(2).map_or("", |m| m.as_str()))
+            .take(MAX_CALL_FRAMES)
+            .collect();
+        if !frames.is_empty() {
+            text.push_str(&format!("\n    Call trace: {}", frames.join(" < ")));
+        }
+        blocks.push(text);
+        index = end.max(index + 1);
+    }
+    section(body, "Oopses and BUGs", blocks);
+}
+
+/// `app[1234]: segfault at 8 ip ... error 4 in libfoo.so`, with the error
+/// code's page-fault bits spelled out.
+fn segfaults(lines: &[String], body: &mut String) {
+    let segfaults = lines
+        .iter()
+        .filter_map(|line| SEGFAULT.captures(line))
+        .map(|fault| {
+            let code = u32::from_str_radix(&fault[4], 16).unwrap_or(0);
+            let mut text = format!("{} (pid {}) at address {}", &fault[1], &fault[2], &fault[3]);
+            if let Some(object) = fault.get(5) {
+                text.push_str(&format!(" in {}", object.as_str()));
+            }
+            text.push_str(&format!(": {} (error {})", page_fault(code), &fault[4]));
+            text
+        })
+        .collect();
+    section(body, "Segfaults", segfaults);
+}
+
+/// The x86 page-fault error code: `4` is a read of an unmapped address in
+/// user mode.
+fn page_fault(code: u32) -> String {
+    let access = if code & 0x10 != 0 {
+        "instruction fetch"
+    } else if code & 0x2 != 0 {
+        "write"
+    } else {
+        "read"
+    };
+    let page = if code & 0x1 != 0 { "a page it may not access" } else { "an unmapped address" };
+    let mode = if code & 0x4 != 0 { "user" } else { "kernel" };
+    let mut text = format!("{} of {} in {} mode", access, page, mode);
+    if code & 0x8 != 0 {
+        text.push_str(", reserved bit set in a page table");
+    }
+    text
+}
+
+/// Each OOM kill: the process killed and its memory, who invoked the
+/// killer and whether a cgroup's limit was hit.
+fn oom_kills(lines: &[String], body: &mut String) {
+    let mut kills = Vec::new();
+    let mut invoker: Option<String> = None;
+    let mut constraint: Option<String> = None;
+    for line in lines {
+        if let Some(invoked) = OOM_INVOKED.captures(line) {
+            invoker = Some(invoked[1].to_string());
+            constraint = None;
+        } else if let Some(oom) = OOM_CONSTRAINT.captures(line) {
+            constraint = Some(match OOM_MEMCG.captures(line) {
+                Some(memcg) => format!("{} in {}", &oom[1], &memcg[1]),
+                None => oom[1].to_string(),
+            });
+        } else if let Some(killed) = OOM_KILLED.captures(line) {
+            let mut text = format!("{} (pid {}) killed, anon-rss {}", &killed[2], &killed[1], &killed[3]);
+            if let Some(invoker) = invoker.take() {
+                text.push_str(&format!(", invoked by {}", invoker));
+            }
+            if let Some(constraint) = constraint.take() {
+                text.push_str(&format!(", {}", constraint));
+            }
+            kills.push(text);
+        }
+    }
+    section(body, "OOM kills", kills);
+}
+
+/// The log with each known error code followed by its name and meaning,
+/// and how often each occurred.
+fn translate(log: &str) -> (String, Vec<String>) {
+    let mut counts: Vec<(u32, usize)> = Vec::new();
+    let translated = ERROR_CODE.replace_all(log, |code: &Captures| {
+        let value: u32 = code[3].parse().unwrap_or(0);
+        match ERROR_CODES.iter().find(|(known, _, _)| *known == value) {
+            Some((_, name, meaning)) => {
+                match counts.iter_mut().find(|(counted, _)| *counted == value) {
+                    Some((_, count)) => *count += 1,
+                    None => counts.push((value, 1)),
+                }
+                format!("{} ({}: {})", &code[0], name, meaning)
+            }
+            None => code[0].to_string(),
+        }
+    });
+    let codes = counts
+        .iter()
+        .filter_map(|(value, count)| {
+            let (_, name, meaning) = ERROR_CODES.iter().find(|(known, _, _)| known == value)?;
+            Some(format!("-{} {}: {} (x{})", value, name, meaning, count))
+        })
+        .collect();
+    (translated.into_owned(), codes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_oops() {
+        let log = "[ 1021.443210] BUG: kernel NULL pointer dereference, address: 0000000000000008
+[ 1021.443215] #PF: supervisor read access in kernel mode
+[ 1021.443220] Oops: 0000 [#1] PREEMPT SMP NOPTI
+[ 1021.443225] CPU: 3 PID: 812 Comm: irq/42-mydrv Tainted: G           O      6.1.0 #1
+[ 1021.443230] RIP: 0010:mydrv_irq+0x1c/0x80 [mydrv]
+[ 1021.443240] Call Trace:
+[ 1021.443241]  <TASK>
+[ 1021.443242]  ? __die+0x20/0x70
+[ 1021.443243]  irq_thread_fn+0x20/0x60
+[ 1021.443244]  irq_thread+0xe7/0x1a0
+[ 1021.443250] ---[ end trace 0000000000000000 ]---
+";
+        let digest = digest(log).unwrap();
+        assert_eq!(
+            digest.body,
+            "Oopses and BUGs (1):\n  BUG: kernel NULL pointer dereference, address: 0000000000000008\n\
+             \x20   Oops: 0000 [#1] PREEMPT SMP NOPTI\n\
+             \x20   CPU: 3 PID: 812 Comm: irq/42-mydrv Tainted: G           O      6.1.0 #1\n\
+             \x20   RIP: 0010:mydrv_irq+0x1c/0x80 [mydrv]\n\
+             \x20   Call trace: irq_thread_fn+0x20/0x60 < irq_thread+0xe7/0x1a0\n"
+        );
+    }
+
+    #[test]
+    fn test_segfault_and_oom() {
+        let log = "[ 5.1] app[1234]: segfault at 8 ip 00007f3a2b1c4d5e sp 00007ffd1e2f3a40 error 4 in libfoo.so[7f3a2b1c0000+5000]
+[ 9.0] nginx invoked oom-killer: gfp_mask=0xcc0(GFP_KERNEL), order=0, oom_score_adj=0
+[ 9.1] oom-kill:constraint=CONSTRAINT_MEMCG,nodemask=(null),cpuset=/,mems_allowed=0,oom_memcg=/kubepods/pod1,task_memcg=/kubepods/pod1/c1,task=java,pid=4321,uid=0
+[ 9.2] Memory cgroup out of memory: Killed process 4321 (java) total-vm:4000000kB, anon-rss:2048000kB, file-rss:0kB
+[ 9.3] blk_update_request: I/O error, dev sda, sector 2048 op 0x0:(READ) flags 0x0 phys_seg 1 prio class 0
+[ 9.4] ata1.00: failed command: READ FPDMA QUEUED, error -5
+";
+        let digest = digest(log).unwrap();
+        assert!(digest.body.starts_with(
+            "Segfaults (1):\n  app (pid 1234) at address 8 in libfoo.so: read of an unmapped address in user mode (error 4)\n"
+        ), "{}", digest.body);
+        assert!(digest.body.contains(
+            "OOM kills (1):\n  java (pid 4321) killed, anon-rss 2048000kB, invoked by nginx, CONSTRAINT_MEMCG in /kubepods/pod1/c1\n"
+        ));
+        assert!(digest.body.ends_with("Error codes (1):\n  -5 EIO: input/output error (x1)\n"));
+        assert!(digest.scope.unwrap().contains("error -5 (EIO: input/output error)\n"));
+        assert_eq!(page_fault(0x7), "write of a page it may not access in user mode");
+        assert_eq!(super::digest("[ 1.0] usb 1-1: new high-speed USB device\n"), None);
+    }
+}
@@ -0,0 +1,335 @@
+//! Kubernetes: `kubectl describe pod` and `kubectl get events` output, with
+//! the resource, its namespace, the state of its containers and the warning
+//! events, and what their reasons usually mean.
+
+use regex::Regex;
+use std::sync::LazyLock;
+
+use super::{push_limited, Digest};
+
+const MAX_EVENTS: usize = 10;
+
+static FIELD: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^(\w[\w ]*):\s+(\S.*)$").unwrap());
+/// A container of `describe pod`: `  web:` under `Containers:`.
+static CONTAINER: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^  ([a-z0-9][a-z0-9.-]*):$").unwrap());
+static CONTAINER_FIELD: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\s{4,}([A-Za-z][\w ]*):\s+(\S.*)$").unwrap());
+static COLUMNS: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\s{2,}").unwrap());
+
+/// Reasons of container states and events, and what they usually mean.
+const REASONS: &[(&str, &str)] = &[
+    ("ImagePullBackOff", "the image cannot be pulled: a wrong name or tag, a private registry without imagePullSecrets, or an unreachable registry"),
+    ("ErrImagePull", "the image cannot be pulled: a wrong name or tag, a private registry without imagePullSecrets, or an unreachable registry"),
+    ("InvalidImageName", "the image reference is not valid"),
+    ("CrashLoopBackOff", "the container keeps exiting after it starts; its previous logs (`kubectl logs --previous`) say why"),
+    ("OOMKilled", "the container used more memory than its limit and was killed"),
+    ("Error", "the container exited with a non-zero code"),
+    ("CreateContainerConfigError", "a ConfigMap, Secret or key the container refers to does not exist"),
+    ("FailedScheduling", "no node fits the pod: its resource requests, node selector, affinity, taints or volumes"),
+    ("FailedMount", "a volume cannot be mounted: a missing Secret, ConfigMap or PersistentVolumeClaim, or a storage error"),
+    ("FailedAttachVolume", "a volume cannot be attached to the node, often because another node still holds it"),
+    ("Unhealthy", "a liveness, readiness or startup probe fails"),
+    ("Evicted", "the node ran short of memory, disk or PIDs and evicted the pod"),
+    ("BackOff", "the kubelet is backing off restarting the container or pulling its image"),
+];
+
+const PROMPT: &str = "You are a {{ROLE}} and an expert Kubernetes operator. The log below is output of `kubectl \
+    describe` or `kubectl get events`, followed by a digest of it: the resource and its namespace, the state of its \
+    containers, the warning events and what their reasons usually mean. Explain why the resource is failing, \
+    starting from the earliest cause: an image that cannot be pulled, a container killed for memory, a pod no node \
+    can schedule, a missing Secret or volume. Then give numbered remediation steps as `kubectl` commands or \
+    manifest changes that name the resource and its namespace (`kubectl -n <namespace> ...`). Be brief. \
+    Use Markdown.";
+
+#[derive(Debug, Default, PartialEq)]
+struct Container {
+    name: String,
+    fields: Vec<(String, String)>,
+}
+
+impl Container {
+    fn field(&self, name: &str) -> Option<&str> {
+        self.fields.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+    }
+
+    /// `web (shop/web:1.4): Waiting (CrashLoopBackOff), last Terminated (OOMKilled), exit code 137, 5 restarts`.
+    fn summary(&self) -> String {
+        let mut summary = self.name.clone();
+        if let Some(image) = self.field("Image") {
+            summary.push_str(&format!(" ({})", image));
+        }
+        let mut states = Vec::new();
+        for (state, reason) in [("State", "Reason"), ("Last State", "Last Reason")] {
+            if let Some(value) = self.field(state) {
+                let prefix = if state == "Last State" { "last " } else { "" };
+                match self.field(reason) {
+                    Some(reason) => states.push(format!("{}{} ({})", prefix, value, reason)),
+                    None => states.push(format!("{}{}", prefix, value)),
+                }
+            }
+        }
+        if let Some(code) = self.field("Exit Code") {
+            states.push(format!("exit code {}", code));
+        }
+        if let Some(restarts) = self.field("Restart Count").filter(|count| *count != "0") {
+            states.push(format!("{} restarts", restarts));
+        }
+        if let Some(memory) = self.field("memory") {
+            states.push(format!("memory {}", memory));
+        }
+        if !states.is_empty() {
+            summary.push_str(&format!(": {}", states.join(", ")));
+        }
+        summary
+    }
+
+    fn reasons(&self) -> impl Iterator<Item = &str> {
+        ["Reason", "Last Reason"].into_iter().filter_map(|name| self.field(name))
+    }
+}
+
+/// A warning event, from `describe`'s `Events:` or `get events`.
+#[derive(Debug, PartialEq)]
+struct Event {
+    /// `pod/web-1` and its namespace, from `get events` only.
+    object: Option<String>,
+    namespace: Option<String>,
+    reason: String,
+    message: String,
+    /// `x4 over 3m`.
+    count: Option<String>,
+}
+
+/// Recognizes `kubectl describe` or `kubectl get events` output and digests it.
+pub fn digest(log: &str) -> Option<Digest> {
+    let lines: Vec<&str> = log.lines().map(str::trim_end).collect();
+    let field = |name: &str| {
+        lines.iter().filter_map(|line| FIELD.captures(line)).find(|field| &field[1] == name).map(|field| field[2].to_string())
+    };
+    let containers = containers(&lines);
+    let mut events = describe_events(&lines);
+    events.extend(table_events(&lines));
+
+    let mut reasons: Vec<&str> = containers.iter().flat_map(Container::reasons).collect();
+    reasons.extend(events.iter().map(|event| event.reason.as_str()));
+    // Failed pulls are reported as `Failed` events
+    if events.iter().any(|event| event.reason == "Failed" && event.message.contains("pull")) {
+        reasons.push("ErrImagePull");
+    }
+    let known: Vec<(&str, &str)> = REASONS.iter().copied().filter(|(reason, _)| reasons.contains(reason)).collect();
+    if known.is_empty() && events.is_empty() {
+        return None;
+    }
+
+    let mut body = String::new();
+    let name = field("Name");
+    if let Some(name) = &name {
+        let kind = if containers.is_empty() { "" } else { "pod/" };
+        body.push_str(&format!("Resource: {}{}", kind, name));
+        if let Some(namespace) = field("Namespace") {
+            body.push_str(&format!(" in namespace {}", namespace));
+        }
+        body.push('\n');
+        if let Some(node) = field("Node").filter(|node| node != "<none>") {
+            body.push_str(&format!("Node: {}\n", node));
+        }
+        if let Some(owner) = field("Controlled By") {
+            body.push_str(&format!("Controlled by: {}\n", owner));
+        }
+    }
+    if !containers.is_empty() {
+        body.push_str("Containers:\n");
+        for container in &containers {
+            body.push_str(&format!("  {}\n", container.summary()));
+        }
+    }
+    if !events.is_empty() {
+        body.push_str("Warning events:\n");
+        let mut shown: Vec<String> = Vec::new();
+        for event in &events {
+            let mut line = String::from("  ");
+            if let Some(object) = &event.object {
+                line.push_str(object);
+                if let Some(namespace) = &event.namespace {
+                    line.push_str(&format!(" in {}", namespace));
+                }
+                line.push_str(": ");
+            }
+            line.push_str(&format!("{}: {}", event.reason, event.message));
+            if let Some(count) = &event.count {
+                line.push_str(&format!(" ({})", count));
+            }
+            if !shown.contains(&line) {
+                shown.push(line);
+            }
+        }
+        push_limited(&mut body, &shown, MAX_EVENTS);
+    }
+    if !known.is_empty() {
+        body.push_str("What the reasons usually mean:\n");
+        for (reason, meaning) in known {
+            body.push_str(&format!("  {}: {}\n", reason, meaning));
+        }
+    }
+
+    Some(Digest { tool: "Kubernetes", title: "Kubernetes Resource Status", body, prompt: PROMPT, scope: None })
+}
+
+/// The containers under `describe pod`'s `Containers:` and `Init Containers:`,
+/// with their fields; a terminated container's `Reason` under `Last State`
+/// becomes its `Last Reason`.
+fn containers(lines: &[&str]) -> Vec<Container> {
+    let mut containers: Vec<Container> = Vec::new();
+    let mut in_section = false;
+    let mut in_last_state = false;
+    for line in lines {
+        if *line == "Containers:" || *line == "Init Containers:" {
+            in_section = true;
+            continue;
+        }
+        if !line.is_empty() && !line.starts_with(char::is_whitespace) {
+            in_section = false;
+        }
+        if !in_section {
+            continue;
+        }
+        if let Some(container) = CONTAINER.captures(line) {
+            containers.push(Container { name: container[1].to_string(), ..Container::default() });
+            in_last_state = false;
+        } else if let (Some(container), Some(field)) = (containers.last_mut(), CONTAINER_FIELD.captures(line)) {
+            let mut name = field[1].trim().to_string();
+            match name.as_str() {
+                "State" => in_last_state = false,
+                "Last State" => in_last_state = true,
+                "Reason" if in_last_state => name = "Last Reason".to_string(),
+                _ => {}
+            }
+            if container.field(&name).is_none() {
+                container.fields.push((name, field[2].trim().to_string()));
+            }
+        }
+    }
+    containers
+}
+
+/// The warning events of `describe`'s `Events:` table: type, reason, age,
+/// source and message, separated by runs of spaces.
+fn describe_events(lines: &[&str]) -> Vec<Event> {
+    let Some(start) = lines.iter().position(|line| line.starts_with("Events:")) else {
+        return Vec::new();
+    };
+    lines[start + 1..]
+        .iter()
+        .take_while(|line| line.starts_with(char::is_whitespace))
+        .filter_map(|line| {
+            let columns: Vec<&str> = COLUMNS.split(line.trim()).collect();
+            let [kind, reason, age, _source, message @ ..] = columns.as_slice() else { return None };
+            (*kind == "Warning").then(|| Event {
+                object: None,
+                namespace: None,
+                reason: reason.to_string(),
+                message: message.join("  "),
+                count: age.split_once('(').and_then(|(_, count)| count.strip_suffix(')')).map(str::to_string),
+            })
+        })
+        .collect()
+}
+
+/// The warning events of `kubectl get events`, whose columns are found by
+/// the header's names; `NAMESPACE` appears with `--all-namespaces`.
+fn table_events(lines: &[&str]) -> Vec<Event> {
+    let Some(start) = lines.iter().position(|line| line.contains("REASON") && line.contains("OBJECT") && line.contains("MESSAGE")) else {
+        return Vec::new();
+    };
+    let header = lines[start];
+    let column = |name: &str| header.find(name);
+    let cell = |line: &str, name: &str| -> Option<String> {
+        let begin = column(name)?;
+        let end = ["NAMESPACE", "LAST SEEN", "TYPE", "REASON", "OBJECT", "MESSAGE"]
+            .iter()
+            .filter_map(|other| column(other))
+            .filter(|&other| other > begin)
+            .min()
+            .unwrap_or(line.len());
+        line.get(begin..end.min(line.len())).or_else(|| line.get(begin..)).map(|cell| cell.trim().to_string())
+    };
+    lines[start + 1..]
+        .iter()
+        .filter(|line| cell(line, "TYPE").as_deref() == Some("Warning"))
+        .map(|line| Event {
+            object: cell(line, "OBJECT"),
+            namespace: cell(line, "NAMESPACE"),
+            reason: cell(line, "REASON").unwrap_or_default(),
+            message: column("MESSAGE").and_then(|begin| line.get(begin..)).unwrap_or("").trim().to_string(),
+            count: None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DESCRIBE: &str = "Name:             web-7d9f8c6b5-x2x4k
+Namespace:        shop
+Node:             worker-2/10.0.0.12
+Controlled By:    ReplicaSet/web-7d9f8c6b5
+Containers:
+  web:
+    Image:          registry.example.com/web:1.4
+    State:          Waiting
+      Reason:       CrashLoopBackOff
+    Last State:     Terminated
+      Reason:       OOMKilled
+      Exit Code:    137
+    Restart Count:  5
+    Limits:
+      memory:  256Mi
+Conditions:
+  Type              Status
+  Ready             False
+Events:
+  Type     Reason   Age                  From     Message
+  ----     ------   ----                 ----     -------
+  Normal   Pulled   5m                   kubelet  Container image already present on machine
+  Warning  BackOff  2m (x12 over 5m)     kubelet  Back-off restarting failed container web in pod web-7d9f8c6b5-x2x4k_shop
+";
+
+    #[test]
+    fn test_describe() {
+        let lines: Vec<&str> = DESCRIBE.lines().collect();
+        let containers = containers(&lines);
+        assert_eq!(containers.len(), 1);
+        assert_eq!(
+            containers[0].summary(),
+            "web (registry.example.com/web:1.4): Waiting (CrashLoopBackOff), last Terminated (OOMKilled), exit code 137, \
+             5 restarts, memory 256Mi"
+        );
+        let events = describe_events(&lines);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].count.as_deref(), Some("x12 over 5m"));
+
+        let body = digest(DESCRIBE).unwrap().body;
+        assert!(body.starts_with("Resource: pod/web-7d9f8c6b5-x2x4k in namespace shop\nNode: worker-2/10.0.0.12\n"), "{}", body);
+        assert!(body.contains("  BackOff: Back-off restarting failed container web in pod web-7d9f8c6b5-x2x4k_shop (x12 over 5m)\n"));
+        assert!(body.contains("  OOMKilled: the container used more memory than its limit and was killed\n"));
+    }
+
+    #[test]
+    fn test_get_events() {
+        let log = "NAMESPACE   LAST SEEN   TYPE      REASON             OBJECT                    MESSAGE
+shop        3m          Warning   FailedScheduling   pod/api-5f6d7c8b9-abcde   0/3 nodes are available: 3 Insufficient memory.
+shop        1m          Normal    Scheduled          pod/web-1                 Successfully assigned shop/web-1 to worker-1
+";
+        let events = table_events(&log.lines().collect::<Vec<_>>());
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].object.as_deref(), Some("pod/api-5f6d7c8b9-abcde"));
+        assert_eq!(events[0].namespace.as_deref(), Some("shop"));
+        assert_eq!(events[0].message, "0/3 nodes are available: 3 Insufficient memory.");
+
+        let body = digest(log).unwrap().body;
+        assert!(body.starts_with(
+            "Warning events:\n  pod/api-5f6d7c8b9-abcde in shop: FailedScheduling: 0/3 nodes are available: 3 Insufficient memory.\n"
+        ), "{}", body);
+        assert_eq!(digest("Name: web\nStatus: Running\n"), None);
+    }
+}
@@ -0,0 +1,75 @@
+//! Pre-analyzers for the output of well-known tools: each recognizes its
+//! tool's messages, condenses them into a digest of the errors (codes,
+//! locations, snippets) and brings a prompt that knows the tool. The digest
+//! goes after the log, where truncation cannot cut it off.
+
+pub mod ci;
+pub mod cpp;
+pub mod database;
+pub mod docker;
+pub mod go;
+pub mod jvm;
+pub mod kernel;
+pub mod kubernetes;
+pub mod node;
+pub mod python;
+pub mod rust;
+pub mod terraform;
+pub mod test_report;
+pub mod webserver;
+
+/// What a pre-analyzer extracted from a log.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Digest {
+    /// The tool recognized, e.g. `Rust`.
+    pub tool: &'static str,
+    /// Title of the digest's section in the prompt.
+    pub title: &'static str,
+    pub body: String,
+    /// A system prompt specialized for the tool.
+    pub prompt: &'static str,
+    /// The part of the log that matters, such as the output of the build
+    /// step that failed, analyzed instead of the whole log.
+    pub scope: Option<String>,
+}
+
+impl Digest {
+    /// The digest as a block to append after the log.
+    pub fn render(&self) -> String {
+        format!("\n\n=== {} ===\n{}", self.title, self.body.trim_end())
+    }
+}
+
+/// Tried in order; the first that recognizes the log wins. Docker comes
+/// first since its steps print the output of the other tools.
+const ANALYZERS: &[fn(&str) -> Option<Digest>] = &[docker::digest, rust::digest, python::digest, jvm::digest, kubernetes::digest, node::digest, go::digest, cpp::digest, database::digest, webserver::digest, terraform::digest];
+
+/// The digest of the first pre-analyzer that recognizes `log`.
+pub fn detect(log: &str) -> Option<Digest> {
+    ANALYZERS.iter().find_map(|digest| digest(log))
+}
+
+/// Keeps the first `max` items, noting how many more there were.
+pub(crate) fn push_limited(out: &mut String, items: &[String], max: usize) {
+    for item in items.iter().take(max) {
+        out.push_str(item);
+        out.push('\n');
+    }
+    if items.len() > max {
+        out.push_str(&format!("... and {} more\n", items.len() - max));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        let log = "error[E0382]: borrow of moved value: `v`\n --> src/main.rs:4:20\n";
+        let digest = detect(log).unwrap();
+        assert_eq!(digest.tool, "Rust");
+        assert!(digest.render().starts_with("\n\n=== Rust Compiler Errors ===\n"));
+        assert_eq!(detect("Compiling app v0.1.0\nFinished dev profile\n"), None);
+    }
+}
@@ -0,0 +1,216 @@
+//! npm, yarn and pnpm: dependency conflicts, native builds with node-gyp and
+//! failed package scripts.
+
+use regex::Regex;
+use std::sync::LazyLock;
+
+use super::{push_limited, Digest};
+
+const MAX_CONFLICTS: usize = 5;
+const MAX_COMPILER_ERRORS: usize = 5;
+/// Lines of a failed script's own output kept from before npm's summary.
+const MAX_SCRIPT_LINES: usize = 10;
+
+/// npm 9 and earlier write `npm ERR!`, npm 10 `npm error`.
+static NPM: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^npm (?:ERR!|error)\s?(.*)$").unwrap());
+static CODE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^code (E[A-Z0-9_]+)$").unwrap());
+/// `peer react@"^17.0.0" from react-widget@1.0.0` and
+/// `Conflicting peer dependency: react@17.0.2`.
+static PEER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"^(?:peer(?:Optional)? |Conflicting peer dependency: )(\S+)(?: from (\S+))?"#).unwrap());
+static GYP: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^gyp ERR! (\w+) (.+)$").unwrap());
+static COMPILER_ERROR: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\S+:\d+(?::\d+)?: (?:fatal )?error: .+$").unwrap());
+/// npm's `app@1.0.0 build: `tsc``, pnpm's ` ERR_PNPM_RECURSIVE_RUN_FIRST_FAIL  app@1.0.0 build: `tsc``.
+static SCRIPT: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(\S+@\S+) (\S+): `(.+)`").unwrap());
+static EXIT: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)(?:exit status|exit code|failed with exit code) (\d+)").unwrap());
+static YARN: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^error (.+)$").unwrap());
+static PNPM: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(ERR_PNPM_[A-Z_]+)\s+(.+)$").unwrap());
+
+const PROMPT: &str = "You are a {{ROLE}} and an expert Node.js developer who knows npm, yarn and pnpm well. \
+    The log below is output of a package manager, followed by a digest of its failure. \
+    For a dependency conflict, name the packages and versions that conflict and give the exact install \
+    command or package.json change that resolves it; prefer upgrading the package that is out of date \
+    over --force or --legacy-peer-deps. For a native build, name the missing compiler, header, library or \
+    Python and how to install it. For a failed script, explain the script's own error, which comes before \
+    the package manager's summary. Be brief. Use Markdown.";
+
+/// Recognizes npm, yarn or pnpm failures and digests them.
+pub fn digest(log: &str) -> Option<Digest> {
+    let lines: Vec<&str> = log.lines().map(str::trim_end).collect();
+    let npm: Vec<&str> = lines.iter().filter_map(|line| NPM.captures(line)).map(|c| c.get(1).map_or("", |m| m.as_str())).collect();
+
+    let mut body = String::new();
+    let code = npm.iter().find_map(|line| CODE.captures(line)).map(|code| code[1].to_string());
+    if let Some(code) = &code {
+        body.push_str(&format!("npm error code {}\n", code));
+    }
+    if code.as_deref() == Some("ERESOLVE") || npm.iter().any(|line| line.contains("ERESOLVE")) {
+        resolve_digest(&npm, &mut body);
+    }
+    gyp_digest(&lines, &mut body);
+    script_digest(&lines, &npm, &mut body);
+    if code.is_some() && body.lines().count() == 1 {
+        // E404, ETARGET, EACCES, ...: the message after the code and errno
+        let message = npm.iter().find(|line| !line.is_empty() && !line.starts_with("code ") && !line.starts_with("errno "));
+        if let Some(message) = message {
+            body.push_str(&format!("{}\n", message));
+        }
+    }
+    // yarn's `error ...` is too plain to trust unless yarn ran
+    let yarn = log.contains("yarn");
+    for line in &lines {
+        if let Some(pnpm) = PNPM.captures(line) {
+            body.push_str(&format!("pnpm {}: {}\n", &pnpm[1], pnpm[2].trim()));
+        } else if let Some(yarn) = YARN.captures(line).filter(|_| yarn) {
+            body.push_str(&format!("yarn: {}\n", &yarn[1]));
+        }
+    }
+    if body.is_empty() {
+        return None;
+    }
+    Some(Digest { tool: "Node", title: "Package Manager Failure", body, prompt: PROMPT, scope: None })
+}
+
+/// What npm was resolving, what it found and the peer requirements that
+/// conflict with it, instead of the whole tree.
+fn resolve_digest(npm: &[&str], body: &mut String) {
+    body.push_str("The dependency tree could not be resolved.\n");
+    for prefix in ["While resolving: ", "Found: "] {
+        if let Some(line) = npm.iter().find(|line| line.starts_with(prefix)) {
+            body.push_str(&format!("{}\n", line));
+        }
+    }
+    let mut conflicts: Vec<String> = Vec::new();
+    for line in npm {
+        if let Some(peer) = PEER.captures(line.trim()) {
+            let conflict = match peer.get(2) {
+                Some(from) => format!("{} wants peer {}", from.as_str(), &peer[1]),
+                None => format!("conflicting peer {}", &peer[1]),
+            };
+            if !conflicts.contains(&conflict) {
+                conflicts.push(conflict);
+            }
+        }
+    }
+    push_limited(body, &conflicts, MAX_CONFLICTS);
+}
+
+/// The package node-gyp was building, its error and the compiler's.
+fn gyp_digest(lines: &[&str], body: &mut String) {
+    let gyp: Vec<(&str, &str)> = lines
+        .iter()
+        .filter_map(|line| GYP.captures(line))
+        .map(|c| (c.get(1).map_or("", |m| m.as_str()), c.get(2).map_or("", |m| m.as_str())))
+        .collect();
+    if gyp.is_empty() {
+        return;
+    }
+    let field = |name: &str| gyp.iter().find(|(key, _)| *key == name).map(|(_, value)| *value);
+    let package = field("cwd").map(|cwd| cwd.rsplit("node_modules/").next().unwrap_or(cwd));
+    body.push_str(&format!("Native build failed (node-gyp) in {}", package.unwrap_or("a package")));
+    if let Some(version) = field("node") {
+        body.push_str(&format!(", node {}", version.trim_start_matches("-v ")));
+    }
+    body.push('\n');
+    // The first `stack` line is the error, the rest its JavaScript stack
+    if let Some(stack) = field("stack") {
+        body.push_str(&format!("{}\n", stack));
+    }
+    let python: Vec<String> = gyp.iter().filter(|(key, _)| *key == "find").map(|(_, value)| value.to_string()).collect();
+    push_limited(body, &python, MAX_COMPILER_ERRORS);
+    let errors: Vec<String> = lines.iter().filter(|line| COMPILER_ERROR.is_match(line)).map(|line| line.to_string()).collect();
+    push_limited(body, &errors, MAX_COMPILER_ERRORS);
+}
+
+/// The script that failed, its exit code and the end of its own output.
+fn script_digest(lines: &[&str], npm: &[&str], body: &mut String) {
+    let lifecycle = lines.iter().any(|line| line.contains("ELIFECYCLE") || line.contains("Lifecycle script"))
+        || lines.iter().any(|line| line.starts_with("error Command failed with exit code"));
+    if !lifecycle {
+        return;
+    }
+    match npm.iter().chain(lines).find_map(|line| SCRIPT.captures(line)) {
+        Some(script) => body.push_str(&format!("Script `{}` of {} failed: `{}`", &script[2], &script[1], &script[3])),
+        None => body.push_str("A package script failed"),
+    }
+    if let Some(exit) = lines.iter().find_map(|line| EXIT.captures(line)) {
+        body.push_str(&format!(" (exit code {})", &exit[1]));
+    }
+    body.push('\n');
+    // The script's own output ends where the package manager's summary begins
+    let end = lines.iter().position(|line| {
+        NPM.is_match(line) || line.contains("ELIFECYCLE") || line.starts_with("error Command failed")
+    });
+    let output: Vec<&str> =
+        lines[..end.unwrap_or(0)].iter().copied().filter(|line| !line.trim().is_empty() && !line.starts_with('>')).collect();
+    if !output.is_empty() {
+        body.push_str("Last lines of its output:\n");
+        for line in &output[output.len().saturating_sub(MAX_SCRIPT_LINES)..] {
+            body.push_str(&format!("{}\n", line));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eresolve() {
+        let log = "npm ERR! code ERESOLVE
+npm ERR! ERESOLVE unable to resolve dependency tree
+npm ERR!
+npm ERR! While resolving: shop@1.0.0
+npm ERR! Found: react@18.2.0
+npm ERR! node_modules/react
+npm ERR!   react@\"^18.2.0\" from the root project
+npm ERR!
+npm ERR! Could not resolve dependency:
+npm ERR! peer react@\"^17.0.0\" from react-widget@1.0.0
+npm ERR! node_modules/react-widget
+npm ERR!   react-widget@\"^1.0.0\" from the root project
+";
+        let body = digest(log).unwrap().body;
+        assert_eq!(
+            body,
+            "npm error code ERESOLVE\nThe dependency tree could not be resolved.\nWhile resolving: shop@1.0.0\n\
+             Found: react@18.2.0\nreact-widget@1.0.0 wants peer react@\"^17.0.0\"\n"
+        );
+    }
+
+    #[test]
+    fn test_gyp() {
+        let log = "../src/bcrypt_node.cc:1:10: fatal error: node.h: No such file or directory
+gyp ERR! build error
+gyp ERR! stack Error: `make` failed with exit code: 2
+gyp ERR! cwd /app/node_modules/bcrypt
+gyp ERR! node -v v20.1.0
+";
+        let body = digest(log).unwrap().body;
+        assert!(body.starts_with("Native build failed (node-gyp) in bcrypt, node v20.1.0\n"), "{}", body);
+        assert!(body.contains("Error: `make` failed with exit code: 2\n"));
+        assert!(body.ends_with("fatal error: node.h: No such file or directory\n"));
+    }
+
+    #[test]
+    fn test_script() {
+        let log = "> shop@1.0.0 build
+> tsc
+
+src/cart.ts(3,7): error TS2322: Type 'string' is not assignable to type 'number'.
+npm ERR! code ELIFECYCLE
+npm ERR! errno 2
+npm ERR! shop@1.0.0 build: `tsc`
+npm ERR! Exit status 2
+";
+        let body = digest(log).unwrap().body;
+        assert!(body.contains("Script `build` of shop@1.0.0 failed: `tsc` (exit code 2)\n"), "{}", body);
+        assert!(body.ends_with("Last lines of its output:\nsrc/cart.ts(3,7): error TS2322: Type 'string' is not assignable to type 'number'.\n"));
+
+        let yarn = "yarn install v1.22.19\nerror Couldn't find package \"left-pad@^9.0.0\" on the \"npm\" registry.\n";
+        assert_eq!(digest(yarn).unwrap().body, "yarn: Couldn't find package \"left-pad@^9.0.0\" on the \"npm\" registry.\n");
+        assert_eq!(digest("added 120 packages in 3s\n"), None);
+    }
+}
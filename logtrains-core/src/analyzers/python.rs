@@ -0,0 +1,252 @@
+//! Python: tracebacks with their chained exceptions, and pytest failures.
+
+use regex::Regex;
+use std::sync::LazyLock;
+
+use super::{push_limited, Digest};
+
+/// Frames shown per exception, counted from the innermost.
+const MAX_FRAMES: usize = 3;
+const MAX_CHAINS: usize = 3;
+const MAX_FAILURES: usize = 10;
+
+const TRACEBACK: &str = "Traceback (most recent call last):";
+static FRAME: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"^\s+File "(.+)", line (\d+), in (.+)$"#).unwrap());
+/// The line of a pytest failure's traceback that holds the error, and the
+/// `path.py:12: AssertionError` line that ends it.
+static PYTEST_ERROR: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^E\s+(.+)$").unwrap());
+static PYTEST_LOCATION: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^([\w./\\-]+\.py):(\d+): ([A-Za-z_][\w.]*)$").unwrap());
+
+const PROMPT: &str = "You are a {{ROLE}} and an expert Python programmer. The log below is output of a Python program \
+    or its tests, followed by a digest of its exceptions: their type and message, the innermost frames and \
+    any chained exceptions. Explain what raised the exception and why, starting from the innermost frame in \
+    the application's own code rather than in a library. When exceptions are chained, say which one is the \
+    root cause. For test failures, say whether the test or the code under test is wrong. \
+    Then show the smallest code change that fixes it, as a Python snippet. Be brief. Use Markdown.";
+
+#[derive(Debug, PartialEq)]
+struct Frame {
+    file: String,
+    line: String,
+    function: String,
+    code: Option<String>,
+}
+
+impl Frame {
+    /// Frames in installed packages or the standard library.
+    fn is_library(&self) -> bool {
+        ["site-packages", "dist-packages", "/lib/python", "<frozen "].iter().any(|dir| self.file.contains(dir))
+    }
+
+    fn render(&self, out: &mut String) {
+        out.push_str(&format!("  File \"{}\", line {}, in {}\n", self.file, self.line, self.function));
+        if let Some(code) = &self.code {
+            out.push_str(&format!("    {}\n", code));
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Exception {
+    frames: Vec<Frame>,
+    /// `ValueError: invalid literal for int()`.
+    message: String,
+    /// How it relates to the exception before it in a chain.
+    link: Option<&'static str>,
+}
+
+/// Recognizes tracebacks or pytest failures and digests them.
+pub fn digest(log: &str) -> Option<Digest> {
+    let chains = chains(log);
+    let failures = pytest_failures(log);
+    if chains.is_empty() && failures.is_empty() {
+        return None;
+    }
+
+    let mut body = String::new();
+    for chain in chains.iter().take(MAX_CHAINS) {
+        // Python prints the original exception first; the last one escaped
+        let (last, causes) = chain.split_last().expect("chains are not empty");
+        body.push_str(&format!("{}\n", last.message));
+        render_frames(&mut body, &last.frames);
+        let mut link = last.link;
+        for cause in causes.iter().rev() {
+            let relation = match link {
+                Some("cause") => "Its direct cause",
+                _ => "Raised while handling",
+            };
+            body.push_str(&format!("{}: {}\n", relation, cause.message));
+            if let Some(frame) = cause.frames.last() {
+                frame.render(&mut body);
+            }
+            link = cause.link;
+        }
+        body.push('\n');
+    }
+    if chains.len() > MAX_CHAINS {
+        body.push_str(&format!("... and {} more tracebacks\n\n", chains.len() - MAX_CHAINS));
+    }
+    if !failures.is_empty() {
+        body.push_str("Test failures:\n");
+        push_limited(&mut body, &failures, MAX_FAILURES);
+    }
+
+    Some(Digest { tool: "Python", title: "Python Exceptions", body, prompt: PROMPT, scope: None })
+}
+
+/// The innermost frames, and the innermost one of the application's own
+/// code when those are all in libraries.
+fn render_frames(out: &mut String, frames: &[Frame]) {
+    let shown = &frames[frames.len().saturating_sub(MAX_FRAMES)..];
+    let own = frames.iter().rposition(|frame| !frame.is_library());
+    if let Some(own) = own.filter(|&own| own + MAX_FRAMES < frames.len()) {
+        frames[own].render(out);
+        out.push_str(&format!("  ... {} frames in libraries\n", frames.len() - MAX_FRAMES - own - 1));
+    } else if frames.len() > MAX_FRAMES {
+        out.push_str(&format!("  ... {} outer frames\n", frames.len() - MAX_FRAMES));
+    }
+    for frame in shown {
+        frame.render(out);
+    }
+}
+
+/// The tracebacks in the log, grouped into chains of exceptions linked by
+/// "During handling of the above exception" or "The above exception was the
+/// direct cause".
+fn chains(log: &str) -> Vec<Vec<Exception>> {
+    let mut chains: Vec<Vec<Exception>> = Vec::new();
+    let mut link = None;
+    // The column the current traceback starts at, after any log prefix
+    let mut current: Option<(usize, Exception)> = None;
+    for line in log.lines().map(str::trim_end) {
+        if let Some((offset, exception)) = current.as_mut() {
+            let text = line.get(*offset..).unwrap_or("");
+            if let Some(frame) = FRAME.captures(text) {
+                exception.frames.push(Frame {
+                    file: frame[1].to_string(),
+                    line: frame[2].to_string(),
+                    function: frame[3].to_string(),
+                    code: None,
+                });
+                continue;
+            }
+            if text.is_empty() || text.starts_with(char::is_whitespace) {
+                // The frame's code, then `^^^^` markers or continued lines
+                if let Some(frame) = exception.frames.last_mut().filter(|frame| frame.code.is_none()) {
+                    frame.code = Some(text.trim().to_string()).filter(|code| !code.is_empty());
+                }
+                continue;
+            }
+            let (_, mut exception) = current.take().expect("checked above");
+            exception.message = text.to_string();
+            match (exception.link, chains.last_mut()) {
+                (Some(_), Some(chain)) => chain.push(exception),
+                _ => chains.push(vec![exception]),
+            }
+        }
+        if line.contains("During handling of the above exception, another exception occurred") {
+            link = Some("handling");
+        } else if line.contains("The above exception was the direct cause of the following exception") {
+            link = Some("cause");
+        } else if let Some(offset) = line.find(TRACEBACK) {
+            let exception = Exception { frames: Vec::new(), message: String::new(), link: link.take() };
+            current = Some((offset, exception));
+        } else if !line.trim().is_empty() {
+            link = None;
+        }
+    }
+    chains
+}
+
+/// pytest's failures as `AssertionError at tests/test_api.py:12: assert 1 == 2`.
+fn pytest_failures(log: &str) -> Vec<String> {
+    let mut failures = Vec::new();
+    let mut error: Option<&str> = None;
+    for line in log.lines().map(str::trim_end) {
+        if line.starts_with("____") {
+            error = None;
+        } else if let Some(e) = PYTEST_ERROR.captures(line) {
+            error.get_or_insert(e.get(1).map_or("", |e| e.as_str()));
+        } else if let Some(location) = PYTEST_LOCATION.captures(line) {
+            if let Some(error) = error.take() {
+                failures.push(format!("{} at {}:{}: {}", &location[3], &location[1], &location[2], error));
+            }
+        }
+    }
+    failures
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHAINED: &str = "2024-05-01 12:00:00 ERROR Internal Server Error: /orders/
+Traceback (most recent call last):
+  File \"/app/orders/views.py\", line 12, in detail
+    order = ORDERS[order_id]
+            ~~~~~~^^^^^^^^^^
+KeyError: 'A-17'
+
+During handling of the above exception, another exception occurred:
+
+Traceback (most recent call last):
+  File \"/venv/lib/python3.12/site-packages/django/core/handlers/exception.py\", line 55, in inner
+    response = get_response(request)
+  File \"/app/orders/views.py\", line 14, in detail
+    raise Http404(f\"no order {order_id}\")
+django.http.response.Http404: no order A-17
+";
+
+    #[test]
+    fn test_chains() {
+        let chains = chains(CHAINED);
+        assert_eq!(chains.len(), 1);
+        let chain = &chains[0];
+        assert_eq!(chain[0].message, "KeyError: 'A-17'");
+        assert_eq!(chain[0].frames[0].code.as_deref(), Some("order = ORDERS[order_id]"));
+        assert_eq!(chain[1].link, Some("handling"));
+        assert_eq!(chain[1].frames.len(), 2);
+        assert!(chain[1].frames[0].is_library());
+
+        let digest = digest(CHAINED).unwrap();
+        assert!(digest.body.starts_with("django.http.response.Http404: no order A-17\n"), "{}", digest.body);
+        assert!(digest.body.contains("Raised while handling: KeyError: 'A-17'\n  File \"/app/orders/views.py\", line 12"));
+
+        let prefixed = "web-1  | Traceback (most recent call last):\n\
+                        web-1  |   File \"app.py\", line 3, in <module>\n\
+                        web-1  |     main()\n\
+                        web-1  | RuntimeError: boom\n";
+        let chains = super::chains(prefixed);
+        assert_eq!(chains[0][0].message, "RuntimeError: boom");
+        assert_eq!(chains[0][0].frames[0].code.as_deref(), Some("main()"));
+    }
+
+    #[test]
+    fn test_render_frames() {
+        let frame = |file: &str| Frame { file: file.to_string(), line: "1".to_string(), function: "f".to_string(), code: None };
+        let mut frames = vec![frame("/app/main.py"), frame("/app/jobs.py")];
+        frames.extend((0..4).map(|_| frame("/usr/lib/python3.12/json/decoder.py")));
+        let mut out = String::new();
+        render_frames(&mut out, &frames);
+        assert!(out.starts_with("  File \"/app/jobs.py\", line 1, in f\n  ... 1 frames in libraries\n"), "{}", out);
+        assert_eq!(out.matches("decoder.py").count(), 3);
+    }
+
+    #[test]
+    fn test_pytest_failures() {
+        let log = "________________________ test_total ________________________
+
+    def test_total():
+>       assert total([1, 2]) == 4
+E       assert 3 == 4
+E        +  where 3 = total([1, 2])
+
+tests/test_cart.py:7: AssertionError
+=========================== short test summary info ============================
+FAILED tests/test_cart.py::test_total - assert 3 == 4
+";
+        assert_eq!(pytest_failures(log), ["AssertionError at tests/test_cart.py:7: assert 3 == 4"]);
+        assert_eq!(digest(log).unwrap().tool, "Python");
+    }
+}
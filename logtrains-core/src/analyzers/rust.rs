@@ -0,0 +1,238 @@
+//! cargo and rustc: compiler diagnostics with their codes, spans and
+//! snippets, and runtime panics.
+
+use regex::Regex;
+use std::sync::LazyLock;
+
+use super::{push_limited, Digest};
+
+/// Errors shown with their snippet; the rest are listed by message.
+const MAX_DETAILED: usize = 3;
+const MAX_LISTED: usize = 10;
+/// Snippet lines kept per error.
+const MAX_SNIPPET_LINES: usize = 12;
+
+static HEADER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(error|warning)(?:\[(E\d{4})\])?: (.+)$").unwrap());
+static SPAN: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\s*--> (.+:\d+:\d+)\s*$").unwrap());
+/// The source lines, markers and notes that follow a span.
+static SNIPPET: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\s*(\d+\s*)?\||^\s*\.\.\.$|^\s*= ").unwrap());
+static CRATE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"could not compile `([^`]+)`").unwrap());
+/// `thread 'main' panicked at src/main.rs:5:9:` (Rust 1.73+), with the
+/// message on the next line, or `... panicked at 'message', src/main.rs:5:9`.
+static PANIC: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"thread '([^']+)' panicked at (?:'(.*)', (\S+:\d+:\d+)|(\S+:\d+:\d+):?)$").unwrap()
+});
+
+const PROMPT: &str = "You are a {{ROLE}} and an expert Rust programmer. The log below is output of cargo or rustc, \
+    followed by a digest of its errors with their codes, locations and source snippets. \
+    Explain the first error in plain words: what the compiler rejects and why. For borrow-checker errors, \
+    name the value, where it is borrowed or moved and where the conflicting use is. \
+    Then show the smallest code change that fixes it, as a Rust snippet. Be brief. Use Markdown.";
+
+/// Borrow-checker codes, whose messages say little on their own.
+const BORROW_CODES: &[(&str, &str)] = &[
+    ("E0373", "a closure may outlive a value it borrows; `move` it in"),
+    ("E0382", "a value is used after it was moved to a new owner"),
+    ("E0499", "two mutable borrows of a value are alive at once"),
+    ("E0502", "a value is borrowed mutably while a shared borrow is alive"),
+    ("E0505", "a value is moved while it is borrowed"),
+    ("E0506", "a value is assigned to while it is borrowed"),
+    ("E0507", "a value is moved out from behind a reference"),
+    ("E0515", "a reference to a local value is returned"),
+    ("E0597", "a value is dropped while it is still borrowed"),
+    ("E0716", "a temporary is dropped while it is still borrowed"),
+];
+
+#[derive(Debug, PartialEq)]
+struct Diagnostic {
+    error: bool,
+    code: Option<String>,
+    message: String,
+    location: Option<String>,
+    snippet: Vec<String>,
+}
+
+impl Diagnostic {
+    fn heading(&self) -> String {
+        let mut heading = match &self.code {
+            Some(code) => format!("error[{}]: {}", code, self.message),
+            None => format!("error: {}", self.message),
+        };
+        if let Some(location) = &self.location {
+            heading.push_str(&format!(" (at {})", location));
+        }
+        heading
+    }
+}
+
+/// Recognizes rustc errors or a panic and digests them.
+pub fn digest(log: &str) -> Option<Digest> {
+    let (errors, warnings) = diagnostics(log);
+    let panics = panics(log);
+    if errors.is_empty() && panics.is_empty() {
+        return None;
+    }
+
+    let mut body = String::new();
+    if !errors.is_empty() {
+        body.push_str(&format!("{} error(s), {} warning(s)", errors.len(), warnings));
+        if let Some(name) = CRATE.captures(log) {
+            body.push_str(&format!(" in crate `{}`", &name[1]));
+        }
+        body.push('\n');
+    }
+    for error in errors.iter().take(MAX_DETAILED) {
+        body.push_str(&format!("\n{}\n", error.heading()));
+        if let Some((_, meaning)) = BORROW_CODES.iter().find(|(code, _)| error.code.as_deref() == Some(code)) {
+            body.push_str(&format!("Meaning: {}.\n", meaning));
+        }
+        for line in error.snippet.iter().take(MAX_SNIPPET_LINES) {
+            body.push_str(line);
+            body.push('\n');
+        }
+    }
+    if errors.len() > MAX_DETAILED {
+        body.push_str("\nOther errors:\n");
+        let rest: Vec<String> = errors[MAX_DETAILED..].iter().map(Diagnostic::heading).collect();
+        push_limited(&mut body, &rest, MAX_LISTED);
+    }
+    for panic in &panics {
+        body.push_str(&format!("\n{}\n", panic));
+    }
+    if let Some(code) = errors.iter().find_map(|error| error.code.as_deref()) {
+        body.push_str(&format!("\nDetails of a code: `rustc --explain {}`\n", code));
+    }
+
+    Some(Digest { tool: "Rust", title: "Rust Compiler Errors", body, prompt: PROMPT, scope: None })
+}
+
+/// The distinct errors that point at code, in order, and the number of
+/// warnings that do. Summaries like `aborting due to 2 previous errors` or
+/// `generated 1 warning` point at nothing and are left out.
+fn diagnostics(log: &str) -> (Vec<Diagnostic>, usize) {
+    let mut errors: Vec<Diagnostic> = Vec::new();
+    let mut warnings = 0;
+    let mut current: Option<Diagnostic> = None;
+    let mut finish = |errors: &mut Vec<Diagnostic>, diagnostic: Option<Diagnostic>| {
+        match diagnostic.filter(|diagnostic| diagnostic.location.is_some()) {
+            // Several targets of one crate report the same error
+            Some(error) if error.error && !errors.contains(&error) => errors.push(error),
+            Some(warning) if !warning.error => warnings += 1,
+            _ => {}
+        }
+    };
+    for line in log.lines().map(str::trim_end) {
+        if let Some(header) = HEADER.captures(line) {
+            finish(&mut errors, current.take());
+            current = Some(Diagnostic {
+                error: &header[1] == "error",
+                code: header.get(2).map(|code| code.as_str().to_string()),
+                message: header[3].to_string(),
+                location: None,
+                snippet: Vec::new(),
+            });
+        } else if let Some(error) = current.as_mut() {
+            if error.location.is_none() {
+                match SPAN.captures(line) {
+                    Some(span) => error.location = Some(span[1].to_string()),
+                    None => finish(&mut errors, current.take()),
+                }
+            } else if SNIPPET.is_match(line) {
+                error.snippet.push(line.to_string());
+            } else {
+                finish(&mut errors, current.take());
+            }
+        }
+    }
+    finish(&mut errors, current);
+    (errors, warnings)
+}
+
+/// The panics in the log, each with its thread, location and message.
+fn panics(log: &str) -> Vec<String> {
+    let lines: Vec<&str> = log.lines().map(str::trim_end).collect();
+    let mut panics = Vec::new();
+    for (index, line) in lines.iter().enumerate() {
+        let Some(panic) = PANIC.captures(line) else { continue };
+        let (message, location) = match (panic.get(2), panic.get(3), panic.get(4)) {
+            (Some(message), Some(location), _) => (message.as_str(), location.as_str()),
+            (_, _, Some(location)) => (lines.get(index + 1).copied().unwrap_or("").trim(), location.as_str()),
+            _ => continue,
+        };
+        panics.push(format!("panic in thread '{}' at {}: {}", &panic[1], location, message));
+    }
+    panics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BUILD: &str = "   Compiling app v0.1.0 (/work/app)
+warning: unused variable: `x`
+ --> src/lib.rs:2:9
+  |
+2 |     let x = 1;
+  |         ^ help: if this is intentional, prefix it with an underscore: `_x`
+
+error[E0382]: borrow of moved value: `names`
+ --> src/main.rs:5:20
+  |
+3 |     let names = vec![String::new()];
+  |         ----- move occurs because `names` has type `Vec<String>`, which does not implement the `Copy` trait
+4 |     let moved = names;
+  |                 ----- value moved here
+5 |     println!(\"{:?}\", names);
+  |                    ^^^^^ value borrowed here after move
+  |
+  = note: this error originates in the macro `println`
+help: consider cloning the value if the performance cost is acceptable
+  |
+4 |     let moved = names.clone();
+  |                      ++++++++
+
+error[E0308]: mismatched types
+  --> src/main.rs:9:18
+   |
+9  |     let n: i32 = \"1\";
+   |            ---   ^^^ expected `i32`, found `&str`
+
+warning: `app` (bin \"app\") generated 1 warning
+error: aborting due to 2 previous errors
+
+Some errors have detailed explanations: E0308, E0382.
+error: could not compile `app` (bin \"app\") due to 2 previous errors; 1 warning emitted
+";
+
+    #[test]
+    fn test_digest() {
+        let digest = digest(BUILD).unwrap();
+        let body = &digest.body;
+        assert!(body.starts_with("2 error(s), 1 warning(s) in crate `app`\n"), "{}", body);
+        assert!(body.contains("error[E0382]: borrow of moved value: `names` (at src/main.rs:5:20)\n"));
+        assert!(body.contains("Meaning: a value is used after it was moved to a new owner.\n"));
+        assert!(body.contains("  |                 ----- value moved here\n"));
+        assert!(body.contains("  = note: this error originates in the macro `println`\n"));
+        assert!(!body.contains("consider cloning"), "the help after the note ends the snippet");
+        assert!(body.contains("error[E0308]: mismatched types (at src/main.rs:9:18)\n"));
+        assert!(!body.contains("aborting"));
+        assert!(body.ends_with("`rustc --explain E0382`\n"));
+    }
+
+    #[test]
+    fn test_panics() {
+        let current = "thread 'main' panicked at src/main.rs:5:9:\nindex out of bounds: the len is 0 but the index is 3\n";
+        assert_eq!(
+            panics(current),
+            ["panic in thread 'main' at src/main.rs:5:9: index out of bounds: the len is 0 but the index is 3"]
+        );
+        let old = "thread 'worker' panicked at 'called `Option::unwrap()` on a `None` value', src/lib.rs:10:5";
+        assert_eq!(
+            panics(old),
+            ["panic in thread 'worker' at src/lib.rs:10:5: called `Option::unwrap()` on a `None` value"]
+        );
+        assert!(digest(current).unwrap().body.contains("panic in thread 'main'"));
+        assert_eq!(digest("warning: unused import\n --> src/lib.rs:1:5\n"), None);
+    }
+}
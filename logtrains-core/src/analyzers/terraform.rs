@@ -0,0 +1,238 @@
+//! Terraform and OpenTofu: errors of `plan` and `apply` split into provider
+//! errors, state and locking problems and configuration errors, each with
+//! its resource address, source line and the plan's diff for the resource.
+
+use regex::Regex;
+use std::sync::LazyLock;
+
+use super::Digest;
+
+const MAX_ERRORS: usize = 5;
+const MAX_DETAIL_LINES: usize = 8;
+const MAX_HUNK_LINES: usize = 30;
+
+static HEADER: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^(Error|Warning): (.+)$").unwrap());
+/// `with module.app.aws_instance.web[0],`.
+static ADDRESS: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^with (\S+?),?$").unwrap());
+/// `on main.tf line 12, in resource "aws_instance" "web":`.
+static LOCATION: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^on (\S+) line (\d+)").unwrap());
+static SOURCE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\s*\d+: ").unwrap());
+/// The state backend, its lock and snapshots, rather than a resource.
+static STATE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)state lock|lock info|\bbackend\b|state snapshot|(?:load|loading|save|saving|persist|refresh|refreshing|read|reading|write|writing) (?:the )?state|state file|state data").unwrap()
+});
+/// Errors raised by a provider's API rather than by Terraform.
+static PROVIDER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)status code: \d+|StatusCode: \d+|api error|request id|\bprovider\b|googleapi|azurerm|\d{3} \w+:").unwrap());
+
+const PROMPT: &str = "You are a {{ROLE}} and an expert in Terraform and OpenTofu. The log below is output of \
+    `terraform plan` or `apply`, followed by a digest of its errors, split into provider errors, state and \
+    locking problems and configuration errors, with the resource address, the source line and the plan's diff \
+    for the resource. Explain the cause of each error: for provider errors, what the cloud API rejected and why; \
+    for state and locking, whether it is safe to wait, `force-unlock` or migrate the state, and the exact \
+    command. Then give the minimal configuration change as an HCL snippet of only the lines that change. Be \
+    brief. Use Markdown.";
+
+#[derive(Debug, Default, PartialEq)]
+struct Error {
+    summary: String,
+    /// `aws_instance.web`.
+    address: Option<String>,
+    /// `main.tf line 12` and the source line.
+    location: Option<String>,
+    source: Option<String>,
+    details: Vec<String>,
+}
+
+/// Recognizes Terraform or OpenTofu errors and digests them.
+pub fn digest(log: &str) -> Option<Digest> {
+    let lines: Vec<String> = log.lines().map(unbox).collect();
+    let terraform = log.contains('╷')
+        || log.contains("Terraform")
+        || log.contains("OpenTofu")
+        || lines.iter().any(|line| LOCATION.is_match(line.trim()) && line.contains(".tf line"));
+    if !terraform {
+        return None;
+    }
+    let errors = errors(&lines);
+    if errors.is_empty() {
+        return None;
+    }
+
+    let (state, rest): (Vec<&Error>, Vec<&Error>) = errors.iter().partition(|error| is_state(error));
+    let (provider, config): (Vec<&Error>, Vec<&Error>) =
+        rest.into_iter().partition(|error| error.address.is_some() && is_provider(error));
+    let mut body = String::new();
+    for (heading, errors) in [("Provider errors", provider), ("State and locking errors", state), ("Configuration errors", config)] {
+        if errors.is_empty() {
+            continue;
+        }
+        if !body.is_empty() {
+            body.push('\n');
+        }
+        body.push_str(&format!("{}:\n", heading));
+        for error in errors.iter().take(MAX_ERRORS) {
+            render(&mut body, error, &lines);
+        }
+        if errors.len() > MAX_ERRORS {
+            body.push_str(&format!("... and {} more\n", errors.len() - MAX_ERRORS));
+        }
+    }
+    Some(Digest { tool: "Terraform", title: "Terraform Errors", body, prompt: PROMPT, scope: None })
+}
+
+fn is_state(error: &Error) -> bool {
+    STATE.is_match(&error.summary) || error.details.iter().any(|detail| detail.starts_with("Lock Info:"))
+}
+
+fn is_provider(error: &Error) -> bool {
+    // Terraform's own errors about a resource block name an argument
+    let config = ["Unsupported argument", "Missing required argument", "Reference to undeclared", "Invalid reference", "Unsupported attribute", "Incorrect attribute value type", "Invalid value for"];
+    !config.iter().any(|prefix| error.summary.starts_with(prefix))
+        || error.details.iter().any(|detail| PROVIDER.is_match(detail))
+}
+
+/// The line without the box Terraform draws around diagnostics.
+fn unbox(line: &str) -> String {
+    let line = line.trim_end();
+    let line = line.strip_prefix('│').map(|line| line.strip_prefix(' ').unwrap_or(line)).unwrap_or(line);
+    if line == "╷" || line == "╵" {
+        return String::new();
+    }
+    line.to_string()
+}
+
+fn render(body: &mut String, error: &Error, lines: &[String]) {
+    body.push_str(&format!("  Error: {}\n", error.summary));
+    if let Some(address) = &error.address {
+        body.push_str(&format!("    Resource: {}\n", address));
+    }
+    if let Some(location) = &error.location {
+        body.push_str(&format!("    At: {}\n", location));
+    }
+    if let Some(source) = &error.source {
+        body.push_str(&format!("    {}\n", source));
+    }
+    for detail in error.details.iter().take(MAX_DETAIL_LINES) {
+        body.push_str(&format!("    {}\n", detail));
+    }
+    if let Some(hunk) = error.address.as_deref().and_then(|address| hunk(lines, address)) {
+        body.push_str("    Planned change:\n");
+        for line in hunk {
+            body.push_str(&format!("      {}\n", line));
+        }
+    }
+}
+
+/// Each `Error:` diagnostic with its address, location, source line and
+/// detail, up to the end of its box or the next diagnostic.
+fn errors(lines: &[String]) -> Vec<Error> {
+    let mut errors: Vec<Error> = Vec::new();
+    let mut current: Option<Error> = None;
+    for line in lines {
+        let text = line.trim();
+        if let Some(header) = HEADER.captures(text) {
+            errors.extend(current.take());
+            if &header[1] == "Error" {
+                current = Some(Error { summary: header[2].to_string(), ..Error::default() });
+            }
+            continue;
+        }
+        let Some(error) = current.as_mut() else { continue };
+        if let Some(address) = ADDRESS.captures(text) {
+            error.address = Some(address[1].to_string());
+        } else if let Some(location) = LOCATION.captures(text) {
+            error.location = Some(format!("{} line {}", &location[1], &location[2]));
+        } else if SOURCE.is_match(text) && error.location.is_some() && error.source.is_none() {
+            error.source = Some(text.to_string());
+        } else if !text.is_empty() {
+            error.details.push(text.to_string());
+        }
+    }
+    errors.extend(current);
+    errors
+}
+
+/// The plan's block for `address`: from its `# aws_instance.web will be
+/// updated in-place` comment to the brace that closes the resource.
+fn hunk(lines: &[String], address: &str) -> Option<Vec<String>> {
+    let comment = format!("# {} ", address);
+    let start = lines.iter().position(|line| line.trim_start().starts_with(&comment))?;
+    let indent = lines[start].len() - lines[start].trim_start().len();
+    let mut hunk = Vec::new();
+    for line in &lines[start..] {
+        hunk.push(line[indent.min(line.len() - line.trim_start().len())..].to_string());
+        let closing = line.trim() == "}" && line.len() - line.trim_start().len() <= indent + 2;
+        if closing || hunk.len() >= MAX_HUNK_LINES {
+            break;
+        }
+    }
+    Some(hunk)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const APPLY: &str = "Terraform will perform the following actions:
+
+  # aws_instance.web will be updated in-place
+  ~ resource \"aws_instance\" \"web\" {
+        id            = \"i-0abc\"
+      ~ instance_type = \"t3.micro\" -> \"t3.mega\"
+        # (28 unchanged attributes hidden)
+    }
+
+Plan: 0 to add, 1 to change, 0 to destroy.
+aws_instance.web: Modifying... [id=i-0abc]
+╷
+│ Error: updating EC2 Instance (i-0abc): InvalidParameterValue: Invalid value 't3.mega' for InstanceType.
+│ \tstatus code: 400, request id: 5f1c
+│
+│   with aws_instance.web,
+│   on main.tf line 14, in resource \"aws_instance\" \"web\":
+│   14: resource \"aws_instance\" \"web\" {
+│
+╵
+╷
+│ Error: Error acquiring the state lock
+│
+│ Error message: ConditionalCheckFailedException: The conditional request failed
+│ Lock Info:
+│   ID:        9a1e
+│   Who:       ci@runner-7
+│   Operation: OperationTypeApply
+╵
+";
+
+    #[test]
+    fn test_errors() {
+        let lines: Vec<String> = APPLY.lines().map(unbox).collect();
+        let errors = errors(&lines);
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].address.as_deref(), Some("aws_instance.web"));
+        assert_eq!(errors[0].location.as_deref(), Some("main.tf line 14"));
+        assert_eq!(errors[0].source.as_deref(), Some("14: resource \"aws_instance\" \"web\" {"));
+        assert_eq!(errors[0].details, ["status code: 400, request id: 5f1c"]);
+        assert!(is_state(&errors[1]));
+        assert_eq!(hunk(&lines, "aws_instance.web").unwrap().len(), 6);
+    }
+
+    #[test]
+    fn test_digest() {
+        let body = digest(APPLY).unwrap().body;
+        assert!(body.starts_with(
+            "Provider errors:\n  Error: updating EC2 Instance (i-0abc): InvalidParameterValue: Invalid value 't3.mega' for InstanceType.\n\
+             \x20   Resource: aws_instance.web\n    At: main.tf line 14\n"
+        ), "{}", body);
+        assert!(body.contains(
+            "    Planned change:\n      # aws_instance.web will be updated in-place\n      ~ resource \"aws_instance\" \"web\" {\n"
+        ));
+        assert!(body.contains("      }\n\nState and locking errors:\n  Error: Error acquiring the state lock\n"));
+        assert!(body.ends_with("    Operation: OperationTypeApply\n"));
+
+        let config = "Error: Unsupported argument\n\n  on main.tf line 5, in resource \"aws_s3_bucket\" \"logs\":\n   5:   acl = \"private\"\n";
+        assert!(digest(config).unwrap().body.starts_with("Configuration errors:\n  Error: Unsupported argument\n    At: main.tf line 5\n"));
+        assert_eq!(digest("Error: something failed\n"), None);
+    }
+}
@@ -0,0 +1,375 @@
+//! Test reports of pytest, jest, `cargo test` and `go test`: each failed test
+//! with its location, failed assertion and captured output, as a table.
+//! Not detected on its own; `--mode test-report` asks for it.
+
+use regex::Regex;
+use std::sync::LazyLock;
+
+use super::{go, Digest};
+
+const MAX_ROWS: usize = 20;
+/// Lines of a failure's message and of its captured output kept in the table.
+const MAX_CELL_LINES: usize = 3;
+const MAX_CELL_CHARS: usize = 200;
+
+static PYTEST_SECTION: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^_{3,} (.+?) _{3,}$").unwrap());
+static PYTEST_ERROR: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^E\s+(.+)$").unwrap());
+static PYTEST_LOCATION: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^([\w./\\-]+\.py):(\d+): ([A-Za-z_][\w.]*)$").unwrap());
+static PYTEST_CAPTURED: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^-{3,} Captured \w+ \w+ -{3,}$").unwrap());
+static PYTEST_FAILED: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^(?:FAILED|ERROR) (\S+)(?: - (.+))?$").unwrap());
+static JEST_TEST: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\s*● (.+)$").unwrap());
+/// A line of jest's code frame: `  > 11 |     expect(...)`.
+static JEST_CODE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\s*>?\s*\d*\s+\|").unwrap());
+static JEST_LOCATION: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\s*at .*?\(?([^()\s]+:\d+):\d+\)?$").unwrap());
+static CARGO_STDOUT: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^---- (\S+) stdout ----$").unwrap());
+static CARGO_FAILED: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^test (\S+) \.\.\. FAILED$").unwrap());
+/// `thread 'x' panicked at src/lib.rs:10:9:` since Rust 1.73, before it
+/// `thread 'x' panicked at 'message', src/lib.rs:10:9`.
+static CARGO_PANIC: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^thread '[^']*' panicked at (?:'(.*)', )?(\S+?:\d+)(?::\d+)?:?$").unwrap());
+static GO_FAIL: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^--- FAIL: (\S+)").unwrap());
+static GO_LOCATION: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^(\S+\.go:\d+): (.*)$").unwrap());
+/// pytest's `=== 2 failed, 10 passed in 0.52s ===`, cargo's `test result:
+/// FAILED. ...` and jest's `Tests: 2 failed, 8 passed, 10 total`.
+static SUMMARY: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(?:=+ (.*\d+ (?:failed|error).*?) =+|(test result: FAILED\..*)|(Tests:\s+.*\d+ failed.*))$").unwrap()
+});
+
+const PROMPT: &str = "You are a {{ROLE}} and an expert in software testing. The log below is output of a test \
+    run, followed by a table of its failed tests: the test, where it failed, the failed assertion or error and \
+    the end of its captured output. For each failed test, in the order of the table, explain in one or two \
+    sentences why it failed and whether the test or the code under test is likely wrong. Then say whether the \
+    failures probably share a root cause, such as the same error, module, fixture or environment problem, and \
+    which; or whether they look unrelated. Be brief. Use Markdown, with a heading per failed test.";
+
+#[derive(Debug, Default, PartialEq)]
+struct Failure {
+    test: String,
+    /// `tests/test_cart.py:7`.
+    location: Option<String>,
+    /// The failed assertion or error, line by line.
+    message: Vec<String>,
+    /// What the test printed.
+    output: Vec<String>,
+}
+
+/// The failed tests of pytest, jest, `cargo test` or `go test` output as a
+/// table, with a prompt asking about each failure and what they share.
+pub fn digest(log: &str) -> Option<Digest> {
+    let lines: Vec<&str> = log.lines().map(str::trim_end).collect();
+    let mut failures = pytest(&lines);
+    failures.extend(jest(&lines));
+    failures.extend(cargo(&lines));
+    failures.extend(go_test(&lines));
+    if failures.is_empty() {
+        return None;
+    }
+
+    let mut body = String::new();
+    for line in &lines {
+        if let Some(summary) = SUMMARY.captures(line) {
+            let summary = summary.iter().skip(1).flatten().next().map_or("", |m| m.as_str());
+            body.push_str(&format!("{}\n", summary.trim()));
+        }
+    }
+    if !body.is_empty() {
+        body.push('\n');
+    }
+    body.push_str("| # | Test | Location | Failure | Captured output |\n|---|---|---|---|---|\n");
+    for (index, failure) in failures.iter().take(MAX_ROWS).enumerate() {
+        let output = &failure.output[failure.output.len().saturating_sub(MAX_CELL_LINES)..];
+        body.push_str(&format!(
+            "| {} | {} | {} | {} | {} |\n",
+            index + 1,
+            cell(std::slice::from_ref(&failure.test)),
+            cell(failure.location.as_slice()),
+            cell(&failure.message[..failure.message.len().min(MAX_CELL_LINES)]),
+            cell(output),
+        ));
+    }
+    if failures.len() > MAX_ROWS {
+        body.push_str(&format!("... and {} more failed tests\n", failures.len() - MAX_ROWS));
+    }
+
+    Some(Digest { tool: "test runner", title: "Test Report", body, prompt: PROMPT, scope: None })
+}
+
+/// Lines joined into one table cell, shortened and with `|` escaped.
+fn cell(lines: &[String]) -> String {
+    let joined = lines.iter().map(|line| line.trim()).filter(|line| !line.is_empty()).collect::<Vec<_>>().join("; ");
+    let mut text: String = joined.chars().take(MAX_CELL_CHARS).collect();
+    if joined.chars().count() > MAX_CELL_CHARS {
+        text.push_str("...");
+    }
+    if text.is_empty() {
+        return "-".to_string();
+    }
+    text.replace('|', "\\|")
+}
+
+/// pytest's failure sections, named by the short test summary's ids when
+/// it has them. Without sections (`--tb=no`) the summary's lines alone.
+fn pytest(lines: &[&str]) -> Vec<Failure> {
+    let mut failures: Vec<Failure> = Vec::new();
+    let mut in_section = false;
+    let mut captured = false;
+    for line in lines {
+        if let Some(section) = PYTEST_SECTION.captures(line) {
+            failures.push(Failure { test: section[1].to_string(), ..Failure::default() });
+            in_section = true;
+            captured = false;
+            continue;
+        }
+        if line.starts_with("===") {
+            in_section = false;
+        }
+        let Some(failure) = failures.last_mut().filter(|_| in_section) else { continue };
+        if PYTEST_CAPTURED.is_match(line) {
+            captured = true;
+        } else if captured {
+            if !line.trim().is_empty() {
+                failure.output.push(line.to_string());
+            }
+        } else if let Some(error) = PYTEST_ERROR.captures(line) {
+            failure.message.push(error[1].to_string());
+        } else if let Some(location) = PYTEST_LOCATION.captures(line) {
+            failure.location = Some(format!("{}:{}", &location[1], &location[2]));
+            let exception = &location[3];
+            if !failure.message.first().is_some_and(|message| message.starts_with(exception)) {
+                failure.message.insert(0, exception.to_string());
+            }
+        }
+    }
+    for line in lines {
+        let Some(summary) = PYTEST_FAILED.captures(line) else { continue };
+        let id = &summary[1];
+        // The section of `tests/test_cart.py::TestCart::test_total` is `TestCart.test_total`
+        let section = failures.iter_mut().find(|failure| id.ends_with(&format!("::{}", failure.test.replace('.', "::"))));
+        match section {
+            Some(failure) => failure.test = id.to_string(),
+            None => failures.push(Failure {
+                test: id.to_string(),
+                message: summary.get(2).map(|message| message.as_str().to_string()).into_iter().collect(),
+                ..Failure::default()
+            }),
+        }
+    }
+    failures
+}
+
+/// jest's `● Suite › test` blocks: the message up to the code frame, and
+/// the first location of the stack.
+fn jest(lines: &[&str]) -> Vec<Failure> {
+    let mut failures: Vec<Failure> = Vec::new();
+    let mut in_message = false;
+    let mut in_block = false;
+    for line in lines {
+        if let Some(test) = JEST_TEST.captures(line) {
+            in_block = &test[1] != "Console";
+            in_message = in_block;
+            if in_block {
+                failures.push(Failure { test: test[1].to_string(), ..Failure::default() });
+            }
+            continue;
+        }
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("PASS ") || trimmed.starts_with("FAIL ") || trimmed.starts_with("Test Suites:") {
+            in_block = false;
+        }
+        let Some(failure) = failures.last_mut().filter(|_| in_block) else { continue };
+        if let Some(location) = JEST_LOCATION.captures(line) {
+            in_message = false;
+            failure.location.get_or_insert_with(|| location[1].to_string());
+        } else if JEST_CODE.is_match(line) {
+            in_message = false;
+        } else if in_message && !line.trim().is_empty() {
+            failure.message.push(line.trim().to_string());
+        }
+    }
+    failures
+}
+
+/// `cargo test`'s `---- test stdout ----` blocks: the panic's location and
+/// message, and what the test printed before it. Failed tests without a
+/// block (`--nocapture`) come from their `... FAILED` lines.
+fn cargo(lines: &[&str]) -> Vec<Failure> {
+    let mut failures: Vec<Failure> = Vec::new();
+    let mut in_block = false;
+    let mut in_message = false;
+    for line in lines {
+        if let Some(test) = CARGO_STDOUT.captures(line) {
+            failures.push(Failure { test: test[1].to_string(), ..Failure::default() });
+            in_block = true;
+            in_message = false;
+            continue;
+        }
+        if line.starts_with("failures:") || line.starts_with("test result:") || line.starts_with("---- ") {
+            in_block = false;
+        }
+        let Some(failure) = failures.last_mut().filter(|_| in_block) else { continue };
+        if let Some(panic) = CARGO_PANIC.captures(line) {
+            failure.location = Some(panic[2].to_string());
+            failure.message.extend(panic.get(1).map(|message| message.as_str().to_string()));
+            in_message = true;
+        } else if in_message {
+            if line.starts_with("note: ") || line.trim().is_empty() {
+                in_message = false;
+            } else {
+                failure.message.push(line.to_string());
+            }
+        } else if !line.trim().is_empty() {
+            failure.output.push(line.to_string());
+        }
+    }
+    for line in lines {
+        if let Some(test) = CARGO_FAILED.captures(line) {
+            if !failures.iter().any(|failure| failure.test == test[1]) {
+                failures.push(Failure { test: test[1].to_string(), ..Failure::default() });
+            }
+        }
+    }
+    failures
+}
+
+/// `go test`'s failed tests: the lines with a `_test.go:N:` location are
+/// the failure, the rest what the test printed. A parent test that failed
+/// only through its subtests is left out.
+fn go_test(lines: &[&str]) -> Vec<Failure> {
+    let mut failures: Vec<Failure> = Vec::new();
+    for (result, output) in go::test_failures(lines) {
+        let Some(test) = GO_FAIL.captures(&result) else { continue };
+        let mut failure = Failure { test: test[1].to_string(), ..Failure::default() };
+        for line in output {
+            match GO_LOCATION.captures(&line) {
+                Some(location) => {
+                    failure.location.get_or_insert_with(|| location[1].to_string());
+                    failure.message.push(location[2].to_string());
+                }
+                None => failure.output.push(line),
+            }
+        }
+        failures.push(failure);
+    }
+    let parents: Vec<String> = failures
+        .iter()
+        .filter(|failure| failure.message.is_empty() && failure.output.is_empty())
+        .filter(|parent| failures.iter().any(|failure| failure.test.starts_with(&format!("{}/", parent.test))))
+        .map(|parent| parent.test.clone())
+        .collect();
+    failures.retain(|failure| !parents.contains(&failure.test));
+    failures
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(log: &str) -> Vec<&str> {
+        log.lines().collect()
+    }
+
+    #[test]
+    fn test_pytest() {
+        let log = "________________________ TestCart.test_total ________________________
+
+    def test_total(self):
+>       assert total([1, 2]) == 4
+E       assert 3 == 4
+
+tests/test_cart.py:7: AssertionError
+----------------------------- Captured stdout call -----------------------------
+loading cart
+=========================== short test summary info ============================
+FAILED tests/test_cart.py::TestCart::test_total - assert 3 == 4
+FAILED tests/test_api.py::test_fetch - ConnectionError: refused
+========================= 2 failed, 10 passed in 0.52s =========================
+";
+        let failures = pytest(&lines(log));
+        assert_eq!(
+            failures[0],
+            Failure {
+                test: "tests/test_cart.py::TestCart::test_total".to_string(),
+                location: Some("tests/test_cart.py:7".to_string()),
+                message: vec!["AssertionError".to_string(), "assert 3 == 4".to_string()],
+                output: vec!["loading cart".to_string()],
+            }
+        );
+        assert_eq!(failures[1].test, "tests/test_api.py::test_fetch");
+        assert_eq!(failures[1].message, ["ConnectionError: refused"]);
+
+        let body = digest(log).unwrap().body;
+        assert!(body.starts_with("2 failed, 10 passed in 0.52s\n\n| # | Test |"), "{}", body);
+        assert!(body.contains(
+            "| 1 | tests/test_cart.py::TestCart::test_total | tests/test_cart.py:7 | AssertionError; assert 3 == 4 | loading cart |\n"
+        ));
+        assert!(body.ends_with("| 2 | tests/test_api.py::test_fetch | - | ConnectionError: refused | - |\n"));
+    }
+
+    #[test]
+    fn test_jest() {
+        let log = " FAIL  src/cart.test.js
+  ● Cart › adds prices
+
+    expect(received).toBe(expected) // Object.is equality
+
+    Expected: 4
+    Received: 3
+
+      10 |   it('adds prices', () => {
+    > 11 |     expect(total([1, 2])).toBe(4);
+         |                           ^
+
+      at Object.toBe (src/cart.test.js:11:27)
+
+Tests:       1 failed, 3 passed, 4 total
+";
+        let failures = jest(&lines(log));
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].test, "Cart › adds prices");
+        assert_eq!(failures[0].location.as_deref(), Some("src/cart.test.js:11"));
+        assert_eq!(failures[0].message.len(), 3);
+        assert!(digest(log).unwrap().body.starts_with("Tests:       1 failed, 3 passed, 4 total\n"));
+    }
+
+    #[test]
+    fn test_cargo_and_go() {
+        let log = "running 2 tests
+test tests::parse ... FAILED
+test tests::format ... FAILED
+
+failures:
+
+---- tests::parse stdout ----
+reading fixture
+thread 'tests::parse' panicked at src/lib.rs:10:9:
+assertion `left == right` failed
+  left: 3
+ right: 4
+note: run with `RUST_BACKTRACE=1` environment variable to display a backtrace
+
+failures:
+    tests::parse
+
+test result: FAILED. 0 passed; 2 failed; 0 ignored
+";
+        let failures = cargo(&lines(log));
+        assert_eq!(failures.len(), 2);
+        assert_eq!(failures[0].location.as_deref(), Some("src/lib.rs:10"));
+        assert_eq!(failures[0].message, ["assertion `left == right` failed", "  left: 3", " right: 4"]);
+        assert_eq!(failures[0].output, ["reading fixture"]);
+        assert_eq!(failures[1], Failure { test: "tests::format".to_string(), ..Failure::default() });
+
+        let go = "--- FAIL: TestParse (0.00s)
+    --- FAIL: TestParse/empty (0.00s)
+        parse_test.go:14: got 3, want 4
+FAIL
+FAIL\texample.com/app/parse\t0.012s
+";
+        let failures = go_test(&lines(go));
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].test, "TestParse/empty");
+        assert_eq!(failures[0].location.as_deref(), Some("parse_test.go:14"));
+        assert_eq!(digest("ok  \texample.com/app\t0.010s\n"), None);
+    }
+}
@@ -0,0 +1,214 @@
+//! nginx and Apache: access logs aggregated into status-code statistics
+//! with the minutes where server errors peak, and error logs into counts of
+//! their upstream errors, instead of raw lines.
+
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::sync::LazyLock;
+
+use super::Digest;
+
+/// Fewer access lines than this are a quote in another log, not an access log.
+const MIN_ACCESS_LINES: usize = 3;
+const MAX_ROWS: usize = 5;
+
+/// The common and combined formats: `1.2.3.4 - - [10/Oct/2024:13:55:36 +0000] "GET /api HTTP/1.1" 502 157`.
+static ACCESS: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"^\S+ \S+ \S+ \[(\d{2}/\w{3}/\d{4}:\d{2}:\d{2}):\d{2}[^\]]*\] "(?:\S+) (\S+)[^"]*" (\d{3}) (?:\d+|-)"#).unwrap()
+});
+/// `2024/10/10 13:55:36 [error] 31#31: *7 connect() failed ... , client: ..., upstream: "http://10.0.0.5:8080/api"`.
+static NGINX_ERROR: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(\d{4}/\d{2}/\d{2} \d{2}:\d{2}):\d{2} \[(\w+)\] \d+#\d+: (?:\*\d+ )?(.*)$").unwrap()
+});
+/// `[Thu Oct 10 13:55:36.123456 2024] [proxy:error] [pid 123] (111)Connection refused: AH00957: ...`.
+static APACHE_ERROR: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^\[\w{3} (\w{3} \d{2} \d{2}:\d{2}):\d{2}(?:\.\d+)? \d{4}\] \[(?:[\w-]+:)?(\w+)\] (?:\[pid \d+(?::tid \d+)?\] )?(?:\[client [^\]]+\] )?(.*)$").unwrap()
+});
+static UPSTREAM: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"upstream: "([^"]+)""#).unwrap());
+/// Per-request details that keep equal errors apart: nginx's `, client: ...`
+/// tail and Apache's `AH01114: ... to 10.0.0.5:8080 (backend)`.
+static DETAILS: LazyLock<Regex> = LazyLock::new(|| Regex::new(r", client: .*$|\S+:\d+ \([^)]*\)").unwrap());
+
+const PROMPT: &str = "You are a {{ROLE}} and an expert in nginx, Apache and the services behind them. The log below \
+    is a web server's access or error log, followed by statistics of it: requests per status class, the most \
+    frequent errors, the paths and upstreams that failed and the minutes where errors peaked. Work from the \
+    statistics rather than single lines. Explain what the spikes show, and for recurring 502s (an upstream that \
+    refused, closed or crashed) and 504s (an upstream that timed out), the most likely cause and how to confirm \
+    it. Then give the fix: to the upstream service, or to timeouts, buffers or upstream settings, as \
+    configuration. Be brief. Use Markdown.";
+
+/// Counts of keys, listed most frequent first.
+#[derive(Default)]
+struct Tally(BTreeMap<String, usize>);
+
+impl Tally {
+    fn add(&mut self, key: impl Into<String>) {
+        *self.0.entry(key.into()).or_default() += 1;
+    }
+
+    fn top(&self, max: usize) -> Vec<(&str, usize)> {
+        let mut counts: Vec<(&str, usize)> = self.0.iter().map(|(key, &count)| (key.as_str(), count)).collect();
+        counts.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+        counts.truncate(max);
+        counts
+    }
+
+    fn push(&self, body: &mut String, heading: &str, max: usize) {
+        if self.0.is_empty() {
+            return;
+        }
+        body.push_str(&format!("{}:\n", heading));
+        for (key, count) in self.top(max) {
+            body.push_str(&format!("  {:>6}  {}\n", count, key));
+        }
+    }
+}
+
+/// Recognizes nginx or Apache access or error logs and aggregates them.
+pub fn digest(log: &str) -> Option<Digest> {
+    let mut body = String::new();
+    let access = access_digest(log, &mut body);
+    let errors = error_digest(log, &mut body);
+    if !access && !errors {
+        return None;
+    }
+    Some(Digest { tool: "web server", title: "Web Server Statistics", body, prompt: PROMPT, scope: None })
+}
+
+/// Requests per status class, the error codes, the paths of 502/503/504s
+/// and the minutes with the most 5xx responses.
+fn access_digest(log: &str, body: &mut String) -> bool {
+    let requests: Vec<(String, String, u16)> = log
+        .lines()
+        .filter_map(|line| ACCESS.captures(line))
+        .filter_map(|c| Some((c[1].to_string(), c[2].to_string(), c[3].parse().ok()?)))
+        .collect();
+    if requests.len() < MIN_ACCESS_LINES {
+        return false;
+    }
+    let mut classes = [0usize; 6];
+    let mut codes = Tally::default();
+    let mut gateway_paths = Tally::default();
+    let mut server_errors = Tally::default();
+    let mut span = Tally::default();
+    for (minute, path, status) in &requests {
+        span.add(minute.clone());
+        classes[usize::from(status / 100).min(5)] += 1;
+        if *status >= 400 {
+            codes.add(status.to_string());
+        }
+        if matches!(status, 502..=504) {
+            // The query string makes every request a path of its own
+            gateway_paths.add(format!("{} {}", status, path.split('?').next().unwrap_or(path)));
+        }
+        if *status >= 500 {
+            server_errors.add(minute.clone());
+        }
+    }
+    // Lines are not in time order when several workers write the log
+    let time = |minute: &&str| chrono::NaiveDateTime::parse_from_str(minute, "%d/%b/%Y:%H:%M").ok();
+    let minutes = || requests.iter().map(|(minute, _, _)| minute.as_str());
+    let first = minutes().min_by_key(time).unwrap_or_default();
+    let last = minutes().max_by_key(time).unwrap_or_default();
+    body.push_str(&format!("{} requests from {} to {}\n", requests.len(), first, last));
+    let share = |count: usize| count as f64 * 100.0 / requests.len() as f64;
+    for (class, &count) in classes.iter().enumerate().skip(1).filter(|(_, &count)| count > 0) {
+        body.push_str(&format!("  {}xx: {} ({:.1}%)\n", class, count, share(count)));
+    }
+    codes.push(body, "Error status codes", MAX_ROWS);
+    gateway_paths.push(body, "Paths answered with 502, 503 or 504", MAX_ROWS);
+    spikes(body, &server_errors, span.0.len(), "Minutes with the most 5xx responses");
+    true
+}
+
+/// nginx and Apache error-log messages by level, the most frequent with
+/// their per-request details removed, the upstreams and the minutes with
+/// the most errors.
+fn error_digest(log: &str, body: &mut String) -> bool {
+    let mut levels = Tally::default();
+    let mut messages = Tally::default();
+    let mut upstreams = Tally::default();
+    let mut minutes = Tally::default();
+    let mut span = Tally::default();
+    for line in log.lines() {
+        let Some(entry) = NGINX_ERROR.captures(line).or_else(|| APACHE_ERROR.captures(line)) else { continue };
+        let level = &entry[2];
+        levels.add(level);
+        span.add(&entry[1]);
+        if !matches!(level, "error" | "crit" | "alert" | "emerg") {
+            continue;
+        }
+        let message = DETAILS.replace_all(&entry[3], "");
+        messages.add(message.split_whitespace().collect::<Vec<_>>().join(" "));
+        if let Some(upstream) = UPSTREAM.captures(&entry[3]) {
+            // `http://10.0.0.5:8080/api/orders` is counted by its server
+            let server = upstream[1].splitn(4, '/').take(3).collect::<Vec<_>>().join("/");
+            upstreams.add(server);
+        }
+        minutes.add(&entry[1]);
+    }
+    if levels.0.is_empty() {
+        return false;
+    }
+    if !body.is_empty() {
+        body.push('\n');
+    }
+    let counts: Vec<String> = levels.top(usize::MAX).iter().map(|(level, count)| format!("{} {}", count, level)).collect();
+    body.push_str(&format!("Error log entries: {}\n", counts.join(", ")));
+    messages.push(body, "Most frequent errors", MAX_ROWS);
+    upstreams.push(body, "Upstreams in errors", MAX_ROWS);
+    spikes(body, &minutes, span.0.len(), "Minutes with the most errors");
+    true
+}
+
+/// The minutes with the most events, when they stand out from the average
+/// over the `span` minutes the log covers.
+fn spikes(body: &mut String, minutes: &Tally, span: usize, heading: &str) {
+    let total: usize = minutes.0.values().sum();
+    let average = total as f64 / span.max(1) as f64;
+    let peaks: Vec<(&str, usize)> =
+        minutes.top(3).into_iter().filter(|&(_, count)| count > 1 && count as f64 >= 2.0 * average).collect();
+    if !peaks.is_empty() {
+        let peaks: Vec<String> = peaks.iter().map(|(minute, count)| format!("{} ({})", minute, count)).collect();
+        body.push_str(&format!("{} (average {:.1} per minute): {}\n", heading, average, peaks.join(", ")));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_access() {
+        let mut log = String::new();
+        for minute in 0..5 {
+            log.push_str(&format!("10.0.0.1 - - [10/Oct/2024:13:0{}:01 +0000] \"GET / HTTP/1.1\" 200 512 \"-\" \"curl\"\n", minute));
+        }
+        for _ in 0..4 {
+            log.push_str("10.0.0.2 - - [10/Oct/2024:13:03:30 +0000] \"POST /api/orders?id=7 HTTP/1.1\" 504 167\n");
+        }
+        log.push_str("10.0.0.3 - - [10/Oct/2024:13:04:02 +0000] \"GET /missing HTTP/1.1\" 404 153\n");
+        log.push_str("10.0.0.3 - - [10/Oct/2024:13:01:02 +0000] \"GET /api/orders HTTP/1.1\" 502 157\n");
+
+        let body = digest(&log).unwrap().body;
+        assert!(body.starts_with("11 requests from 10/Oct/2024:13:00 to 10/Oct/2024:13:04\n  2xx: 5 (45.5%)\n"), "{}", body);
+        assert!(body.contains("  4xx: 1 (9.1%)\n  5xx: 5 (45.5%)\nError status codes:\n       4  504\n"));
+        assert!(body.contains("Paths answered with 502, 503 or 504:\n       4  504 /api/orders\n       1  502 /api/orders\n"));
+        assert!(body.ends_with("Minutes with the most 5xx responses (average 1.0 per minute): 10/Oct/2024:13:03 (4)\n"));
+        assert_eq!(digest("10.0.0.1 - - [10/Oct/2024:13:00:01 +0000] \"GET / HTTP/1.1\" 200 512\n"), None);
+    }
+
+    #[test]
+    fn test_errors() {
+        let log = "2024/10/10 13:55:36 [error] 31#31: *7 connect() failed (111: Connection refused) while connecting to upstream, client: 10.0.0.9, server: shop, request: \"GET /api HTTP/1.1\", upstream: \"http://10.0.0.5:8080/api\", host: \"shop\"
+2024/10/10 13:55:37 [error] 31#31: *9 connect() failed (111: Connection refused) while connecting to upstream, client: 10.0.0.7, server: shop, request: \"GET /cart HTTP/1.1\", upstream: \"http://10.0.0.5:8080/cart\", host: \"shop\"
+2024/10/10 13:56:01 [warn] 31#31: *12 an upstream response is buffered to a temporary file
+[Thu Oct 10 13:57:02.123456 2024] [proxy:error] [pid 123] (111)Connection refused: AH00957: http: attempt to connect to 10.0.0.6:8080 (backend) failed
+";
+        let body = digest(log).unwrap().body;
+        assert!(body.starts_with("Error log entries: 3 error, 1 warn\nMost frequent errors:\n"), "{}", body);
+        assert!(body.contains("       2  connect() failed (111: Connection refused) while connecting to upstream\n"));
+        assert!(body.contains("       1  (111)Connection refused: AH00957: http: attempt to connect to failed\n"));
+        assert!(body.contains("Upstreams in errors:\n       2  http://10.0.0.5:8080\n"));
+    }
+}
@@ -0,0 +1,194 @@
+//! Chat templates: how a system prompt and a conversation are laid out for a
+//! model. Instruction-tuned models only follow prompts in the format they were
+//! trained on, so the format is detected from the GGUF metadata or the model
+//! file name, or given with `--chat-format`.
+
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatFormat {
+    /// `<|system|>`/`<|user|>`/`<|assistant|>`, as used by TinyLlama and Zephyr.
+    Zephyr,
+    /// `[INST] ... [/INST]`, as used by Mistral, Mixtral and Llama 2.
+    Mistral,
+    /// `<|im_start|>role ... <|im_end|>`, as used by Qwen, OpenHermes and others.
+    ChatMl,
+    /// `<|start_header_id|>role<|end_header_id|>`, as used by Llama 3.
+    Llama3,
+}
+
+impl ChatFormat {
+    pub const ALL: [ChatFormat; 4] = [ChatFormat::Zephyr, ChatFormat::Mistral, ChatFormat::ChatMl, ChatFormat::Llama3];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ChatFormat::Zephyr => "zephyr",
+            ChatFormat::Mistral => "mistral",
+            ChatFormat::ChatMl => "chatml",
+            ChatFormat::Llama3 => "llama3",
+        }
+    }
+
+    /// The format of a model, from the Jinja `tokenizer.chat_template` in its
+    /// GGUF metadata if there is one, or else from its file name.
+    pub fn detect(chat_template: Option<&str>, model_file: &str) -> Self {
+        if let Some(template) = chat_template {
+            if template.contains("<|im_start|>") {
+                return ChatFormat::ChatMl;
+            }
+            if template.contains("<|start_header_id|>") {
+                return ChatFormat::Llama3;
+            }
+            if template.contains("[INST]") {
+                return ChatFormat::Mistral;
+            }
+            if template.contains("<|user|>") {
+                return ChatFormat::Zephyr;
+            }
+        }
+        let name = model_file.to_lowercase();
+        if ["llama-3", "llama3"].iter().any(|n| name.contains(n)) {
+            ChatFormat::Llama3
+        } else if ["mistral", "mixtral", "llama-2", "llama2", "codellama"].iter().any(|n| name.contains(n)) {
+            ChatFormat::Mistral
+        } else if ["qwen", "chatml", "hermes", "dolphin", "yi-"].iter().any(|n| name.contains(n)) {
+            ChatFormat::ChatMl
+        } else {
+            ChatFormat::Zephyr
+        }
+    }
+
+    /// A single-turn prompt.
+    pub fn chat(self, system: &str, user: &str) -> String {
+        self.conversation(system, &[user.to_string()])
+    }
+
+    /// A multi-turn prompt: `messages` alternate between the user and the
+    /// assistant, starting and ending with the user. The prompt ends where
+    /// the assistant's answer begins.
+    pub fn conversation(self, system: &str, messages: &[String]) -> String {
+        let mut prompt = String::new();
+        match self {
+            ChatFormat::Zephyr => {
+                prompt.push_str(&format!("<|system|>\n{}</s>\n", system));
+                for (i, message) in messages.iter().enumerate() {
+                    if i % 2 == 0 {
+                        prompt.push_str(&format!("<|user|>\n{}\n</s>\n", message));
+                    } else {
+                        prompt.push_str(&format!("<|assistant|>\n{}</s>\n", message));
+                    }
+                }
+                prompt.push_str("<|assistant|>\n");
+            }
+            ChatFormat::Mistral => {
+                // There is no system role; it goes into the first instruction.
+                // The tokenizer adds the leading <s>.
+                for (i, message) in messages.iter().enumerate() {
+                    if i == 0 {
+                        prompt.push_str(&format!("[INST] {}\n\n{} [/INST]", system, message));
+                    } else if i % 2 == 0 {
+                        prompt.push_str(&format!("[INST] {} [/INST]", message));
+                    } else {
+                        prompt.push_str(&format!(" {}</s>", message));
+                    }
+                }
+            }
+            ChatFormat::ChatMl => {
+                prompt.push_str(&format!("<|im_start|>system\n{}<|im_end|>\n", system));
+                for (i, message) in messages.iter().enumerate() {
+                    let role = if i % 2 == 0 { "user" } else { "assistant" };
+                    prompt.push_str(&format!("<|im_start|>{}\n{}<|im_end|>\n", role, message));
+                }
+                prompt.push_str("<|im_start|>assistant\n");
+            }
+            ChatFormat::Llama3 => {
+                let turn = |role: &str, text: &str| {
+                    format!("<|start_header_id|>{}<|end_header_id|>\n\n{}<|eot_id|>", role, text)
+                };
+                prompt.push_str(&turn("system", system));
+                for (i, message) in messages.iter().enumerate() {
+                    prompt.push_str(&turn(if i % 2 == 0 { "user" } else { "assistant" }, message));
+                }
+                prompt.push_str("<|start_header_id|>assistant<|end_header_id|>\n\n");
+            }
+        }
+        prompt
+    }
+
+    /// The token that ends the assistant's turn.
+    pub fn end_token(self) -> &'static str {
+        match self {
+            ChatFormat::Zephyr | ChatFormat::Mistral => "</s>",
+            ChatFormat::ChatMl => "<|im_end|>",
+            ChatFormat::Llama3 => "<|eot_id|>",
+        }
+    }
+
+    /// Generated text containing any of these means the model has finished
+    /// its answer and started another turn.
+    pub fn stop_markers(self) -> &'static [&'static str] {
+        match self {
+            ChatFormat::Zephyr => &["</s>", "<|user|>", "<|system|>"],
+            ChatFormat::Mistral => &["</s>", "[INST]"],
+            ChatFormat::ChatMl => &["<|im_end|>", "<|im_start|>"],
+            ChatFormat::Llama3 => &["<|eot_id|>", "<|start_header_id|>"],
+        }
+    }
+}
+
+impl FromStr for ChatFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_lowercase();
+        ChatFormat::ALL.into_iter().find(|format| format.name() == s).ok_or_else(|| {
+            let names: Vec<&str> = ChatFormat::ALL.iter().map(|format| format.name()).collect();
+            format!("unknown chat format `{}`; expected one of: {}", s, names.join(", "))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conversation() {
+        let messages = ["log", "analysis", "why?"].map(String::from);
+        assert_eq!(
+            ChatFormat::Zephyr.conversation("sys", &messages),
+            "<|system|>\nsys</s>\n<|user|>\nlog\n</s>\n<|assistant|>\nanalysis</s>\n\
+             <|user|>\nwhy?\n</s>\n<|assistant|>\n"
+        );
+        assert_eq!(
+            ChatFormat::Mistral.conversation("sys", &messages),
+            "[INST] sys\n\nlog [/INST] analysis</s>[INST] why? [/INST]"
+        );
+        assert_eq!(
+            ChatFormat::ChatMl.chat("sys", "log"),
+            "<|im_start|>system\nsys<|im_end|>\n<|im_start|>user\nlog<|im_end|>\n<|im_start|>assistant\n"
+        );
+        assert_eq!(
+            ChatFormat::Llama3.chat("sys", "log"),
+            "<|start_header_id|>system<|end_header_id|>\n\nsys<|eot_id|>\
+             <|start_header_id|>user<|end_header_id|>\n\nlog<|eot_id|>\
+             <|start_header_id|>assistant<|end_header_id|>\n\n"
+        );
+    }
+
+    #[test]
+    fn test_detect() {
+        let chatml = "{% for message in messages %}{{'<|im_start|>' + message['role'] }}{% endfor %}";
+        assert_eq!(ChatFormat::detect(Some(chatml), "model.gguf"), ChatFormat::ChatMl);
+        assert_eq!(
+            ChatFormat::detect(None, "mistral-7b-instruct-v0.2.Q4_K_M.gguf"),
+            ChatFormat::Mistral
+        );
+        assert_eq!(
+            ChatFormat::detect(None, "tinyllama-1.1b-chat-v1.0.Q4_K_M.gguf"),
+            ChatFormat::Zephyr
+        );
+        assert_eq!("ChatML".parse::<ChatFormat>(), Ok(ChatFormat::ChatMl));
+        assert!("alpaca".parse::<ChatFormat>().unwrap_err().contains("zephyr, mistral"));
+    }
+}
@@ -0,0 +1,69 @@
+//! Splits logs that are far larger than the context window into overlapping
+//! chunks for the map-reduce (`--deep`) analysis.
+
+use crate::truncate;
+
+/// Records repeated at the start of each chunk, so an error that straddles a
+/// chunk boundary is seen whole at least once.
+pub const OVERLAP_RECORDS: usize = 10;
+
+/// Splits records (see [`crate::preprocess::group_records`]) into chunks of
+/// at most `budget` (as measured by `measure`), each starting with the last
+/// `overlap` records of the previous one. Records are never split unless a
+/// single record is larger than the budget; it then becomes its own, trimmed
+/// chunk.
+pub fn split<S: AsRef<str>>(records: &[S], budget: usize, overlap: usize, measure: impl Fn(&str) -> usize) -> Vec<String> {
+    let records: Vec<&str> = records.iter().map(|record| record.as_ref()).collect();
+    // Measure each record once; +1 accounts for the joining newline
+    let costs: Vec<usize> = records.iter().map(|record| measure(record) + 1).collect();
+
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while start < records.len() {
+        let mut end = start;
+        let mut cost = 0;
+        while end < records.len() && cost + costs[end] <= budget {
+            cost += costs[end];
+            end += 1;
+        }
+        if end == start {
+            chunks.push(truncate::smart_truncate_by(records[start], budget, &measure));
+            start += 1;
+            continue;
+        }
+        chunks.push(records[start..end].join("\n"));
+        if end == records.len() {
+            break;
+        }
+        // Only overlap when the chunk still makes progress
+        start = if end - start > overlap { end - overlap } else { end };
+    }
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_with_overlap() {
+        let records: Vec<String> = (1..=10).map(|i| format!("line{}", i)).collect();
+        // Every record costs 2 (one "token" plus the newline)
+        let chunks = split(&records, 8, 1, |_| 1);
+        assert_eq!(
+            chunks,
+            vec![
+                "line1\nline2\nline3\nline4",
+                "line4\nline5\nline6\nline7",
+                "line7\nline8\nline9\nline10",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_oversized_line_is_trimmed() {
+        let records = ["short".to_string(), "x".repeat(50), "short".to_string()];
+        let chunks = split(&records, 10, 0, str::len);
+        assert_eq!(chunks, vec!["short".to_string(), "x".repeat(10), "short".to_string()]);
+    }
+}
@@ -0,0 +1,197 @@
+//! Constrained decoding: a character-level matcher for a JSON object of
+//! fixed shape. The generator masks every token whose text the matcher
+//! would reject, so the answer always parses.
+
+/// The value of one field of the object.
+#[derive(Debug, Clone, Copy)]
+pub enum Field {
+    /// A non-empty string of at most this many characters (escapes count as one).
+    Text(usize),
+    /// One of these strings, which must not contain `"` or `\`.
+    OneOf(&'static [&'static str]),
+}
+
+#[derive(Debug, Clone)]
+enum Segment {
+    Literal(Vec<char>),
+    Text(usize),
+    OneOf(&'static [&'static str]),
+}
+
+/// Where the matcher is: a segment, and how far into it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct State {
+    segment: usize,
+    pos: usize,
+    escape: bool,
+    /// For `OneOf`: bit i is set while value i still matches.
+    alive: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Matcher {
+    segments: Vec<Segment>,
+    state: State,
+}
+
+impl Matcher {
+    /// Matches `{"name": <value>, ...}` with the fields in the given order,
+    /// on a single line.
+    pub fn object(fields: &[(&str, Field)]) -> Self {
+        let mut segments = Vec::new();
+        for (i, (name, field)) in fields.iter().enumerate() {
+            let open = if i == 0 { "{" } else { ", " };
+            segments.push(Segment::Literal(format!("{}\"{}\": \"", open, name).chars().collect()));
+            segments.push(match field {
+                Field::Text(max) => Segment::Text(*max),
+                Field::OneOf(values) => Segment::OneOf(values),
+            });
+        }
+        segments.push(Segment::Literal(vec!['}']));
+        let mut matcher = Self { segments, state: State { segment: 0, pos: 0, escape: false, alive: 0 } };
+        matcher.state = matcher.enter(0);
+        matcher
+    }
+
+    /// Whether the whole object has been matched.
+    pub fn is_complete(&self) -> bool {
+        self.state.segment >= self.segments.len()
+    }
+
+    /// Whether `text` can come next.
+    pub fn accepts(&self, text: &str) -> bool {
+        !text.is_empty() && self.run(text).is_some()
+    }
+
+    /// Consumes `text`, which must have been accepted.
+    pub fn advance(&mut self, text: &str) {
+        if let Some(state) = self.run(text) {
+            self.state = state;
+        }
+    }
+
+    /// The shortest text that completes the object from here, for an answer
+    /// that was cut off: open strings are closed, later fields left empty
+    /// and choices take their first value.
+    pub fn close(&self) -> String {
+        let mut out = String::new();
+        let mut state = self.state;
+        while state.segment < self.segments.len() {
+            match &self.segments[state.segment] {
+                Segment::Literal(chars) => out.extend(&chars[state.pos..]),
+                Segment::Text(_) => {
+                    if state.escape {
+                        out.push('\\');
+                    }
+                    out.push('"');
+                }
+                Segment::OneOf(values) => {
+                    let value = (0..values.len())
+                        .find(|i| state.alive & (1 << i) != 0)
+                        .map_or(values[0], |i| values[i]);
+                    out.push_str(&value[state.pos..]);
+                    out.push('"');
+                }
+            }
+            state = self.enter(state.segment + 1);
+        }
+        out
+    }
+
+    fn enter(&self, segment: usize) -> State {
+        let alive = match self.segments.get(segment) {
+            Some(Segment::OneOf(values)) => (1u32 << values.len()) - 1,
+            _ => 0,
+        };
+        State { segment, pos: 0, escape: false, alive }
+    }
+
+    fn run(&self, text: &str) -> Option<State> {
+        let mut state = self.state;
+        for c in text.chars() {
+            state = self.step(state, c)?;
+        }
+        Some(state)
+    }
+
+    fn step(&self, state: State, c: char) -> Option<State> {
+        let State { segment, pos, escape, alive } = state;
+        match self.segments.get(segment)? {
+            Segment::Literal(chars) => {
+                if chars[pos] != c {
+                    return None;
+                }
+                if pos + 1 == chars.len() {
+                    Some(self.enter(segment + 1))
+                } else {
+                    Some(State { pos: pos + 1, ..state })
+                }
+            }
+            Segment::Text(max) => {
+                if escape {
+                    return "\"\\/bfnrt".contains(c).then_some(State { pos: pos + 1, escape: false, ..state });
+                }
+                match c {
+                    '"' if pos > 0 => Some(self.enter(segment + 1)),
+                    '"' => None,
+                    _ if pos >= *max || c.is_control() => None,
+                    '\\' => Some(State { escape: true, ..state }),
+                    _ => Some(State { pos: pos + 1, ..state }),
+                }
+            }
+            Segment::OneOf(values) => {
+                if c == '"' {
+                    let done = (0..values.len()).any(|i| alive & (1 << i) != 0 && values[i].len() == pos);
+                    return done.then(|| self.enter(segment + 1));
+                }
+                let alive = (0..values.len())
+                    .filter(|&i| alive & (1 << i) != 0 && values[i][pos..].starts_with(c))
+                    .fold(0, |mask, i| mask | (1 << i));
+                (alive != 0).then_some(State { pos: pos + c.len_utf8(), alive, ..state })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matcher() -> Matcher {
+        Matcher::object(&[("summary", Field::Text(20)), ("severity", Field::OneOf(&["ok", "error"]))])
+    }
+
+    #[test]
+    fn test_matcher() {
+        let mut m = matcher();
+        assert!(m.accepts("{\"sum"));
+        assert!(!m.accepts("Sure! {"));
+        assert!(!m.accepts(""));
+        m.advance("{\"summary\": \"");
+        assert!(!m.accepts("\""), "empty strings are rejected");
+        assert!(!m.accepts("a\nb"), "raw control characters are rejected");
+        assert!(!m.accepts("\\x"));
+        m.advance("Disk \\\"full\\\"");
+        m.advance("\", \"severity\": \"");
+        assert!(!m.accepts("warn"));
+        assert!(m.accepts("err"));
+        m.advance("error\"}");
+        assert!(m.is_complete());
+        assert!(!m.accepts(" "));
+    }
+
+    #[test]
+    fn test_close() {
+        let mut m = matcher();
+        assert_eq!(m.close(), "{\"summary\": \"\", \"severity\": \"ok\"}");
+        m.advance("{\"summary\": \"Out of mem");
+        let answer = format!("{{\"summary\": \"Out of mem{}", m.close());
+        let value: serde_json::Value = serde_json::from_str(&answer).unwrap();
+        assert_eq!(value["summary"], "Out of mem");
+        assert_eq!(value["severity"], "ok");
+
+        let mut m = matcher();
+        m.advance("{\"summary\": \"x\", \"severity\": \"e");
+        assert_eq!(m.close(), "rror\"}");
+    }
+}
@@ -0,0 +1,187 @@
+use anyhow::{Context, Result};
+use flate2::read::MultiGzDecoder;
+use regex::Regex;
+use std::io::Read;
+use std::sync::LazyLock;
+
+static GITHUB_RUN: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^https://github\.com/([\w.-]+/[\w.-]+)/actions/runs/(\d+)(?:/job/(\d+))?/?(?:[?#].*)?$").unwrap()
+});
+
+/// Returns true if the given input argument looks like an HTTP(S) URL rather
+/// than a local file path.
+pub fn is_url(input: &str) -> bool {
+    input.starts_with("http://") || input.starts_with("https://")
+}
+
+/// Downloads a log from a URL (CI artifact links, pastebin raw URLs, ...).
+pub async fn fetch_url(url: &str) -> Result<String> {
+    let response = reqwest::Client::builder()
+        .user_agent(concat!("logtrains/", env!("CARGO_PKG_VERSION")))
+        .build()?
+        .get(url)
+        .send()
+        .await
+        .with_context(|| format!("Failed to fetch URL: {}", url))?
+        .error_for_status()
+        .with_context(|| format!("Server returned an error for URL: {}", url))?;
+
+    response
+        .text()
+        .await
+        .with_context(|| format!("Failed to read response body from: {}", url))
+}
+
+/// A GitHub Actions run, or one job of it, as linked from its page.
+#[derive(Debug, PartialEq)]
+pub struct GithubRun {
+    /// `owner/repository`.
+    pub repo: String,
+    pub run: u64,
+    pub job: Option<u64>,
+}
+
+/// Parses `https://github.com/owner/repo/actions/runs/123` or
+/// `.../actions/runs/123/job/456`.
+pub fn github_run(url: &str) -> Option<GithubRun> {
+    let captures = GITHUB_RUN.captures(url)?;
+    Some(GithubRun {
+        repo: captures[1].to_string(),
+        run: captures[2].parse().ok()?,
+        job: captures.get(3).and_then(|job| job.as_str().parse().ok()),
+    })
+}
+
+/// Downloads the log of a GitHub Actions job through the API, or of every
+/// failed job of a run. The API serves logs only with a token, even for
+/// public repositories.
+pub async fn fetch_github_log(run: &GithubRun, token: &str) -> Result<String> {
+    let client = reqwest::Client::builder()
+        .user_agent(concat!("logtrains/", env!("CARGO_PKG_VERSION")))
+        .build()?;
+    let get = |url: String| {
+        let request = client
+            .get(url.clone())
+            .bearer_auth(token)
+            .header("Accept", "application/vnd.github+json")
+            .header("X-GitHub-Api-Version", "2022-11-28");
+        async move {
+            request
+                .send()
+                .await
+                .with_context(|| format!("Failed to fetch {}", url))?
+                .error_for_status()
+                .with_context(|| format!("GitHub returned an error for {}", url))?
+                .text()
+                .await
+                .with_context(|| format!("Failed to read response body from: {}", url))
+        }
+    };
+    let api = format!("https://api.github.com/repos/{}/actions", run.repo);
+    if let Some(job) = run.job {
+        return get(format!("{}/jobs/{}/logs", api, job)).await;
+    }
+
+    let jobs: serde_json::Value = serde_json::from_str(&get(format!("{}/runs/{}/jobs?per_page=100", api, run.run)).await?)
+        .context("GitHub returned an invalid list of jobs")?;
+    let failed: Vec<(u64, String)> = jobs["jobs"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|job| job["conclusion"] == "failure")
+        .filter_map(|job| Some((job["id"].as_u64()?, job["name"].as_str().unwrap_or("").to_string())))
+        .collect();
+    if failed.is_empty() {
+        anyhow::bail!("Run {} of {} has no failed jobs.", run.run, run.repo);
+    }
+    let mut log = String::new();
+    for (id, name) in failed {
+        log.push_str(&format!("Job: {}\n", name));
+        log.push_str(&get(format!("{}/jobs/{}/logs", api, id)).await?);
+        log.push('\n');
+    }
+    Ok(log)
+}
+
+/// Downloads an object from S3 (or any S3-compatible store the AWS CLI is
+/// configured for). Delegating to `aws s3 cp` means the standard credential
+/// chain (env vars, profiles, SSO, instance roles) just works.
+pub fn fetch_s3(uri: &str) -> Result<String> {
+    if !uri.starts_with("s3://") {
+        return Err(anyhow::anyhow!("Invalid S3 URI (expected s3://bucket/key): {}", uri));
+    }
+
+    let output = duct::cmd("aws", ["s3", "cp", uri, "-"])
+        .stdout_capture()
+        .stderr_capture()
+        .unchecked()
+        .run()
+        .context("Failed to run the AWS CLI. Is `aws` installed and on your PATH?")?;
+
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "Failed to download {}: {}",
+            uri,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    decode_log_bytes(output.stdout)
+}
+
+/// Turns raw downloaded bytes into text, transparently decompressing gzip
+/// (archived logs are almost always `.gz`).
+pub fn decode_log_bytes(bytes: Vec<u8>) -> Result<String> {
+    let bytes = if bytes.starts_with(&[0x1f, 0x8b]) {
+        let mut decompressed = Vec::new();
+        MultiGzDecoder::new(&bytes[..])
+            .read_to_end(&mut decompressed)
+            .context("Failed to decompress gzip data")?;
+        decompressed
+    } else {
+        bytes
+    };
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_url() {
+        assert!(is_url("https://ci.example.com/job/42/log.txt"));
+        assert!(is_url("http://paste.example.com/raw/abc"));
+        assert!(!is_url("/var/log/syslog"));
+        assert!(!is_url("logs/https.log"));
+    }
+
+    #[test]
+    fn test_decode_log_bytes_gzip() {
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+        use std::io::Write;
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"ERROR disk full\n").unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        assert_eq!(decode_log_bytes(compressed).unwrap(), "ERROR disk full\n");
+        assert_eq!(decode_log_bytes(b"plain".to_vec()).unwrap(), "plain");
+    }
+
+    #[test]
+    fn test_github_run() {
+        assert_eq!(
+            github_run("https://github.com/acme/shop/actions/runs/9012345678/job/24789012345"),
+            Some(GithubRun { repo: "acme/shop".to_string(), run: 9012345678, job: Some(24789012345) })
+        );
+        assert_eq!(github_run("https://github.com/acme/shop/actions/runs/42?pr=7").unwrap().job, None);
+        assert_eq!(github_run("https://github.com/acme/shop/pull/7"), None);
+    }
+
+    #[test]
+    fn test_fetch_s3_rejects_bad_uri() {
+        assert!(fetch_s3("https://bucket/key").is_err());
+    }
+}
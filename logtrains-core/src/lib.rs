@@ -0,0 +1,47 @@
+//! The log analysis pipeline behind the `logtrains` CLI, for embedding in
+//! other tools: clean a log, fit it into a local model's context, and stream
+//! an explanation of what went wrong.
+//!
+//! ```no_run
+//! use logtrains_core::{analyze, AnalysisRequest, Flow, ModelLoaderBuilder};
+//!
+//! # async fn run() -> anyhow::Result<()> {
+//! let mut engine = ModelLoaderBuilder::new(
+//!     "TheBloke/Mistral-7B-Instruct-v0.2-GGUF",
+//!     "mistral-7b-instruct-v0.2.Q4_K_M.gguf",
+//! )
+//! .load()
+//! .await?;
+//! let request = AnalysisRequest::new(std::fs::read_to_string("build.log")?);
+//! let result = analyze(&mut engine, &request, |token| {
+//!     print!("{}", token);
+//!     Ok(Flow::Continue)
+//! })?;
+//! println!("\nSeverity: {}", result.severity.label());
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The modules below are the building blocks the CLI combines with its own
+//! flags; [`analyze`] runs them in the default order.
+
+pub mod analysis;
+pub mod analyzers;
+pub mod chat_format;
+pub mod chunk;
+pub mod constrain;
+pub mod input;
+pub mod llm;
+pub mod model;
+pub mod preprocess;
+pub mod redact;
+pub mod report;
+pub mod rules;
+pub mod severity;
+pub mod template;
+pub mod timestamps;
+pub mod truncate;
+
+pub use analysis::{analyze, AnalysisRequest, AnalysisResult};
+pub use llm::{Flow, Inferencer, ModelLoaderBuilder, Prompt};
+pub use severity::Severity;
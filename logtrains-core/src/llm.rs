@@ -0,0 +1,1256 @@
+use anyhow::{Error as E, Result};
+use candle_core::quantized::gguf_file;
+use candle_core::utils::{cuda_is_available, metal_is_available};
+use candle_core::{Device, Tensor, D};
+use candle_transformers::generation::{LogitsProcessor, Sampling};
+use hf_hub::api::tokio::Api;
+use hf_hub::{Repo, RepoType};
+use std::collections::VecDeque;
+use tokenizers::Tokenizer;
+
+use crate::chat_format::ChatFormat;
+use crate::constrain::Matcher;
+use crate::model::ModelWeights;
+use crate::template::{self, Example, Vars};
+use crate::truncate;
+
+pub struct Inferencer {
+    model: ModelWeights,
+    tokenizer: Tokenizer,
+    device: Device,
+    /// Context window of the loaded model, in tokens.
+    context_size: usize,
+    /// Language answers are written in, when not English.
+    language: Option<String>,
+    /// How prompts are laid out for this model.
+    format: ChatFormat,
+    /// Maximum number of tokens generated for a full analysis or answer;
+    /// the log gets what is left of the context.
+    max_tokens: usize,
+    sampling: SamplingParams,
+    /// The text of every token, built on first use by constrained generation;
+    /// `None` for special tokens and pieces of a character.
+    token_texts: Option<Vec<Option<String>>>,
+    /// The tokens whose keys and values the model has cached. The next
+    /// prompt only runs from where it stops sharing them: usually the log,
+    /// after the same system prompt and examples.
+    cached: Vec<u32>,
+    /// Proposes tokens for the model to check, when configured.
+    draft: Option<Draft>,
+}
+
+/// How the next token is picked. Unset fields keep their defaults:
+/// temperature 0.7, top-p 0.9, no top-k, and a fixed seed.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SamplingParams {
+    /// 0 always picks the most likely token, which makes answers repeatable.
+    pub temperature: Option<f64>,
+    pub top_p: Option<f64>,
+    pub top_k: Option<usize>,
+    pub seed: Option<u64>,
+}
+
+impl SamplingParams {
+    fn validate(&self) -> Result<()> {
+        if self.temperature.is_some_and(|t| !(0.0..=5.0).contains(&t)) {
+            return Err(E::msg("--temperature must be between 0 and 5"));
+        }
+        if self.top_p.is_some_and(|p| !(p > 0.0 && p <= 1.0)) {
+            return Err(E::msg("--top-p must be greater than 0 and at most 1"));
+        }
+        if self.top_k == Some(0) {
+            return Err(E::msg("--top-k must be at least 1"));
+        }
+        Ok(())
+    }
+
+    fn logits_processor(&self) -> LogitsProcessor {
+        let temperature = self.temperature.unwrap_or(0.7);
+        let sampling = if temperature == 0.0 {
+            Sampling::ArgMax
+        } else {
+            match (self.top_k, self.top_p.unwrap_or(0.9)) {
+                (Some(k), p) if p < 1.0 => Sampling::TopKThenTopP { k, p, temperature },
+                (Some(k), _) => Sampling::TopK { k, temperature },
+                (None, p) if p < 1.0 => Sampling::TopP { p, temperature },
+                (None, _) => Sampling::All { temperature },
+            }
+        };
+        LogitsProcessor::from_sampling(self.seed.unwrap_or(299792458), sampling)
+    }
+}
+
+/// `(repository, file)` of the small, fast preset model (~600MB).
+pub const TINY_MODEL: (&str, &str) = ("TheBloke/TinyLlama-1.1B-Chat-v1.0-GGUF", "tinyllama-1.1b-chat-v1.0.Q4_K_M.gguf");
+
+/// `(repository, file)` of the default preset model (~4.1GB).
+pub const MEDIUM_MODEL: (&str, &str) = ("TheBloke/Mistral-7B-Instruct-v0.2-GGUF", "mistral-7b-instruct-v0.2.Q4_K_M.gguf");
+
+#[derive(Debug, Clone)]
+pub struct ModelLoaderBuilder {
+    repo_id: String,
+    model_file: String,
+    tokenizer_fallback_repo: Option<String>,
+    language: Option<String>,
+    chat_format: Option<ChatFormat>,
+    max_tokens: Option<usize>,
+    sampling: SamplingParams,
+    /// `(repository, file)` of the draft model for speculative decoding.
+    draft: Option<(String, String)>,
+    gpu_layers: Option<usize>,
+}
+
+impl ModelLoaderBuilder {
+    pub fn new(repo_id: &str, model_file: &str) -> Self {
+        Self {
+            repo_id: repo_id.to_string(),
+            model_file: model_file.to_string(),
+            tokenizer_fallback_repo: Some("TinyLlama/TinyLlama-1.1B-Chat-v1.0".to_string()),
+            language: None,
+            chat_format: None,
+            max_tokens: None,
+            sampling: SamplingParams::default(),
+            draft: None,
+            gpu_layers: None,
+        }
+    }
+
+    /// Language to answer in, as an ISO 639-1 code (`de`, `ja`) or a name.
+    pub fn language(mut self, language: Option<&str>) -> Self {
+        self.language = language.and_then(language_name);
+        self
+    }
+
+    /// The model's chat format; detected from the model when not given.
+    pub fn chat_format(mut self, format: Option<ChatFormat>) -> Self {
+        self.chat_format = format;
+        self
+    }
+
+    /// Maximum length of an analysis in tokens (512 by default).
+    pub fn max_tokens(mut self, max_tokens: Option<usize>) -> Self {
+        self.max_tokens = max_tokens;
+        self
+    }
+
+    pub fn sampling(mut self, sampling: SamplingParams) -> Self {
+        self.sampling = sampling;
+        self
+    }
+
+    /// A small model with the same vocabulary, e.g. TinyLlama for a Llama 2
+    /// model, that drafts tokens for the model to check several at a time
+    /// (speculative decoding). The answer stays the same; only faster.
+    pub fn draft_model(mut self, draft: Option<(String, String)>) -> Self {
+        self.draft = draft;
+        self
+    }
+
+    /// How many of the model's layers run on the GPU, like llama.cpp's
+    /// `-ngl`; the rest run on the CPU, for GPUs too small for the whole
+    /// model. All of them by default.
+    pub fn gpu_layers(mut self, gpu_layers: Option<usize>) -> Self {
+        self.gpu_layers = gpu_layers;
+        self
+    }
+
+    pub async fn load(self) -> Result<Inferencer> {
+        if self.max_tokens == Some(0) {
+            return Err(E::msg("--max-tokens must be at least 1"));
+        }
+        self.sampling.validate()?;
+        eprintln!("Locating model: {} ({})", self.repo_id, self.model_file);
+        let api = Api::new()?;
+        let repo = api.repo(Repo::new(self.repo_id.clone(), RepoType::Model));
+
+        let model_path = repo.get(&self.model_file).await?;
+
+        let tokenizer_path = match repo.get("tokenizer.json").await {
+            Ok(path) => path,
+            Err(_) => {
+                if let Some(fallback_repo_id) = self.tokenizer_fallback_repo {
+                    eprintln!("Tokenizer not found in GGUF repo, fetching from base repo: {}", fallback_repo_id);
+                    let base_api = Api::new()?;
+                    let base_repo = base_api.repo(Repo::new(fallback_repo_id, RepoType::Model));
+                    base_repo.get("tokenizer.json").await?
+                } else {
+                    return Err(E::msg("Tokenizer not found and no fallback was specified."));
+                }
+            }
+        };
+
+        let tokenizer = Tokenizer::from_file(tokenizer_path).map_err(E::msg)?;
+
+        let device = if cuda_is_available() {
+            match Device::new_cuda(0) {
+                Ok(device) => device,
+                Err(_) => {
+                    eprintln!("Warning: CUDA device not found or failed to initialize, falling back to CPU.");
+                    Device::Cpu
+                }
+            }
+        } else if metal_is_available() {
+            match Device::new_metal(0) {
+                Ok(device) => device,
+                Err(_) => {
+                    eprintln!("Warning: Metal device not found or failed to initialize, falling back to CPU.");
+                    Device::Cpu
+                }
+            }
+        } else {
+            Device::Cpu
+        };
+
+        eprintln!("Using device: {:?}", device);
+
+        let mut file = std::fs::File::open(&model_path)?;
+        let model_content = gguf_file::Content::read(&mut file)
+            .map_err(|e| E::msg(format!("Failed to read GGUF: {}", e)))?;
+        let context_size = model_content
+            .metadata
+            .get("llama.context_length")
+            .and_then(|v| v.to_u32().ok())
+            .map(|n| n as usize)
+            .unwrap_or(DEFAULT_CONTEXT_SIZE);
+        let format = self.chat_format.unwrap_or_else(|| {
+            let chat_template = model_content
+                .metadata
+                .get("tokenizer.chat_template")
+                .and_then(|v| v.to_string().ok());
+            ChatFormat::detect(chat_template.map(String::as_str), &self.model_file)
+        });
+        // Leave at least a quarter of the context for the prompt and the log
+        let max_tokens = self.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS);
+        let max_tokens = if max_tokens > context_size * 3 / 4 {
+            eprintln!(
+                "Warning: --max-tokens {} leaves no room for the log in a {}-token context; using {}.",
+                max_tokens,
+                context_size,
+                context_size * 3 / 4
+            );
+            context_size * 3 / 4
+        } else {
+            max_tokens
+        };
+        let vocabulary = vocabulary(&model_content);
+        let block_count = model_content
+            .metadata
+            .get("llama.block_count")
+            .and_then(|v| v.to_u32().ok())
+            .map_or(0, |n| n as usize);
+        let gpu_layers = match self.gpu_layers {
+            Some(_) if device.is_cpu() => {
+                eprintln!("Warning: --gpu-layers has no effect without a GPU.");
+                usize::MAX
+            }
+            Some(n) => {
+                eprintln!("Offloading {} of {} layers to the GPU", n.min(block_count), block_count);
+                n
+            }
+            None => usize::MAX,
+        };
+        let model = ModelWeights::from_gguf_offloaded(model_content, &mut file, &device, gpu_layers).map_err(|e| {
+            if device.is_cpu() || self.gpu_layers.is_some() {
+                E::from(e)
+            } else {
+                E::from(e).context(format!(
+                    "Failed to load the model onto {:?}; if it does not fit, pass --gpu-layers N to run only N of its {} layers there",
+                    device, block_count
+                ))
+            }
+        })?;
+
+        let draft = match &self.draft {
+            Some((draft_repo, draft_file)) => {
+                eprintln!("Locating draft model: {} ({})", draft_repo, draft_file);
+                let draft_path = api.repo(Repo::new(draft_repo.clone(), RepoType::Model)).get(draft_file).await?;
+                let mut file = std::fs::File::open(&draft_path)?;
+                let content = gguf_file::Content::read(&mut file)
+                    .map_err(|e| E::msg(format!("Failed to read the draft GGUF: {}", e)))?;
+                // Its tokens are checked by ID, so they must mean the same
+                if vocabulary.is_some() && vocabulary == self::vocabulary(&content) {
+                    let context_size = content
+                        .metadata
+                        .get("llama.context_length")
+                        .and_then(|v| v.to_u32().ok())
+                        .map_or(DEFAULT_CONTEXT_SIZE, |n| n as usize);
+                    // Left on the CPU when the GPU cannot even hold the model
+                    let draft_layers = if self.gpu_layers.is_some() { 0 } else { usize::MAX };
+                    let model = ModelWeights::from_gguf_offloaded(content, &mut file, &device, draft_layers)?;
+                    Some(Draft { model, context_size, cached: Vec::new() })
+                } else {
+                    eprintln!(
+                        "Warning: the draft model {} does not share the model's vocabulary; decoding without it.",
+                        draft_file
+                    );
+                    None
+                }
+            }
+            None => None,
+        };
+
+        Ok(Inferencer {
+            model,
+            tokenizer,
+            device,
+            context_size,
+            language: self.language,
+            format,
+            max_tokens,
+            sampling: self.sampling,
+            token_texts: None,
+            cached: Vec::new(),
+            draft,
+        })
+    }
+}
+
+/// The tokens of a GGUF model's vocabulary, in ID order.
+fn vocabulary(content: &gguf_file::Content) -> Option<Vec<String>> {
+    let tokens = content.metadata.get("tokenizer.ggml.tokens")?.to_vec().ok()?;
+    tokens.iter().map(|token| token.to_string().ok().cloned()).collect()
+}
+
+const SYSTEM_PROMPT: &str = "You are a {{ROLE}}. Your job is to explain errors concisely. \n\
+    Analyze the following log output. Provide a summary of the error and a suggested fix.\n\
+    Do NOT repeat the full log. Be brief. Use Markdown.";
+
+const BRIEF_SYSTEM_PROMPT: &str = "You are a CLI log analysis expert. \
+    Explain the following single error in at most three sentences: what went wrong and how to fix it. \
+    Do NOT repeat the log. Use Markdown.";
+
+const CHUNK_SYSTEM_PROMPT: &str = "You are a CLI log analysis expert. \
+    The following is one section of a much larger log. List the errors, warnings and notable events in it \
+    as at most five short bullet points, quoting key identifiers exactly. \
+    If nothing notable happens, answer only: nothing notable.";
+
+const LIVE_SYSTEM_PROMPT: &str = "You are a CLI log analysis expert watching the output of a command \
+    that is still running. In one or two sentences, name the errors and failures seen so far and where they are, \
+    e.g. \"3 test failures so far, all in the auth module\". If there are none, answer only: none.";
+
+const SYNTHESIS_SYSTEM_PROMPT: &str = "You are a {{ROLE}}. \
+    The following are notes taken from consecutive sections of one large log, in order. \
+    Identify the root cause of the failure, summarize it and suggest a fix. Be brief. Use Markdown.";
+
+const REPORT_SYSTEM_PROMPT: &str = "You are a {{ROLE}}. Analyze the following log output. \
+    Answer in exactly this format and nothing else:\n\
+    Summary: <one sentence describing what failed>\n\
+    Probable cause: <the most likely root cause>\n\
+    Suggested fix: <concrete steps or commands to fix it>";
+
+const REPORT_JSON_SYSTEM_PROMPT: &str = "You are a {{ROLE}}. Analyze the following log output. \
+    Answer with a single-line JSON object with these fields and nothing else: \
+    \"summary\" (one sentence describing what failed), \"probable_cause\" (the most likely root cause), \
+    \"suggested_fix\" (concrete steps or commands to fix it) and \"severity\" \
+    (\"ok\", \"warning\", \"error\" or \"fatal\").";
+
+const SEVERITY_SYSTEM_PROMPT: &str = "You classify how severe a failure is from its analysis. \
+    Answer with exactly one word: ok (nothing failed), warning (degraded but working), \
+    error (the task failed) or fatal (a crash, data loss or an outage).";
+
+const ASK_SYSTEM_PROMPT: &str = "You are a CLI log analysis expert. \
+    Answer the user's question about the following log output. Be brief, quote the relevant log lines \
+    where they help, and say so if the log does not contain the answer. Use Markdown.";
+
+const FOLLOW_UP_SYSTEM_PROMPT: &str = "You are a CLI log analysis expert. You have analyzed the log the user sent. \
+Answer their follow-up questions about it concisely, quoting the relevant log lines where they help.";
+
+/// Log tokens kept in a follow-up prompt before older turns are dropped.
+const MIN_FOLLOW_UP_LOG: usize = 256;
+
+/// Used when the GGUF file does not record the model's context length.
+const DEFAULT_CONTEXT_SIZE: usize = 2048;
+/// Maximum number of tokens generated for a full analysis, unless
+/// `--max-tokens` says otherwise.
+const DEFAULT_MAX_TOKENS: usize = 512;
+/// Maximum number of tokens generated for a brief, per-error analysis.
+const BRIEF_GEN_TOKENS: usize = 160;
+/// Maximum number of tokens generated for an interim `--live` note.
+const LIVE_GEN_TOKENS: usize = 96;
+/// Maximum number of tokens generated when summarizing one chunk of a log.
+const CHUNK_GEN_TOKENS: usize = 200;
+
+/// What a streaming callback wants the generator to do after a token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    Continue,
+    Stop,
+}
+
+impl Inferencer {
+    /// Explains `log_text` with `prompt` (or the default prompt), filled in
+    /// with `vars`.
+    pub fn explain<F: FnMut(String) -> Result<Flow>>(
+        &mut self,
+        log_text: &str,
+        prompt: Option<&Prompt>,
+        vars: &Vars,
+        callback: F,
+    ) -> Result<()> {
+        let prompt = self.render(prompt.unwrap_or(&default_prompt()), log_text, vars);
+        self.generate(&prompt, self.max_tokens, callback)
+    }
+
+    /// Like [`Inferencer::explain`], but only tokens that `matcher` accepts can
+    /// be generated, so the answer has the matcher's JSON shape. An answer cut
+    /// off by `--max-tokens` is closed with empty fields.
+    pub fn explain_constrained<F: FnMut(String) -> Result<Flow>>(
+        &mut self,
+        log_text: &str,
+        prompt: &Prompt,
+        vars: &Vars,
+        matcher: Matcher,
+        mut callback: F,
+    ) -> Result<()> {
+        let prompt = self.render(prompt, log_text, vars);
+        self.build_token_texts()?;
+        let mut generation = self.start(&prompt, self.max_tokens)?;
+        generation.constraint = Some(matcher);
+        while let Some(token) = generation.next_token()? {
+            if callback(token)? == Flow::Stop {
+                return Ok(());
+            }
+        }
+        if let Some(matcher) = generation.constraint.filter(|matcher| !matcher.is_complete()) {
+            callback(matcher.close())?;
+        }
+        Ok(())
+    }
+
+    /// Short analysis of a single error, used when a log is split into
+    /// several independent failures.
+    pub fn explain_brief<F: FnMut(String) -> Result<Flow>>(
+        &mut self,
+        error_text: &str,
+        callback: F,
+    ) -> Result<()> {
+        let overhead = self.count_tokens(&self.format.chat(BRIEF_SYSTEM_PROMPT, &self.with_language("")));
+        let budget = self.context_size.saturating_sub(BRIEF_GEN_TOKENS + overhead);
+        let error_text = truncate::smart_truncate_by(error_text, budget, |s| self.count_tokens(s));
+        let error_text = self.with_language(&error_text);
+        self.generate(&self.format.chat(BRIEF_SYSTEM_PROMPT, &error_text), BRIEF_GEN_TOKENS, callback)
+    }
+
+    /// Answers `question` about a log instead of explaining it. The log must
+    /// fit [`Inferencer::ask_budget`].
+    pub fn ask<F: FnMut(String) -> Result<Flow>>(&mut self, log_text: &str, question: &str, callback: F) -> Result<()> {
+        let prompt = self.format.chat(ASK_SYSTEM_PROMPT, &self.with_language(&ask_message(log_text, question)));
+        self.generate(&prompt, self.max_tokens, callback)
+    }
+
+    /// Answers a follow-up question about an analyzed log. `turns` holds the
+    /// earlier questions and answers; the oldest are dropped, then the log is
+    /// truncated, until the conversation fits the context.
+    pub fn follow_up<F: FnMut(String) -> Result<Flow>>(
+        &mut self,
+        log_text: &str,
+        analysis: &str,
+        turns: &[(String, String)],
+        question: &str,
+        callback: F,
+    ) -> Result<()> {
+        let question = self.with_language(question);
+        let messages = |log: &str, turns: &[(String, String)]| {
+            let mut messages = vec![log.to_string(), analysis.to_string()];
+            for (q, a) in turns {
+                messages.push(q.clone());
+                messages.push(a.clone());
+            }
+            messages.push(question.clone());
+            messages
+        };
+
+        let mut turns = turns;
+        let budget = loop {
+            let overhead = self.count_tokens(&self.format.conversation(FOLLOW_UP_SYSTEM_PROMPT, &messages("", turns)));
+            let budget = self.context_size.saturating_sub(self.max_tokens + overhead);
+            if budget >= MIN_FOLLOW_UP_LOG || turns.is_empty() {
+                break budget;
+            }
+            turns = &turns[1..];
+        };
+        let log_text = truncate::smart_truncate_by(log_text, budget, |s| self.count_tokens(s));
+        let prompt = self.format.conversation(FOLLOW_UP_SYSTEM_PROMPT, &messages(&log_text, turns));
+        self.generate(&prompt, self.max_tokens, callback)
+    }
+
+    /// A short note on the problems in the output of a command that is still
+    /// running (`--live`): `earlier` holds the previous notes, `output` what
+    /// the command printed since.
+    pub fn live_note<F: FnMut(String) -> Result<Flow>>(&mut self, earlier: &str, output: &str, callback: F) -> Result<()> {
+        let message = |output: &str| format!("Findings so far: {}\n\nNew output:\n{}", earlier, output);
+        let overhead = self.count_tokens(&self.format.chat(LIVE_SYSTEM_PROMPT, &self.with_language(&message(""))));
+        let budget = self.context_size.saturating_sub(LIVE_GEN_TOKENS + overhead);
+        let output = truncate::smart_truncate_by(output, budget, |s| self.count_tokens(s));
+        let message = self.with_language(&message(&output));
+        self.generate(&self.format.chat(LIVE_SYSTEM_PROMPT, &message), LIVE_GEN_TOKENS, callback)
+    }
+
+    /// Summarizes one chunk of a huge log into short notes (the map step of
+    /// `--deep`).
+    pub fn summarize_chunk<F: FnMut(String) -> Result<Flow>>(&mut self, chunk: &str, callback: F) -> Result<()> {
+        self.generate(&self.format.chat(CHUNK_SYSTEM_PROMPT, chunk), CHUNK_GEN_TOKENS, callback)
+    }
+
+    /// Combines the notes of all chunks into the final answer (the reduce
+    /// step of `--deep`). A custom prompt template receives the notes as
+    /// `{{LOG_TEXT}}`.
+    pub fn synthesize<F: FnMut(String) -> Result<Flow>>(
+        &mut self,
+        notes: &str,
+        prompt: Option<&Prompt>,
+        vars: &Vars,
+        callback: F,
+    ) -> Result<()> {
+        let prompt = self.render(prompt.unwrap_or(&Prompt::System(SYNTHESIS_SYSTEM_PROMPT.to_string())), notes, vars);
+        self.generate(&prompt, self.max_tokens, callback)
+    }
+
+    /// How many tokens of log text fit into [`Inferencer::ask`] with `question`
+    /// and the `reserved` text.
+    pub fn ask_budget(&self, question: &str, reserved: &str) -> usize {
+        let empty = self.format.chat(ASK_SYSTEM_PROMPT, &self.with_language(&ask_message("", question)));
+        self.budget(&empty, reserved, self.max_tokens)
+    }
+
+    /// How many tokens of log text fit into a single chunk summary.
+    pub fn chunk_budget(&self) -> usize {
+        self.budget(&self.format.chat(CHUNK_SYSTEM_PROMPT, ""), "", CHUNK_GEN_TOKENS)
+    }
+
+    /// How many tokens of notes fit into the final `--deep` synthesis.
+    pub fn synthesis_budget(&self, prompt: Option<&Prompt>, vars: &Vars, reserved: &str) -> usize {
+        let empty = self.render(prompt.unwrap_or(&Prompt::System(SYNTHESIS_SYSTEM_PROMPT.to_string())), "", vars);
+        self.budget(&empty, reserved, self.max_tokens)
+    }
+
+    /// Asks which of `labels` best describes the severity of `analysis`, with
+    /// the answer constrained to the labels: a single forward pass, then the
+    /// label whose first token is most likely wins. Returns its index.
+    pub fn classify_severity(&mut self, analysis: &str, labels: &[&str]) -> Result<usize> {
+        let overhead = self.count_tokens(&self.format.chat(SEVERITY_SYSTEM_PROMPT, ""));
+        let budget = self.context_size.saturating_sub(overhead + 1);
+        let analysis = truncate::smart_truncate_by(analysis, budget, |s| self.count_tokens(s));
+        let prompt = self.format.chat(SEVERITY_SYSTEM_PROMPT, &analysis);
+
+        let tokens = self.tokenizer.encode(prompt, true).map_err(E::msg)?;
+        let start_pos = self.reuse_cache(tokens.get_ids())?;
+        let logits = self.forward(tokens.get_ids(), start_pos)?.squeeze(0)?;
+        let logits = if logits.rank() == 2 {
+            logits.get(logits.dim(0)? - 1)?
+        } else {
+            logits
+        };
+        let logits: Vec<f32> = logits.to_dtype(candle_core::DType::F32)?.to_vec1()?;
+
+        let mut best = (0, f32::NEG_INFINITY);
+        for (i, label) in labels.iter().enumerate() {
+            let encoding = self.tokenizer.encode(*label, false).map_err(E::msg)?;
+            let Some(&id) = encoding.get_ids().first() else {
+                continue;
+            };
+            let score = logits.get(id as usize).copied().unwrap_or(f32::NEG_INFINITY);
+            if score > best.1 {
+                best = (i, score);
+            }
+        }
+        Ok(best.0)
+    }
+
+    /// Appends the answer-language instruction to the user's part of a
+    /// prompt. It goes last so small models still see it after a long log.
+    fn with_language(&self, text: &str) -> String {
+        with_language(text, self.language.as_deref())
+    }
+
+    /// Another session on the same weights with an empty cache, for running
+    /// prompts on several threads. Each session keeps its own cache.
+    pub fn fork(&self) -> Result<Self> {
+        let mut model = self.model.clone();
+        model.truncate_cache(0)?;
+        Ok(Self {
+            model,
+            tokenizer: self.tokenizer.clone(),
+            device: self.device.clone(),
+            context_size: self.context_size,
+            language: self.language.clone(),
+            format: self.format,
+            max_tokens: self.max_tokens,
+            sampling: self.sampling,
+            token_texts: self.token_texts.clone(),
+            cached: Vec::new(),
+            draft: match &self.draft {
+                Some(draft) => Some(draft.fork()?),
+                None => None,
+            },
+        })
+    }
+
+    /// Number of tokens `text` encodes to with the model's tokenizer.
+    pub fn count_tokens(&self, text: &str) -> usize {
+        self.tokenizer
+            .encode(text, false)
+            .map(|encoding| encoding.len())
+            .unwrap_or(text.len())
+    }
+
+    /// How many tokens of log text fit into a full analysis once the prompt
+    /// (with its examples), the `reserved` text (e.g. gathered context) and
+    /// the generated answer are accounted for.
+    pub fn input_budget(&self, prompt: Option<&Prompt>, vars: &Vars, reserved: &str) -> usize {
+        let empty = self.render(prompt.unwrap_or(&default_prompt()), "", vars);
+        self.budget(&empty, reserved, self.max_tokens)
+    }
+
+    /// Keeps the leading `examples` that fit into a quarter of the context,
+    /// so few-shot examples never crowd out the log. Returns how many were
+    /// dropped.
+    pub fn fit_examples(&self, examples: &mut Vec<Example>) -> usize {
+        let mut used = 0;
+        let keep = examples
+            .iter()
+            .take_while(|example| {
+                used += self.count_tokens(&example.log) + self.count_tokens(&example.explanation);
+                used <= self.context_size / 4
+            })
+            .count();
+        let dropped = examples.len() - keep;
+        examples.truncate(keep);
+        dropped
+    }
+
+    fn render(&self, prompt: &Prompt, log_text: &str, vars: &Vars) -> String {
+        render_prompt(self.format, prompt, log_text, vars, |text| self.with_language(text))
+    }
+
+    fn budget(&self, empty_prompt: &str, reserved: &str, max_new_tokens: usize) -> usize {
+        let overhead = self.count_tokens(empty_prompt) + self.count_tokens(reserved);
+        self.context_size.saturating_sub(max_new_tokens + overhead)
+    }
+
+    fn generate<F: FnMut(String) -> Result<Flow>>(
+        &mut self,
+        prompt: &str,
+        max_new_tokens: usize,
+        mut callback: F,
+    ) -> Result<()> {
+        let mut generation = self.start(prompt, max_new_tokens)?;
+        while let Some(token) = generation.next_token()? {
+            if callback(token)? == Flow::Stop {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Keeps the cached keys and values of the leading tokens `tokens` shares
+    /// with the cache and returns how many there are, i.e. where to start
+    /// running `tokens`.
+    fn reuse_cache(&mut self, tokens: &[u32]) -> Result<usize> {
+        let shared = shared_prefix(&self.cached, tokens);
+        if shared > 0 {
+            self.model.truncate_cache(shared)?;
+        }
+        Ok(shared)
+    }
+
+    /// Runs `tokens[start_pos..]` through the model after the `start_pos`
+    /// cached ones and returns the logits for the next token.
+    fn forward(&mut self, tokens: &[u32], start_pos: usize) -> Result<Tensor> {
+        let input = Tensor::new(&tokens[start_pos..], &self.device)?.unsqueeze(0)?;
+        // A failed run leaves the cache in an unknown state
+        self.cached.clear();
+        let logits = self.model.forward(&input, start_pos)?;
+        self.cached.extend_from_slice(tokens);
+        Ok(logits)
+    }
+
+    /// Like [`Inferencer::forward`], but returns the logits after each of
+    /// `tokens[start_pos..]`.
+    fn forward_all(&mut self, tokens: &[u32], start_pos: usize) -> Result<Tensor> {
+        let input = Tensor::new(&tokens[start_pos..], &self.device)?.unsqueeze(0)?;
+        self.cached.clear();
+        let logits = self.model.forward_all(&input, start_pos)?;
+        self.cached.extend_from_slice(tokens);
+        Ok(logits)
+    }
+
+    /// Forgets all but the first `len` cached tokens.
+    fn rewind(&mut self, len: usize) -> Result<()> {
+        self.model.truncate_cache(len)?;
+        self.cached.truncate(len);
+        Ok(())
+    }
+
+    /// Decodes every token after a plain prefix token, which keeps the
+    /// leading space that decoding a token on its own would drop.
+    fn build_token_texts(&mut self) -> Result<()> {
+        if self.token_texts.is_some() {
+            return Ok(());
+        }
+        let special = self.tokenizer.get_added_tokens_decoder();
+        let prefix = self.tokenizer.encode("a", false).map_err(E::msg)?.get_ids().last().copied();
+        let prefix = prefix.ok_or_else(|| E::msg("The tokenizer cannot encode text"))?;
+        let prefix_text = self.tokenizer.decode(&[prefix], false).map_err(E::msg)?;
+        let texts = (0..self.tokenizer.get_vocab_size(true) as u32)
+            .map(|id| {
+                if special.contains_key(&id) {
+                    return None;
+                }
+                let text = self.tokenizer.decode(&[prefix, id], false).ok()?;
+                let text = text.strip_prefix(&prefix_text)?;
+                (!text.is_empty() && !text.contains('\u{FFFD}')).then(|| text.to_string())
+            })
+            .collect();
+        self.token_texts = Some(texts);
+        Ok(())
+    }
+
+    fn start(&mut self, prompt: &str, max_new_tokens: usize) -> Result<Generation<'_>> {
+        let all_tokens = self.tokenizer.encode(prompt, true).map_err(E::msg)?.get_ids().to_vec();
+        // Inputs are budgeted before the prompt is built; only shorten the
+        // answer if the prompt still came out slightly too long.
+        let max_new_tokens = max_new_tokens.min(self.context_size.saturating_sub(all_tokens.len()));
+        if max_new_tokens == 0 {
+            return Err(E::msg(format!(
+                "Prompt is {} tokens, which does not fit the model's context of {} tokens.",
+                all_tokens.len(),
+                self.context_size
+            )));
+        }
+
+        let eos_token_id = self
+            .tokenizer
+            .token_to_id(self.format.end_token())
+            .or_else(|| self.tokenizer.token_to_id("</s>"))
+            .unwrap_or(2);
+        let logits_processor = self.sampling.logits_processor();
+
+        Ok(Generation {
+            inferencer: self,
+            all_tokens,
+            logits_processor,
+            eos_token_id,
+            generated: 0,
+            max_new_tokens,
+            finished: false,
+            constraint: None,
+            stream: TokenOutputStream::default(),
+            pending: VecDeque::new(),
+        })
+    }
+}
+
+/// Tokens the draft model proposes per step of speculative decoding.
+const DRAFT_TOKENS: usize = 4;
+
+/// The small model of speculative decoding, with its own cache.
+struct Draft {
+    model: ModelWeights,
+    context_size: usize,
+    /// The tokens whose keys and values the draft has cached.
+    cached: Vec<u32>,
+}
+
+impl Draft {
+    fn fork(&self) -> Result<Self> {
+        let mut model = self.model.clone();
+        model.truncate_cache(0)?;
+        Ok(Self { model, context_size: self.context_size, cached: Vec::new() })
+    }
+
+    /// The `n` tokens the draft finds most likely after `tokens`, one after
+    /// another; fewer near the end of its context.
+    fn propose(&mut self, tokens: &[u32], n: usize, device: &Device) -> Result<Vec<u32>> {
+        let n = n.min(self.context_size.saturating_sub(tokens.len()));
+        let mut proposed = Vec::with_capacity(n);
+        if n == 0 {
+            return Ok(proposed);
+        }
+        let mut start_pos = shared_prefix(&self.cached, tokens);
+        self.model.truncate_cache(start_pos)?;
+        self.cached.clear();
+        let mut input = &tokens[start_pos..];
+        let mut context = tokens.to_vec();
+        while proposed.len() < n {
+            let logits = self.model.forward(&Tensor::new(input, device)?.unsqueeze(0)?, start_pos)?;
+            let token = logits.squeeze(0)?.argmax(D::Minus1)?.to_scalar::<u32>()?;
+            start_pos = context.len();
+            context.push(token);
+            proposed.push(token);
+            input = &context[start_pos..];
+        }
+        // The last proposal was never run
+        context.pop();
+        self.cached = context;
+        Ok(proposed)
+    }
+}
+
+/// An in-progress generation, advanced one token at a time by whoever
+/// consumes the output.
+struct Generation<'a> {
+    inferencer: &'a mut Inferencer,
+    all_tokens: Vec<u32>,
+    logits_processor: LogitsProcessor,
+    eos_token_id: u32,
+    generated: usize,
+    max_new_tokens: usize,
+    finished: bool,
+    /// Restricts the output to what the matcher accepts.
+    constraint: Option<Matcher>,
+    stream: TokenOutputStream,
+    /// Tokens picked but not returned yet, when a step picks several.
+    pending: VecDeque<u32>,
+}
+
+impl Generation<'_> {
+    /// Returns the next piece of text, or `None` once generation is done.
+    fn next_token(&mut self) -> Result<Option<String>> {
+        while !self.finished {
+            let Some(next_token) = self.pending.pop_front() else {
+                if self.generated >= self.max_new_tokens {
+                    break;
+                }
+                if self.constraint.as_ref().is_some_and(Matcher::is_complete) {
+                    self.finished = true;
+                    break;
+                }
+                let picked = self.step()?;
+                self.pending.extend(picked);
+                continue;
+            };
+            if next_token == self.eos_token_id {
+                self.finished = true;
+                break;
+            }
+            self.all_tokens.push(next_token);
+
+            if let Some(matcher) = &mut self.constraint {
+                let texts = self.inferencer.token_texts.as_deref().unwrap_or_default();
+                let text = texts.get(next_token as usize).cloned().flatten().unwrap_or_default();
+                matcher.advance(&text);
+                return Ok(Some(text));
+            }
+            if let Some(t) = self.inferencer.tokenizer.id_to_token(next_token) {
+                if self.inferencer.format.stop_markers().iter().any(|marker| t.contains(marker)) {
+                    self.finished = true;
+                    break;
+                }
+            }
+            if let Some(text) = self.stream.next_token(&self.inferencer.tokenizer, next_token)? {
+                return Ok(Some(text));
+            }
+        }
+        self.stream.rest(&self.inferencer.tokenizer)
+    }
+
+    /// Runs the model and picks the next token, or with a draft model up to
+    /// `DRAFT_TOKENS + 1` of them.
+    fn step(&mut self) -> Result<Vec<u32>> {
+        // The prompt itself always runs alone
+        if self.generated == 0 {
+            let start_pos = self.inferencer.reuse_cache(&self.all_tokens)?;
+            let logits = self.inferencer.forward(&self.all_tokens, start_pos)?;
+            return Ok(vec![self.pick(&logits.squeeze(0)?)?]);
+        }
+        let start_pos = self.all_tokens.len() - 1;
+        let remaining = self.max_new_tokens - self.generated;
+        let device = self.inferencer.device.clone();
+        let proposed = match &mut self.inferencer.draft {
+            Some(draft) if self.constraint.is_none() && remaining > 1 => {
+                draft.propose(&self.all_tokens, DRAFT_TOKENS.min(remaining - 1), &device)?
+            }
+            _ => Vec::new(),
+        };
+        if proposed.is_empty() {
+            let logits = self.inferencer.forward(&self.all_tokens, start_pos)?;
+            return Ok(vec![self.pick(&logits.squeeze(0)?)?]);
+        }
+
+        // Each position picks as it would have one token at a time; the
+        // first pick that differs from the draft ends the step
+        let mut tokens = self.all_tokens.clone();
+        tokens.extend(&proposed);
+        let logits = self.inferencer.forward_all(&tokens, start_pos)?.squeeze(0)?;
+        let mut picked = Vec::new();
+        for (i, &draft_token) in proposed.iter().enumerate() {
+            let token = self.pick(&logits.get(i)?)?;
+            picked.push(token);
+            if token != draft_token || token == self.eos_token_id {
+                break;
+            }
+        }
+        if picked == proposed && picked.last() != Some(&self.eos_token_id) {
+            picked.push(self.pick(&logits.get(proposed.len())?)?);
+        }
+        // The cache keeps the accepted draft tokens; the last pick runs next
+        self.inferencer.rewind(self.all_tokens.len() + picked.len() - 1)?;
+        Ok(picked)
+    }
+
+    /// Samples the next token from `logits`, within the constraint if any.
+    fn pick(&mut self, logits: &Tensor) -> Result<u32> {
+        let logits = if logits.rank() == 2 {
+            logits.get(logits.dim(0)? - 1)?
+        } else {
+            logits.clone()
+        };
+        let next_token = match &self.constraint {
+            Some(matcher) => {
+                let texts = self.inferencer.token_texts.as_deref().unwrap_or_default();
+                let mut logits: Vec<f32> = logits.to_dtype(candle_core::DType::F32)?.to_vec1()?;
+                for (id, logit) in logits.iter_mut().enumerate() {
+                    if !texts.get(id).and_then(Option::as_deref).is_some_and(|text| matcher.accepts(text)) {
+                        *logit = f32::NEG_INFINITY;
+                    }
+                }
+                if logits.iter().all(|logit| *logit == f32::NEG_INFINITY) {
+                    return Err(E::msg("No token of the model's vocabulary continues the constrained answer"));
+                }
+                self.logits_processor.sample(&Tensor::new(logits, &Device::Cpu)?)?
+            }
+            None => self.logits_processor.sample(&logits)?,
+        };
+        self.generated += 1;
+        Ok(next_token)
+    }
+}
+
+/// Turns generated token IDs into text as they arrive. A token decoded on its
+/// own loses the leading space SentencePiece marks with `▁`, and characters
+/// spread over byte tokens (`<0xE2><0x80><0x99>`) come out as `�`. So the
+/// tokens since the last emitted text are decoded together with the token
+/// before them, and only the new text is emitted, once it is complete.
+#[derive(Default)]
+struct TokenOutputStream {
+    tokens: Vec<u32>,
+    /// Start of the tokens decoded for context.
+    prev_index: usize,
+    /// Start of the tokens whose text is not emitted yet.
+    current_index: usize,
+}
+
+impl TokenOutputStream {
+    /// The text `token` completes, if any.
+    fn next_token(&mut self, tokenizer: &Tokenizer, token: u32) -> Result<Option<String>> {
+        let before = self.decode(tokenizer, self.current_index)?;
+        self.tokens.push(token);
+        let text = self.decode(tokenizer, self.tokens.len())?;
+        if text.ends_with('\u{FFFD}') {
+            return Ok(None);
+        }
+        match text.strip_prefix(before.as_str()) {
+            Some(new) if !new.is_empty() => {
+                let new = new.to_string();
+                self.prev_index = self.current_index;
+                self.current_index = self.tokens.len();
+                Ok(Some(new))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Text still held back, e.g. an unfinished character when generation
+    /// stopped. Returns it once.
+    fn rest(&mut self, tokenizer: &Tokenizer) -> Result<Option<String>> {
+        let before = self.decode(tokenizer, self.current_index)?;
+        let text = self.decode(tokenizer, self.tokens.len())?;
+        self.prev_index = self.tokens.len();
+        self.current_index = self.tokens.len();
+        Ok(text.get(before.len()..).filter(|rest| !rest.is_empty()).map(str::to_string))
+    }
+
+    fn decode(&self, tokenizer: &Tokenizer, end: usize) -> Result<String> {
+        tokenizer.decode(&self.tokens[self.prev_index..end], true).map_err(E::msg)
+    }
+}
+
+/// How many leading tokens `tokens` shares with `cached`, leaving at least
+/// its last token to run for the next token's logits.
+fn shared_prefix(cached: &[u32], tokens: &[u32]) -> usize {
+    let shared = cached.iter().zip(tokens).take_while(|(a, b)| a == b).count();
+    shared.min(tokens.len().saturating_sub(1))
+}
+
+/// Languages accepted by their ISO 639-1 code; anything else is used as a
+/// language name as given.
+const LANGUAGES: &[(&str, &str)] = &[
+    ("de", "German"),
+    ("es", "Spanish"),
+    ("fr", "French"),
+    ("it", "Italian"),
+    ("pt", "Portuguese"),
+    ("nl", "Dutch"),
+    ("pl", "Polish"),
+    ("sv", "Swedish"),
+    ("cs", "Czech"),
+    ("tr", "Turkish"),
+    ("ru", "Russian"),
+    ("uk", "Ukrainian"),
+    ("ja", "Japanese"),
+    ("zh", "Chinese"),
+    ("ko", "Korean"),
+    ("hi", "Hindi"),
+];
+
+/// The language name for `--lang`, or `None` for English (the prompts'
+/// own language, which needs no instruction).
+fn language_name(code: &str) -> Option<String> {
+    let code = code.trim();
+    let name = LANGUAGES
+        .iter()
+        .find(|(c, _)| c.eq_ignore_ascii_case(code))
+        .map(|(_, name)| name.to_string())
+        .unwrap_or_else(|| code.to_string());
+    if name.is_empty() || name.eq_ignore_ascii_case("en") || name.eq_ignore_ascii_case("english") {
+        None
+    } else {
+        Some(name)
+    }
+}
+
+fn with_language(text: &str, language: Option<&str>) -> String {
+    match language {
+        Some(language) => format!("{}\n\n{}", text, language_instruction(language)),
+        None => text.to_string(),
+    }
+}
+
+fn language_instruction(language: &str) -> String {
+    format!(
+        "Write your answer in {}. Keep commands, code, identifiers, error messages and any required labels unchanged.",
+        language
+    )
+}
+
+/// How the prompt of an analysis is built.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Prompt {
+    /// A system prompt. The chat layout is added around it, with the few-shot
+    /// examples as earlier turns of the conversation.
+    System(String),
+    /// A complete prompt template (see [`crate::template`]); examples go
+    /// where it says `{{EXAMPLES}}`.
+    Template(String),
+}
+
+impl Prompt {
+    /// The text whose placeholders are filled in.
+    pub fn text(&self) -> &str {
+        match self {
+            Prompt::System(text) | Prompt::Template(text) => text,
+        }
+    }
+}
+
+/// The prompt used when no other is given.
+pub fn default_prompt() -> Prompt {
+    Prompt::System(SYSTEM_PROMPT.to_string())
+}
+
+/// The prompt for `--format json`, whose answer is parsed into fields.
+pub fn report_prompt() -> Prompt {
+    Prompt::System(REPORT_SYSTEM_PROMPT.to_string())
+}
+
+/// The prompt for `--format json --constrained`, which asks for the fields
+/// of [`crate::report::schema`].
+pub fn report_json_prompt() -> Prompt {
+    Prompt::System(REPORT_JSON_SYSTEM_PROMPT.to_string())
+}
+
+/// The prompt an analysis of `log_text` is given, built without loading a
+/// model (for `logtrains prompts test`). `language` is as for `--lang`.
+pub fn preview(format: ChatFormat, prompt: Option<&Prompt>, log_text: &str, vars: &Vars, language: Option<&str>) -> String {
+    let language = language.and_then(language_name);
+    let default = default_prompt();
+    render_prompt(format, prompt.unwrap_or(&default), log_text, vars, |text| with_language(text, language.as_deref()))
+}
+
+/// Builds the prompt text from `prompt`, `log_text` and `vars`. A question
+/// the prompt has no placeholder for follows the log; `finish` appends to
+/// the log text last (e.g. the answer language).
+fn render_prompt(
+    format: ChatFormat,
+    prompt: &Prompt,
+    log_text: &str,
+    vars: &Vars,
+    finish: impl Fn(&str) -> String,
+) -> String {
+    let mut text = log_text.to_string();
+    if let Some(question) = vars.question().filter(|_| !template::uses(prompt.text(), "QUESTION")) {
+        text.push_str(&format!("\n\nIn your analysis, address this request from the user: {}", question));
+    }
+    let text = finish(&text);
+    match prompt {
+        Prompt::System(system) => {
+            let mut messages: Vec<String> = vars
+                .examples
+                .iter()
+                .flat_map(|example| [example.log.clone(), example.explanation.clone()])
+                .collect();
+            messages.push(text);
+            format.conversation(&template::render(system.trim(), "", vars), &messages)
+        }
+        Prompt::Template(template) => template::render(template, &text, vars),
+    }
+}
+
+/// The log, then the question, so a small model reads the question last.
+fn ask_message(log_text: &str, question: &str) -> String {
+    format!("{}\n\nQuestion: {}", log_text, question.trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_output_stream() {
+        // A SentencePiece vocabulary with byte fallback, decoded like Llama's
+        let tokenizer: Tokenizer = r#"{
+            "version": "1.0", "truncation": null, "padding": null, "added_tokens": [],
+            "normalizer": null, "pre_tokenizer": null, "post_processor": null,
+            "decoder": {"type": "Sequence", "decoders": [
+                {"type": "Replace", "pattern": {"String": "▁"}, "content": " "},
+                {"type": "ByteFallback"}, {"type": "Fuse"},
+                {"type": "Strip", "content": " ", "start": 1, "stop": 0}
+            ]},
+            "model": {"type": "BPE", "dropout": null, "unk_token": "<unk>", "continuing_subword_prefix": null,
+                "end_of_word_suffix": null, "fuse_unk": true, "byte_fallback": true, "merges": [],
+                "vocab": {"<unk>": 0, "▁It": 1, "▁failed": 2, "<0xE2>": 3, "<0x80>": 4, "<0x99>": 5,
+                    "s": 6, "<0x0A>": 7, "▁ok": 8}}
+        }"#
+        .parse()
+        .unwrap();
+        let mut stream = TokenOutputStream::default();
+        let pieces: Vec<Option<String>> = [1, 2, 3, 4, 5, 6, 7, 8]
+            .into_iter()
+            .map(|token| stream.next_token(&tokenizer, token).unwrap())
+            .collect();
+        let text = |s: &str| Some(s.to_string());
+        // The apostrophe is held back until its last byte arrives
+        assert_eq!(pieces, [text("It"), text(" failed"), None, None, text("’"), text("s"), text("\n"), text(" ok")]);
+        assert_eq!(stream.rest(&tokenizer).unwrap(), None);
+
+        // An unfinished character still comes out at the end, once
+        let mut stream = TokenOutputStream::default();
+        assert_eq!(stream.next_token(&tokenizer, 3).unwrap(), None);
+        assert_eq!(stream.rest(&tokenizer).unwrap(), text("\u{FFFD}"));
+        assert_eq!(stream.rest(&tokenizer).unwrap(), None);
+    }
+
+    /// The tiny random model with a tokenizer for its 24 tokens.
+    fn tiny_inferencer(draft: Option<ModelWeights>) -> Inferencer {
+        let vocab: Vec<String> = (0..24).map(|id| format!("\"▁t{}\": {}", id, id)).collect();
+        let tokenizer: Tokenizer = format!(
+            r#"{{"version": "1.0", "truncation": null, "padding": null, "added_tokens": [],
+                "normalizer": null, "pre_tokenizer": null, "post_processor": null, "decoder": null,
+                "model": {{"type": "BPE", "dropout": null, "unk_token": null, "continuing_subword_prefix": null,
+                    "end_of_word_suffix": null, "fuse_unk": false, "byte_fallback": false, "merges": [],
+                    "vocab": {{{}}}}}}}"#,
+            vocab.join(", ")
+        )
+        .parse()
+        .unwrap();
+        Inferencer {
+            model: crate::model::tiny_model(),
+            tokenizer,
+            device: Device::Cpu,
+            context_size: 64,
+            language: None,
+            format: ChatFormat::Zephyr,
+            max_tokens: 16,
+            sampling: SamplingParams::default(),
+            token_texts: None,
+            cached: Vec::new(),
+            draft: draft.map(|model| Draft { model, context_size: 64, cached: Vec::new() }),
+        }
+    }
+
+    fn generate_ids(inferencer: &mut Inferencer, sampling: SamplingParams, prompt: &[u32], n: usize) -> Vec<u32> {
+        let mut generation = Generation {
+            inferencer,
+            all_tokens: prompt.to_vec(),
+            logits_processor: sampling.logits_processor(),
+            eos_token_id: u32::MAX,
+            generated: 0,
+            max_new_tokens: n,
+            finished: false,
+            constraint: None,
+            stream: TokenOutputStream::default(),
+            pending: VecDeque::new(),
+        };
+        while generation.next_token().unwrap().is_some() {}
+        generation.all_tokens.split_off(prompt.len())
+    }
+
+    #[test]
+    fn test_speculative_decoding() {
+        let mut plain = tiny_inferencer(None);
+        let same_draft = plain.model.clone();
+        let mut drafted = Inferencer { draft: Some(Draft { model: same_draft, context_size: 64, cached: Vec::new() }), ..plain.fork().unwrap() };
+        let mut unrelated = Inferencer { model: plain.model.clone(), ..tiny_inferencer(Some(crate::model::tiny_model())) };
+
+        for sampling in [
+            SamplingParams { temperature: Some(0.0), ..Default::default() },
+            SamplingParams { temperature: Some(1.0), top_p: Some(1.0), seed: Some(7), ..Default::default() },
+        ] {
+            let expected = generate_ids(&mut plain, sampling, &[1, 7, 3], 20);
+            assert_eq!(expected.len(), 20);
+            // A draft that is always right, and one that mostly is not,
+            // change nothing about the answer
+            assert_eq!(generate_ids(&mut drafted, sampling, &[1, 7, 3], 20), expected);
+            assert_eq!(generate_ids(&mut unrelated, sampling, &[1, 7, 3], 20), expected);
+            // Continuing from the cache of the earlier run works too
+            assert_eq!(generate_ids(&mut unrelated, sampling, &[1, 7, 3], 20), expected);
+        }
+    }
+
+    #[test]
+    fn test_shared_prefix() {
+        assert_eq!(shared_prefix(&[], &[1, 5, 6]), 0);
+        assert_eq!(shared_prefix(&[1, 5, 6, 7, 9], &[1, 5, 6, 8]), 3);
+        // The same prompt again still runs its last token
+        assert_eq!(shared_prefix(&[1, 5, 6], &[1, 5, 6]), 2);
+        assert_eq!(shared_prefix(&[1, 5, 6, 7], &[1, 5]), 1);
+    }
+
+    #[test]
+    fn test_sampling_params_validate() {
+        assert!(SamplingParams::default().validate().is_ok());
+        let deterministic = SamplingParams { temperature: Some(0.0), seed: Some(7), ..Default::default() };
+        assert!(deterministic.validate().is_ok());
+        assert!(SamplingParams { top_p: Some(1.5), ..Default::default() }.validate().is_err());
+        assert!(SamplingParams { top_k: Some(0), ..Default::default() }.validate().is_err());
+        assert!(SamplingParams { temperature: Some(-1.0), ..Default::default() }.validate().is_err());
+    }
+
+    #[test]
+    fn test_render_prompt() {
+        let plain = |text: &str| text.to_string();
+        let mut vars = Vars::local(None);
+        vars.question = Some(" why? ".to_string());
+        let template = Prompt::Template("Log: {{LOG_TEXT}} Q: {{QUESTION}}".to_string());
+        assert_eq!(render_prompt(ChatFormat::Zephyr, &template, "boom", &vars, plain), "Log: boom Q: why?");
+
+        vars.question = Some("only TLS".to_string());
+        vars.examples = vec![Example { log: "disk full".to_string(), explanation: "Next steps: df -h".to_string() }];
+        assert_eq!(
+            render_prompt(ChatFormat::Zephyr, &Prompt::System("You are a {{ROLE}}.".to_string()), "boom", &vars, plain),
+            "<|system|>\nYou are a CLI log analysis expert.</s>\n<|user|>\ndisk full\n</s>\n\
+             <|assistant|>\nNext steps: df -h</s>\n<|user|>\nboom\n\n\
+             In your analysis, address this request from the user: only TLS\n</s>\n<|assistant|>\n"
+        );
+    }
+
+    #[test]
+    fn test_language_name() {
+        assert_eq!(language_name("de").as_deref(), Some("German"));
+        assert_eq!(language_name("JA").as_deref(), Some("Japanese"));
+        assert_eq!(language_name("Brazilian Portuguese").as_deref(), Some("Brazilian Portuguese"));
+        assert_eq!(language_name("en"), None);
+        assert_eq!(language_name(" "), None);
+    }
+}
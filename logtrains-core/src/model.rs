@@ -0,0 +1,478 @@
+//! The quantized llama model, adapted from `candle-transformers` 0.8
+//! (`models::quantized_llama`, MIT/Apache-2.0) so the KV cache can be kept
+//! across prompts: a prompt that shares a token prefix with the cached one
+//! is truncated to that prefix and only the rest is run through the model.
+//! Upstream masks attention as if every multi-token input started at
+//! position 0, which rules that out.
+
+use candle_core::quantized::{gguf_file, QMatMul, QTensor};
+use candle_core::{DType, Device, IndexOp, Module, Result, Tensor};
+use candle_nn::Embedding;
+use candle_transformers::quantized_nn::RmsNorm;
+use candle_transformers::utils::repeat_kv;
+
+/// Positions with rotary embeddings when the GGUF does not give a context length.
+const DEFAULT_MAX_SEQ_LEN: usize = 4096;
+
+#[derive(Debug, Clone)]
+struct Mlp {
+    feed_forward_w1: QMatMul,
+    feed_forward_w2: QMatMul,
+    feed_forward_w3: QMatMul,
+}
+
+impl Module for Mlp {
+    fn forward(&self, xs: &Tensor) -> Result<Tensor> {
+        let w1 = self.feed_forward_w1.forward(xs)?;
+        let w3 = self.feed_forward_w3.forward(xs)?;
+        self.feed_forward_w2.forward(&(candle_nn::ops::silu(&w1)? * w3)?)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum MlpOrMoe {
+    Mlp(Mlp),
+    MoE {
+        n_expert_used: usize,
+        feed_forward_gate_inp: QMatMul,
+        experts: Vec<Mlp>,
+    },
+}
+
+impl Module for MlpOrMoe {
+    fn forward(&self, xs: &Tensor) -> Result<Tensor> {
+        match self {
+            Self::MoE { feed_forward_gate_inp, experts, n_expert_used } => {
+                let (b_size, seq_len, hidden_dim) = xs.dims3()?;
+                let xs = xs.reshape(((), hidden_dim))?;
+                let router_logits = feed_forward_gate_inp.forward(&xs)?;
+                let routing_weights = candle_nn::ops::softmax_last_dim(&router_logits)?;
+                let routing_weights = routing_weights.to_dtype(DType::F32)?.to_vec2::<f32>()?;
+
+                // The rows each expert evaluates, and their normalized weights
+                let mut top_x = vec![vec![]; experts.len()];
+                let mut selected_rws = vec![vec![]; experts.len()];
+                for (row_idx, rw) in routing_weights.iter().enumerate() {
+                    let mut dst = (0..rw.len() as u32).collect::<Vec<u32>>();
+                    dst.sort_by(|&i, &j| rw[j as usize].total_cmp(&rw[i as usize]));
+                    let sum_routing_weights: f32 = dst.iter().take(*n_expert_used).map(|&i| rw[i as usize]).sum();
+                    for &expert_idx in dst.iter().take(*n_expert_used) {
+                        let expert_idx = expert_idx as usize;
+                        top_x[expert_idx].push(row_idx as u32);
+                        selected_rws[expert_idx].push(rw[expert_idx] / sum_routing_weights);
+                    }
+                }
+
+                let mut ys = xs.zeros_like()?;
+                for (expert_idx, expert_layer) in experts.iter().enumerate() {
+                    let top_x = &top_x[expert_idx];
+                    if top_x.is_empty() {
+                        continue;
+                    }
+                    let top_x = Tensor::new(top_x.as_slice(), xs.device())?;
+                    let selected_rws = Tensor::new(selected_rws[expert_idx].as_slice(), xs.device())?.reshape(((), 1))?;
+                    let current_state = xs.index_select(&top_x, 0)?.reshape(((), hidden_dim))?;
+                    let current_hidden_states = expert_layer.forward(&current_state)?.broadcast_mul(&selected_rws)?;
+                    ys = ys.index_add(&top_x, &current_hidden_states, 0)?;
+                }
+                ys.reshape((b_size, seq_len, hidden_dim))
+            }
+            Self::Mlp(mlp) => mlp.forward(xs),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct LayerWeights {
+    attention_wq: QMatMul,
+    attention_wk: QMatMul,
+    attention_wv: QMatMul,
+    attention_wo: QMatMul,
+    attention_norm: RmsNorm,
+    mlp_or_moe: MlpOrMoe,
+    ffn_norm: RmsNorm,
+    n_head: usize,
+    n_kv_head: usize,
+    head_dim: usize,
+    cos: Tensor,
+    sin: Tensor,
+    neg_inf: Tensor,
+    kv_cache: Option<(Tensor, Tensor)>,
+    /// Where the layer's weights live; its input is moved here.
+    device: Device,
+}
+
+fn masked_fill(on_false: &Tensor, mask: &Tensor, on_true: &Tensor) -> Result<Tensor> {
+    let shape = mask.shape();
+    mask.where_cond(&on_true.broadcast_as(shape.dims())?, on_false)
+}
+
+impl LayerWeights {
+    fn apply_rotary_emb(&self, x: &Tensor, index_pos: usize) -> Result<Tensor> {
+        let (_b_sz, _n_head, seq_len, _n_embd) = x.dims4()?;
+        let cos = self.cos.narrow(0, index_pos, seq_len)?;
+        let sin = self.sin.narrow(0, index_pos, seq_len)?;
+        candle_nn::rotary_emb::rope_i(&x.contiguous()?, &cos, &sin)
+    }
+
+    fn forward_attn(&mut self, x: &Tensor, mask: Option<&Tensor>, index_pos: usize) -> Result<Tensor> {
+        let (b_sz, seq_len, n_embd) = x.dims3()?;
+        let q = self.attention_wq.forward(x)?;
+        let k = self.attention_wk.forward(x)?;
+        let v = self.attention_wv.forward(x)?;
+
+        let q = q.reshape((b_sz, seq_len, self.n_head, self.head_dim))?.transpose(1, 2)?;
+        let k = k.reshape((b_sz, seq_len, self.n_kv_head, self.head_dim))?.transpose(1, 2)?;
+        // Contiguous so the fast kernels apply below; a no-op for single tokens
+        let v = v
+            .reshape((b_sz, seq_len, self.n_kv_head, self.head_dim))?
+            .transpose(1, 2)?
+            .contiguous()?;
+
+        let q = self.apply_rotary_emb(&q, index_pos)?;
+        let k = self.apply_rotary_emb(&k, index_pos)?;
+
+        let (k, v) = match &self.kv_cache {
+            Some((k_cache, v_cache)) if index_pos > 0 => {
+                (Tensor::cat(&[k_cache, &k], 2)?, Tensor::cat(&[v_cache, &v], 2)?)
+            }
+            _ => (k, v),
+        };
+        self.kv_cache = Some((k.clone(), v.clone()));
+
+        let y = if q.device().is_metal() && seq_len == 1 {
+            // SDPA does MQA itself
+            candle_nn::ops::sdpa(&q, &k, &v, 1. / (self.head_dim as f32).sqrt(), 1.)?
+        } else {
+            let k = repeat_kv(k, self.n_head / self.n_kv_head)?;
+            let v = repeat_kv(v, self.n_head / self.n_kv_head)?;
+            let att = (q.matmul(&k.t()?)? / (self.head_dim as f64).sqrt())?;
+            let att = match mask {
+                None => att,
+                Some(mask) => masked_fill(&att, &mask.broadcast_as(att.shape())?, &self.neg_inf)?,
+            };
+            let att = candle_nn::ops::softmax_last_dim(&att)?;
+            att.matmul(&v.contiguous()?)?
+        };
+
+        let y = y.transpose(1, 2)?.reshape(&[b_sz, seq_len, n_embd])?;
+        self.attention_wo.forward(&y)
+    }
+
+    /// Keeps the first `len` positions of the KV cache.
+    fn truncate_cache(&mut self, len: usize) -> Result<()> {
+        self.kv_cache = match self.kv_cache.take() {
+            Some((k, v)) if len > 0 && len <= k.dim(2)? => Some((k.narrow(2, 0, len)?, v.narrow(2, 0, len)?)),
+            _ => None,
+        };
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ModelWeights {
+    tok_embeddings: Embedding,
+    layers: Vec<LayerWeights>,
+    norm: RmsNorm,
+    output: QMatMul,
+}
+
+/// Masks what each of `t` new tokens must not see: the new tokens after it.
+/// All `offset` cached tokens come before and stay visible.
+fn causal_mask(t: usize, offset: usize, device: &Device) -> Result<Tensor> {
+    let mask: Vec<u8> = (0..t)
+        .flat_map(|i| (0..offset + t).map(move |j| u8::from(j > i + offset)))
+        .collect();
+    Tensor::from_slice(&mask, (t, offset + t), device)
+}
+
+fn precompute_freqs_cis(head_dim: usize, freq_base: f32, max_seq_len: usize, device: &Device) -> Result<(Tensor, Tensor)> {
+    let theta: Vec<_> = (0..head_dim)
+        .step_by(2)
+        .map(|i| 1f32 / freq_base.powf(i as f32 / head_dim as f32))
+        .collect();
+    let theta = Tensor::new(theta.as_slice(), device)?;
+    let idx_theta = Tensor::arange(0, max_seq_len as u32, device)?
+        .to_dtype(DType::F32)?
+        .reshape((max_seq_len, 1))?
+        .matmul(&theta.reshape((1, theta.elem_count()))?)?;
+    Ok((idx_theta.cos()?, idx_theta.sin()?))
+}
+
+impl ModelWeights {
+    pub fn from_gguf<R: std::io::Seek + std::io::Read>(ct: gguf_file::Content, reader: &mut R, device: &Device) -> Result<Self> {
+        Self::from_gguf_offloaded(ct, reader, device, usize::MAX)
+    }
+
+    /// Loads the last `gpu_layers` layers and the output onto `device` and
+    /// the rest onto the CPU, for GPUs without room for the whole model.
+    pub fn from_gguf_offloaded<R: std::io::Seek + std::io::Read>(
+        ct: gguf_file::Content,
+        reader: &mut R,
+        device: &Device,
+        gpu_layers: usize,
+    ) -> Result<Self> {
+        let md_get = |s: &str| match ct.metadata.get(s) {
+            None => candle_core::bail!("cannot find {s} in metadata"),
+            Some(v) => Ok(v),
+        };
+
+        let n_expert = md_get("llama.expert_count").and_then(|v| v.to_u32()).unwrap_or(0) as usize;
+        let n_expert_used = md_get("llama.expert_used_count").and_then(|v| v.to_u32()).unwrap_or(0) as usize;
+        let head_count = md_get("llama.attention.head_count")?.to_u32()? as usize;
+        let head_count_kv = md_get("llama.attention.head_count_kv")?.to_u32()? as usize;
+        let block_count = md_get("llama.block_count")?.to_u32()? as usize;
+        let embedding_length = md_get("llama.embedding_length")?.to_u32()? as usize;
+        let rope_dim = md_get("llama.rope.dimension_count")?.to_u32()? as usize;
+        let rms_norm_eps = md_get("llama.attention.layer_norm_rms_epsilon")?.to_f32()? as f64;
+        let rope_freq_base = md_get("llama.rope.freq_base").and_then(|m| m.to_f32()).unwrap_or(10000f32);
+        // Upstream stops at 4096 positions, short of what Mistral allows
+        let max_seq_len = md_get("llama.context_length")
+            .and_then(|m| m.to_u32())
+            .map_or(DEFAULT_MAX_SEQ_LEN, |n| n as usize);
+
+        let first_offloaded = block_count.saturating_sub(gpu_layers);
+        let layer_device = |layer_idx: usize| if layer_idx >= first_offloaded { device } else { &Device::Cpu };
+        let input_device = layer_device(0);
+        let output_device = layer_device(block_count.saturating_sub(1));
+
+        let (cos, sin) = precompute_freqs_cis(rope_dim, rope_freq_base, max_seq_len, &Device::Cpu)?;
+        let neg_inf = Tensor::new(f32::NEG_INFINITY, &Device::Cpu)?;
+
+        let tok_embeddings = ct.tensor(reader, "token_embd.weight", input_device)?.dequantize(input_device)?;
+        let norm = RmsNorm::from_qtensor(ct.tensor(reader, "output_norm.weight", output_device)?, rms_norm_eps)?;
+        let output = match ct.tensor(reader, "output.weight", output_device) {
+            Ok(tensor) => tensor,
+            Err(_) => ct.tensor(reader, "token_embd.weight", output_device)?,
+        };
+        let mut layers = Vec::with_capacity(block_count);
+        for layer_idx in 0..block_count {
+            let device = layer_device(layer_idx);
+            let mut tensor = |name: String| -> Result<QTensor> { ct.tensor(reader, &name, device) };
+            let prefix = format!("blk.{layer_idx}");
+            let attention_wq = tensor(format!("{prefix}.attn_q.weight"))?;
+            let attention_wk = tensor(format!("{prefix}.attn_k.weight"))?;
+            let attention_wv = tensor(format!("{prefix}.attn_v.weight"))?;
+            let attention_wo = tensor(format!("{prefix}.attn_output.weight"))?;
+            let mlp_or_moe = if n_expert <= 1 {
+                MlpOrMoe::Mlp(Mlp {
+                    feed_forward_w1: QMatMul::from_qtensor(tensor(format!("{prefix}.ffn_gate.weight"))?)?,
+                    feed_forward_w2: QMatMul::from_qtensor(tensor(format!("{prefix}.ffn_down.weight"))?)?,
+                    feed_forward_w3: QMatMul::from_qtensor(tensor(format!("{prefix}.ffn_up.weight"))?)?,
+                })
+            } else {
+                let feed_forward_gate_inp = tensor(format!("{prefix}.ffn_gate_inp.weight"))?;
+                let mut experts = Vec::with_capacity(n_expert);
+                for i in 0..n_expert {
+                    experts.push(Mlp {
+                        feed_forward_w1: QMatMul::from_qtensor(tensor(format!("{prefix}.ffn_gate.{i}.weight"))?)?,
+                        feed_forward_w2: QMatMul::from_qtensor(tensor(format!("{prefix}.ffn_down.{i}.weight"))?)?,
+                        feed_forward_w3: QMatMul::from_qtensor(tensor(format!("{prefix}.ffn_up.{i}.weight"))?)?,
+                    })
+                }
+                MlpOrMoe::MoE {
+                    n_expert_used,
+                    feed_forward_gate_inp: QMatMul::from_qtensor(feed_forward_gate_inp)?,
+                    experts,
+                }
+            };
+            let attention_norm = tensor(format!("{prefix}.attn_norm.weight"))?;
+            let ffn_norm = tensor(format!("{prefix}.ffn_norm.weight"))?;
+            layers.push(LayerWeights {
+                attention_wq: QMatMul::from_qtensor(attention_wq)?,
+                attention_wk: QMatMul::from_qtensor(attention_wk)?,
+                attention_wv: QMatMul::from_qtensor(attention_wv)?,
+                attention_wo: QMatMul::from_qtensor(attention_wo)?,
+                attention_norm: RmsNorm::from_qtensor(attention_norm, rms_norm_eps)?,
+                mlp_or_moe,
+                ffn_norm: RmsNorm::from_qtensor(ffn_norm, rms_norm_eps)?,
+                n_head: head_count,
+                n_kv_head: head_count_kv,
+                head_dim: embedding_length / head_count,
+                cos: cos.to_device(device)?,
+                sin: sin.to_device(device)?,
+                neg_inf: neg_inf.to_device(device)?,
+                kv_cache: None,
+                device: device.clone(),
+            })
+        }
+        Ok(Self {
+            tok_embeddings: Embedding::new(tok_embeddings, embedding_length),
+            layers,
+            norm,
+            output: QMatMul::from_qtensor(output)?,
+        })
+    }
+
+    /// Runs `x` at positions `index_pos..` after the first `index_pos`
+    /// cached positions, and returns the logits of the last one. Position 0
+    /// starts over.
+    pub fn forward(&mut self, x: &Tensor, index_pos: usize) -> Result<Tensor> {
+        let (_b_sz, seq_len) = x.dims2()?;
+        let x = self.hidden_states(x, index_pos)?;
+        self.output.forward(&x.i((.., seq_len - 1, ..))?)
+    }
+
+    /// Like [`ModelWeights::forward`], but returns the logits of every
+    /// position of `x`, shaped `(batch, seq_len, vocab)`.
+    pub fn forward_all(&mut self, x: &Tensor, index_pos: usize) -> Result<Tensor> {
+        let x = self.hidden_states(x, index_pos)?;
+        self.output.forward(&x)
+    }
+
+    fn hidden_states(&mut self, x: &Tensor, index_pos: usize) -> Result<Tensor> {
+        let (_b_sz, seq_len) = x.dims2()?;
+        let mask = if seq_len == 1 {
+            None
+        } else {
+            Some(causal_mask(seq_len, index_pos, &Device::Cpu)?)
+        };
+        let mut layer_in = self.tok_embeddings.forward(&x.to_device(self.tok_embeddings.embeddings().device())?)?;
+        for layer in self.layers.iter_mut() {
+            // Moves nothing unless the previous layer is on another device
+            let x = layer_in.to_device(&layer.device)?;
+            let mask = mask.as_ref().map(|mask| mask.to_device(&layer.device)).transpose()?;
+            let residual = &x;
+            let x = layer.attention_norm.forward(&x)?;
+            let attn = layer.forward_attn(&x, mask.as_ref(), index_pos)?;
+            let x = (attn + residual)?;
+
+            let residual = &x;
+            let x = layer.ffn_norm.forward(&x)?;
+            let x = layer.mlp_or_moe.forward(&x)?;
+            layer_in = (x + residual)?;
+        }
+        self.norm.forward(&layer_in)
+    }
+
+    /// Drops all but the first `len` positions of the KV cache, so the next
+    /// `forward` can continue at `index_pos = len`.
+    pub fn truncate_cache(&mut self, len: usize) -> Result<()> {
+        for layer in &mut self.layers {
+            layer.truncate_cache(len)?;
+        }
+        Ok(())
+    }
+}
+
+/// A random two-layer model with a vocabulary of 24 tokens, written to GGUF
+/// and read back.
+#[cfg(test)]
+pub(crate) fn tiny_model() -> ModelWeights {
+    let mut file = tiny_gguf();
+    let content = gguf_file::Content::read(&mut file).unwrap();
+    ModelWeights::from_gguf(content, &mut file, &Device::Cpu).unwrap()
+}
+
+/// The GGUF file of a randomly initialized two-layer model.
+#[cfg(test)]
+fn tiny_gguf() -> std::io::Cursor<Vec<u8>> {
+    use candle_core::quantized::GgmlDType;
+    use gguf_file::Value;
+
+    let (dim, hidden, vocab, heads, kv_heads) = (16, 32, 24, 4, 2);
+    let head_dim = dim / heads;
+    let random = |shape: (usize, usize)| {
+        QTensor::quantize(&Tensor::randn(0f32, 0.5, shape, &Device::Cpu).unwrap(), GgmlDType::F32).unwrap()
+    };
+    let ones = QTensor::quantize(&Tensor::ones(dim, DType::F32, &Device::Cpu).unwrap(), GgmlDType::F32).unwrap();
+    let mut tensors = vec![
+        ("token_embd.weight".to_string(), random((vocab, dim))),
+        ("output.weight".to_string(), random((vocab, dim))),
+    ];
+    for layer in 0..2 {
+        let name = |part: &str| format!("blk.{layer}.{part}.weight");
+        tensors.push((name("attn_q"), random((dim, dim))));
+        tensors.push((name("attn_k"), random((kv_heads * head_dim, dim))));
+        tensors.push((name("attn_v"), random((kv_heads * head_dim, dim))));
+        tensors.push((name("attn_output"), random((dim, dim))));
+        tensors.push((name("ffn_gate"), random((hidden, dim))));
+        tensors.push((name("ffn_up"), random((hidden, dim))));
+        tensors.push((name("ffn_down"), random((dim, hidden))));
+    }
+    let metadata = [
+        ("llama.attention.head_count", Value::U32(heads as u32)),
+        ("llama.attention.head_count_kv", Value::U32(kv_heads as u32)),
+        ("llama.block_count", Value::U32(2)),
+        ("llama.embedding_length", Value::U32(dim as u32)),
+        ("llama.rope.dimension_count", Value::U32(head_dim as u32)),
+        ("llama.attention.layer_norm_rms_epsilon", Value::F32(1e-5)),
+        ("llama.context_length", Value::U32(64)),
+    ];
+    let norms: Vec<String> = (0..2)
+        .flat_map(|layer| [format!("blk.{layer}.attn_norm.weight"), format!("blk.{layer}.ffn_norm.weight")])
+        .chain(["output_norm.weight".to_string()])
+        .collect();
+    let mut all: Vec<(&str, &QTensor)> = tensors.iter().map(|(name, tensor)| (name.as_str(), tensor)).collect();
+    all.extend(norms.iter().map(|name| (name.as_str(), &ones)));
+
+    let mut file = std::io::Cursor::new(Vec::new());
+    let metadata: Vec<(&str, &Value)> = metadata.iter().map(|(key, value)| (*key, value)).collect();
+    gguf_file::write(&mut file, &metadata, &all).unwrap();
+    file.set_position(0);
+    file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_truncate_cache() {
+        let mut model = tiny_model();
+        let tokens = Tensor::new(&[[1u32, 7, 3, 9, 4, 12]], &Device::Cpu).unwrap();
+        let full: Vec<f32> = model.forward(&tokens, 0).unwrap().flatten_all().unwrap().to_vec1().unwrap();
+
+        // An earlier prompt that shares the first three tokens
+        let earlier = Tensor::new(&[[1u32, 7, 3, 20, 21]], &Device::Cpu).unwrap();
+        model.forward(&earlier, 0).unwrap();
+        model.truncate_cache(3).unwrap();
+        let rest = tokens.narrow(1, 3, 3).unwrap();
+        let reused: Vec<f32> = model.forward(&rest, 3).unwrap().flatten_all().unwrap().to_vec1().unwrap();
+
+        let difference = full.iter().zip(&reused).map(|(a, b)| (a - b).abs()).fold(0f32, f32::max);
+        assert!(difference < 1e-4, "logits differ by {}", difference);
+    }
+
+    #[test]
+    fn test_forward_all() {
+        let mut model = tiny_model();
+        let tokens = Tensor::new(&[[1u32, 7, 3, 9]], &Device::Cpu).unwrap();
+        let all = model.forward_all(&tokens, 0).unwrap();
+        assert_eq!(all.dims(), [1, 4, 24]);
+        // Each position predicts what a run that stops there would
+        for end in [2, 4] {
+            model.truncate_cache(0).unwrap();
+            let last: Vec<f32> = model.forward(&tokens.narrow(1, 0, end).unwrap(), 0).unwrap().flatten_all().unwrap().to_vec1().unwrap();
+            let row: Vec<f32> = all.i((0, end - 1)).unwrap().to_vec1().unwrap();
+            let difference = last.iter().zip(&row).map(|(a, b)| (a - b).abs()).fold(0f32, f32::max);
+            assert!(difference < 1e-4, "logits differ by {}", difference);
+        }
+    }
+
+    #[test]
+    fn test_from_gguf_offloaded() {
+        let file = tiny_gguf();
+        let tokens = Tensor::new(&[[1u32, 7, 3, 9]], &Device::Cpu).unwrap();
+        let mut logits = Vec::new();
+        // None, some and more than all of the layers
+        for gpu_layers in [0, 1, 5] {
+            let mut file = file.clone();
+            let content = gguf_file::Content::read(&mut file).unwrap();
+            let mut model = ModelWeights::from_gguf_offloaded(content, &mut file, &Device::Cpu, gpu_layers).unwrap();
+            let out: Vec<f32> = model.forward(&tokens, 0).unwrap().flatten_all().unwrap().to_vec1().unwrap();
+            logits.push(out);
+        }
+        assert_eq!(logits[0], logits[1]);
+        assert_eq!(logits[0], logits[2]);
+    }
+
+    #[test]
+    fn test_causal_mask() {
+        let fresh = causal_mask(3, 0, &Device::Cpu).unwrap().to_vec2::<u8>().unwrap();
+        assert_eq!(fresh, [[0, 1, 1], [0, 0, 1], [0, 0, 0]]);
+        // After two cached positions, every new token sees them
+        let continued = causal_mask(2, 2, &Device::Cpu).unwrap().to_vec2::<u8>().unwrap();
+        assert_eq!(continued, [[0, 0, 0, 1], [0, 0, 0, 0]]);
+    }
+}
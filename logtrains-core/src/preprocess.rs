@@ -0,0 +1,248 @@
+//! Text clean-up passes applied to the log before it is truncated and sent to
+//! the model. Each pass is a plain `&str -> String` function.
+
+use crate::timestamps;
+use regex::Regex;
+use std::sync::LazyLock;
+
+static ANSI_ESCAPE: LazyLock<Regex> = LazyLock::new(|| {
+    // CSI sequences (colors, cursor movement), OSC sequences (titles,
+    // hyperlinks) and the remaining two-byte escapes.
+    Regex::new(r"\x1b\[[0-?]*[ -/]*[@-~]|\x1b\][^\x07\x1b]*(?:\x07|\x1b\\)|\x1b[@-Z\\-_]").unwrap()
+});
+
+/// Removes ANSI escape sequences (colors, cursor control, OSC titles/links).
+pub fn strip_ansi(text: &str) -> String {
+    ANSI_ESCAPE.replace_all(text, "").into_owned()
+}
+
+/// Resolves carriage returns and backspaces the way a terminal would render
+/// them, so `\r`-redrawn progress bars collapse to their final state.
+pub fn collapse_carriage_returns(text: &str) -> String {
+    let text = text.replace("\r\n", "\n");
+    let mut out = String::with_capacity(text.len());
+
+    for (i, line) in text.split('\n').enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        let mut rendered: Vec<char> = Vec::new();
+        let mut cursor: usize = 0;
+        for c in line.chars() {
+            match c {
+                '\r' => cursor = 0,
+                '\x08' => cursor = cursor.saturating_sub(1),
+                c => {
+                    if cursor < rendered.len() {
+                        rendered[cursor] = c;
+                    } else {
+                        rendered.push(c);
+                    }
+                    cursor += 1;
+                }
+            }
+        }
+        out.extend(rendered);
+    }
+
+    out
+}
+
+static VOLATILE_TOKEN: LazyLock<Regex> = LazyLock::new(|| {
+    // Hex ids/addresses and numbers (timestamps, counters, ports, PIDs)
+    Regex::new(r"\b0x[0-9a-fA-F]+\b|\b[0-9a-fA-F]{8,}\b|\d+").unwrap()
+});
+
+/// Collapses runs of identical or near-identical consecutive lines (differing
+/// only in numbers, timestamps or hex ids) into the first line followed by a
+/// `(xN)` count, so repeated warnings do not eat the context window.
+pub fn dedupe_lines(text: &str) -> String {
+    let mut out: Vec<String> = Vec::new();
+    let mut current: Option<(&str, String, usize)> = None;
+
+    let flush = |out: &mut Vec<String>, run: Option<(&str, String, usize)>| {
+        if let Some((line, _, count)) = run {
+            if count > 1 && !line.trim().is_empty() {
+                out.push(format!("{} (x{})", line, count));
+            } else {
+                out.push(line.to_string());
+            }
+        }
+    };
+
+    for line in text.lines() {
+        let key = VOLATILE_TOKEN.replace_all(line.trim(), "#").into_owned();
+        match &mut current {
+            Some((_, current_key, count)) if *current_key == key => *count += 1,
+            _ => {
+                let finished = current.take();
+                flush(&mut out, finished);
+                current = Some((line, key, 1));
+            }
+        }
+    }
+    flush(&mut out, current);
+
+    out.join("\n")
+}
+
+/// Keeps lines matching any `include` pattern (all lines if there are none),
+/// plus `context` lines around each match, after dropping every line that
+/// matches an `exclude` pattern. Non-adjacent groups are separated by `--`
+/// like `grep -C`.
+#[cfg(test)]
+pub fn filter_lines(text: &str, include: &[Regex], exclude: &[Regex], context: usize) -> String {
+    filter_records(&text.lines().collect::<Vec<_>>(), include, exclude, context)
+}
+
+/// [`filter_lines`] over whole records (see [`group_records`]): a record is
+/// kept or dropped as a unit if any of its lines matches, and `context`
+/// counts records.
+pub fn filter_records<S: AsRef<str>>(records: &[S], include: &[Regex], exclude: &[Regex], context: usize) -> String {
+    let records: Vec<&str> = records
+        .iter()
+        .map(|record| record.as_ref())
+        .filter(|record| !exclude.iter().any(|re| re.is_match(record)))
+        .collect();
+    if include.is_empty() {
+        return records.join("\n");
+    }
+
+    let mut keep = vec![false; records.len()];
+    for (i, record) in records.iter().enumerate() {
+        if include.iter().any(|re| re.is_match(record)) {
+            let start = i.saturating_sub(context);
+            let end = (i + context).min(records.len() - 1);
+            keep[start..=end].iter_mut().for_each(|k| *k = true);
+        }
+    }
+
+    let mut out: Vec<&str> = Vec::new();
+    let mut previous: Option<usize> = None;
+    for (i, record) in records.iter().enumerate().filter(|(i, _)| keep[*i]) {
+        if previous.is_some_and(|p| p + 1 != i) {
+            out.push("--");
+        }
+        out.push(record);
+        previous = Some(i);
+    }
+    out.join("\n")
+}
+
+/// Groups continuation lines with the record they belong to, so stack traces
+/// and wrapped messages survive filtering and chunking intact.
+///
+/// With `record_start`, a record begins at every line matching it. Otherwise
+/// indented lines continue the previous record, and in logs whose first line
+/// starts with a timestamp, so does every line without one.
+pub fn group_records(text: &str, record_start: Option<&Regex>) -> Vec<String> {
+    let starts_with_timestamp = |line: &str| timestamps::find(line).is_some_and(|ts| ts.span.start <= 1);
+    let timestamped = text
+        .lines()
+        .find(|line| !line.trim().is_empty())
+        .is_some_and(starts_with_timestamp);
+
+    let mut records: Vec<String> = Vec::new();
+    for line in text.lines() {
+        let starts_record = match record_start {
+            Some(re) => re.is_match(line),
+            None if timestamped => starts_with_timestamp(line),
+            None => !line.starts_with(char::is_whitespace) || line.trim().is_empty(),
+        };
+        match records.last_mut() {
+            Some(record) if !starts_record => {
+                record.push('\n');
+                record.push_str(line);
+            }
+            _ => records.push(line.to_string()),
+        }
+    }
+    records
+}
+
+/// Runs the terminal clean-up passes: ANSI stripping, then CR collapsing.
+pub fn clean_terminal_output(text: &str) -> String {
+    collapse_carriage_returns(&strip_ansi(text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_ansi() {
+        let input = "\x1b[1m\x1b[31merror\x1b[0m: mismatched types\x1b]0;title\x07";
+        assert_eq!(strip_ansi(input), "error: mismatched types");
+    }
+
+    #[test]
+    fn test_collapse_carriage_returns() {
+        let input = "Downloading 10%\rDownloading 50%\rDownloading 100%\ndone\r\n";
+        assert_eq!(collapse_carriage_returns(input), "Downloading 100%\ndone\n");
+    }
+
+    #[test]
+    fn test_dedupe_lines() {
+        let input = "start\nwarn: retrying\nwarn: retrying\nwarn: retrying\nend";
+        assert_eq!(dedupe_lines(input), "start\nwarn: retrying (x3)\nend");
+    }
+
+    #[test]
+    fn test_dedupe_near_duplicates() {
+        let input = "12:00:01 GC pause 15ms\n12:00:02 GC pause 18ms\n12:00:03 GC pause 9ms\nerror: boom\nerror: boom";
+        assert_eq!(
+            dedupe_lines(input),
+            "12:00:01 GC pause 15ms (x3)\nerror: boom (x2)"
+        );
+        // Non-consecutive repeats are kept: order matters for diagnosis
+        assert_eq!(dedupe_lines("a\nb\na"), "a\nb\na");
+    }
+
+    #[test]
+    fn test_filter_lines() {
+        let text = "a\nb\nERROR auth failed\nc\nd\ne\nDEBUG noise\nERROR db down\nf";
+        let include = [Regex::new("ERROR").unwrap()];
+        let exclude = [Regex::new("DEBUG").unwrap()];
+
+        assert_eq!(
+            filter_lines(text, &include, &[], 0),
+            "ERROR auth failed\n--\nERROR db down"
+        );
+        assert_eq!(
+            filter_lines(text, &include, &exclude, 1),
+            "b\nERROR auth failed\nc\n--\ne\nERROR db down\nf"
+        );
+        assert_eq!(filter_lines("x\nDEBUG y", &[], &exclude, 3), "x");
+    }
+
+    #[test]
+    fn test_group_records() {
+        let log = "INFO start\nERROR boom\n  at a.b(A.java:1)\n  at c.d(C.java:2)\nINFO done";
+        let records = group_records(log, None);
+        assert_eq!(records, vec!["INFO start", "ERROR boom\n  at a.b(A.java:1)\n  at c.d(C.java:2)", "INFO done"]);
+
+        // In timestamped logs, unstamped lines continue the previous record
+        let log = "2024-05-02 10:00:00 ERROR bad row\nid=7 name=x\n2024-05-02 10:00:01 INFO ok";
+        assert_eq!(group_records(log, None).len(), 2);
+
+        let start = Regex::new(r"^\[").unwrap();
+        let log = "[a] one\ncontinued\n[b] two";
+        assert_eq!(group_records(log, Some(&start)), vec!["[a] one\ncontinued", "[b] two"]);
+    }
+
+    #[test]
+    fn test_filter_records_keeps_traces_whole() {
+        let log = "INFO start\nERROR boom\n  at a.b(A.java:1)\nINFO done";
+        let include = [Regex::new("A.java").unwrap()];
+        assert_eq!(
+            filter_records(&group_records(log, None), &include, &[], 0),
+            "ERROR boom\n  at a.b(A.java:1)"
+        );
+    }
+
+    #[test]
+    fn test_collapse_partial_overwrite_and_backspace() {
+        assert_eq!(collapse_carriage_returns("abcdef\rXY"), "XYcdef");
+        assert_eq!(collapse_carriage_returns("abc\x08\x08Z"), "aZc");
+    }
+}
@@ -0,0 +1,174 @@
+//! Structured (`--format json`) results: the model is asked for labeled
+//! sections, which are parsed into fields and combined with deterministic
+//! facts from the rule engine. With `--constrained` it writes the fields as
+//! JSON instead, held to [`schema`] token by token.
+
+use crate::constrain::{Field, Matcher};
+use crate::rules::Finding;
+use crate::severity::Severity;
+use crate::truncate::ERROR_MARKER;
+use regex::Regex;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::sync::LazyLock;
+
+/// Lines listed in `referenced_lines` at most.
+const MAX_REFERENCED_LINES: usize = 20;
+
+static SECTION_LABEL: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?im)^[\s#*_-]*(summary|probable cause|suggested fix)[*_]*\s*:[*_]*\s*").unwrap()
+});
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct Report {
+    /// The analyzed file, with `--each`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    pub summary: String,
+    pub probable_cause: String,
+    pub suggested_fix: String,
+    pub severity: Severity,
+    pub referenced_lines: Vec<ReferencedLine>,
+    pub model: String,
+    pub duration_ms: u128,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct ReferencedLine {
+    /// 1-based line number in the analyzed (cleaned and filtered) log.
+    pub line: usize,
+    pub text: String,
+}
+
+/// The three labeled sections of a model answer, in the order
+/// `(summary, probable_cause, suggested_fix)`. An answer without labels is
+/// used as the summary.
+pub fn parse_sections(answer: &str) -> (String, String, String) {
+    let mut sections = [String::new(), String::new(), String::new()];
+    let labels: Vec<_> = SECTION_LABEL.captures_iter(answer).collect();
+    if labels.is_empty() {
+        sections[0] = answer.trim().to_string();
+    }
+    for (i, cap) in labels.iter().enumerate() {
+        let index = match cap[1].to_lowercase().as_str() {
+            "summary" => 0,
+            "probable cause" => 1,
+            _ => 2,
+        };
+        let start = cap.get(0).unwrap().end();
+        let end = labels.get(i + 1).map_or(answer.len(), |next| next.get(0).unwrap().start());
+        sections[index] = answer[start..end].trim().to_string();
+    }
+    let [summary, cause, fix] = sections;
+    (summary, cause, fix)
+}
+
+/// The fields of a constrained answer.
+#[derive(Debug, Deserialize, PartialEq)]
+pub struct Answer {
+    pub summary: String,
+    pub probable_cause: String,
+    pub suggested_fix: String,
+    pub severity: String,
+}
+
+/// The JSON object a constrained answer must be, in the order the model
+/// writes it: the verdict comes last, after the reasoning.
+pub fn schema() -> Matcher {
+    Matcher::object(&[
+        ("summary", Field::Text(300)),
+        ("probable_cause", Field::Text(800)),
+        ("suggested_fix", Field::Text(1500)),
+        ("severity", Field::OneOf(&["ok", "warning", "error", "fatal"])),
+    ])
+}
+
+pub fn parse_answer(answer: &str) -> Result<Answer> {
+    let answer: Answer = serde_json::from_str(answer.trim()).context("The constrained answer is not valid JSON")?;
+    Ok(Answer {
+        summary: answer.summary.trim().to_string(),
+        probable_cause: answer.probable_cause.trim().to_string(),
+        suggested_fix: answer.suggested_fix.trim().to_string(),
+        ..answer
+    })
+}
+
+impl Answer {
+    pub fn severity(&self) -> Option<Severity> {
+        Severity::from_label(&self.severity)
+    }
+
+    /// The answer as the labeled sections [`parse_sections`] reads, which is
+    /// how analyses are stored.
+    pub fn to_sections(&self) -> String {
+        format!(
+            "Summary: {}\nProbable cause: {}\nSuggested fix: {}",
+            self.summary, self.probable_cause, self.suggested_fix
+        )
+    }
+}
+
+/// Lines that matched a rule or look like errors, in log order.
+pub fn referenced_lines(log: &str, findings: &[Finding]) -> Vec<ReferencedLine> {
+    let lines: Vec<&str> = log.lines().collect();
+    error_line_numbers(log, findings)
+        .into_iter()
+        .take(MAX_REFERENCED_LINES)
+        .map(|line| ReferencedLine { line, text: lines[line - 1].trim().to_string() })
+        .collect()
+}
+
+/// 1-based numbers of every line that matched a rule or looks like an error.
+pub fn error_line_numbers(log: &str, findings: &[Finding]) -> BTreeSet<usize> {
+    log.lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line))
+        .filter(|(n, line)| findings.iter().any(|f| f.line_number == *n) || ERROR_MARKER.is_match(line))
+        .map(|(n, _)| n)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sections() {
+        let answer = "**Summary:** The build ran out of disk.\n\n**Probable cause:** /var is full.\nSuggested fix: `docker system prune`";
+        let (summary, cause, fix) = parse_sections(answer);
+        assert_eq!(summary, "The build ran out of disk.");
+        assert_eq!(cause, "/var is full.");
+        assert_eq!(fix, "`docker system prune`");
+
+        let (summary, cause, _) = parse_sections("Just prose.");
+        assert_eq!(summary, "Just prose.");
+        assert!(cause.is_empty());
+    }
+
+    #[test]
+    fn test_constrained_answer() {
+        let mut matcher = schema();
+        let json = "{\"summary\": \"Disk full\", \"probable_cause\": \"/var is at 100%\", \
+                    \"suggested_fix\": \"Run `docker system prune`\", \"severity\": \"error\"}";
+        assert!(matcher.accepts(json));
+        matcher.advance(json);
+        assert!(matcher.is_complete());
+
+        let answer = parse_answer(json).unwrap();
+        assert_eq!(answer.severity(), Some(Severity::Error));
+        let (summary, cause, fix) = parse_sections(&answer.to_sections());
+        assert_eq!((summary.as_str(), cause.as_str()), ("Disk full", "/var is at 100%"));
+        assert_eq!(fix, "Run `docker system prune`");
+    }
+
+    #[test]
+    fn test_referenced_lines() {
+        let log = "starting\nwrite failed: No space left on device\ndone";
+        let findings = crate::rules::evaluate(log);
+        assert_eq!(
+            referenced_lines(log, &findings),
+            vec![ReferencedLine { line: 2, text: "write failed: No space left on device".to_string() }]
+        );
+    }
+}
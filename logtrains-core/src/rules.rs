@@ -0,0 +1,86 @@
+//! A small deterministic rule engine: recognizes well-known failure
+//! signatures line by line, independent of the model. Findings drive the
+//! automatic enrichment steps and give scripts something stable to act on.
+
+use regex::Regex;
+use std::sync::LazyLock;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rule {
+    /// ENOSPC / "No space left on device" / exhausted inodes.
+    DiskFull,
+    /// EACCES / EPERM / "Permission denied".
+    PermissionDenied,
+    /// OOM killer or allocation failures.
+    OutOfMemory,
+    /// Connection refused / reset / timed out.
+    ConnectionFailed,
+    /// DNS resolution failures.
+    DnsFailure,
+    /// Certificate verification or TLS handshake failures.
+    TlsFailure,
+}
+
+/// A line that matched a rule.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    pub rule: Rule,
+    /// 1-based line number in the analyzed text.
+    pub line_number: usize,
+    pub line: String,
+}
+
+static RULES: LazyLock<Vec<(Rule, Regex)>> = LazyLock::new(|| {
+    [
+        (Rule::DiskFull, r"(?i)(ENOSPC|no space left on device|disk quota exceeded|EDQUOT|out of inodes|inode.*(exhausted|full))"),
+        (Rule::PermissionDenied, r"(?i)(EACCES|EPERM\b|permission denied|operation not permitted|access is denied)"),
+        (Rule::OutOfMemory, r"(?i)(out of memory|oom-kill|OOMKilled|killed process \d+|cannot allocate memory|ENOMEM|memory allocation of \d+ bytes failed)"),
+        (Rule::ConnectionFailed, r"(?i)(connection refused|ECONNREFUSED|connection reset|ECONNRESET|connection timed out|ETIMEDOUT|no route to host|network is unreachable)"),
+        (Rule::DnsFailure, r"(?i)(could not resolve host|name or service not known|ENOTFOUND|EAI_AGAIN|temporary failure in name resolution|no such host)"),
+        (Rule::TlsFailure, r"(?i)(certificate verify failed|CERTIFICATE_VERIFY_FAILED|x509: certificate|self[- ]signed certificate|unable to get local issuer|tls handshake (error|failure|timeout)|SSL routines)"),
+    ]
+    .into_iter()
+    .map(|(rule, pattern)| (rule, Regex::new(pattern).unwrap()))
+    .collect()
+});
+
+/// Runs every rule over the log and returns the matches in line order.
+pub fn evaluate(log: &str) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for (index, line) in log.lines().enumerate() {
+        for (rule, pattern) in RULES.iter() {
+            if pattern.is_match(line) {
+                findings.push(Finding {
+                    rule: *rule,
+                    line_number: index + 1,
+                    line: line.trim().to_string(),
+                });
+            }
+        }
+    }
+    findings
+}
+
+/// Returns true if any finding matched `rule`.
+pub fn matched(findings: &[Finding], rule: Rule) -> bool {
+    findings.iter().any(|f| f.rule == rule)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evaluate() {
+        let log = "step 1 ok\n\
+                   write /var/lib/docker/tmp/x: no space left on device\n\
+                   open /etc/shadow: permission denied\n";
+        let findings = evaluate(log);
+        assert_eq!(findings.len(), 2);
+        assert_eq!(findings[0].rule, Rule::DiskFull);
+        assert_eq!(findings[0].line_number, 2);
+        assert_eq!(findings[1].rule, Rule::PermissionDenied);
+        assert!(matched(&findings, Rule::DiskFull));
+        assert!(!matched(&findings, Rule::OutOfMemory));
+    }
+}
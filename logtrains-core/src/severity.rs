@@ -0,0 +1,70 @@
+//! Severity of an analyzed log: a deterministic floor from the rules, which
+//! a constrained classification pass by the model can raise.
+
+use crate::rules::{self, Finding, Rule};
+use crate::truncate::ERROR_MARKER;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::sync::LazyLock;
+
+static FATAL_MARKER: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)(\bpanic(ked)?\b|\bfatal\b|segmentation fault|core dumped|kernel panic|unrecoverable)").unwrap()
+});
+static WARNING_MARKER: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)\bwarn(ing)?\b").unwrap());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Ok,
+    Warning,
+    Error,
+    Fatal,
+}
+
+impl Severity {
+    /// In the order the model is asked to choose from.
+    pub const ALL: [Severity; 4] = [Severity::Ok, Severity::Warning, Severity::Error, Severity::Fatal];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Severity::Ok => "ok",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+            Severity::Fatal => "fatal",
+        }
+    }
+
+    pub fn from_label(label: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|s| s.label() == label)
+    }
+
+    /// What the log shows without asking the model: crashes and resource
+    /// exhaustion are fatal, error markers or rule findings are errors.
+    pub fn from_log(log: &str, findings: &[Finding]) -> Self {
+        if FATAL_MARKER.is_match(log) || rules::matched(findings, Rule::OutOfMemory) {
+            Severity::Fatal
+        } else if !findings.is_empty() || ERROR_MARKER.is_match(log) {
+            Severity::Error
+        } else if WARNING_MARKER.is_match(log) {
+            Severity::Warning
+        } else {
+            Severity::Ok
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_log() {
+        let classify = |log: &str| Severity::from_log(log, &rules::evaluate(log));
+        assert_eq!(classify("thread 'main' panicked at src/main.rs:3:5"), Severity::Fatal);
+        assert_eq!(classify("Killed process 4242 (java) total-vm:8GB"), Severity::Fatal);
+        assert_eq!(classify("write failed: No space left on device"), Severity::Error);
+        assert_eq!(classify("warning: unused variable `x`"), Severity::Warning);
+        assert_eq!(classify("Finished in 0.4s, 0 errors"), Severity::Ok);
+        assert!(Severity::Fatal > Severity::Error && Severity::Warning > Severity::Ok);
+    }
+}
@@ -0,0 +1,142 @@
+//! Prompt templates: the `{{NAME}}` placeholders a custom prompt may use, and
+//! the values they are filled with for one analysis.
+
+use anyhow::{bail, Result};
+use regex::{Captures, Regex};
+use serde::Deserialize;
+use std::collections::BTreeSet;
+use std::sync::LazyLock;
+
+static PLACEHOLDER: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\{\{([A-Za-z0-9_]+)\}\}").unwrap());
+
+/// Every placeholder a template may use.
+pub const NAMES: [&str; 8] = ["ROLE", "LOG_TEXT", "COMMAND", "EXIT_CODE", "OS", "CWD", "QUESTION", "EXAMPLES"];
+
+/// Who the model is asked to be when the config sets no `role`.
+pub const DEFAULT_ROLE: &str = "CLI log analysis expert";
+
+/// Filled in for a command, exit code or directory that is not known, e.g.
+/// for a log read from a file.
+const UNKNOWN: &str = "unknown";
+
+/// A few-shot example from the `[[examples]]` tables of the config: a log
+/// and the explanation the model should have given for it.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Example {
+    pub log: String,
+    pub explanation: String,
+}
+
+/// Values for the placeholders other than `{{LOG_TEXT}}`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Vars {
+    pub role: String,
+    /// The command whose output is analyzed.
+    pub command: Option<String>,
+    pub exit_code: Option<i32>,
+    pub os: String,
+    /// Where the command ran.
+    pub cwd: Option<String>,
+    pub question: Option<String>,
+    pub examples: Vec<Example>,
+}
+
+impl Vars {
+    /// Values for this machine and the current directory, with `role` or
+    /// the default one.
+    pub fn local(role: Option<&str>) -> Self {
+        Self {
+            role: role.map(str::trim).filter(|r| !r.is_empty()).unwrap_or(DEFAULT_ROLE).to_string(),
+            command: None,
+            exit_code: None,
+            os: std::env::consts::OS.to_string(),
+            cwd: std::env::current_dir().ok().map(|dir| dir.display().to_string()),
+            question: None,
+            examples: Vec::new(),
+        }
+    }
+
+    /// The user's question, if there is a non-blank one.
+    pub fn question(&self) -> Option<&str> {
+        self.question.as_deref().map(str::trim).filter(|q| !q.is_empty())
+    }
+}
+
+/// Checks that `template` only uses placeholders from [`NAMES`], naming
+/// every unknown one, e.g. a misspelled `{{LOGTEXT}}`.
+pub fn validate(template: &str) -> Result<()> {
+    let unknown: BTreeSet<&str> = PLACEHOLDER
+        .captures_iter(template)
+        .filter(|cap| !NAMES.contains(&&cap[1]))
+        .map(|cap| cap.get(0).map_or("", |m| m.as_str()))
+        .collect();
+    if !unknown.is_empty() {
+        let unknown: Vec<&str> = unknown.into_iter().collect();
+        let known: Vec<String> = NAMES.iter().map(|name| format!("{{{{{}}}}}", name)).collect();
+        bail!("Unknown placeholder {} in the prompt template; available: {}", unknown.join(", "), known.join(", "));
+    }
+    Ok(())
+}
+
+/// Whether `template` uses the placeholder `name`.
+pub fn uses(template: &str, name: &str) -> bool {
+    PLACEHOLDER.captures_iter(template).any(|cap| &cap[1] == name)
+}
+
+/// Fills the placeholders of `template` in a single pass, so values that
+/// contain `{{...}}` themselves (a log, a command line) are left as they are.
+pub fn render(template: &str, log_text: &str, vars: &Vars) -> String {
+    PLACEHOLDER
+        .replace_all(template, |cap: &Captures| match &cap[1] {
+            "ROLE" => vars.role.clone(),
+            "LOG_TEXT" => log_text.to_string(),
+            "COMMAND" => vars.command.clone().unwrap_or_else(|| UNKNOWN.to_string()),
+            "EXIT_CODE" => vars.exit_code.map_or_else(|| UNKNOWN.to_string(), |code| code.to_string()),
+            "OS" => vars.os.clone(),
+            "CWD" => vars.cwd.clone().unwrap_or_else(|| UNKNOWN.to_string()),
+            "QUESTION" => vars.question().unwrap_or("").to_string(),
+            "EXAMPLES" => render_examples(&vars.examples),
+            _ => cap[0].to_string(),
+        })
+        .into_owned()
+}
+
+/// Examples as plain text, for templates that lay out the prompt themselves.
+fn render_examples(examples: &[Example]) -> String {
+    examples
+        .iter()
+        .map(|example| format!("Example log:\n{}\n\nExample analysis:\n{}", example.log.trim(), example.explanation.trim()))
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let vars = Vars {
+            role: "Postgres DBA".to_string(),
+            command: Some("psql -f {{OS}}.sql".to_string()),
+            exit_code: Some(3),
+            os: "linux".to_string(),
+            cwd: None,
+            question: Some(" why? ".to_string()),
+            examples: Vec::new(),
+        };
+        let template = "You are a {{ROLE}} on {{OS}}. `{{COMMAND}}` in {{CWD}} exited with {{EXIT_CODE}}.\n{{LOG_TEXT}}\n{{QUESTION}}";
+        assert_eq!(
+            render(template, "ERROR {{ROLE}}", &vars),
+            "You are a Postgres DBA on linux. `psql -f {{OS}}.sql` in unknown exited with 3.\nERROR {{ROLE}}\nwhy?"
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(validate("{{ROLE}}: {{LOG_TEXT}}").is_ok());
+        let err = validate("{{X}} {{LOGTEXT}} {{ROLE}} {{X}}").unwrap_err().to_string();
+        assert!(err.starts_with("Unknown placeholder {{LOGTEXT}}, {{X}} in"), "{}", err);
+        assert!(err.contains("{{EXIT_CODE}}"), "{}", err);
+    }
+}
@@ -0,0 +1,469 @@
+//! Timestamp recognition and normalization for log lines, used to merge logs
+//! from several sources (and timezones) into one trustworthy timeline.
+
+use anyhow::{anyhow, Result};
+use chrono::{
+    DateTime, Datelike, FixedOffset, Local, NaiveDate, NaiveDateTime, NaiveTime, Offset, TimeZone,
+    Timelike, Utc,
+};
+use regex::Regex;
+use std::ops::Range;
+use std::sync::LazyLock;
+
+/// Only look for a timestamp this far into a line (access logs put the client
+/// address first, everything else starts with the timestamp).
+const SEARCH_PREFIX: usize = 80;
+/// Sources whose clocks disagree by more than this are reported as skewed.
+const SKEW_THRESHOLD_SECS: i64 = 120;
+
+static ISO: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(\d{4})[-/](\d{2})[-/](\d{2})[T ](\d{2}:\d{2}:\d{2})(?:[.,](\d{1,9}))?(?:\s?(Z|UTC|[+-]\d{2}:?\d{2}))?").unwrap()
+});
+static CLF: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(\d{2})/(\w{3})/(\d{4}):(\d{2}:\d{2}:\d{2}) ([+-]\d{4})").unwrap()
+});
+static SYSLOG: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(\w{3}) +(\d{1,2}) (\d{2}:\d{2}:\d{2})").unwrap());
+static TIME_ONLY: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\[?(\d{2}:\d{2}:\d{2})(?:[.,](\d{1,9}))?").unwrap());
+
+/// A timestamp found in a log line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Timestamp {
+    pub naive: NaiveDateTime,
+    /// The UTC offset written in the log, if any.
+    pub offset: Option<FixedOffset>,
+    /// False for time-only stamps (`10:30:01`), whose date is unknown.
+    pub date_known: bool,
+    /// Byte range of the timestamp within the line.
+    pub span: Range<usize>,
+}
+
+impl Timestamp {
+    /// Resolves to an absolute instant, interpreting offset-less stamps in
+    /// `assumed`. Time-only stamps cannot be resolved.
+    pub fn to_utc(&self, assumed: &Zone) -> Option<DateTime<Utc>> {
+        if !self.date_known {
+            return None;
+        }
+        match self.offset {
+            Some(offset) => offset
+                .from_local_datetime(&self.naive)
+                .single()
+                .map(|dt| dt.with_timezone(&Utc)),
+            None => assumed.to_utc(&self.naive),
+        }
+    }
+}
+
+/// Finds the timestamp at (or near) the start of a line.
+pub fn find(line: &str) -> Option<Timestamp> {
+    let mut end = line.len().min(SEARCH_PREFIX);
+    while !line.is_char_boundary(end) {
+        end -= 1;
+    }
+    let prefix = &line[..end];
+
+    if let Some(cap) = ISO.captures(prefix) {
+        let date = NaiveDate::from_ymd_opt(cap[1].parse().ok()?, cap[2].parse().ok()?, cap[3].parse().ok()?)?;
+        let time = parse_time(&cap[4], cap.get(5).map(|m| m.as_str()))?;
+        let offset = cap.get(6).and_then(|m| parse_offset(m.as_str()));
+        return Some(Timestamp {
+            naive: date.and_time(time),
+            offset,
+            date_known: true,
+            span: cap.get(0).unwrap().range(),
+        });
+    }
+    if let Some(cap) = CLF.captures(prefix) {
+        let date = NaiveDate::from_ymd_opt(cap[3].parse().ok()?, month(&cap[2])?, cap[1].parse().ok()?)?;
+        let time = parse_time(&cap[4], None)?;
+        return Some(Timestamp {
+            naive: date.and_time(time),
+            offset: parse_offset(&cap[5]),
+            date_known: true,
+            span: cap.get(0).unwrap().range(),
+        });
+    }
+    if let Some(cap) = SYSLOG.captures(prefix) {
+        // Syslog omits the year; assume the current one.
+        let date = NaiveDate::from_ymd_opt(Local::now().year(), month(&cap[1])?, cap[2].parse().ok()?)?;
+        let time = parse_time(&cap[3], None)?;
+        return Some(Timestamp {
+            naive: date.and_time(time),
+            offset: None,
+            date_known: true,
+            span: cap.get(0).unwrap().range(),
+        });
+    }
+    if let Some(cap) = TIME_ONLY.captures(prefix) {
+        let time = parse_time(&cap[1], cap.get(2).map(|m| m.as_str()))?;
+        let start = cap.get(1).unwrap().start();
+        return Some(Timestamp {
+            naive: NaiveDate::default().and_time(time),
+            offset: None,
+            date_known: false,
+            span: start..cap.get(0).unwrap().end(),
+        });
+    }
+    None
+}
+
+fn parse_time(hms: &str, fraction: Option<&str>) -> Option<NaiveTime> {
+    let time = NaiveTime::parse_from_str(hms, "%H:%M:%S").ok()?;
+    match fraction {
+        Some(f) => {
+            let nanos: u32 = format!("{:0<9}", f).parse().ok()?;
+            time.with_nanosecond(nanos)
+        }
+        None => Some(time),
+    }
+}
+
+fn parse_offset(s: &str) -> Option<FixedOffset> {
+    if s == "Z" || s == "UTC" {
+        return FixedOffset::east_opt(0);
+    }
+    let sign = if s.starts_with('-') { -1 } else { 1 };
+    let digits: String = s[1..].chars().filter(|c| c.is_ascii_digit()).collect();
+    if digits.len() != 4 {
+        return None;
+    }
+    let hours: i32 = digits[..2].parse().ok()?;
+    let minutes: i32 = digits[2..].parse().ok()?;
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+fn month(name: &str) -> Option<u32> {
+    const MONTHS: [&str; 12] = ["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+    let name = name.to_ascii_lowercase();
+    MONTHS.iter().position(|m| *m == name).map(|i| i as u32 + 1)
+}
+
+/// A timezone to normalize into (or to assume for offset-less timestamps).
+#[derive(Debug, Clone, PartialEq)]
+pub enum Zone {
+    Utc,
+    Local,
+    Fixed(FixedOffset),
+    Named(chrono_tz::Tz),
+}
+
+impl Zone {
+    /// Parses `UTC`, `local`, a fixed offset like `+05:30`, or an IANA name
+    /// like `Europe/Berlin`.
+    pub fn parse(s: &str) -> Result<Self> {
+        match s {
+            "UTC" | "utc" | "Z" => Ok(Zone::Utc),
+            "local" | "Local" => Ok(Zone::Local),
+            _ if s.starts_with('+') || s.starts_with('-') => parse_offset(s)
+                .map(Zone::Fixed)
+                .ok_or_else(|| anyhow!("Invalid UTC offset: {} (expected e.g. +02:00)", s)),
+            _ => s
+                .parse::<chrono_tz::Tz>()
+                .map(Zone::Named)
+                .map_err(|_| anyhow!("Unknown timezone: {} (use UTC, local, +HH:MM or an IANA name)", s)),
+        }
+    }
+
+    pub fn to_utc(&self, naive: &NaiveDateTime) -> Option<DateTime<Utc>> {
+        let utc = match self {
+            Zone::Utc => Utc.from_local_datetime(naive).single()?,
+            Zone::Local => Local.from_local_datetime(naive).earliest()?.with_timezone(&Utc),
+            Zone::Fixed(offset) => offset.from_local_datetime(naive).single()?.with_timezone(&Utc),
+            Zone::Named(tz) => tz.from_local_datetime(naive).earliest()?.with_timezone(&Utc),
+        };
+        Some(utc)
+    }
+
+    pub fn localize(&self, utc: &DateTime<Utc>) -> DateTime<FixedOffset> {
+        let offset = match self {
+            Zone::Utc => Utc.fix(),
+            Zone::Local => Local.offset_from_utc_datetime(&utc.naive_utc()).fix(),
+            Zone::Fixed(offset) => *offset,
+            Zone::Named(tz) => tz.offset_from_utc_datetime(&utc.naive_utc()).fix(),
+        };
+        utc.with_timezone(&offset)
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Zone::Utc => "UTC".to_string(),
+            Zone::Local => "local time".to_string(),
+            Zone::Fixed(offset) => offset.to_string(),
+            Zone::Named(tz) => tz.name().to_string(),
+        }
+    }
+}
+
+/// A `--since`/`--until` bound: either a time of day or a full date-time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Bound {
+    Time(NaiveTime),
+    DateTime(NaiveDateTime),
+}
+
+impl Bound {
+    /// Parses `HH:MM[:SS]`, `YYYY-MM-DD`, or `YYYY-MM-DD[T ]HH:MM[:SS]`.
+    /// For an end bound, a value without seconds (or without a time) covers
+    /// the whole minute (or day), so `--until 10:45` includes 10:45:30.
+    pub fn parse(s: &str, is_end: bool) -> Result<Self> {
+        let s = s.trim();
+        let end_of = |secs: i64| {
+            if is_end {
+                chrono::Duration::seconds(secs) - chrono::Duration::nanoseconds(1)
+            } else {
+                chrono::Duration::zero()
+            }
+        };
+
+        if let Ok(t) = NaiveTime::parse_from_str(s, "%H:%M:%S") {
+            return Ok(Bound::Time(t));
+        }
+        if let Ok(t) = NaiveTime::parse_from_str(s, "%H:%M") {
+            return Ok(Bound::Time(t + end_of(60)));
+        }
+        for format in ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S"] {
+            if let Ok(dt) = NaiveDateTime::parse_from_str(s, format) {
+                return Ok(Bound::DateTime(dt));
+            }
+        }
+        for format in ["%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M"] {
+            if let Ok(dt) = NaiveDateTime::parse_from_str(s, format) {
+                return Ok(Bound::DateTime(dt + end_of(60)));
+            }
+        }
+        if let Ok(d) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+            return Ok(Bound::DateTime(d.and_time(NaiveTime::MIN) + end_of(86_400)));
+        }
+        Err(anyhow!(
+            "Invalid time: {} (expected HH:MM[:SS], YYYY-MM-DD or YYYY-MM-DD HH:MM[:SS])",
+            s
+        ))
+    }
+
+    /// Compares a line's timestamp against this bound.
+    fn cmp_timestamp(&self, ts: &Timestamp) -> std::cmp::Ordering {
+        match self {
+            Bound::Time(t) => ts.naive.time().cmp(t),
+            Bound::DateTime(dt) if ts.date_known => ts.naive.cmp(dt),
+            Bound::DateTime(dt) => ts.naive.time().cmp(&dt.time()),
+        }
+    }
+}
+
+/// Keeps the lines whose timestamp (as written in the log) falls within
+/// `[since, until]`. Lines without a timestamp follow the line before them;
+/// leading lines before the first timestamp are kept only without `since`.
+pub fn filter_range(text: &str, since: Option<&Bound>, until: Option<&Bound>) -> String {
+    let mut inside = since.is_none();
+    text.lines()
+        .filter(|line| {
+            if let Some(ts) = find(line) {
+                let after_start = since.is_none_or(|b| b.cmp_timestamp(&ts).is_ge());
+                let before_end = until.is_none_or(|b| b.cmp_timestamp(&ts).is_le());
+                inside = after_start && before_end;
+            }
+            inside
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// One input log taking part in a merge.
+pub struct Source {
+    pub name: String,
+    pub text: String,
+    /// Last modification time of the source, used for clock-skew detection.
+    pub modified: Option<DateTime<Utc>>,
+}
+
+/// The merged timeline plus human-readable notes about offsets applied and
+/// clock skew detected.
+pub struct Merged {
+    pub text: String,
+    pub notes: Vec<String>,
+}
+
+/// Normalizes every source's timestamps into `target` (offset-less stamps are
+/// assumed to be local time) and, for several sources, interleaves their
+/// records chronologically, tagging each line with its source name.
+pub fn merge(sources: &[Source], target: &Zone) -> Merged {
+    let tag_lines = sources.len() > 1;
+    let mut notes = Vec::new();
+    let mut records: Vec<(Option<DateTime<Utc>>, usize, String)> = Vec::new();
+    let mut implied_skews: Vec<(&str, i64)> = Vec::new();
+
+    for (index, source) in sources.iter().enumerate() {
+        let mut shifts: Vec<i32> = Vec::new();
+        let mut current: Option<DateTime<Utc>> = None;
+        let mut last_seen: Option<DateTime<Utc>> = None;
+
+        for line in source.text.lines() {
+            let mut rendered = line.to_string();
+            if let Some(ts) = find(line) {
+                if let Some(utc) = ts.to_utc(&Zone::Local) {
+                    let normalized = target.localize(&utc);
+                    let source_offset = ts
+                        .offset
+                        .unwrap_or_else(|| Zone::Local.localize(&utc).offset().fix())
+                        .local_minus_utc();
+                    let shift = normalized.offset().local_minus_utc() - source_offset;
+                    if !shifts.contains(&shift) {
+                        shifts.push(shift);
+                    }
+                    rendered = format!(
+                        "{}{}{}",
+                        &line[..ts.span.start],
+                        normalized.format("%Y-%m-%dT%H:%M:%S%.f%:z"),
+                        &line[ts.span.end..]
+                    );
+                    current = Some(utc);
+                    last_seen = Some(utc);
+                }
+            }
+            if tag_lines {
+                rendered = format!("[{}] {}", source.name, rendered);
+            }
+            records.push((current, index, rendered));
+        }
+
+        for shift in shifts.iter().filter(|s| **s != 0) {
+            notes.push(format!(
+                "{}: timestamps shifted by {} to {}",
+                source.name,
+                format_shift(*shift),
+                target.name()
+            ));
+        }
+        if let (Some(last), Some(modified)) = (last_seen, source.modified) {
+            implied_skews.push((&source.name, (last - modified).num_seconds()));
+        }
+    }
+
+    if let (Some(ahead), Some(behind)) = (
+        implied_skews.iter().max_by_key(|(_, s)| *s),
+        implied_skews.iter().min_by_key(|(_, s)| *s),
+    ) {
+        let difference = ahead.1 - behind.1;
+        if difference > SKEW_THRESHOLD_SECS {
+            notes.push(format!(
+                "Possible clock skew: {} appears {}s ahead of {} (last entry vs. file modification time)",
+                ahead.0, difference, behind.0
+            ));
+        }
+    }
+
+    if tag_lines {
+        // Stable sort: lines without a timestamp stay attached to their record
+        records.sort_by_key(|(ts, _, _)| *ts);
+    }
+
+    Merged {
+        text: records.into_iter().map(|(_, _, line)| line).collect::<Vec<_>>().join("\n"),
+        notes,
+    }
+}
+
+fn format_shift(seconds: i32) -> String {
+    let sign = if seconds < 0 { '-' } else { '+' };
+    let seconds = seconds.abs();
+    format!("{}{:02}:{:02}", sign, seconds / 3600, (seconds % 3600) / 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_formats() {
+        let iso = find("2025-11-28T10:30:00.250+02:00 ERROR boom").unwrap();
+        assert_eq!(iso.naive.to_string(), "2025-11-28 10:30:00.250");
+        assert_eq!(iso.offset, FixedOffset::east_opt(7200));
+        assert_eq!(iso.span, 0..29);
+
+        let clf = find(r#"10.0.0.1 - - [28/Nov/2025:10:30:00 +0100] "GET / HTTP/1.1" 502"#).unwrap();
+        assert_eq!(clf.naive.to_string(), "2025-11-28 10:30:00");
+        assert_eq!(clf.offset, FixedOffset::east_opt(3600));
+
+        let nginx = find("2025/11/28 10:30:00 [error] 123#0: upstream timed out").unwrap();
+        assert_eq!(nginx.offset, None);
+
+        let time_only = find("[10:30:05] step failed").unwrap();
+        assert!(!time_only.date_known);
+        assert_eq!(time_only.naive.time().to_string(), "10:30:05");
+
+        assert!(find("no timestamp here").is_none());
+    }
+
+    #[test]
+    fn test_zone_parse() {
+        assert_eq!(Zone::parse("UTC").unwrap(), Zone::Utc);
+        assert_eq!(Zone::parse("+05:30").unwrap(), Zone::Fixed(FixedOffset::east_opt(19800).unwrap()));
+        assert!(matches!(Zone::parse("Europe/Berlin").unwrap(), Zone::Named(_)));
+        assert!(Zone::parse("Mars/Olympus").is_err());
+    }
+
+    #[test]
+    fn test_merge_interleaves_and_notes_shift() {
+        let sources = vec![
+            Source {
+                name: "app".to_string(),
+                text: "2025-11-28T10:00:00+02:00 app start\n2025-11-28T10:00:10+02:00 app error\n  at handler".to_string(),
+                modified: None,
+            },
+            Source {
+                name: "db".to_string(),
+                text: "2025-11-28T08:00:05Z db deadlock".to_string(),
+                modified: None,
+            },
+        ];
+        let merged = merge(&sources, &Zone::Utc);
+        assert_eq!(
+            merged.text,
+            "[app] 2025-11-28T08:00:00+00:00 app start\n\
+             [db] 2025-11-28T08:00:05+00:00 db deadlock\n\
+             [app] 2025-11-28T08:00:10+00:00 app error\n\
+             [app]   at handler"
+        );
+        assert_eq!(merged.notes, vec!["app: timestamps shifted by -02:00 to UTC"]);
+    }
+
+    #[test]
+    fn test_filter_range() {
+        let log = "boot banner\n\
+                   2025-11-28 10:29:59 INFO warmup\n\
+                   2025-11-28 10:30:00 ERROR first\n  at frame\n\
+                   2025-11-28 10:45:30 ERROR last\n\
+                   2025-11-28 10:46:00 INFO recovered";
+        let since = Bound::parse("10:30", false).unwrap();
+        let until = Bound::parse("10:45", true).unwrap();
+        assert_eq!(
+            filter_range(log, Some(&since), Some(&until)),
+            "2025-11-28 10:30:00 ERROR first\n  at frame\n2025-11-28 10:45:30 ERROR last"
+        );
+
+        let until_day = Bound::parse("2025-11-27", true).unwrap();
+        assert_eq!(filter_range(log, None, Some(&until_day)), "boot banner");
+        assert!(Bound::parse("half past ten", false).is_err());
+    }
+
+    #[test]
+    fn test_merge_detects_clock_skew() {
+        let modified = Utc.with_ymd_and_hms(2025, 11, 28, 8, 0, 0).unwrap();
+        let sources = vec![
+            Source {
+                name: "a".to_string(),
+                text: "2025-11-28T08:00:00Z done".to_string(),
+                modified: Some(modified),
+            },
+            Source {
+                name: "b".to_string(),
+                text: "2025-11-28T08:10:00Z done".to_string(),
+                modified: Some(modified),
+            },
+        ];
+        let merged = merge(&sources, &Zone::Utc);
+        assert!(merged.notes.iter().any(|n| n.contains("b appears 600s ahead of a")));
+    }
+}
@@ -0,0 +1,311 @@
+//! Error-focused truncation: when a log does not fit, keep the lines around
+//! failures (plus the head and tail) instead of blindly keeping the end.
+//! Stack traces are kept whole where possible, and never cut mid-frame.
+
+use regex::Regex;
+use std::collections::BTreeSet;
+use std::ops::RangeInclusive;
+use std::sync::LazyLock;
+
+pub(crate) static ERROR_MARKER: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)(\berror\b|\berror\[|\berr!|\bpanic(ked)?\b|exception|\bfailed\b|\bfail\b|\bfatal\b|traceback|\bcritical\b|segmentation fault)").unwrap()
+});
+
+static PYTHON_TRACE_START: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^Traceback \(most recent call last\):").unwrap());
+static JAVA_FRAME: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\s+(at \S+\(.*\)|\.\.\. \d+ (more|common frames omitted))\s*$").unwrap());
+static JAVA_CAUSE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\s*(Caused by|Suppressed): ").unwrap());
+static RUST_TRACE_START: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^stack backtrace:").unwrap());
+static RUST_FRAME: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\s+(\d+: |at \S)").unwrap());
+static RUST_LOCATION: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\s+at \S").unwrap());
+
+const HEAD_LINES: usize = 5;
+const TAIL_LINES: usize = 15;
+const LINES_BEFORE_MARKER: usize = 5;
+const LINES_AFTER_MARKER: usize = 15;
+
+/// Truncates `input` to at most `max_chars` bytes, preferring windows around
+/// error markers, then the tail and head of the log. Without any markers the
+/// tail is kept, which is where most tools print their final error.
+#[cfg(test)]
+pub fn smart_truncate(input: &str, max_chars: usize) -> String {
+    smart_truncate_by(input, max_chars, str::len)
+}
+
+/// Like [`smart_truncate`], but with the size of a candidate text measured by
+/// `measure` (e.g. a tokenizer's token count) against `budget`.
+pub fn smart_truncate_by(input: &str, budget: usize, measure: impl Fn(&str) -> usize) -> String {
+    if measure(input) <= budget {
+        return input.to_string();
+    }
+
+    let lines: Vec<&str> = input.lines().collect();
+    let markers: Vec<usize> = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| ERROR_MARKER.is_match(line))
+        .map(|(i, _)| i)
+        .collect();
+    if markers.is_empty() || lines.len() < 2 {
+        return keep_tail(input, budget, &measure);
+    }
+
+    let last = lines.len() - 1;
+    let window = |m: usize| m.saturating_sub(LINES_BEFORE_MARKER)..=(m + LINES_AFTER_MARKER).min(last);
+
+    // Candidate ranges in priority order: the first and last failure are the
+    // most informative, then the very end of the log, then the start.
+    let traces = Traces::find(&lines);
+    let mut candidates = vec![window(markers[0])];
+    if markers.len() > 1 {
+        candidates.push(window(*markers.last().unwrap()));
+    }
+    candidates.push(last.saturating_sub(TAIL_LINES - 1)..=last);
+    candidates.push(0..=(HEAD_LINES - 1).min(last));
+    if markers.len() > 2 {
+        for &m in &markers[1..markers.len() - 1] {
+            candidates.push(window(m));
+        }
+    }
+
+    let mut selected: BTreeSet<usize> = BTreeSet::new();
+    // Each candidate is tried with any stack trace it touches kept whole,
+    // then trimmed to frame boundaries if the whole trace does not fit.
+    for range in candidates {
+        for range in [traces.expand(range.clone()), traces.snap(range)] {
+            let mut trial = selected.clone();
+            trial.extend(range);
+            if measure(&render(&lines, &trial)) <= budget {
+                selected = trial;
+                break;
+            }
+        }
+    }
+
+    if selected.is_empty() {
+        return keep_tail(input, budget, &measure);
+    }
+    render(&lines, &selected)
+}
+
+/// Multi-line stack traces (Python, Java, Rust backtraces) found in a log.
+struct Traces {
+    blocks: Vec<RangeInclusive<usize>>,
+    /// Lines that belong to the frame above them (Python source lines, Rust
+    /// `at file:line` locations) and must not be separated from it.
+    continuation: Vec<bool>,
+}
+
+impl Traces {
+    fn find(lines: &[&str]) -> Self {
+        let mut blocks = Vec::new();
+        let mut continuation = vec![false; lines.len()];
+        let mut i = 0;
+        while i < lines.len() {
+            let start = i;
+            if PYTHON_TRACE_START.is_match(lines[i]) {
+                // Indented frames, ending with the unindented exception line
+                i += 1;
+                while i < lines.len() && lines[i].starts_with(' ') {
+                    continuation[i] = lines[i].starts_with("    ");
+                    i += 1;
+                }
+                let end = i.min(lines.len() - 1);
+                blocks.push(start..=end);
+            } else if RUST_TRACE_START.is_match(lines[i]) {
+                i += 1;
+                while i < lines.len() && RUST_FRAME.is_match(lines[i]) {
+                    continuation[i] = RUST_LOCATION.is_match(lines[i]);
+                    i += 1;
+                }
+                blocks.push(start..=i - 1);
+            } else if i + 1 < lines.len()
+                && !lines[i].starts_with(char::is_whitespace)
+                && JAVA_FRAME.is_match(lines[i + 1])
+            {
+                // Exception line followed by `at` frames and `Caused by:` chains
+                i += 1;
+                while i < lines.len() && (JAVA_FRAME.is_match(lines[i]) || JAVA_CAUSE.is_match(lines[i])) {
+                    i += 1;
+                }
+                blocks.push(start..=i - 1);
+            }
+            i = i.max(start + 1);
+        }
+        Self { blocks, continuation }
+    }
+
+    /// Grows `range` to cover every trace it overlaps.
+    fn expand(&self, range: RangeInclusive<usize>) -> RangeInclusive<usize> {
+        let (mut start, mut end) = range.into_inner();
+        for block in &self.blocks {
+            if *block.start() <= end && start <= *block.end() {
+                start = start.min(*block.start());
+                end = end.max(*block.end());
+            }
+        }
+        start..=end
+    }
+
+    /// Moves the edges of `range` so it does not split a frame.
+    fn snap(&self, range: RangeInclusive<usize>) -> RangeInclusive<usize> {
+        let (mut start, mut end) = range.into_inner();
+        while start > 0 && self.continuation[start] {
+            start -= 1;
+        }
+        while end + 1 < self.continuation.len() && self.continuation[end + 1] {
+            end += 1;
+        }
+        start..=end
+    }
+}
+
+/// Joins the selected lines, marking each gap with the number of lines cut.
+fn render(lines: &[&str], selected: &BTreeSet<usize>) -> String {
+    let mut out = String::new();
+    let mut previous: Option<usize> = None;
+    for &i in selected {
+        let gap = match previous {
+            Some(p) => i - p - 1,
+            None => i,
+        };
+        if gap > 0 {
+            out.push_str(&format!("... [{} lines omitted] ...\n", gap));
+        }
+        out.push_str(lines[i]);
+        out.push('\n');
+        previous = Some(i);
+    }
+    if let Some(p) = previous {
+        let gap = lines.len() - 1 - p;
+        if gap > 0 {
+            out.push_str(&format!("... [{} lines omitted] ...\n", gap));
+        }
+    }
+    out
+}
+
+/// Keeps the longest suffix of `input` that fits the budget, starting on a
+/// char boundary.
+fn keep_tail(input: &str, budget: usize, measure: impl Fn(&str) -> usize) -> String {
+    let boundary = |mut i: usize| {
+        while !input.is_char_boundary(i) {
+            i += 1;
+        }
+        i
+    };
+    // Binary search for the earliest start whose suffix still fits
+    let (mut low, mut high) = (0, input.len());
+    while low < high {
+        let mid = (low + high) / 2;
+        if measure(&input[boundary(mid)..]) <= budget {
+            high = mid;
+        } else {
+            low = mid + 1;
+        }
+    }
+    input[boundary(low)..].to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn numbered(n: usize) -> Vec<String> {
+        (0..n).map(|i| format!("line {:03} ok", i)).collect()
+    }
+
+    #[test]
+    fn test_keeps_early_error_window() {
+        let mut lines = numbered(300);
+        lines[20] = "ERROR: database connection refused".to_string();
+        let input = lines.join("\n");
+
+        let out = smart_truncate(&input, 1_000);
+        assert!(out.len() <= 1_000);
+        assert!(out.contains("ERROR: database connection refused"));
+        assert!(out.contains("line 015 ok"));
+        assert!(out.contains("line 299 ok"));
+        assert!(out.contains("lines omitted"));
+    }
+
+    #[test]
+    fn test_keeps_python_traceback_whole() {
+        let mut lines = numbered(300);
+        let trace = [
+            "Traceback (most recent call last):",
+            "  File \"app.py\", line 10, in <module>",
+            "    main()",
+            "  File \"app.py\", line 6, in main",
+            "    load()",
+            "  File \"app.py\", line 3, in load",
+            "    open(\"missing.cfg\")",
+            "  File \"io.py\", line 99, in open",
+            "    raise FileNotFoundError(path)",
+            "  File \"io.py\", line 120, in _raise",
+            "    raise",
+            "  File \"io.py\", line 130, in _raise_inner",
+            "    raise",
+            "  File \"io.py\", line 140, in _raise_deeper",
+            "    raise",
+            "  File \"io.py\", line 150, in _raise_deepest",
+            "    raise",
+            "FileNotFoundError: missing.cfg",
+        ];
+        for (i, line) in trace.iter().enumerate() {
+            lines[100 + i] = line.to_string();
+        }
+        let input = lines.join("\n");
+
+        let out = smart_truncate(&input, 1_500);
+        assert!(out.len() <= 1_500);
+        // The plain marker window would stop 15 lines after "Traceback"
+        assert!(out.contains("Traceback (most recent call last):\n  File \"app.py\", line 10"));
+        assert!(out.contains("FileNotFoundError: missing.cfg"));
+    }
+
+    #[test]
+    fn test_traces_are_found_and_snapped() {
+        let lines = [
+            "starting",
+            "java.lang.IllegalStateException: boom",
+            "\tat com.acme.App.run(App.java:10)",
+            "Caused by: java.io.IOException: disk",
+            "\tat com.acme.Io.read(Io.java:5)",
+            "\t... 3 more",
+            "stack backtrace:",
+            "   0: std::panicking::begin_panic",
+            "             at /rustc/library/std/src/panicking.rs:616:12",
+            "   1: app::main",
+            "             at ./src/main.rs:4:5",
+            "done",
+        ];
+        let traces = Traces::find(&lines);
+        assert_eq!(traces.blocks, vec![1..=5, 6..=10]);
+        assert_eq!(traces.expand(0..=2), 0..=5);
+        // A window must not start on a location line or end before one
+        assert_eq!(traces.snap(8..=9), 7..=10);
+    }
+
+    #[test]
+    fn test_without_markers_keeps_tail() {
+        assert_eq!(smart_truncate("hello world", 5), "world");
+        assert_eq!(smart_truncate("hello world", 50), "hello world");
+    }
+
+    #[test]
+    fn test_keep_tail_respects_char_boundaries() {
+        assert_eq!(keep_tail("größe", 2, str::len), "e");
+    }
+
+    #[test]
+    fn test_custom_measure() {
+        let words = |s: &str| s.split_whitespace().count();
+        let tail = keep_tail("one two three four", 2, words);
+        assert!(tail.ends_with("three four") && words(&tail) == 2);
+        let input = "boot ok\nstep ok\nerror: bad config\nstep ok\nshutdown ok";
+        assert_eq!(smart_truncate_by(input, 11, words), input);
+        assert!(words(&smart_truncate_by(input, 6, words)) <= 6);
+    }
+}
@@ -0,0 +1,169 @@
+//! Python bindings for the analysis pipeline: the `logtrains` package.
+//!
+//! ```python
+//! import logtrains
+//!
+//! result = logtrains.analyze(open("build.log").read(), model="tiny",
+//!                            callback=lambda text: print(text, end=""))
+//! print(result["severity"])
+//! ```
+
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList};
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+
+use logtrains_core::analysis::{self, AnalysisRequest, AnalysisResult};
+use logtrains_core::llm::{self, Flow, Inferencer, ModelLoaderBuilder};
+
+/// Models loaded by name for `analyze(model="...")`, kept for later calls.
+static MODELS: LazyLock<Mutex<HashMap<String, Py<Model>>>> = LazyLock::new(Default::default);
+
+/// A loaded model. Loading takes a while (and downloads the weights on first
+/// use), so create one and reuse it for many analyses.
+#[pyclass(module = "logtrains", frozen)]
+struct Model {
+    engine: Mutex<Inferencer>,
+    #[pyo3(get)]
+    name: String,
+}
+
+#[pymethods]
+impl Model {
+    /// `name` is a preset (`"tiny"`, `"medium"`) or `"<repository>/<file>.gguf"`
+    /// on the Hugging Face Hub.
+    #[new]
+    #[pyo3(signature = (name = "medium", language = None, max_tokens = None))]
+    fn new(py: Python<'_>, name: &str, language: Option<&str>, max_tokens: Option<usize>) -> PyResult<Self> {
+        let (repo, file) = model_files(name)?;
+        let loader = ModelLoaderBuilder::new(&repo, &file).language(language).max_tokens(max_tokens);
+        let engine = py.allow_threads(|| {
+            tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .map_err(anyhow::Error::from)
+                .and_then(|runtime| runtime.block_on(loader.load()))
+        });
+        Ok(Self { engine: Mutex::new(engine.map_err(to_py_err)?), name: name.to_string() })
+    }
+
+    /// Explains `text`, or answers `question` about it. `callback` receives
+    /// the explanation piece by piece as it is written; returning `False`
+    /// stops early. Returns a dict with `explanation`, `severity`,
+    /// `truncated` and `findings`.
+    #[pyo3(signature = (text, question = None, callback = None))]
+    fn analyze<'py>(
+        &self,
+        py: Python<'py>,
+        text: String,
+        question: Option<String>,
+        callback: Option<PyObject>,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let mut request = AnalysisRequest::new(text);
+        request.question = question.filter(|q| !q.trim().is_empty());
+        let result = py.allow_threads(|| {
+            let mut engine = self.engine.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            analysis::analyze(&mut engine, &request, |token| {
+                let Some(callback) = &callback else {
+                    return Ok(Flow::Continue);
+                };
+                Python::with_gil(|py| {
+                    let returned = callback.call1(py, (token,))?;
+                    let stop = returned.extract::<bool>(py).is_ok_and(|keep_going| !keep_going);
+                    Ok(if stop { Flow::Stop } else { Flow::Continue })
+                })
+            })
+        });
+        to_dict(py, result.map_err(to_py_err)?)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("logtrains.Model({:?})", self.name)
+    }
+}
+
+/// Analyzes `text` with `model`: a `Model`, or a name as for `Model(...)`
+/// (loaded on first use and kept). See `Model.analyze` for the rest.
+#[pyfunction]
+#[pyo3(signature = (text, model = None, callback = None, question = None))]
+fn analyze<'py>(
+    py: Python<'py>,
+    text: String,
+    model: Option<&Bound<'py, PyAny>>,
+    callback: Option<PyObject>,
+    question: Option<String>,
+) -> PyResult<Bound<'py, PyDict>> {
+    let model = match model {
+        Some(model) if model.is_instance_of::<Model>() => model.downcast::<Model>()?.clone(),
+        Some(name) => named_model(py, &name.extract::<String>()?)?,
+        None => named_model(py, "medium")?,
+    };
+    model.get().analyze(py, text, question, callback)
+}
+
+fn named_model<'py>(py: Python<'py>, name: &str) -> PyResult<Bound<'py, Model>> {
+    let cached = MODELS.lock().unwrap_or_else(|e| e.into_inner()).get(name).map(|model| model.clone_ref(py));
+    if let Some(model) = cached {
+        return Ok(model.into_bound(py));
+    }
+    // Loaded without holding the cache: the load releases the GIL
+    let model = Bound::new(py, Model::new(py, name, None, None)?)?;
+    MODELS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .entry(name.to_string())
+        .or_insert_with(|| model.clone().unbind());
+    Ok(model)
+}
+
+/// The `(repository, file)` of a preset or `"<repository>/<file>"`.
+fn model_files(name: &str) -> PyResult<(String, String)> {
+    let (repo, file) = match name {
+        "tiny" => llm::TINY_MODEL,
+        "medium" => llm::MEDIUM_MODEL,
+        _ => match name.rsplit_once('/') {
+            Some((repo, file)) if repo.contains('/') && file.ends_with(".gguf") => (repo, file),
+            _ => {
+                return Err(PyValueError::new_err(format!(
+                    "Unknown model {:?}: use \"tiny\", \"medium\" or \"<owner>/<repository>/<file>.gguf\"",
+                    name
+                )))
+            }
+        },
+    };
+    Ok((repo.to_string(), file.to_string()))
+}
+
+fn to_dict(py: Python<'_>, result: AnalysisResult) -> PyResult<Bound<'_, PyDict>> {
+    let findings = PyList::empty(py);
+    for finding in &result.findings {
+        let item = PyDict::new(py);
+        item.set_item("rule", format!("{:?}", finding.rule))?;
+        item.set_item("line_number", finding.line_number)?;
+        item.set_item("line", &finding.line)?;
+        findings.append(item)?;
+    }
+    let dict = PyDict::new(py);
+    dict.set_item("explanation", result.explanation)?;
+    dict.set_item("severity", result.severity.label())?;
+    dict.set_item("truncated", result.truncated)?;
+    dict.set_item("findings", findings)?;
+    Ok(dict)
+}
+
+/// Python exceptions raised by a callback pass through unchanged.
+fn to_py_err(e: anyhow::Error) -> PyErr {
+    match e.downcast::<PyErr>() {
+        Ok(e) => e,
+        Err(e) => PyRuntimeError::new_err(format!("{:#}", e)),
+    }
+}
+
+#[pymodule]
+fn logtrains(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Model>()?;
+    m.add_function(wrap_pyfunction!(analyze, m)?)?;
+    m.add("__version__", env!("CARGO_PKG_VERSION"))?;
+    Ok(())
+}
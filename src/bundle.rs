@@ -0,0 +1,299 @@
+//! Incident bundles: selected history entries with their logs, tags, notes
+//! and analyses in one `.tar.zst` file, so a failure can be handed to a
+//! teammate or attached to a postmortem and imported on another machine.
+//!
+//! The archive holds `manifest.json` and one `logs/{n}.log` per entry. Logs
+//! and text fields are redacted on export unless the caller opts out.
+
+use crate::history::{Analysis, Entry, History};
+use crate::redact::Redactor;
+use crate::severity::Severity;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+const MANIFEST: &str = "manifest.json";
+const VERSION: u32 = 1;
+const ZSTD_LEVEL: i32 = 19;
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Manifest {
+    version: u32,
+    created_at: i64,
+    redacted: bool,
+    entries: Vec<BundledEntry>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct BundledEntry {
+    command: String,
+    cwd: Option<String>,
+    started_at: i64,
+    ended_at: Option<i64>,
+    exit_code: Option<i32>,
+    /// Path of the log inside the archive.
+    log: String,
+    #[serde(default)]
+    tags: Vec<String>,
+    note: Option<String>,
+    #[serde(default)]
+    analyses: Vec<BundledAnalysis>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct BundledAnalysis {
+    source: String,
+    created_at: i64,
+    model: String,
+    severity: String,
+    explanation: String,
+}
+
+/// Writes `entries` to a bundle at `path`, redacting with `redactor` when
+/// given. Returns the number of secrets masked.
+pub fn export(history: &History, entries: &[Entry], path: &Path, redactor: Option<&Redactor>) -> Result<usize> {
+    let mut masked = 0;
+    let mut redact = |text: &str| match redactor {
+        Some(redactor) => {
+            let (text, counts) = redactor.redact(text);
+            masked += counts.iter().map(|(_, n)| n).sum::<usize>();
+            text
+        }
+        None => text.to_string(),
+    };
+
+    let mut manifest = Manifest {
+        version: VERSION,
+        created_at: chrono::Utc::now().timestamp(),
+        redacted: redactor.is_some(),
+        entries: Vec::new(),
+    };
+    let mut logs = Vec::new();
+    for (i, entry) in entries.iter().enumerate() {
+        let log = format!("logs/{}.log", i + 1);
+        logs.push((log.clone(), redact(&crate::history::read_log(&entry.log_path)?)));
+        let analyses = history
+            .analyses_for(entry.id)?
+            .into_iter()
+            .map(|a| BundledAnalysis {
+                source: redact(&a.source),
+                created_at: a.created_at,
+                model: a.model,
+                severity: a.severity.label().to_string(),
+                explanation: redact(&a.explanation),
+            })
+            .collect();
+        manifest.entries.push(BundledEntry {
+            command: redact(&entry.command),
+            cwd: entry.cwd.as_deref().map(&mut redact),
+            started_at: entry.started_at,
+            ended_at: entry.ended_at,
+            exit_code: entry.exit_code,
+            log,
+            tags: history.tags(entry.id)?,
+            note: history.note(entry.id)?.as_deref().map(&mut redact),
+            analyses,
+        });
+    }
+
+    let file = File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+    let encoder = zstd::Encoder::new(file, ZSTD_LEVEL)?;
+    let mut archive = tar::Builder::new(encoder);
+    append(&mut archive, MANIFEST, serde_json::to_string_pretty(&manifest)?.as_bytes(), manifest.created_at)?;
+    for (name, text) in &logs {
+        append(&mut archive, name, text.as_bytes(), manifest.created_at)?;
+    }
+    archive.into_inner()?.finish()?;
+    Ok(masked)
+}
+
+fn append<W: std::io::Write>(archive: &mut tar::Builder<W>, name: &str, data: &[u8], mtime: i64) -> Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(mtime.max(0) as u64);
+    header.set_cksum();
+    archive.append_data(&mut header, name, data)?;
+    Ok(())
+}
+
+/// Adds the entries of the bundle at `path` to `history`, copying their logs
+/// into the history directory. Returns the new entry IDs.
+pub fn import(history: &History, path: &Path) -> Result<Vec<i64>> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut archive = tar::Archive::new(zstd::Decoder::new(file)?);
+
+    let mut manifest: Option<Manifest> = None;
+    let mut logs = std::collections::HashMap::new();
+    for item in archive.entries().context("Not a logtrains bundle")? {
+        let mut item = item?;
+        let name = item.path()?.to_string_lossy().into_owned();
+        let mut data = Vec::new();
+        item.read_to_end(&mut data)?;
+        if name == MANIFEST {
+            manifest = Some(serde_json::from_slice(&data).context("Invalid bundle manifest")?);
+        } else {
+            logs.insert(name, data);
+        }
+    }
+    let Some(manifest) = manifest else {
+        bail!("{} has no {}; is it a logtrains bundle?", path.display(), MANIFEST);
+    };
+    if manifest.version > VERSION {
+        bail!(
+            "{} was written by a newer logtrains (bundle version {}); please upgrade",
+            path.display(),
+            manifest.version
+        );
+    }
+
+    // Checked up front, so a broken bundle adds nothing
+    if let Some(missing) = manifest.entries.iter().find(|bundled| !logs.contains_key(&bundled.log)) {
+        bail!("Bundle is missing {}", missing.log);
+    }
+
+    let mut ids = Vec::new();
+    for bundled in manifest.entries {
+        let log = &logs[&bundled.log];
+        let log_path = unused_log_path(history.dir(), bundled.started_at);
+        std::fs::write(&log_path, log).with_context(|| format!("Failed to write {}", log_path.display()))?;
+        let id = history.add(&Entry {
+            id: 0,
+            command: bundled.command,
+            cwd: bundled.cwd,
+            started_at: bundled.started_at,
+            ended_at: bundled.ended_at,
+            exit_code: bundled.exit_code,
+            log_path,
+        })?;
+        for tag in &bundled.tags {
+            history.tag(id, tag)?;
+        }
+        history.set_note(id, bundled.note.as_deref())?;
+        for a in bundled.analyses {
+            history.add_analysis(&Analysis {
+                id: 0,
+                entry_id: Some(id),
+                source: a.source,
+                created_at: a.created_at,
+                model: a.model,
+                severity: Severity::from_label(&a.severity).unwrap_or(Severity::Error),
+                explanation: a.explanation,
+                // Made with someone else's settings, so only recalled
+                key: None,
+            })?;
+        }
+        ids.push(id);
+    }
+    Ok(ids)
+}
+
+/// A log file name in the recorder's format that no existing log uses.
+fn unused_log_path(dir: &Path, started_at: i64) -> PathBuf {
+    (1..)
+        .map(|n| dir.join(format!("log_{}_imported-{}.log", started_at, n)))
+        .find(|path| !path.exists())
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_export_and_import_round_trip() {
+        let source = tempfile::tempdir().unwrap();
+        let history = History::open(source.path()).unwrap();
+        let log_path = source.path().join("log_100_deploy.log");
+        std::fs::write(&log_path, "connecting with password=hunter2\nerror: refused\n").unwrap();
+        let id = history
+            .add(&Entry {
+                id: 0,
+                command: "deploy --token=abc".to_string(),
+                cwd: Some("/src/app".to_string()),
+                started_at: 100,
+                ended_at: Some(104),
+                exit_code: Some(1),
+                log_path,
+            })
+            .unwrap();
+        history.tag(id, "outage").unwrap();
+        history.set_note(id, Some("only on staging")).unwrap();
+        history
+            .add_analysis(&Analysis {
+                id: 0,
+                entry_id: Some(id),
+                source: "deploy".to_string(),
+                created_at: 110,
+                model: "test-model".to_string(),
+                severity: Severity::Error,
+                explanation: "The database refused the connection.".to_string(),
+                key: Some("settings".to_string()),
+            })
+            .unwrap();
+
+        let bundle = source.path().join("incident.tar.zst");
+        let entries = history.recent(10).unwrap();
+        let redactor = Redactor::new(&[]).unwrap();
+        assert_eq!(export(&history, &entries, &bundle, Some(&redactor)).unwrap(), 2);
+
+        let target = tempfile::tempdir().unwrap();
+        let imported = History::open(target.path()).unwrap();
+        let ids = import(&imported, &bundle).unwrap();
+        assert_eq!(ids.len(), 1);
+        let entry = imported.select(crate::history::Selector::Id(ids[0])).unwrap().unwrap();
+        assert_eq!(entry.command, "deploy --token=[REDACTED:secret-flag]");
+        assert_eq!(entry.exit_code, Some(1));
+        assert!(entry.log_path.starts_with(target.path()));
+        let log = std::fs::read_to_string(&entry.log_path).unwrap();
+        assert!(log.contains("password=[REDACTED:secret]") && !log.contains("hunter2"));
+        assert_eq!(imported.tags(entry.id).unwrap(), vec!["outage"]);
+        assert_eq!(imported.note(entry.id).unwrap().as_deref(), Some("only on staging"));
+        let analysis = imported.analyses_for(entry.id).unwrap().pop().unwrap();
+        assert_eq!(analysis.explanation, "The database refused the connection.");
+        assert_eq!(analysis.key, None);
+
+        // Importing twice keeps both copies
+        import(&imported, &bundle).unwrap();
+        assert_eq!(imported.recent(10).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_import_with_missing_log_adds_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let entry = |log: &str| BundledEntry {
+            command: "make".to_string(),
+            cwd: None,
+            started_at: 100,
+            ended_at: None,
+            exit_code: Some(2),
+            log: log.to_string(),
+            tags: Vec::new(),
+            note: None,
+            analyses: Vec::new(),
+        };
+        let manifest = Manifest {
+            version: VERSION,
+            created_at: 100,
+            redacted: false,
+            entries: vec![entry("logs/1.log"), entry("logs/2.log")],
+        };
+        let bundle = dir.path().join("broken.tar.zst");
+        let mut archive = tar::Builder::new(zstd::Encoder::new(File::create(&bundle).unwrap(), 1).unwrap());
+        append(&mut archive, MANIFEST, serde_json::to_string(&manifest).unwrap().as_bytes(), 100).unwrap();
+        append(&mut archive, "logs/1.log", b"make: *** [all] Error 2\n", 100).unwrap();
+        archive.into_inner().unwrap().finish().unwrap();
+
+        let history_dir = dir.path().join("history");
+        std::fs::create_dir(&history_dir).unwrap();
+        let history = History::open(&history_dir).unwrap();
+        let err = import(&history, &bundle).unwrap_err();
+        assert_eq!(err.to_string(), "Bundle is missing logs/2.log");
+        assert!(history.recent(10).unwrap().is_empty());
+        assert!(!std::fs::read_dir(&history_dir)
+            .unwrap()
+            .any(|file| file.unwrap().file_name().to_string_lossy().ends_with(".log")));
+    }
+}
@@ -0,0 +1,153 @@
+//! Generated explanations keyed by a hash of everything that decides them
+//! (the preprocessed input, the model and its settings, the prompt), so
+//! repeating an analysis answers instantly without loading the model.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+use crate::llm::{self, Prompt};
+use crate::severity::Severity;
+use crate::template::{self, Vars};
+
+/// Results kept; the oldest are removed beyond this.
+const MAX_ENTRIES: usize = 500;
+
+/// The key of an analysis: a hash of `parts`, each length-prefixed so that
+/// moving text between parts changes it.
+pub fn key(parts: &[&str]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(env!("CARGO_PKG_VERSION"));
+    for part in parts {
+        hasher.update((part.len() as u64).to_le_bytes());
+        hasher.update(part);
+    }
+    hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// The part of a key that comes from the prompt: the prompt as filled in for
+/// this analysis, without the log, so values it does not use (such as the
+/// working directory, for most prompts) leave the key alone. The question and
+/// examples are sent to the model whether or not the prompt places them.
+pub fn prompt_part(prompt: Option<&Prompt>, vars: &Vars) -> String {
+    let prompt = prompt.cloned().unwrap_or_else(llm::default_prompt);
+    let kind = match prompt {
+        Prompt::System(_) => "system",
+        Prompt::Template(_) => "template",
+    };
+    format!(
+        "{}\n{}\n{}\n{:?}",
+        kind,
+        template::render(prompt.text(), "", vars),
+        vars.question().unwrap_or(""),
+        vars.examples
+    )
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Cached {
+    pub created_at: i64,
+    pub model: String,
+    pub severity: Severity,
+    pub explanation: String,
+}
+
+/// A directory of results, one JSON file per key.
+pub struct ResultCache {
+    dir: PathBuf,
+}
+
+impl ResultCache {
+    pub fn open(dir: &Path) -> Self {
+        Self { dir: dir.to_path_buf() }
+    }
+
+    /// The result stored under `key`. Unreadable entries count as missing.
+    pub fn get(&self, key: &str) -> Option<Cached> {
+        let text = std::fs::read_to_string(self.path(key)).ok()?;
+        serde_json::from_str(&text).ok()
+    }
+
+    pub fn put(&self, key: &str, cached: &Cached) -> Result<()> {
+        std::fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create {}", self.dir.display()))?;
+        // Written aside and renamed so a concurrent reader never sees half of it
+        let path = self.path(key);
+        let partial = path.with_extension("tmp");
+        std::fs::write(&partial, serde_json::to_string(cached)?)
+            .with_context(|| format!("Failed to write {}", partial.display()))?;
+        std::fs::rename(&partial, &path).with_context(|| format!("Failed to write {}", path.display()))?;
+        self.prune(MAX_ENTRIES)
+    }
+
+    /// Removes the least recently written results beyond `keep`.
+    fn prune(&self, keep: usize) -> Result<()> {
+        let mut entries = Vec::new();
+        for entry in std::fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            if entry.path().extension().is_some_and(|ext| ext == "json") {
+                let modified = entry.metadata()?.modified()?;
+                entries.push((modified, entry.path()));
+            }
+        }
+        if entries.len() > keep {
+            entries.sort();
+            for (_, path) in &entries[..entries.len() - keep] {
+                std::fs::remove_file(path).with_context(|| format!("Failed to remove {}", path.display()))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key() {
+        assert_eq!(key(&["error: boom", "model"]), key(&["error: boom", "model"]));
+        assert_ne!(key(&["error: boom", "model"]), key(&["error: boom", "other model"]));
+        assert_ne!(key(&["ab", "c"]), key(&["a", "bc"]));
+        assert_eq!(key(&[]).len(), 64);
+    }
+
+    #[test]
+    fn test_prompt_part() {
+        let vars = Vars::local(None);
+        let elsewhere = Vars { cwd: Some("/tmp/other".to_string()), ..vars.clone() };
+        assert_eq!(prompt_part(None, &vars), prompt_part(None, &elsewhere));
+        let template = Prompt::Template("Ran in {{CWD}}: {{LOG_TEXT}}".to_string());
+        assert_ne!(prompt_part(Some(&template), &vars), prompt_part(Some(&template), &elsewhere));
+        let question = Vars { question: Some("Is it DNS?".to_string()), ..vars.clone() };
+        assert_ne!(prompt_part(None, &vars), prompt_part(None, &question));
+    }
+
+    #[test]
+    fn test_result_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ResultCache::open(&dir.path().join("results"));
+        assert_eq!(cache.get("missing"), None);
+
+        let cached = Cached {
+            created_at: 1_700_000_000,
+            model: "repo/model.gguf".to_string(),
+            severity: Severity::Error,
+            explanation: "**Summary:** the linker is missing.".to_string(),
+        };
+        for n in 0..3 {
+            cache.put(&format!("key{}", n), &cached).unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert_eq!(cache.get("key1"), Some(cached.clone()));
+
+        cache.prune(2).unwrap();
+        assert_eq!(cache.get("key0"), None, "the oldest result is removed");
+        assert_eq!(cache.get("key2"), Some(cached));
+    }
+}
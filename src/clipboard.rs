@@ -0,0 +1,65 @@
+use anyhow::{Context, Result};
+
+/// Candidate (paste, copy) commands per platform, in order of preference.
+fn backends() -> Vec<(&'static [&'static str], &'static [&'static str])> {
+    if cfg!(target_os = "macos") {
+        vec![(&["pbpaste"], &["pbcopy"])]
+    } else if cfg!(windows) {
+        vec![(&["powershell", "-NoProfile", "-Command", "Get-Clipboard"], &["clip"])]
+    } else {
+        let mut backends: Vec<(&'static [&'static str], &'static [&'static str])> = Vec::new();
+        if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+            backends.push((&["wl-paste", "--no-newline"], &["wl-copy"]));
+        }
+        backends.push((
+            &["xclip", "-selection", "clipboard", "-o"],
+            &["xclip", "-selection", "clipboard", "-i"],
+        ));
+        backends.push((
+            &["xsel", "--clipboard", "--output"],
+            &["xsel", "--clipboard", "--input"],
+        ));
+        backends
+    }
+}
+
+/// Reads the system clipboard as text.
+pub fn read() -> Result<String> {
+    for (paste, _) in backends() {
+        if let Ok(output) = duct::cmd(paste[0], &paste[1..])
+            .stdout_capture()
+            .stderr_null()
+            .unchecked()
+            .run()
+        {
+            if output.status.success() {
+                return Ok(String::from_utf8_lossy(&output.stdout).into_owned());
+            }
+        }
+    }
+    Err(no_backend())
+}
+
+/// Places `text` on the system clipboard.
+pub fn write(text: &str) -> Result<()> {
+    for (_, copy) in backends() {
+        let result = duct::cmd(copy[0], &copy[1..])
+            .stdin_bytes(text.as_bytes())
+            .stdout_null()
+            .stderr_null()
+            .unchecked()
+            .run();
+        if let Ok(output) = result {
+            if output.status.success() {
+                return Ok(());
+            }
+        }
+    }
+    Err(no_backend()).context("Failed to copy the explanation to the clipboard")
+}
+
+fn no_backend() -> anyhow::Error {
+    anyhow::anyhow!(
+        "No clipboard tool found. Install wl-clipboard (Wayland), xclip or xsel (X11)."
+    )
+}
@@ -0,0 +1,134 @@
+use super::{probe, trim_lines, Section};
+use regex::Regex;
+use std::sync::LazyLock;
+
+const MAX_TREE_LINES: usize = 60;
+const MAX_PACKAGES: usize = 3;
+
+static CARGO_CONFLICT: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?:failed to select a version for (?:the requirement )?|multiple different versions of crate )`([A-Za-z0-9_-]+)").unwrap()
+});
+static NPM_PACKAGE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?m)^npm (?:ERR!|error)\s+(?:Found:|peer|Could not resolve dependency:\s*peer)\s+(@?[\w./-]+)@"#).unwrap()
+});
+static PIP_PACKAGE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?m)^\s+[\w.-]+ [\w.]+ depends on ([\w.-]+)").unwrap());
+
+/// The package manager whose resolver failed, with the packages involved.
+#[derive(Debug, PartialEq)]
+pub enum Conflict {
+    Cargo(Vec<String>),
+    Npm(Vec<String>),
+    Pip(Vec<String>),
+}
+
+/// Recognizes dependency-resolution failures and extracts the package names
+/// that were in conflict.
+pub fn detect(log: &str) -> Option<Conflict> {
+    if log.contains("failed to select a version") || log.contains("multiple different versions of crate") {
+        return Some(Conflict::Cargo(captures(&CARGO_CONFLICT, log)));
+    }
+    if log.contains("ERESOLVE") {
+        return Some(Conflict::Npm(captures(&NPM_PACKAGE, log)));
+    }
+    if log.contains("ResolutionImpossible") || log.contains("conflicting dependencies") {
+        return Some(Conflict::Pip(captures(&PIP_PACKAGE, log)));
+    }
+    None
+}
+
+/// Runs the relevant dependency-tree command(s) for a detected conflict.
+pub fn collect(log: &str) -> Option<Section> {
+    let conflict = detect(log)?;
+    let mut body = String::new();
+
+    match conflict {
+        Conflict::Cargo(crates) => {
+            for name in &crates {
+                let tree = probe("cargo", &["tree", "--invert", name, "--edges", "normal"])
+                    .or_else(|| probe("cargo", &["tree", "--duplicates"]));
+                push_tree(&mut body, &format!("cargo tree -i {}", name), tree);
+            }
+            if crates.is_empty() {
+                push_tree(&mut body, "cargo tree -d", probe("cargo", &["tree", "--duplicates"]));
+            }
+        }
+        Conflict::Npm(packages) => {
+            for name in &packages {
+                push_tree(&mut body, &format!("npm ls {}", name), probe("npm", &["ls", name]));
+            }
+        }
+        Conflict::Pip(packages) => {
+            for name in &packages {
+                let tree = probe("pipdeptree", &["--reverse", "--packages", name])
+                    .or_else(|| probe("python3", &["-m", "pip", "show", name]));
+                push_tree(&mut body, &format!("pipdeptree -r -p {}", name), tree);
+            }
+            push_tree(&mut body, "pip check", probe("python3", &["-m", "pip", "check"]));
+        }
+    }
+
+    if body.is_empty() {
+        return None;
+    }
+    Some(Section::new("Dependency Tree", body))
+}
+
+/// The package names `re` captures in `log`. They become arguments of the
+/// tree commands, so a name that would read as a flag is dropped.
+fn captures(re: &Regex, log: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for cap in re.captures_iter(log) {
+        let name = cap[1].to_string();
+        if !name.starts_with('-') && !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names.truncate(MAX_PACKAGES);
+    names
+}
+
+fn push_tree(body: &mut String, label: &str, output: Option<String>) {
+    if let Some(output) = output.filter(|o| !o.trim().is_empty()) {
+        body.push_str(&format!("$ {}\n{}\n", label, trim_lines(&output, MAX_TREE_LINES)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_cargo_conflict() {
+        let log = "error: failed to select a version for `serde`.\n    ... required by package `app v0.1.0`";
+        assert_eq!(detect(log), Some(Conflict::Cargo(vec!["serde".to_string()])));
+    }
+
+    #[test]
+    fn test_detect_npm_eresolve() {
+        let log = "npm ERR! code ERESOLVE\nnpm ERR! ERESOLVE unable to resolve dependency tree\nnpm ERR! Found: react@18.2.0\nnpm ERR! Could not resolve dependency:\nnpm ERR! peer react@\"^17.0.0\" from react-widget@1.0.0\n";
+        assert_eq!(detect(log), Some(Conflict::Npm(vec!["react".to_string()])));
+    }
+
+    #[test]
+    fn test_detect_pip_conflict() {
+        let log = "ERROR: Cannot install foo==1.0 and bar==2.0 because these package versions have conflicting dependencies.\nThe conflict is caused by:\n    foo 1.0 depends on requests==2.20\n    bar 2.0 depends on requests>=2.28\nERROR: ResolutionImpossible";
+        assert_eq!(detect(log), Some(Conflict::Pip(vec!["requests".to_string()])));
+    }
+
+    #[test]
+    fn test_flag_like_names_are_dropped() {
+        let log = "error: failed to select a version for `--manifest-path=/tmp/evil`\n\
+                   error: failed to select a version for `serde`";
+        assert_eq!(detect(log), Some(Conflict::Cargo(vec!["serde".to_string()])));
+        let log = "npm ERR! code ERESOLVE\nnpm ERR! Found: --prefix=/tmp@1.0.0\n";
+        assert_eq!(detect(log), Some(Conflict::Npm(Vec::new())));
+        let log = "ERROR: ResolutionImpossible\n    foo 1.0 depends on --index-url\n";
+        assert_eq!(detect(log), Some(Conflict::Pip(Vec::new())));
+    }
+
+    #[test]
+    fn test_detect_none() {
+        assert_eq!(detect("everything compiled fine"), None);
+    }
+}
@@ -0,0 +1,72 @@
+use super::{absolute_paths, probe, Section};
+use crate::rules::{Finding, Rule};
+use std::path::{Path, PathBuf};
+
+const MAX_PATHS: usize = 3;
+
+/// For disk-full findings, runs `df -h` and `df -i` on the mounts holding the
+/// paths mentioned in the failing lines (or the current directory).
+pub fn collect(findings: &[Finding]) -> Option<Section> {
+    let lines: Vec<&str> = findings
+        .iter()
+        .filter(|f| f.rule == Rule::DiskFull)
+        .map(|f| f.line.as_str())
+        .collect();
+    if lines.is_empty() {
+        return None;
+    }
+
+    let mut paths = referenced_paths(&lines);
+    if paths.is_empty() {
+        paths.push(std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")));
+    }
+
+    let mut body = String::new();
+    for path in paths {
+        let path = path.to_string_lossy();
+        if let Some(usage) = probe("df", &["-h", &path]) {
+            body.push_str(&format!("$ df -h {}\n{}\n", path, usage));
+        }
+        if let Some(inodes) = probe("df", &["-i", &path]) {
+            body.push_str(&format!("$ df -i {}\n{}\n", path, inodes));
+        }
+    }
+
+    if body.is_empty() {
+        return None;
+    }
+    Some(Section::new("Disk Usage", body))
+}
+
+/// Finds absolute paths in the failing lines and maps each to its nearest
+/// existing ancestor, so `df` can report on the right mount.
+fn referenced_paths(lines: &[&str]) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = Vec::new();
+    for path in absolute_paths(lines) {
+        if let Some(existing) = nearest_existing(&path) {
+            if !paths.contains(&existing) {
+                paths.push(existing);
+            }
+        }
+    }
+    paths.truncate(MAX_PATHS);
+    paths
+}
+
+fn nearest_existing(path: &Path) -> Option<PathBuf> {
+    path.ancestors().find(|p| p.exists()).map(Path::to_path_buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_referenced_paths_uses_existing_ancestor() {
+        let dir = tempdir().unwrap();
+        let missing = dir.path().join("build/output.bin");
+        let line = format!("cp: error writing '{}': No space left on device", missing.display());
+        assert_eq!(referenced_paths(&[line.as_str()]), vec![dir.path().to_path_buf()]);
+    }
+}
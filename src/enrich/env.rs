@@ -0,0 +1,156 @@
+use super::{probe, Section};
+use std::path::Path;
+
+/// Environment variables that commonly decide how a build or tool behaves.
+const VARIABLES: [&str; 22] = [
+    "PATH",
+    "SHELL",
+    "LANG",
+    "CI",
+    "RUSTUP_TOOLCHAIN",
+    "CARGO_TARGET_DIR",
+    "RUSTFLAGS",
+    "NODE_ENV",
+    "NODE_OPTIONS",
+    "VIRTUAL_ENV",
+    "CONDA_DEFAULT_ENV",
+    "PYTHONPATH",
+    "JAVA_HOME",
+    "GOPATH",
+    "GOFLAGS",
+    "CC",
+    "CXX",
+    "CFLAGS",
+    "LDFLAGS",
+    "PKG_CONFIG_PATH",
+    "DOCKER_HOST",
+    "KUBECONFIG",
+];
+
+/// Longer values (a long PATH) are cut to this many characters.
+const MAX_VALUE_CHARS: usize = 300;
+
+/// Words that run another command rather than being the tool themselves.
+const WRAPPERS: [&str; 6] = ["sudo", "env", "time", "exec", "nice", "nohup"];
+
+/// Version commands for the toolchain a program belongs to.
+const TOOLCHAINS: [(&[&str], &[&[&str]]); 10] = [
+    (&["cargo", "rustc", "rustup"], &[&["cargo", "--version"], &["rustc", "--version"]]),
+    (
+        &["node", "npm", "npx", "yarn", "pnpm", "jest", "tsc", "vite", "next"],
+        &[&["node", "--version"], &["npm", "--version"]],
+    ),
+    (
+        &["python", "python3", "pip", "pip3", "pytest", "poetry", "uv", "tox"],
+        &[&["python3", "--version"], &["pip3", "--version"]],
+    ),
+    (&["go"], &[&["go", "version"]]),
+    (&["java", "javac", "mvn", "gradle", "./gradlew", "./mvnw"], &[&["java", "-version"], &["mvn", "--version"], &["gradle", "--version"]]),
+    (&["make", "cmake", "gcc", "g++", "cc", "c++", "clang", "clang++", "ninja"], &[&["cc", "--version"], &["make", "--version"], &["cmake", "--version"]]),
+    (&["docker", "docker-compose"], &[&["docker", "--version"]]),
+    (&["kubectl", "helm"], &[&["kubectl", "version", "--client"]]),
+    (&["terraform", "tofu"], &[&["terraform", "version"]]),
+    (&["ruby", "bundle", "gem", "rake", "rails"], &[&["ruby", "--version"], &["bundle", "--version"]]),
+];
+
+/// The programs the command lines run, e.g. `cargo` and `npm` for
+/// `RUSTFLAGS=-Dwarnings cargo build && sudo npm ci`.
+pub fn programs(commands: &[String]) -> Vec<String> {
+    let mut programs = Vec::new();
+    for command in commands {
+        for part in command.split(['&', ';', '|']) {
+            let program = part
+                .split_whitespace()
+                .find(|word| !word.contains('=') && !WRAPPERS.contains(word))
+                .map(|word| {
+                    if word.starts_with("./") {
+                        word.to_string()
+                    } else {
+                        Path::new(word).file_name().map_or(word.to_string(), |name| name.to_string_lossy().into_owned())
+                    }
+                });
+            if let Some(program) = program {
+                if !programs.contains(&program) {
+                    programs.push(program);
+                }
+            }
+        }
+    }
+    programs
+}
+
+/// The version commands to run for `programs`, each once.
+fn version_commands(programs: &[String]) -> Vec<&'static [&'static str]> {
+    let mut commands: Vec<&'static [&'static str]> = Vec::new();
+    for (members, versions) in TOOLCHAINS {
+        if programs.iter().any(|program| members.contains(&program.as_str())) {
+            for version in versions.iter().copied() {
+                if !commands.contains(&version) {
+                    commands.push(version);
+                }
+            }
+        }
+    }
+    commands
+}
+
+/// Collects the OS, the versions of the toolchains `commands` use and the
+/// build-related environment variables that are set.
+pub fn collect(commands: &[String]) -> Section {
+    let mut body = String::new();
+    let system = probe("uname", &["-srm"])
+        .unwrap_or_else(|| format!("{} {}", std::env::consts::OS, std::env::consts::ARCH));
+    body.push_str(&format!("System: {}\n", system));
+
+    for version in version_commands(&programs(commands)) {
+        if let Some(line) = version_line(version) {
+            body.push_str(&format!("{}: {}\n", version.join(" "), line));
+        }
+    }
+
+    for name in VARIABLES {
+        if let Ok(value) = std::env::var(name) {
+            let mut shown: String = value.chars().take(MAX_VALUE_CHARS).collect();
+            if shown.len() < value.len() {
+                shown.push_str("...");
+            }
+            body.push_str(&format!("{}={}\n", name, shown));
+        }
+    }
+    Section::new("Environment", body)
+}
+
+/// The first line a version command prints. Unlike [`probe`], this reads
+/// stderr too: `java -version` prints there.
+fn version_line(command: &[&str]) -> Option<String> {
+    let output = duct::cmd(command[0], &command[1..])
+        .stderr_to_stdout()
+        .stdout_capture()
+        .unchecked()
+        .run()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let text = String::from_utf8_lossy(&output.stdout);
+    text.lines().map(str::trim).find(|line| !line.is_empty()).map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_programs() {
+        let commands = [
+            "RUSTFLAGS=-Dwarnings cargo build && sudo npm ci".to_string(),
+            "/usr/bin/python3 -m pytest | tee out.log; ./gradlew test".to_string(),
+        ];
+        assert_eq!(programs(&commands), ["cargo", "npm", "python3", "tee", "./gradlew"]);
+        let versions = version_commands(&programs(&commands));
+        assert_eq!(versions[0], ["cargo", "--version"]);
+        assert!(versions.contains(&&["node", "--version"][..]));
+        assert!(versions.contains(&&["java", "-version"][..]));
+        assert!(!versions.contains(&&["go", "version"][..]));
+    }
+}
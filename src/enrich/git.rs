@@ -0,0 +1,99 @@
+use super::{probe, trim_lines, Section};
+use std::path::Path;
+
+const MAX_STATUS_LINES: usize = 40;
+
+/// Returns true if the analyzed command was a git invocation. History slugs
+/// are sanitized (`git rebase main` -> `git_rebase_main`), so both forms count.
+pub fn is_git_command(command: &str) -> bool {
+    let command = command.trim_start();
+    command == "git" || command.starts_with("git ") || command.starts_with("git_")
+}
+
+/// Collects the current repository state: branch, in-progress operations,
+/// upstream divergence and the porcelain status. Returns `None` outside a
+/// work tree.
+pub fn collect() -> Option<Section> {
+    if probe("git", &["rev-parse", "--is-inside-work-tree"])?.trim() != "true" {
+        return None;
+    }
+
+    let mut body = String::new();
+
+    let branch = probe("git", &["symbolic-ref", "--short", "-q", "HEAD"])
+        .filter(|b| !b.is_empty())
+        .unwrap_or_else(|| {
+            let sha = probe("git", &["rev-parse", "--short", "HEAD"]).unwrap_or_default();
+            format!("(detached HEAD at {})", sha)
+        });
+    body.push_str(&format!("Branch: {}\n", branch));
+
+    if let Some(counts) = probe("git", &["rev-list", "--left-right", "--count", "@{upstream}...HEAD"]) {
+        if let Some((behind, ahead)) = counts.split_once('\t') {
+            body.push_str(&format!("Upstream: {} ahead, {} behind\n", ahead.trim(), behind.trim()));
+        }
+    }
+
+    let git_dir = probe("git", &["rev-parse", "--absolute-git-dir"]).unwrap_or_default();
+    let operations = in_progress_operations(Path::new(&git_dir));
+    if operations.is_empty() {
+        body.push_str("In-progress operation: none\n");
+    } else {
+        body.push_str(&format!("In-progress operation: {}\n", operations.join(", ")));
+    }
+
+    let status = probe("git", &["status", "--porcelain"]).unwrap_or_default();
+    if status.is_empty() {
+        body.push_str("Working tree: clean\n");
+    } else {
+        body.push_str("Status (git status --porcelain):\n");
+        body.push_str(&trim_lines(&status, MAX_STATUS_LINES));
+        body.push('\n');
+    }
+
+    Some(Section::new("Git Repository State", body))
+}
+
+/// Detects merges, rebases, cherry-picks, reverts and bisects from the marker
+/// files git leaves in its directory.
+fn in_progress_operations(git_dir: &Path) -> Vec<&'static str> {
+    let markers: [(&str, &str); 6] = [
+        ("rebase-merge", "interactive rebase"),
+        ("rebase-apply", "rebase (am)"),
+        ("MERGE_HEAD", "merge"),
+        ("CHERRY_PICK_HEAD", "cherry-pick"),
+        ("REVERT_HEAD", "revert"),
+        ("BISECT_LOG", "bisect"),
+    ];
+    markers
+        .iter()
+        .filter(|(marker, _)| git_dir.join(marker).exists())
+        .map(|(_, name)| *name)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_is_git_command() {
+        assert!(is_git_command("git rebase main"));
+        assert!(is_git_command("git_pull_origin_main"));
+        assert!(!is_git_command("gitk"));
+        assert!(!is_git_command("cargo build"));
+    }
+
+    #[test]
+    fn test_in_progress_operations() {
+        let dir = tempdir().unwrap();
+        assert!(in_progress_operations(dir.path()).is_empty());
+        std::fs::create_dir(dir.path().join("rebase-merge")).unwrap();
+        std::fs::File::create(dir.path().join("MERGE_HEAD")).unwrap();
+        assert_eq!(
+            in_progress_operations(dir.path()),
+            vec!["interactive rebase", "merge"]
+        );
+    }
+}
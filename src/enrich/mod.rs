@@ -0,0 +1,108 @@
+//! Local, deterministic context gathered alongside the log and appended to
+//! the prompt, so the model reasons about facts instead of guessing.
+
+pub mod deps;
+pub mod disk;
+pub mod env;
+pub mod git;
+pub mod network;
+pub mod permissions;
+pub mod tls;
+
+use regex::Regex;
+use std::path::PathBuf;
+use std::sync::LazyLock;
+
+static ABSOLUTE_PATH: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?:^|[^\w.~-])(/[^\s:'"`,;()]+)"#).unwrap());
+
+/// A titled block of facts appended to the prompt after the log text.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Section {
+    pub title: String,
+    pub body: String,
+}
+
+impl Section {
+    pub fn new(title: &str, body: String) -> Self {
+        Self {
+            title: title.to_string(),
+            body,
+        }
+    }
+}
+
+/// Renders sections as plain-text blocks suitable for appending to the log.
+pub fn render(sections: &[Section]) -> String {
+    let mut out = String::new();
+    for section in sections {
+        out.push_str(&format!("\n\n=== {} ===\n{}", section.title, section.body.trim_end()));
+    }
+    out
+}
+
+/// Runs a probe command and returns its stdout, or `None` if it could not be
+/// started or exited non-zero. Probes must never abort the analysis.
+pub(crate) fn probe(program: &str, args: &[&str]) -> Option<String> {
+    let output = duct::cmd(program, args)
+        .stderr_null()
+        .stdout_capture()
+        .unchecked()
+        .run()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).trim_end().to_string())
+}
+
+/// Keeps at most `max_lines` lines of probe output, noting how many were cut.
+pub(crate) fn trim_lines(text: &str, max_lines: usize) -> String {
+    let total = text.lines().count();
+    let mut out = text.lines().take(max_lines).collect::<Vec<_>>().join("\n");
+    if total > max_lines {
+        out.push_str(&format!("\n... ({} more lines)", total - max_lines));
+    }
+    out
+}
+
+/// Extracts the distinct absolute paths mentioned in the given lines.
+pub(crate) fn absolute_paths(lines: &[&str]) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = Vec::new();
+    for line in lines {
+        for cap in ABSOLUTE_PATH.captures_iter(line) {
+            let path = PathBuf::from(&cap[1]);
+            if !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+    }
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_sections() {
+        let sections = vec![Section::new("Git Repository State", "Branch: main\n".to_string())];
+        assert_eq!(render(&sections), "\n\n=== Git Repository State ===\nBranch: main");
+        assert_eq!(render(&[]), "");
+    }
+
+    #[test]
+    fn test_absolute_paths() {
+        let lines = ["open /etc/shadow: permission denied", "write '/tmp/x.log': ENOSPC /tmp/x.log"];
+        assert_eq!(
+            absolute_paths(&lines),
+            vec![PathBuf::from("/etc/shadow"), PathBuf::from("/tmp/x.log")]
+        );
+    }
+
+    #[test]
+    fn test_trim_lines() {
+        assert_eq!(trim_lines("a\nb\nc", 2), "a\nb\n... (1 more lines)");
+        assert_eq!(trim_lines("a\nb", 5), "a\nb");
+    }
+}
@@ -0,0 +1,186 @@
+use super::{probe, Section};
+use regex::Regex;
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::mpsc;
+use std::sync::LazyLock;
+use std::time::{Duration, Instant};
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+const MAX_TARGETS: usize = 3;
+
+static NETWORK_ERROR: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)(connection refused|econnrefused|timed out|etimedout|could not resolve host|name or service not known|enotfound|eai_again|getaddrinfo|temporary failure in name resolution|no such host|network is unreachable|enetunreach|no route to host)").unwrap()
+});
+static URL_TARGET: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(https?)://([A-Za-z0-9.-]+)(?::(\d{1,5}))?").unwrap());
+static HOST_PORT_TARGET: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?:^|[\s(\[/@'])((?:[A-Za-z0-9-]+\.)*[A-Za-z0-9-]*[A-Za-z][A-Za-z0-9-]*|\d{1,3}(?:\.\d{1,3}){3}):(\d{2,5})\b").unwrap()
+});
+/// `2024-05-02T09:21` looks like `host:port` to the pattern above.
+static ISO_TIMESTAMP_HOUR: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\d{4}-\d{2}-\d{2}T\d{2}$").unwrap());
+static RESOLVE_TARGET: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)(?:could not resolve host:?|enotfound|eai_again|lookup|no such host:?)\s+'?([A-Za-z0-9][A-Za-z0-9.-]*\.[A-Za-z]{2,})").unwrap()
+});
+
+/// A host (and optional port) mentioned near a network error.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Target {
+    pub host: String,
+    pub port: Option<u16>,
+}
+
+/// Returns true if the log contains connection, timeout or DNS failures.
+pub fn has_network_error(log: &str) -> bool {
+    NETWORK_ERROR.is_match(log)
+}
+
+/// Extracts the hosts/ports that failing lines refer to.
+pub fn extract_targets(log: &str) -> Vec<Target> {
+    targets_in_matching_lines(log, &NETWORK_ERROR)
+}
+
+/// Extracts hosts/ports from the lines of `log` that match `filter`.
+pub(crate) fn targets_in_matching_lines(log: &str, filter: &Regex) -> Vec<Target> {
+    let mut targets: Vec<Target> = Vec::new();
+    let mut push = |target: Target| {
+        if !targets.iter().any(|t| t.host == target.host && (t.port == target.port || target.port.is_none())) {
+            targets.retain(|t| !(t.host == target.host && t.port.is_none()));
+            targets.push(target);
+        }
+    };
+
+    for line in log.lines().filter(|l| filter.is_match(l)) {
+        for cap in URL_TARGET.captures_iter(line) {
+            let default_port = if &cap[1] == "https" { 443 } else { 80 };
+            let port = cap.get(3).and_then(|p| p.as_str().parse().ok()).unwrap_or(default_port);
+            push(Target { host: cap[2].to_string(), port: Some(port) });
+        }
+        for cap in HOST_PORT_TARGET.captures_iter(line) {
+            if ISO_TIMESTAMP_HOUR.is_match(&cap[1]) {
+                continue;
+            }
+            if let Ok(port) = cap[2].parse() {
+                push(Target { host: cap[1].to_string(), port: Some(port) });
+            }
+        }
+        for cap in RESOLVE_TARGET.captures_iter(line) {
+            push(Target { host: cap[1].to_string(), port: None });
+        }
+    }
+
+    targets.truncate(MAX_TARGETS);
+    targets
+}
+
+/// Runs DNS, TCP and routing probes for the hosts involved in network errors.
+pub fn collect(log: &str) -> Option<Section> {
+    if !has_network_error(log) {
+        return None;
+    }
+
+    let mut body = String::new();
+    body.push_str(&format!("Default route: {}\n", default_route()));
+
+    for target in extract_targets(log) {
+        let addrs = match resolve(&target.host, target.port.unwrap_or(0)) {
+            Ok(addrs) if !addrs.is_empty() => {
+                let ips: Vec<String> = addrs.iter().map(|a| a.ip().to_string()).collect();
+                body.push_str(&format!("DNS {}: resolves to {}\n", target.host, ips.join(", ")));
+                addrs
+            }
+            Ok(_) => {
+                body.push_str(&format!("DNS {}: no addresses returned\n", target.host));
+                continue;
+            }
+            Err(e) => {
+                body.push_str(&format!("DNS {}: FAILED ({})\n", target.host, e));
+                continue;
+            }
+        };
+
+        if let Some(port) = target.port {
+            let started = Instant::now();
+            let result = TcpStream::connect_timeout(&addrs[0], PROBE_TIMEOUT);
+            let verdict = match result {
+                Ok(_) => format!("connected in {} ms", started.elapsed().as_millis()),
+                Err(e) => format!("FAILED ({})", e),
+            };
+            body.push_str(&format!("TCP {}:{} -> {}\n", target.host, port, verdict));
+        }
+    }
+
+    Some(Section::new("Network Diagnostics", body))
+}
+
+/// Resolves a host with a hard timeout; the system resolver can otherwise
+/// block for a long time on a broken network.
+fn resolve(host: &str, port: u16) -> Result<Vec<SocketAddr>, String> {
+    let (tx, rx) = mpsc::channel();
+    let query = format!("{}:{}", host, port);
+    std::thread::spawn(move || {
+        let _ = tx.send(query.to_socket_addrs().map(|a| a.collect::<Vec<_>>()));
+    });
+    match rx.recv_timeout(PROBE_TIMEOUT) {
+        Ok(Ok(addrs)) => Ok(addrs),
+        Ok(Err(e)) => Err(e.to_string()),
+        Err(_) => Err("timed out".to_string()),
+    }
+}
+
+fn default_route() -> String {
+    if let Ok(table) = std::fs::read_to_string("/proc/net/route") {
+        return match parse_proc_route(&table) {
+            Some(iface) => format!("present (via {})", iface),
+            None => "MISSING".to_string(),
+        };
+    }
+    match probe("route", &["-n", "get", "default"]) {
+        Some(out) if out.contains("gateway") => "present".to_string(),
+        Some(_) => "MISSING".to_string(),
+        None => "unknown".to_string(),
+    }
+}
+
+/// Finds the interface of the default route in `/proc/net/route` format.
+fn parse_proc_route(table: &str) -> Option<String> {
+    table.lines().skip(1).find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        (fields.len() > 2 && fields[1] == "00000000").then(|| fields[0].to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_has_network_error() {
+        assert!(has_network_error("Error: connect ECONNREFUSED 127.0.0.1:5432"));
+        assert!(has_network_error("curl: (6) Could not resolve host: example.invalid"));
+        assert!(!has_network_error("Compiling serde v1.0.0"));
+    }
+
+    #[test]
+    fn test_extract_targets() {
+        let log = "Error: connect ECONNREFUSED 127.0.0.1:5432\n\
+                   curl: (6) Could not resolve host: api.example.com\n\
+                   request to https://registry.npmjs.org/react failed, reason: connect ETIMEDOUT";
+        assert_eq!(
+            extract_targets(log),
+            vec![
+                Target { host: "127.0.0.1".to_string(), port: Some(5432) },
+                Target { host: "api.example.com".to_string(), port: None },
+                Target { host: "registry.npmjs.org".to_string(), port: Some(443) },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_proc_route() {
+        let table = "Iface\tDestination\tGateway\tFlags\n\
+                     eth0\t0000A8C0\t00000000\t0001\n\
+                     eth0\t00000000\t0100A8C0\t0003\n";
+        assert_eq!(parse_proc_route(table), Some("eth0".to_string()));
+        assert_eq!(parse_proc_route("Iface\tDestination\n"), None);
+    }
+}
@@ -0,0 +1,90 @@
+use super::{absolute_paths, probe, Section};
+use crate::rules::{Finding, Rule};
+use regex::Regex;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+const MAX_PATHS: usize = 3;
+
+/// Relative paths right before the error, e.g. `open 'data/x.db': Permission denied`.
+static PATH_BEFORE_ERROR: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?i)['"`]?([\w.~-]*/[^\s'"`:]*|[\w.-]+\.\w+)['"`]?:\s*(?:permission denied|operation not permitted|EACCES)"#).unwrap()
+});
+
+/// For permission-denied findings, reports the owner/mode of each referenced
+/// path (and its parent directory) together with the current user's identity
+/// and groups, so the fix can be a specific chown/chmod/usermod.
+pub fn collect(findings: &[Finding]) -> Option<Section> {
+    let lines: Vec<&str> = findings
+        .iter()
+        .filter(|f| f.rule == Rule::PermissionDenied)
+        .map(|f| f.line.as_str())
+        .collect();
+    if lines.is_empty() {
+        return None;
+    }
+
+    let mut body = String::new();
+    if let Some(id) = probe("id", &[]) {
+        body.push_str(&format!("Current user: {}\n", id));
+    }
+
+    for path in referenced_paths(&lines) {
+        let mut listed = Vec::new();
+        if path.exists() {
+            listed.push(path.clone());
+        }
+        if let Some(parent) = path.ancestors().skip(1).find(|p| p.exists() && !p.as_os_str().is_empty()) {
+            listed.push(parent.to_path_buf());
+        }
+        for entry in listed {
+            let entry = entry.to_string_lossy();
+            if let Some(ls) = probe("ls", &["-ld", &entry]) {
+                body.push_str(&format!("{}\n", ls));
+            }
+        }
+        if !path.exists() {
+            body.push_str(&format!("(path {} does not exist)\n", path.display()));
+        }
+    }
+
+    Some(Section::new("Permissions", body))
+}
+
+fn referenced_paths(lines: &[&str]) -> Vec<PathBuf> {
+    let mut paths = absolute_paths(lines);
+    for line in lines {
+        for cap in PATH_BEFORE_ERROR.captures_iter(line) {
+            let path = expand_home(&cap[1]);
+            if !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+    }
+    paths.truncate(MAX_PATHS);
+    paths
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => Path::new(path).to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_referenced_paths() {
+        let lines = [
+            "Error: EACCES: permission denied, open '/usr/lib/node_modules/x'",
+            "sqlite3.OperationalError: data/app.db: Permission denied",
+        ];
+        assert_eq!(
+            referenced_paths(&lines),
+            vec![PathBuf::from("/usr/lib/node_modules/x"), PathBuf::from("data/app.db")]
+        );
+    }
+}
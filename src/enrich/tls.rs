@@ -0,0 +1,227 @@
+use super::network::{targets_in_matching_lines, Target};
+use super::Section;
+use chrono::{NaiveDateTime, Utc};
+use regex::Regex;
+use std::sync::LazyLock;
+use std::time::{Duration, Instant};
+
+/// How long `openssl s_client` gets to connect and finish the handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Error phrases of TLS libraries, not a mere mention of a certificate.
+static TLS_ERROR: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)(x509: |tls handshake|ssl routines|ssl_error|certificate[ _]verify[ _]failed|certificate has expired|cert has expired|certificate is not (?:yet )?valid|bad certificate|certificate_unknown|unknown authority|self[- ]signed certificate|unable to get local issuer|unable to verify the first certificate|hostname mismatch|pkix path building failed|sslhandshakeexception)").unwrap()
+});
+static PEM_BLOCK: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?s)-----BEGIN CERTIFICATE-----.*?-----END CERTIFICATE-----").unwrap()
+});
+static VERIFY_RESULT: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"Verify return code: (\d+ \([^)]*\))").unwrap());
+
+/// The decoded facts about one certificate in the served chain.
+#[derive(Debug, Default, PartialEq)]
+pub struct CertInfo {
+    pub subject: String,
+    pub issuer: String,
+    pub not_after: Option<NaiveDateTime>,
+    pub dns_names: Vec<String>,
+}
+
+/// Returns true if the log contains TLS handshake or certificate errors.
+pub fn has_tls_error(log: &str) -> bool {
+    TLS_ERROR.is_match(log)
+}
+
+/// Fetches and decodes the certificate chain served by each host involved in
+/// a TLS error, reporting expiry, SAN mismatches and self-signed certificates.
+pub fn collect(log: &str) -> Option<Section> {
+    if !has_tls_error(log) {
+        return None;
+    }
+
+    let mut body = String::new();
+    for target in targets_in_matching_lines(log, &TLS_ERROR) {
+        body.push_str(&inspect(&target));
+    }
+    if body.is_empty() {
+        return None;
+    }
+    Some(Section::new("TLS Certificate Chain", body))
+}
+
+fn inspect(target: &Target) -> String {
+    let port = target.port.unwrap_or(443);
+    let connect = format!("{}:{}", target.host, port);
+    let handle = duct::cmd(
+        "openssl",
+        ["s_client", "-connect", &connect, "-servername", &target.host, "-showcerts"],
+    )
+    .stdin_bytes(Vec::new())
+    .stdout_capture()
+    .stderr_null()
+    .unchecked()
+    .start();
+
+    let Ok(handle) = handle else {
+        return format!("{}: could not run openssl\n", connect);
+    };
+    // An unreachable host would otherwise hold up the analysis for minutes
+    let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+    let output = loop {
+        match handle.try_wait() {
+            Ok(Some(output)) => break output,
+            Ok(None) if Instant::now() < deadline => std::thread::sleep(Duration::from_millis(50)),
+            Ok(None) => {
+                let _ = handle.kill();
+                return format!("{}: no handshake within {}s\n", connect, HANDSHAKE_TIMEOUT.as_secs());
+            }
+            Err(_) => return format!("{}: could not run openssl\n", connect),
+        }
+    };
+    let transcript = String::from_utf8_lossy(&output.stdout);
+    let certs: Vec<CertInfo> = PEM_BLOCK
+        .find_iter(&transcript)
+        .filter_map(|pem| decode(pem.as_str()))
+        .collect();
+    if certs.is_empty() {
+        return format!("{}: no certificate received (handshake failed)\n", connect);
+    }
+
+    let mut out = format!("Server {}:\n", connect);
+    if let Some(verify) = VERIFY_RESULT.captures(&transcript) {
+        out.push_str(&format!("  Verify result: {}\n", &verify[1]));
+    }
+    for finding in findings(&target.host, &certs, Utc::now().naive_utc()) {
+        out.push_str(&format!("  ! {}\n", finding));
+    }
+    for (i, cert) in certs.iter().enumerate() {
+        out.push_str(&format!("  [{}] subject: {}\n      issuer: {}\n", i, cert.subject, cert.issuer));
+        if let Some(not_after) = cert.not_after {
+            out.push_str(&format!("      expires: {} UTC\n", not_after));
+        }
+        if i == 0 && !cert.dns_names.is_empty() {
+            out.push_str(&format!("      SANs: {}\n", cert.dns_names.join(", ")));
+        }
+    }
+    out
+}
+
+/// Decodes one PEM certificate with `openssl x509`.
+fn decode(pem: &str) -> Option<CertInfo> {
+    let output = duct::cmd(
+        "openssl",
+        ["x509", "-noout", "-subject", "-issuer", "-enddate", "-ext", "subjectAltName"],
+    )
+    .stdin_bytes(pem.as_bytes())
+    .stdout_capture()
+    .stderr_null()
+    .unchecked()
+    .run()
+    .ok()?;
+    Some(parse_x509_text(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Parses the output of `openssl x509 -noout -subject -issuer -enddate -ext subjectAltName`.
+fn parse_x509_text(text: &str) -> CertInfo {
+    let mut info = CertInfo::default();
+    for line in text.lines() {
+        let line = line.trim();
+        if let Some(v) = line.strip_prefix("subject=") {
+            info.subject = v.trim().to_string();
+        } else if let Some(v) = line.strip_prefix("issuer=") {
+            info.issuer = v.trim().to_string();
+        } else if let Some(v) = line.strip_prefix("notAfter=") {
+            let normalized = v.split_whitespace().collect::<Vec<_>>().join(" ");
+            info.not_after = NaiveDateTime::parse_from_str(&normalized, "%b %d %H:%M:%S %Y GMT").ok();
+        } else if line.contains("DNS:") {
+            info.dns_names = line
+                .split(',')
+                .filter_map(|entry| entry.trim().strip_prefix("DNS:"))
+                .map(str::to_string)
+                .collect();
+        }
+    }
+    info
+}
+
+/// Turns the decoded chain into concrete problems for `host`.
+fn findings(host: &str, certs: &[CertInfo], now: NaiveDateTime) -> Vec<String> {
+    let mut findings = Vec::new();
+    let Some(leaf) = certs.first() else {
+        return findings;
+    };
+
+    for (i, cert) in certs.iter().enumerate() {
+        if let Some(not_after) = cert.not_after {
+            if not_after < now {
+                findings.push(format!("certificate [{}] EXPIRED on {} UTC", i, not_after));
+            } else if (not_after - now).num_days() < 14 {
+                findings.push(format!("certificate [{}] expires soon ({} UTC)", i, not_after));
+            }
+        }
+    }
+    if !leaf.dns_names.is_empty() && !leaf.dns_names.iter().any(|n| san_matches(n, host)) {
+        findings.push(format!("hostname {} does not match any SAN of the leaf certificate", host));
+    }
+    if !leaf.subject.is_empty() && leaf.subject == leaf.issuer {
+        findings.push("leaf certificate is SELF-SIGNED".to_string());
+    }
+    findings
+}
+
+fn san_matches(pattern: &str, host: &str) -> bool {
+    let pattern = pattern.to_ascii_lowercase();
+    let host = host.to_ascii_lowercase();
+    match pattern.strip_prefix("*.") {
+        Some(suffix) => host
+            .split_once('.')
+            .map(|(label, rest)| !label.is_empty() && rest == suffix)
+            .unwrap_or(false),
+        None => pattern == host,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const X509_TEXT: &str = "subject=CN = api.example.com\n\
+        issuer=C = US, O = Let's Encrypt, CN = R3\n\
+        notAfter=Jan  5 12:00:00 2025 GMT\n\
+        X509v3 Subject Alternative Name: \n    DNS:api.example.com, DNS:*.example.com\n";
+
+    #[test]
+    fn test_parse_x509_text() {
+        let info = parse_x509_text(X509_TEXT);
+        assert_eq!(info.subject, "CN = api.example.com");
+        assert_eq!(info.issuer, "C = US, O = Let's Encrypt, CN = R3");
+        assert_eq!(info.not_after.unwrap().to_string(), "2025-01-05 12:00:00");
+        assert_eq!(info.dns_names, vec!["api.example.com", "*.example.com"]);
+    }
+
+    #[test]
+    fn test_findings() {
+        let leaf = parse_x509_text(X509_TEXT);
+        let now = NaiveDateTime::parse_from_str("2025-06-01 00:00:00", "%Y-%m-%d %H:%M:%S").unwrap();
+        let found = findings("db.internal.net", &[leaf], now);
+        assert!(found.iter().any(|f| f.contains("EXPIRED")));
+        assert!(found.iter().any(|f| f.contains("does not match")));
+        assert!(!found.iter().any(|f| f.contains("SELF-SIGNED")));
+    }
+
+    #[test]
+    fn test_has_tls_error() {
+        assert!(has_tls_error("x509: certificate signed by unknown authority"));
+        assert!(has_tls_error("[SSL: CERTIFICATE_VERIFY_FAILED] certificate verify failed"));
+        assert!(has_tls_error("curl: (60) SSL certificate problem: unable to get local issuer certificate"));
+        assert!(!has_tls_error("Loaded certificate bundle from /etc/ssl/certs/ca-certificates.crt"));
+        assert!(!has_tls_error("Renewing certificate for api.example.com"));
+    }
+
+    #[test]
+    fn test_san_matches() {
+        assert!(san_matches("*.example.com", "api.example.com"));
+        assert!(!san_matches("*.example.com", "a.b.example.com"));
+        assert!(san_matches("Example.com", "example.com"));
+    }
+}
@@ -0,0 +1,136 @@
+//! Offers to run the shell commands suggested by the explanation, one at a
+//! time, with "skip" as the default so nothing runs without an explicit choice.
+//! `logtrains fix` also applies the patches it suggests.
+
+use crate::safety;
+use anyhow::{Context, Result};
+use colored::Colorize;
+use dialoguer::{Confirm, Input, Select};
+
+/// Lists `commands` and asks for each whether to run, skip or edit it.
+/// Returns how many were run.
+pub fn offer(commands: &[String]) -> Result<usize> {
+    println!("\n{}", "=== Suggested Commands ===".cyan().bold());
+    for (i, command) in commands.iter().enumerate() {
+        println!("  {}. {}", i + 1, command);
+    }
+
+    let mut ran = 0;
+    for (i, command) in commands.iter().enumerate() {
+        let choice = Select::new()
+            .with_prompt(format!("{}. {}", i + 1, command))
+            .items(&["Skip", "Run", "Edit"])
+            .default(0)
+            .interact_opt()?;
+        let command = match choice {
+            Some(1) => command.clone(),
+            Some(2) => Input::<String>::new()
+                .with_prompt("Command")
+                .with_initial_text(command.as_str())
+                .interact_text()?,
+            // Esc/q skips the remaining commands too
+            None => break,
+            _ => continue,
+        };
+        if command.trim().is_empty() {
+            continue;
+        }
+
+        let reasons = safety::check_command(&command);
+        if !reasons.is_empty() {
+            println!("{} {}", "Careful:".red().bold(), reasons.join("; "));
+            let confirmed = Confirm::new()
+                .with_prompt("Run it anyway?")
+                .default(false)
+                .interact()?;
+            if !confirmed {
+                continue;
+            }
+        }
+        run(&command)?;
+        ran += 1;
+    }
+    Ok(ran)
+}
+
+/// The unified diffs in the ```diff or ```patch blocks of an explanation.
+pub fn patches(markdown: &str) -> Vec<String> {
+    let mut patches = Vec::new();
+    let mut current: Option<String> = None;
+    for line in markdown.lines() {
+        if let Some(lang) = line.trim().strip_prefix("```") {
+            match current.take() {
+                Some(patch) if patch.lines().any(|l| l.starts_with("@@")) => patches.push(patch),
+                Some(_) => {}
+                None if matches!(lang.trim(), "diff" | "patch") => current = Some(String::new()),
+                None => {}
+            }
+            continue;
+        }
+        if let Some(patch) = &mut current {
+            patch.push_str(line);
+            patch.push('\n');
+        }
+    }
+    patches
+}
+
+/// Shows each patch and applies it with `git apply` if confirmed. Returns
+/// how many were applied.
+pub fn offer_patches(patches: &[String]) -> Result<usize> {
+    let mut applied = 0;
+    for (i, patch) in patches.iter().enumerate() {
+        println!("\n{}", format!("=== Suggested Patch {} ===", i + 1).cyan().bold());
+        for line in patch.lines() {
+            match line.chars().next() {
+                Some('+') if !line.starts_with("+++") => println!("{}", line.green()),
+                Some('-') if !line.starts_with("---") => println!("{}", line.red()),
+                _ => println!("{}", line),
+            }
+        }
+        let confirmed = Confirm::new().with_prompt("Apply this patch?").default(false).interact()?;
+        if !confirmed {
+            continue;
+        }
+        let output = duct::cmd("git", ["apply", "--recount", "-"])
+            .stdin_bytes(patch.as_bytes())
+            .stderr_to_stdout()
+            .stdout_capture()
+            .unchecked()
+            .run()
+            .context("Failed to run git apply")?;
+        if output.status.success() {
+            println!("{}", "Patch applied.".green());
+            applied += 1;
+        } else {
+            println!("{} {}", "Patch did not apply:".red(), String::from_utf8_lossy(&output.stdout).trim());
+        }
+    }
+    Ok(applied)
+}
+
+fn run(command: &str) -> Result<()> {
+    println!("{} {}", "$".dimmed(), command.bold());
+    let output = duct::cmd("sh", ["-c", command]).unchecked().run()?;
+    if output.status.success() {
+        println!("{}", "Command succeeded.".green());
+    } else {
+        println!("{} {}", "Command failed:".red(), output.status);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_patches() {
+        let answer = "Fix the import:\n\n```diff\n--- a/src/main.rs\n+++ b/src/main.rs\n@@ -1 +1 @@\n-use std::fs::Fil;\n+use std::fs::File;\n```\n\n```bash\ncargo build\n```\n\n```diff\nnot a patch\n```\n";
+        assert_eq!(
+            patches(answer),
+            ["--- a/src/main.rs\n+++ b/src/main.rs\n@@ -1 +1 @@\n-use std::fs::Fil;\n+use std::fs::File;\n"]
+        );
+        assert_eq!(safety::fenced_commands(answer), ["cargo build"]);
+    }
+}
@@ -0,0 +1,1038 @@
+//! Index of recorded commands, kept in SQLite next to the logs
+//! (`history.db` in the history directory). The shell hook writes the log
+//! file and then adds an entry with the real command line, working
+//! directory, timing and exit code. Command lines and log contents are
+//! also indexed for full-text search (SQLite FTS5). Entries can be tagged
+//! and annotated with a note, and every generated analysis is kept so it
+//! can be recalled without running the model again.
+
+use crate::llm::{ModelLoaderBuilder, Prompt};
+use crate::preprocess;
+use crate::severity::Severity;
+use crate::template::Vars;
+use anyhow::{Context, Result};
+use rusqlite::{named_params, params, Connection, OptionalExtension, Row};
+use regex::Regex;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::LazyLock;
+
+pub const DB_FILE: &str = "history.db";
+
+/// The index's tables by the history format version that added them (see
+/// `migrate`). Every step can be run again on an index that already has it.
+/// The last version is the one [`History::open`] brings an index to.
+const SCHEMA: &[(u32, &str)] = &[
+    (
+        2,
+        "CREATE TABLE IF NOT EXISTS entries (
+    id INTEGER PRIMARY KEY,
+    command TEXT NOT NULL,
+    cwd TEXT,
+    started_at INTEGER NOT NULL,
+    ended_at INTEGER,
+    exit_code INTEGER,
+    log_path TEXT NOT NULL UNIQUE
+);
+CREATE INDEX IF NOT EXISTS entries_started_at ON entries (started_at);",
+    ),
+    (3, "CREATE VIRTUAL TABLE IF NOT EXISTS entries_fts USING fts5 (command, log);"),
+    (
+        4,
+        "CREATE TABLE IF NOT EXISTS tags (
+    entry_id INTEGER NOT NULL,
+    tag TEXT NOT NULL,
+    PRIMARY KEY (entry_id, tag)
+);
+CREATE TABLE IF NOT EXISTS notes (
+    entry_id INTEGER PRIMARY KEY,
+    note TEXT NOT NULL
+);",
+    ),
+    (
+        5,
+        "CREATE TABLE IF NOT EXISTS analyses (
+    id INTEGER PRIMARY KEY,
+    entry_id INTEGER,
+    source TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    model TEXT NOT NULL,
+    severity TEXT NOT NULL,
+    explanation TEXT NOT NULL
+);",
+    ),
+    (6, ""),
+];
+
+/// Columns added to existing tables, by version. SQLite cannot add a column
+/// only if it is missing, so they are looked up first.
+const ADDED_COLUMNS: &[(u32, &str, &str, &str)] = &[(6, "analyses", "key", "TEXT")];
+
+const COLUMNS: &str = "id, command, cwd, started_at, ended_at, exit_code, log_path";
+
+const ANALYSIS_COLUMNS: &str = "id, entry_id, source, created_at, model, severity, explanation, key";
+
+/// How many analyses are kept; the oldest are dropped first.
+const MAX_ANALYSES: i64 = 200;
+
+/// Matches entries run in `:scope` or below it (any entry when it is NULL).
+const IN_SCOPE: &str = "(:scope IS NULL OR cwd = :scope OR substr(cwd, 1, length(:scope) + 1) = :scope || '/')";
+
+/// How much of a log is indexed for search; the interesting part of a huge
+/// log is rarely past the first few megabytes and the tail.
+const MAX_INDEXED_BYTES: usize = 4 * 1024 * 1024;
+
+static TRANSCRIPT_START: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\A\*{10,}\r?\n(?:Windows )?PowerShell transcript start\r?\n").unwrap());
+static TRANSCRIPT_RULE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?m)^\*{10,}\r?$\n?").unwrap());
+static TRANSCRIPT_END: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?m)^\*{10,}\r?\n(?:Windows )?PowerShell transcript end\r?$").unwrap());
+
+/// One recorded command. Times are Unix seconds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    /// Assigned by the index; ignored by [`History::add`].
+    pub id: i64,
+    pub command: String,
+    pub cwd: Option<String>,
+    pub started_at: i64,
+    pub ended_at: Option<i64>,
+    pub exit_code: Option<i32>,
+    pub log_path: PathBuf,
+}
+
+impl Entry {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            command: row.get(1)?,
+            cwd: row.get(2)?,
+            started_at: row.get(3)?,
+            ended_at: row.get(4)?,
+            exit_code: row.get(5)?,
+            log_path: PathBuf::from(row.get::<_, String>(6)?),
+        })
+    }
+
+    /// The start time in the local zone, for listings.
+    pub fn started_local(&self) -> String {
+        local_time(self.started_at)
+    }
+}
+
+/// Picks one entry: by its position in the `history` listing (`7`, newest
+/// is 1) or by its stable ID (`#42` or `id:42`, as shown by search).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Selector {
+    Index(usize),
+    Id(i64),
+}
+
+impl FromStr for Selector {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(id) = s.strip_prefix('#').or_else(|| s.strip_prefix("id:")) {
+            return id.parse().map(Selector::Id).map_err(|_| format!("invalid history ID: {}", s));
+        }
+        match s.parse() {
+            Ok(0) | Err(_) => Err(format!("expected an index (1 = newest) or an ID like #42, got: {}", s)),
+            Ok(index) => Ok(Selector::Index(index)),
+        }
+    }
+}
+
+impl std::fmt::Display for Selector {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Selector::Index(index) => write!(f, "{}", index),
+            Selector::Id(id) => write!(f, "#{}", id),
+        }
+    }
+}
+
+/// A generated explanation. `entry_id` links it to the recorded command it
+/// analyzed, when it was a single one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Analysis {
+    /// Assigned by the index; ignored by [`History::add_analysis`].
+    pub id: i64,
+    pub entry_id: Option<i64>,
+    /// What was analyzed (command lines, file names or `stdin`).
+    pub source: String,
+    pub created_at: i64,
+    pub model: String,
+    pub severity: Severity,
+    pub explanation: String,
+    /// The [`analysis_key`] of the settings it was made with; only then is it
+    /// reused instead of running the model. `None` for imported analyses.
+    pub key: Option<String>,
+}
+
+impl Analysis {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            entry_id: row.get(1)?,
+            source: row.get(2)?,
+            created_at: row.get(3)?,
+            model: row.get(4)?,
+            severity: Severity::from_label(&row.get::<_, String>(5)?).unwrap_or(Severity::Error),
+            explanation: row.get(6)?,
+            key: row.get(7)?,
+        })
+    }
+
+    pub fn created_local(&self) -> String {
+        local_time(self.created_at)
+    }
+}
+
+/// Identifies what decides an analysis besides the log: the model and its
+/// settings (including the answer language), the prompt, and the role,
+/// question and examples it is filled with.
+pub fn analysis_key(loader: &ModelLoaderBuilder, prompt: Option<&Prompt>, vars: &Vars) -> String {
+    crate::cache::key(&[
+        &format!("{:?}", loader),
+        &format!("{:?}", prompt),
+        &vars.role,
+        vars.question.as_deref().unwrap_or(""),
+        &format!("{:?}", vars.examples),
+    ])
+}
+
+pub struct History {
+    conn: Connection,
+    dir: PathBuf,
+    /// Directory that listings, `select` by index, search and tags are
+    /// limited to (see [`History::scoped`]).
+    scope: Option<String>,
+}
+
+impl History {
+    /// Opens (or creates) the index in `dir` and reconciles it with the log
+    /// files on disk: logs recorded by older hooks are indexed from their
+    /// file names, and entries whose log was deleted are dropped.
+    pub fn open(dir: &Path) -> Result<Self> {
+        let history = Self::connect(dir)?;
+        for (version, _) in SCHEMA {
+            history.apply_schema(*version)?;
+        }
+        history.sync()?;
+        Ok(history)
+    }
+
+    /// Applies the schema change of history format `version` to the index in
+    /// `dir`, for `logtrains migrate`: version 2 indexes the recorded logs by
+    /// their file names and version 3 indexes their contents for search.
+    pub fn upgrade(dir: &Path, version: u32) -> Result<()> {
+        let history = Self::connect(dir)?;
+        history.apply_schema(version)?;
+        match version {
+            2 => history.immediate(|| history.index_legacy_logs(false)),
+            3 => history.immediate(|| history.index_unindexed()),
+            _ => Ok(()),
+        }
+    }
+
+    fn apply_schema(&self, version: u32) -> Result<()> {
+        let (_, sql) = SCHEMA
+            .iter()
+            .find(|(v, _)| *v == version)
+            .with_context(|| format!("No history schema for version {}", version))?;
+        self.conn.execute_batch(sql)?;
+        for (_, table, column, kind) in ADDED_COLUMNS.iter().filter(|(v, ..)| *v == version) {
+            let exists: bool = self.conn.query_row(
+                "SELECT count(*) > 0 FROM pragma_table_info(?1) WHERE name = ?2",
+                [table, column],
+                |row| row.get(0),
+            )?;
+            if !exists {
+                self.conn.execute_batch(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, kind))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn connect(dir: &Path) -> Result<Self> {
+        std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        let path = dir.join(DB_FILE);
+        let conn = Connection::open(&path).with_context(|| format!("Failed to open {}", path.display()))?;
+        // Hooks index commands in the background, so writers can overlap
+        conn.busy_timeout(std::time::Duration::from_secs(5))?;
+        Ok(Self {
+            conn,
+            dir: dir.to_path_buf(),
+            scope: None,
+        })
+    }
+
+    /// The directory holding the index and the recorded logs.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Limits queries to commands run in `root` or a directory below it.
+    pub fn scoped(mut self, root: Option<&Path>) -> Self {
+        self.scope = root.map(|r| r.to_string_lossy().trim_end_matches('/').to_string());
+        self
+    }
+
+    /// Adds an entry (and its log contents to the search index) and returns
+    /// its id.
+    pub fn add(&self, entry: &Entry) -> Result<i64> {
+        self.immediate(|| self.insert(entry))
+    }
+
+    fn insert(&self, entry: &Entry) -> Result<i64> {
+        if let Some(id) = self.id_of(&entry.log_path)? {
+            self.remove(id)?;
+        }
+        let id = self.insert_row(entry)?;
+        self.index_contents(id, &entry.command, &entry.log_path)?;
+        Ok(id)
+    }
+
+    /// Adds the entry to `entries` only, leaving it out of the search index.
+    fn insert_row(&self, entry: &Entry) -> Result<i64> {
+        self.conn.execute(
+            "INSERT INTO entries (command, cwd, started_at, ended_at, exit_code, log_path)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                entry.command,
+                entry.cwd,
+                entry.started_at,
+                entry.ended_at,
+                entry.exit_code,
+                entry.log_path.to_string_lossy()
+            ],
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    /// Entries whose command line or log matches every word of `query`, best
+    /// match first, with a snippet of the matching log text.
+    pub fn search(&self, query: &str, failed_only: bool, limit: usize) -> Result<Vec<(Entry, String)>> {
+        let Some(query) = fts_query(query) else {
+            return Ok(Vec::new());
+        };
+        let columns = COLUMNS
+            .split(", ")
+            .map(|c| format!("e.{}", c))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {}, snippet(entries_fts, 1, '[', ']', '…', 12)
+             FROM entries_fts JOIN entries e ON e.id = entries_fts.rowid
+             WHERE entries_fts MATCH :query AND (:failed = 0 OR e.exit_code != 0) AND {}
+             ORDER BY rank LIMIT :limit",
+            columns, IN_SCOPE
+        ))?;
+        let params = named_params! {
+            ":query": query,
+            ":failed": failed_only,
+            ":scope": self.scope,
+            ":limit": limit as i64,
+        };
+        let results = stmt
+            .query_map(params, |row| {
+                Ok((Entry::from_row(row)?, row.get::<_, String>(7)?))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(results)
+    }
+
+    /// The most recent `limit` entries, newest first.
+    pub fn recent(&self, limit: usize) -> Result<Vec<Entry>> {
+        self.latest(false, limit)
+    }
+
+    /// The most recent `limit` entries that failed, newest first.
+    pub fn recent_failures(&self, limit: usize) -> Result<Vec<Entry>> {
+        self.latest(true, limit)
+    }
+
+    fn latest(&self, failed_only: bool, limit: usize) -> Result<Vec<Entry>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM entries WHERE (:failed = 0 OR exit_code != 0) AND {}
+             ORDER BY started_at DESC, id DESC LIMIT :limit",
+            COLUMNS, IN_SCOPE
+        ))?;
+        let params = named_params! { ":failed": failed_only, ":scope": self.scope, ":limit": limit as i64 };
+        let entries = stmt.query_map(params, Entry::from_row)?.collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(entries)
+    }
+
+    /// The entry picked by `selector`, if it exists.
+    pub fn select(&self, selector: Selector) -> Result<Option<Entry>> {
+        let entry = match selector {
+            Selector::Index(index) => self.recent(index)?.into_iter().nth(index - 1),
+            Selector::Id(id) => self
+                .conn
+                .query_row(
+                    &format!("SELECT {} FROM entries WHERE id = ?1", COLUMNS),
+                    [id],
+                    Entry::from_row,
+                )
+                .optional()?,
+        };
+        Ok(entry)
+    }
+
+    /// Adds `tag` to an entry (a no-op if it is already tagged).
+    pub fn tag(&self, id: i64, tag: &str) -> Result<()> {
+        self.conn
+            .execute("INSERT OR IGNORE INTO tags (entry_id, tag) VALUES (?1, ?2)", params![id, tag])?;
+        Ok(())
+    }
+
+    /// Removes `tag` from an entry. Returns whether it was tagged.
+    pub fn untag(&self, id: i64, tag: &str) -> Result<bool> {
+        let removed = self
+            .conn
+            .execute("DELETE FROM tags WHERE entry_id = ?1 AND tag = ?2", params![id, tag])?;
+        Ok(removed > 0)
+    }
+
+    /// The tags of an entry, sorted.
+    pub fn tags(&self, id: i64) -> Result<Vec<String>> {
+        let mut stmt = self.conn.prepare("SELECT tag FROM tags WHERE entry_id = ?1 ORDER BY tag")?;
+        let tags = stmt
+            .query_map([id], |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(tags)
+    }
+
+    /// Every tag in use, alphabetically.
+    pub fn all_tags(&self) -> Result<Vec<String>> {
+        let mut stmt = self.conn.prepare("SELECT DISTINCT tag FROM tags ORDER BY tag")?;
+        let tags = stmt.query_map([], |row| row.get(0))?.collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(tags)
+    }
+
+    /// All entries carrying `tag`, newest first.
+    pub fn tagged(&self, tag: &str) -> Result<Vec<Entry>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM entries WHERE id IN (SELECT entry_id FROM tags WHERE tag = :tag) AND {}
+             ORDER BY started_at DESC, id DESC",
+            COLUMNS, IN_SCOPE
+        ))?;
+        let entries = stmt
+            .query_map(named_params! { ":tag": tag, ":scope": self.scope }, Entry::from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(entries)
+    }
+
+    /// Sets (or with `None`, removes) the note on an entry.
+    pub fn set_note(&self, id: i64, note: Option<&str>) -> Result<()> {
+        match note {
+            Some(note) => self.conn.execute(
+                "INSERT OR REPLACE INTO notes (entry_id, note) VALUES (?1, ?2)",
+                params![id, note],
+            )?,
+            None => self.conn.execute("DELETE FROM notes WHERE entry_id = ?1", [id])?,
+        };
+        Ok(())
+    }
+
+    pub fn note(&self, id: i64) -> Result<Option<String>> {
+        let note = self
+            .conn
+            .query_row("SELECT note FROM notes WHERE entry_id = ?1", [id], |row| row.get(0))
+            .optional()?;
+        Ok(note)
+    }
+
+    /// Deletes all but the newest `keep` entries along with their logs.
+    /// Returns how many were removed.
+    pub fn prune(&self, keep: usize) -> Result<usize> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM entries ORDER BY started_at DESC, id DESC LIMIT -1 OFFSET ?1",
+            COLUMNS
+        ))?;
+        let old = stmt
+            .query_map([keep as i64], Entry::from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        self.delete(&old)
+    }
+
+    /// Deletes the entries started before `cutoff` (Unix seconds) along
+    /// with their logs. Returns how many were removed.
+    pub fn prune_older_than(&self, cutoff: i64) -> Result<usize> {
+        let mut stmt = self
+            .conn
+            .prepare(&format!("SELECT {} FROM entries WHERE started_at < ?1", COLUMNS))?;
+        let old = stmt
+            .query_map([cutoff], Entry::from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        self.delete(&old)
+    }
+
+    /// Deletes every entry and its log, and all analyses. Returns how many
+    /// entries were removed.
+    pub fn clear(&self) -> Result<usize> {
+        let removed = self.prune(0)?;
+        self.conn.execute("DELETE FROM analyses", [])?;
+        Ok(removed)
+    }
+
+    /// Stores an analysis, dropping the oldest beyond the limit.
+    pub fn add_analysis(&self, analysis: &Analysis) -> Result<i64> {
+        self.conn.execute(
+            "INSERT INTO analyses (entry_id, source, created_at, model, severity, explanation, key)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                analysis.entry_id,
+                analysis.source,
+                analysis.created_at,
+                analysis.model,
+                analysis.severity.label(),
+                analysis.explanation,
+                analysis.key
+            ],
+        )?;
+        let id = self.conn.last_insert_rowid();
+        self.conn.execute(
+            "DELETE FROM analyses WHERE id NOT IN (SELECT id FROM analyses ORDER BY id DESC LIMIT ?1)",
+            [MAX_ANALYSES],
+        )?;
+        Ok(id)
+    }
+
+    /// The `n`th most recent analysis (1 = latest).
+    pub fn analysis(&self, n: usize) -> Result<Option<Analysis>> {
+        let analysis = self
+            .conn
+            .query_row(
+                &format!("SELECT {} FROM analyses ORDER BY id DESC LIMIT 1 OFFSET ?1", ANALYSIS_COLUMNS),
+                [n.saturating_sub(1) as i64],
+                Analysis::from_row,
+            )
+            .optional()?;
+        Ok(analysis)
+    }
+
+    /// Every analysis of a recorded command, oldest first.
+    pub fn analyses_for(&self, entry_id: i64) -> Result<Vec<Analysis>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM analyses WHERE entry_id = ?1 ORDER BY id",
+            ANALYSIS_COLUMNS
+        ))?;
+        let analyses = stmt
+            .query_map([entry_id], Analysis::from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(analyses)
+    }
+
+    /// The latest analysis of a recorded command made with the settings
+    /// `key` identifies (see [`analysis_key`]).
+    pub fn analysis_with(&self, entry_id: i64, key: &str) -> Result<Option<Analysis>> {
+        let analysis = self
+            .conn
+            .query_row(
+                &format!(
+                    "SELECT {} FROM analyses WHERE entry_id = ?1 AND key = ?2 ORDER BY id DESC LIMIT 1",
+                    ANALYSIS_COLUMNS
+                ),
+                params![entry_id, key],
+                Analysis::from_row,
+            )
+            .optional()?;
+        Ok(analysis)
+    }
+
+    fn delete(&self, entries: &[Entry]) -> Result<usize> {
+        for entry in entries {
+            match std::fs::remove_file(&entry.log_path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    return Err(e).with_context(|| format!("Failed to remove {}", entry.log_path.display()));
+                }
+                _ => self.remove(entry.id)?,
+            }
+        }
+        Ok(entries.len())
+    }
+
+    fn id_of(&self, log_path: &Path) -> Result<Option<i64>> {
+        let id = self
+            .conn
+            .query_row(
+                "SELECT id FROM entries WHERE log_path = ?1",
+                [log_path.to_string_lossy()],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(id)
+    }
+
+    fn remove(&self, id: i64) -> Result<()> {
+        self.conn.execute("DELETE FROM entries WHERE id = ?1", [id])?;
+        self.conn.execute("DELETE FROM entries_fts WHERE rowid = ?1", [id])?;
+        self.conn.execute("DELETE FROM tags WHERE entry_id = ?1", [id])?;
+        self.conn.execute("DELETE FROM notes WHERE entry_id = ?1", [id])?;
+        self.conn.execute("DELETE FROM analyses WHERE entry_id = ?1", [id])?;
+        Ok(())
+    }
+
+    fn index_contents(&self, id: i64, command: &str, log_path: &Path) -> Result<()> {
+        let mut text = read_log(log_path).unwrap_or_default();
+        if text.len() > MAX_INDEXED_BYTES {
+            let mut cut = text.len() - MAX_INDEXED_BYTES / 2;
+            while !text.is_char_boundary(cut) {
+                cut += 1;
+            }
+            let tail = text.split_off(cut);
+            let mut head_end = MAX_INDEXED_BYTES / 2;
+            while !text.is_char_boundary(head_end) {
+                head_end -= 1;
+            }
+            text.truncate(head_end);
+            text.push('\n');
+            text.push_str(&tail);
+        }
+        let text = preprocess::clean_terminal_output(&text);
+        self.conn.execute(
+            "INSERT INTO entries_fts (rowid, command, log) VALUES (?1, ?2, ?3)",
+            params![id, command, text],
+        )?;
+        Ok(())
+    }
+
+    /// Runs `f` in a write transaction, so a concurrent `sync` cannot index a
+    /// log as a legacy entry between another process's check and insert.
+    fn immediate<T>(&self, f: impl FnOnce() -> Result<T>) -> Result<T> {
+        self.conn.execute_batch("BEGIN IMMEDIATE")?;
+        match f() {
+            Ok(value) => {
+                self.conn.execute_batch("COMMIT")?;
+                Ok(value)
+            }
+            Err(e) => {
+                let _ = self.conn.execute_batch("ROLLBACK");
+                Err(e)
+            }
+        }
+    }
+
+    fn sync(&self) -> Result<()> {
+        self.immediate(|| self.sync_files())
+    }
+
+    fn sync_files(&self) -> Result<()> {
+        self.index_legacy_logs(true)?;
+
+        let mut stmt = self.conn.prepare("SELECT id, log_path FROM entries")?;
+        let missing = stmt
+            .query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))?
+            .filter_map(|row| row.ok())
+            .filter(|(_, path)| !Path::new(path).exists())
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>();
+        for id in missing {
+            self.remove(id)?;
+        }
+        self.index_unindexed()
+    }
+
+    /// Indexes logs recorded by older hooks from their file names, and their
+    /// contents too when `searchable`.
+    fn index_legacy_logs(&self, searchable: bool) -> Result<()> {
+        for path in log_files(&self.dir)? {
+            if self.id_of(&path)?.is_some() {
+                continue;
+            }
+            if let Some(entry) = legacy_entry(&path) {
+                if searchable {
+                    self.insert(&entry)?;
+                } else {
+                    self.insert_row(&entry)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Indexes the contents of entries added before search existed.
+    fn index_unindexed(&self) -> Result<()> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT id, command, log_path FROM entries WHERE id NOT IN (SELECT rowid FROM entries_fts)")?;
+        let unindexed = stmt
+            .query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        for (id, command, log_path) in unindexed {
+            self.index_contents(id, &command, Path::new(&log_path))?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads a recorded log. Logs recorded by PowerShell are transcripts; their
+/// header and footer (user, host, start and end time) are dropped so only
+/// the command's output remains.
+pub fn read_log(path: &Path) -> Result<String> {
+    let bytes = std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let text = String::from_utf8_lossy(&bytes);
+    Ok(strip_transcript(text.trim_start_matches('\u{feff}')))
+}
+
+fn strip_transcript(text: &str) -> String {
+    if !TRANSCRIPT_START.is_match(text) {
+        return text.to_string();
+    }
+    // The header is the block between the first two rules
+    let mut rules = TRANSCRIPT_RULE.find_iter(text);
+    let body_start = rules.nth(1).map_or(0, |m| m.end());
+    let body = &text[body_start..];
+    let body = match TRANSCRIPT_END.find(body) {
+        Some(m) => &body[..m.start()],
+        None => body,
+    };
+    body.lines()
+        .filter(|line| !line.starts_with("Transcript started, output file is"))
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim_start_matches('\n')
+        .to_string()
+}
+
+/// Parses an age like `30m`, `12h`, `7d` or `2w` into seconds.
+pub fn parse_age(s: &str) -> Result<i64, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: i64 = number.parse().map_err(|_| format!("invalid age: {} (expected e.g. 7d)", s))?;
+    let unit_seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" | "" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(format!("invalid age unit in {} (use s, m, h, d or w)", s)),
+    };
+    number.checked_mul(unit_seconds).ok_or_else(|| format!("age too large: {}", s))
+}
+
+fn local_time(timestamp: i64) -> String {
+    chrono::DateTime::from_timestamp(timestamp, 0)
+        .map(|t| t.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_else(|| "Unknown Time".to_string())
+}
+
+/// The project `dir` belongs to: the nearest enclosing git work tree, or
+/// `dir` itself outside of one.
+pub fn project_root(dir: &Path) -> PathBuf {
+    dir.ancestors()
+        .find(|d| d.join(".git").exists())
+        .unwrap_or(dir)
+        .to_path_buf()
+}
+
+/// Whether only failing commands are kept (`LOGTRAINS_ONLY_FAILURES=1`).
+/// Any recorder must check this before adding a successful command.
+pub fn only_failures() -> bool {
+    env_flag(std::env::var("LOGTRAINS_ONLY_FAILURES").ok().as_deref())
+}
+
+fn env_flag(value: Option<&str>) -> bool {
+    value.is_some_and(|v| matches!(v.trim().to_lowercase().as_str(), "1" | "true" | "yes" | "on"))
+}
+
+/// Turns free text into an FTS5 query matching every word, quoting each
+/// one so characters like `-` or `:` are not read as query syntax.
+fn fts_query(text: &str) -> Option<String> {
+    let terms: Vec<String> = text
+        .split_whitespace()
+        .map(|word| format!("\"{}\"", word.replace('"', "\"\"")))
+        .collect();
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+/// Recorded logs in `dir`, named `log_{timestamp}_{slug}.log`.
+fn log_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let files = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .file_name()
+                    .and_then(|s| s.to_str())
+                    .is_some_and(|s| s.starts_with("log_") && s.ends_with(".log"))
+        })
+        .collect();
+    Ok(files)
+}
+
+/// What can be recovered about a log from its file name alone: the start
+/// time and the sanitized command slug.
+fn legacy_entry(path: &Path) -> Option<Entry> {
+    let name = path.file_name()?.to_str()?.strip_prefix("log_")?.strip_suffix(".log")?;
+    let (timestamp, slug) = name.split_once('_').unwrap_or((name, ""));
+    Some(Entry {
+        id: 0,
+        command: if slug.is_empty() { "unknown".to_string() } else { slug.to_string() },
+        cwd: None,
+        started_at: timestamp.parse().ok()?,
+        ended_at: None,
+        exit_code: None,
+        log_path: path.to_path_buf(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(dir: &Path, command: &str, started_at: i64, exit_code: i32) -> Entry {
+        let log_path = dir.join(format!("log_{}_x.log", started_at));
+        std::fs::write(&log_path, format!("output of {}\n", command)).unwrap();
+        Entry {
+            id: 0,
+            command: command.to_string(),
+            cwd: Some("/src/app".to_string()),
+            started_at,
+            ended_at: Some(started_at + 3),
+            exit_code: Some(exit_code),
+            log_path,
+        }
+    }
+
+    #[test]
+    fn test_add_and_recent() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = History::open(dir.path()).unwrap();
+        history.add(&entry(dir.path(), "cargo build --release", 100, 101)).unwrap();
+        history.add(&entry(dir.path(), "npm ci", 200, 0)).unwrap();
+
+        let recent = history.recent(10).unwrap();
+        assert_eq!(recent.len(), 2);
+        assert_eq!(recent[0].command, "npm ci");
+        assert_eq!(recent[1].command, "cargo build --release");
+        assert_eq!(recent[1].exit_code, Some(101));
+        assert_eq!(recent[1].cwd.as_deref(), Some("/src/app"));
+    }
+
+    #[test]
+    fn test_sync_indexes_legacy_logs_and_drops_missing() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("log_1700000000_npm_install.log"), "npm ERR!\n").unwrap();
+        let gone = {
+            let history = History::open(dir.path()).unwrap();
+            let gone = entry(dir.path(), "make", 1700000100, 2);
+            history.add(&gone).unwrap();
+            gone
+        };
+        std::fs::remove_file(&gone.log_path).unwrap();
+
+        let recent = History::open(dir.path()).unwrap().recent(10).unwrap();
+        assert_eq!(recent.len(), 1);
+        assert_eq!(recent[0].command, "npm_install");
+        assert_eq!(recent[0].started_at, 1700000000);
+        assert_eq!(recent[0].exit_code, None);
+    }
+
+    #[test]
+    fn test_legacy_logs_listed_newest_first() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::File::create(dir.path().join("log_1672531200_test1.log")).unwrap(); // Oldest
+        std::fs::File::create(dir.path().join("log_1672531201_test2.log")).unwrap();
+        std::fs::File::create(dir.path().join("log_1672531202_test3.log")).unwrap(); // Newest
+        std::fs::File::create(dir.path().join("not_a_log_file.txt")).unwrap(); // Should be ignored
+
+        let recent = History::open(dir.path()).unwrap().recent(10).unwrap();
+        let commands: Vec<_> = recent.iter().map(|e| e.command.as_str()).collect();
+        assert_eq!(commands, ["test3", "test2", "test1"]);
+    }
+
+    #[test]
+    fn test_search() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = History::open(dir.path()).unwrap();
+        let mut build = entry(dir.path(), "docker build -t api .", 100, 1);
+        std::fs::write(&build.log_path, "Step 3/7 : RUN npm ci
+npm ERR! network timeout
+").unwrap();
+        build.id = history.add(&build).unwrap();
+        history.add(&entry(dir.path(), "docker ps", 200, 0)).unwrap();
+
+        let found = history.search("docker timeout", false, 10).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].0, build);
+        assert!(found[0].1.contains("network [timeout]"));
+
+        assert_eq!(history.search("docker", false, 10).unwrap().len(), 2);
+        assert_eq!(history.search("docker", true, 10).unwrap().len(), 1);
+        assert!(history.search("\"unbalanced -x", false, 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_tags_and_notes() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = History::open(dir.path()).unwrap();
+        let first = history.add(&entry(dir.path(), "ci run 1", 100, 1)).unwrap();
+        let second = history.add(&entry(dir.path(), "ci run 2", 200, 0)).unwrap();
+        let third = history.add(&entry(dir.path(), "ci run 3", 300, 1)).unwrap();
+        history.tag(first, "flaky-ci").unwrap();
+        history.tag(third, "flaky-ci").unwrap();
+        history.tag(third, "flaky-ci").unwrap();
+        history.tag(third, "arm64").unwrap();
+        history.set_note(third, Some("only fails on the arm runner")).unwrap();
+
+        let tagged = history.tagged("flaky-ci").unwrap();
+        assert_eq!(tagged.iter().map(|e| e.id).collect::<Vec<_>>(), [third, first]);
+        assert_eq!(history.tags(third).unwrap(), ["arm64", "flaky-ci"]);
+        assert_eq!(history.note(third).unwrap().as_deref(), Some("only fails on the arm runner"));
+        assert_eq!(history.note(second).unwrap(), None);
+
+        assert!(history.untag(first, "flaky-ci").unwrap());
+        assert!(!history.untag(first, "flaky-ci").unwrap());
+        history.prune(0).unwrap();
+        assert!(history.tagged("flaky-ci").unwrap().is_empty());
+        assert_eq!(history.note(third).unwrap(), None);
+    }
+
+    #[test]
+    fn test_analyses() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = History::open(dir.path()).unwrap();
+        let id = history.add(&entry(dir.path(), "make", 100, 2)).unwrap();
+        let analysis = |entry_id, explanation: &str| Analysis {
+            id: 0,
+            entry_id,
+            source: "make".to_string(),
+            created_at: 500,
+            model: "repo/model.gguf".to_string(),
+            severity: Severity::Error,
+            explanation: explanation.to_string(),
+            key: Some("settings".to_string()),
+        };
+        history.add_analysis(&analysis(Some(id), "first pass")).unwrap();
+        history.add_analysis(&analysis(Some(id), "second pass")).unwrap();
+        history.add_analysis(&analysis(None, "piped log")).unwrap();
+
+        assert_eq!(history.analysis(1).unwrap().unwrap().explanation, "piped log");
+        assert_eq!(history.analysis(3).unwrap().unwrap().explanation, "first pass");
+        assert_eq!(history.analysis(4).unwrap(), None);
+        let latest = history.analysis_with(id, "settings").unwrap().unwrap();
+        assert_eq!((latest.explanation.as_str(), latest.severity), ("second pass", Severity::Error));
+        assert_eq!(history.analysis_with(id, "other settings").unwrap(), None);
+
+        let loader = ModelLoaderBuilder::new("repo", "model.gguf");
+        let vars = Vars::local(None);
+        let key = analysis_key(&loader, None, &vars);
+        assert_eq!(key, analysis_key(&loader, None, &vars));
+        assert_ne!(key, analysis_key(&loader.clone().language(Some("German")), None, &vars));
+        assert_ne!(key, analysis_key(&loader, Some(&Prompt::System("Be terse.".to_string())), &vars));
+        let question = Vars { question: Some("Is it the proxy?".to_string()), ..vars.clone() };
+        assert_ne!(key, analysis_key(&loader, None, &question));
+
+        history.prune(0).unwrap();
+        assert!(history.analyses_for(id).unwrap().is_empty());
+        assert_eq!(history.analysis(1).unwrap().unwrap().explanation, "piped log");
+    }
+
+    #[test]
+    fn test_prune_older_than_and_clear() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = History::open(dir.path()).unwrap();
+        for started_at in [100, 200, 300] {
+            history.add(&entry(dir.path(), "make", started_at, 0)).unwrap();
+        }
+        assert_eq!(history.prune_older_than(250).unwrap(), 2);
+        assert_eq!(history.recent(10).unwrap()[0].started_at, 300);
+        assert_eq!(history.clear().unwrap(), 1);
+        assert!(history.recent(10).unwrap().is_empty());
+        assert!(!dir.path().join("log_300_x.log").exists());
+    }
+
+    #[test]
+    fn test_powershell_transcript_is_stripped() {
+        let transcript = "\u{feff}**********************\r\nPowerShell transcript start\r\nStart time: 20240101120000\r\n\
+                          **********************\r\nTranscript started, output file is C:\\logs\\log_1_x.log\r\n\
+                          error: build failed\r\n**********************\r\nPowerShell transcript end\r\n\
+                          End time: 20240101120005\r\n**********************\r\n";
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("log_1_x.log");
+        std::fs::write(&path, transcript).unwrap();
+        assert_eq!(read_log(&path).unwrap().trim_end(), "error: build failed");
+        assert_eq!(strip_transcript("plain\noutput\n"), "plain\noutput\n");
+    }
+
+    #[test]
+    fn test_parse_age() {
+        assert_eq!(parse_age("7d"), Ok(7 * 86_400));
+        assert_eq!(parse_age("12h"), Ok(12 * 3_600));
+        assert_eq!(parse_age("2w"), Ok(14 * 86_400));
+        assert_eq!(parse_age("3"), Ok(3 * 86_400));
+        assert!(parse_age("7y").is_err());
+        assert!(parse_age("d").is_err());
+        assert!(parse_age("99999999999999w").is_err());
+    }
+
+    #[test]
+    fn test_scoped_to_project() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = History::open(dir.path()).unwrap();
+        for (started_at, cwd) in [(100, "/src/app"), (200, "/src/app/web"), (300, "/src/application"), (400, "/tmp")] {
+            let mut e = entry(dir.path(), &format!("make in {}", cwd), started_at, 1);
+            e.cwd = Some(cwd.to_string());
+            history.add(&e).unwrap();
+        }
+        let history = history.scoped(Some(Path::new("/src/app/")));
+        let cwds = |entries: Vec<Entry>| entries.into_iter().map(|e| e.cwd.unwrap()).collect::<Vec<_>>();
+        assert_eq!(cwds(history.recent(10).unwrap()), ["/src/app/web", "/src/app"]);
+        assert_eq!(history.select(Selector::Index(2)).unwrap().unwrap().cwd.as_deref(), Some("/src/app"));
+        assert_eq!(history.search("make", false, 10).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_project_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let nested = dir.path().join("repo/src/bin");
+        std::fs::create_dir_all(&nested).unwrap();
+        std::fs::create_dir(dir.path().join("repo/.git")).unwrap();
+        assert_eq!(project_root(&nested), dir.path().join("repo"));
+        assert_eq!(project_root(dir.path()), dir.path());
+    }
+
+    #[test]
+    fn test_env_flag() {
+        assert!(env_flag(Some("1")));
+        assert!(env_flag(Some("true")));
+        assert!(!env_flag(Some("0")));
+        assert!(!env_flag(Some("")));
+        assert!(!env_flag(None));
+    }
+
+    #[test]
+    fn test_select() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = History::open(dir.path()).unwrap();
+        let first = history.add(&entry(dir.path(), "make", 100, 2)).unwrap();
+        history.add(&entry(dir.path(), "make test", 200, 0)).unwrap();
+
+        let command = |selector| history.select(selector).unwrap().map(|e| e.command);
+        assert_eq!(command("1".parse().unwrap()).as_deref(), Some("make test"));
+        assert_eq!(command("2".parse().unwrap()).as_deref(), Some("make"));
+        assert_eq!(command(format!("#{}", first).parse().unwrap()).as_deref(), Some("make"));
+        assert_eq!(command(Selector::Index(3)), None);
+        assert_eq!(command(Selector::Id(999)), None);
+        assert!("0".parse::<Selector>().is_err());
+        assert_eq!("id:7".parse(), Ok(Selector::Id(7)));
+    }
+
+    #[test]
+    fn test_prune_keeps_newest() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = History::open(dir.path()).unwrap();
+        for i in 0..5 {
+            history.add(&entry(dir.path(), &format!("step {}", i), i, 0)).unwrap();
+        }
+        assert_eq!(history.prune(2).unwrap(), 3);
+        let recent = history.recent(10).unwrap();
+        assert_eq!(recent.iter().map(|e| e.command.as_str()).collect::<Vec<_>>(), ["step 4", "step 3"]);
+        assert!(!dir.path().join("log_0_x.log").exists());
+    }
+}
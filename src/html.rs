@@ -0,0 +1,106 @@
+//! Self-contained HTML report (`--format html`): the analyzed log with error
+//! lines highlighted, next to the rendered explanation. No external assets,
+//! so the file can be mailed or attached as is.
+
+use crate::severity::Severity;
+use pulldown_cmark::{html, Event, Parser};
+use std::collections::BTreeSet;
+
+const STYLE: &str = r#"
+body { font-family: -apple-system, "Segoe UI", Helvetica, Arial, sans-serif; margin: 2rem auto; max-width: 70rem; padding: 0 1rem; color: #1f2328; }
+header dl { display: grid; grid-template-columns: max-content auto; gap: .25rem 1rem; }
+dt { font-weight: 600; }
+.badge { display: inline-block; padding: .1rem .6rem; border-radius: 1rem; color: #fff; font-weight: 600; text-transform: uppercase; font-size: .8rem; }
+.badge.ok { background: #1a7f37; } .badge.warning { background: #9a6700; } .badge.error { background: #cf222e; } .badge.fatal { background: #82071e; }
+pre, code { font-family: ui-monospace, SFMono-Regular, Menlo, Consolas, monospace; font-size: .85rem; }
+.explanation pre { background: #f6f8fa; padding: .75rem; overflow-x: auto; }
+.log { background: #0d1117; color: #e6edf3; padding: .75rem 0; overflow-x: auto; line-height: 1.4; }
+.log span { display: block; padding: 0 .75rem; white-space: pre; }
+.log span::before { content: attr(data-line); display: inline-block; width: 4rem; color: #7d8590; user-select: none; }
+.log span.err { background: #5a1e1e; }
+"#;
+
+/// Everything that goes into the report.
+pub struct HtmlReport<'a> {
+    pub source: &'a str,
+    pub model: &'a str,
+    pub severity: Severity,
+    /// Markdown explanation from the model.
+    pub explanation: &'a str,
+    /// The analyzed (cleaned and filtered) log.
+    pub log: &'a str,
+    /// 1-based numbers of the lines to highlight.
+    pub error_lines: &'a BTreeSet<usize>,
+}
+
+pub fn render(report: &HtmlReport) -> String {
+    let mut log = String::new();
+    for (i, line) in report.log.lines().enumerate() {
+        let class = if report.error_lines.contains(&(i + 1)) { " class=\"err\"" } else { "" };
+        log.push_str(&format!("<span data-line=\"{}\"{}>{}</span>\n", i + 1, class, escape(line)));
+    }
+
+    format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>LogTrains Analysis: {source}</title>\n<style>{style}</style>\n</head>\n<body>\n\
+<header>\n<h1>LogTrains Analysis</h1>\n<dl>\n<dt>Source</dt><dd><code>{source}</code></dd>\n<dt>Date</dt><dd>{date}</dd>\n<dt>Model</dt><dd>{model}</dd>\n<dt>Severity</dt><dd><span class=\"badge {severity}\">{severity}</span></dd>\n</dl>\n</header>\n\
+<section class=\"explanation\">\n<h2>Explanation</h2>\n{explanation}</section>\n\
+<section>\n<h2>Log</h2>\n<pre class=\"log\">{log}</pre>\n</section>\n</body>\n</html>\n",
+        source = escape(report.source),
+        style = STYLE,
+        date = chrono::Local::now().format("%Y-%m-%d %H:%M:%S %:z"),
+        model = escape(report.model),
+        severity = report.severity.label(),
+        explanation = markdown_to_html(report.explanation),
+        log = log,
+    )
+}
+
+/// Renders Markdown, escaping any raw HTML the model produced.
+fn markdown_to_html(markdown: &str) -> String {
+    let events = Parser::new(markdown).map(|event| match event {
+        Event::Html(raw) | Event::InlineHtml(raw) => Event::Text(raw),
+        other => other,
+    });
+    let mut out = String::new();
+    html::push_html(&mut out, events);
+    out
+}
+
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let error_lines = BTreeSet::from([2]);
+        let page = render(&HtmlReport {
+            source: "make <all>",
+            model: "repo/model.gguf",
+            severity: Severity::Error,
+            explanation: "The **build** failed.\n\n<script>alert(1)</script>\n",
+            log: "cc -c main.c\nmain.c:3: error: expected ';'\n",
+            error_lines: &error_lines,
+        });
+        assert!(page.contains("<code>make &lt;all&gt;</code>"));
+        assert!(page.contains("<span class=\"badge error\">error</span>"));
+        assert!(page.contains("<strong>build</strong>"));
+        assert!(!page.contains("<script>"));
+        assert!(page.contains("<span data-line=\"1\">cc -c main.c</span>"));
+        assert!(page.contains("<span data-line=\"2\" class=\"err\">main.c:3: error: expected &#39;;&#39;</span>"));
+    }
+}
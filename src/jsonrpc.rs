@@ -0,0 +1,70 @@
+//! JSON-RPC 2.0 as `logtrains mcp` and `logtrains --rpc` speak it: one
+//! message per line on stdin/stdout, with the standard error codes.
+
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use std::io::Write;
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const INTERNAL_ERROR: i64 = -32603;
+
+/// The message on one line of input, or else the error response to it.
+pub fn parse(line: &str) -> Result<Value, Value> {
+    serde_json::from_str(line).map_err(|e| error(Value::Null, PARSE_ERROR, &format!("Invalid JSON: {}", e)))
+}
+
+pub fn response(id: Value, result: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "result": result })
+}
+
+pub fn error(id: Value, code: i64, message: &str) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+pub fn method_not_found(id: Value, method: &str) -> Value {
+    error(id, METHOD_NOT_FOUND, &format!("Method not found: {}", method))
+}
+
+/// Writes `message` on its own line.
+pub fn send(output: &mut impl Write, message: &Value) -> Result<()> {
+    writeln!(output, "{}", message).context("Failed to write a response")?;
+    output.flush().context("Failed to write a response")
+}
+
+/// The `question` of a request's parameters, unless it is blank.
+pub fn question(params: &Value) -> Option<String> {
+    params
+        .get("question")
+        .and_then(Value::as_str)
+        .map(str::trim)
+        .filter(|q| !q.is_empty())
+        .map(str::to_string)
+}
+
+/// The messages a server wrote, one per line.
+#[cfg(test)]
+pub fn messages(output: Vec<u8>) -> Vec<Value> {
+    String::from_utf8(output).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_messages() {
+        assert_eq!(parse("not json").unwrap_err()["error"]["code"], PARSE_ERROR);
+        assert_eq!(method_not_found(json!(3), "format")["error"]["message"], "Method not found: format");
+        assert_eq!(question(&json!({ "question": "  why? " })).as_deref(), Some("why?"));
+        assert_eq!(question(&json!({ "question": " " })), None);
+
+        let mut output = Vec::new();
+        send(&mut output, &response(json!(1), json!({}))).unwrap();
+        send(&mut output, &error(json!("a"), INVALID_PARAMS, "`text` is required")).unwrap();
+        let sent = messages(output);
+        assert_eq!(sent[0], json!({ "jsonrpc": "2.0", "id": 1, "result": {} }));
+        assert_eq!(sent[1]["error"]["code"], INVALID_PARAMS);
+    }
+}
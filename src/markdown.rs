@@ -0,0 +1,183 @@
+//! Renders the model's Markdown for the terminal as it streams in: headings,
+//! emphasis, inline code, lists, quotes and highlighted code blocks.
+
+use colored::Colorize;
+use regex::{Captures, Regex};
+use std::sync::LazyLock;
+
+static FENCE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\s*(```|~~~)\s*([\w+-]*)").unwrap());
+static HEADING: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\s{0,3}(#{1,6})\s+(.*?)\s*#*\s*$").unwrap());
+static BULLET: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^(\s*)[-*+]\s+(.*)$").unwrap());
+static NUMBERED: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^(\s*)(\d+[.)])\s+(.*)$").unwrap());
+static QUOTE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\s*>\s?(.*)$").unwrap());
+static RULE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\s{0,3}([-*_])(\s*[-*_]){2,}\s*$").unwrap());
+static INLINE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"`([^`]+)`|\*\*([^*]+)\*\*|__([^_]+)__|\*([^*\s][^*]*)\*|\[([^\]]+)\]\(([^)\s]+)\)").unwrap()
+});
+static STRING: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#""(?:[^"\\]|\\.)*"|'[^']*'"#).unwrap());
+static KEYWORD: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"\b(fn|let|mut|pub|use|impl|struct|enum|match|def|class|import|from|return|if|then|else|elif|fi|for|while|do|done|in|function|const|var|async|await|export|sudo)\b",
+    )
+    .unwrap()
+});
+
+/// Line-buffered Markdown renderer: text is pushed as it arrives and each
+/// line is rendered once it is complete, since styling depends on the whole
+/// line (and on whether it sits inside a code block).
+#[derive(Default)]
+pub struct Renderer {
+    pending: String,
+    /// Language of the code block being rendered, if inside one.
+    code: Option<String>,
+}
+
+impl Renderer {
+    /// Adds streamed text and returns the rendering of any lines it completed.
+    pub fn push(&mut self, text: &str) -> String {
+        self.pending.push_str(text);
+        let mut out = String::new();
+        while let Some(end) = self.pending.find('\n') {
+            let line: String = self.pending.drain(..=end).collect();
+            out.push_str(&self.render_line(line.trim_end_matches(['\n', '\r'])));
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Renders whatever is left of an unterminated last line and resets the
+    /// state for the next answer.
+    pub fn finish(&mut self) -> String {
+        let rest = std::mem::take(&mut self.pending);
+        let out = if rest.is_empty() { rest } else { self.render_line(&rest) };
+        self.code = None;
+        out
+    }
+
+    fn render_line(&mut self, line: &str) -> String {
+        if let Some(fence) = FENCE.captures(line) {
+            self.code = match self.code {
+                Some(_) => None,
+                None => Some(fence[2].to_lowercase()),
+            };
+            return line.dimmed().to_string();
+        }
+        if let Some(lang) = &self.code {
+            return highlight(line, lang);
+        }
+
+        if let Some(c) = HEADING.captures(line) {
+            let text = inline(&c[2]);
+            return if c[1].len() == 1 {
+                text.bold().underline().to_string()
+            } else {
+                text.bold().to_string()
+            };
+        }
+        if RULE.is_match(line) {
+            return "─".repeat(40).dimmed().to_string();
+        }
+        if let Some(c) = BULLET.captures(line) {
+            return format!("{}{} {}", &c[1], "•".cyan(), inline(&c[2]));
+        }
+        if let Some(c) = NUMBERED.captures(line) {
+            return format!("{}{} {}", &c[1], c[2].cyan(), inline(&c[3]));
+        }
+        if let Some(c) = QUOTE.captures(line) {
+            return format!("{} {}", "│".dimmed(), inline(&c[1]).italic());
+        }
+        inline(line)
+    }
+}
+
+/// Styles emphasis, inline code and links within a line.
+fn inline(text: &str) -> String {
+    INLINE
+        .replace_all(text, |c: &Captures| {
+            if let Some(code) = c.get(1) {
+                code.as_str().cyan().to_string()
+            } else if let Some(bold) = c.get(2).or_else(|| c.get(3)) {
+                bold.as_str().bold().to_string()
+            } else if let Some(italic) = c.get(4) {
+                italic.as_str().italic().to_string()
+            } else {
+                format!("{} {}", c[5].underline(), format!("({})", &c[6]).dimmed())
+            }
+        })
+        .into_owned()
+}
+
+/// A small highlighter for code blocks: comments, string literals and common
+/// keywords, which covers the shell commands and snippets models suggest.
+fn highlight(line: &str, lang: &str) -> String {
+    let comment = match lang {
+        "rust" | "rs" | "c" | "cpp" | "c++" | "go" | "java" | "js" | "javascript" | "ts" | "typescript" | "kotlin"
+        | "swift" => "//",
+        _ => "#",
+    };
+    let (code, trailing) = match comment_start(line, comment) {
+        Some(i) => line.split_at(i),
+        None => (line, ""),
+    };
+
+    let mut out = String::new();
+    let mut last = 0;
+    for m in STRING.find_iter(code) {
+        out.push_str(&keywords(&code[last..m.start()]));
+        out.push_str(&m.as_str().green().to_string());
+        last = m.end();
+    }
+    out.push_str(&keywords(&code[last..]));
+    if !trailing.is_empty() {
+        out.push_str(&trailing.dimmed().to_string());
+    }
+    out
+}
+
+fn keywords(code: &str) -> String {
+    KEYWORD
+        .replace_all(code, |c: &Captures| c[0].magenta().to_string())
+        .into_owned()
+}
+
+/// Where a line comment starts, ignoring markers inside string literals and
+/// (for `#`) in the middle of a word, like `$#` or `foo#bar`.
+fn comment_start(line: &str, marker: &str) -> Option<usize> {
+    let strings: Vec<_> = STRING.find_iter(line).map(|m| m.range()).collect();
+    line.match_indices(marker).map(|(i, _)| i).find(|&i| {
+        let in_string = strings.iter().any(|r| r.contains(&i));
+        let at_word_start = i == 0 || line[..i].ends_with(char::is_whitespace);
+        !in_string && (marker != "#" || at_word_start)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strip(text: &str) -> String {
+        Regex::new("\x1b\\[[0-9;]*m").unwrap().replace_all(text, "").into_owned()
+    }
+
+    #[test]
+    fn test_lines_render_when_complete() {
+        let mut renderer = Renderer::default();
+        assert_eq!(renderer.push("## Probable"), "");
+        assert_eq!(strip(&renderer.push(" Cause\n- run `make`\n1. then")), "Probable Cause\n• run make\n");
+        assert_eq!(strip(&renderer.finish()), "1. then");
+    }
+
+    #[test]
+    fn test_code_blocks_are_not_styled_as_markdown() {
+        let mut renderer = Renderer::default();
+        let out = renderer.push("```bash\n# install it\n- not a bullet **x**\n```\n**bold**\n");
+        assert_eq!(strip(&out), "```bash\n# install it\n- not a bullet **x**\n```\nbold\n");
+    }
+
+    #[test]
+    fn test_comment_start() {
+        assert_eq!(comment_start("echo hi # note", "#"), Some(8));
+        assert_eq!(comment_start("echo '#no' $#", "#"), None);
+        assert_eq!(comment_start("let url = \"http://x\"; // why", "//"), Some(22));
+    }
+}
@@ -0,0 +1,281 @@
+//! `logtrains mcp`: a Model Context Protocol server on stdin/stdout, so
+//! coding agents and editors can call the local analysis pipeline as tools.
+//! Messages are JSON-RPC 2.0, one per line; stdout carries nothing else.
+
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Value};
+use std::io::{BufRead, Write};
+
+use crate::history::{self, History};
+use crate::jsonrpc::{self, question, INVALID_PARAMS};
+use crate::llm::{self, Inferencer, ModelLoaderBuilder};
+use logtrains_core::{analyze, AnalysisRequest};
+
+pub const PROTOCOL_VERSION: &str = "2024-11-05";
+
+/// Results of `search_history` unless the caller asks for more.
+const DEFAULT_SEARCH_LIMIT: u64 = 10;
+
+pub struct Server {
+    history: History,
+    loader: ModelLoaderBuilder,
+    /// Loaded on the first call that needs it.
+    engine: Option<Inferencer>,
+    /// `repo/file`, recorded with stored analyses.
+    model: String,
+    /// The [`history::analysis_key`] of the model and prompt.
+    key: String,
+    /// What every analysis starts from: the prompt, role, examples and
+    /// redaction, without a log.
+    request: AnalysisRequest,
+}
+
+impl Server {
+    pub fn new(
+        history: History,
+        loader: ModelLoaderBuilder,
+        model: String,
+        key: String,
+        request: AnalysisRequest,
+    ) -> Self {
+        Self { history, loader, engine: None, model, key, request }
+    }
+
+    /// Answers requests from `input` on `output` until `input` ends.
+    pub async fn serve(mut self, input: impl BufRead, mut output: impl Write) -> Result<()> {
+        for line in input.lines() {
+            let line = line.context("Failed to read a request")?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let response = match jsonrpc::parse(&line) {
+                Ok(message) => self.handle(message).await,
+                Err(error) => Some(error),
+            };
+            if let Some(response) = response {
+                jsonrpc::send(&mut output, &response)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The response to one message, or `None` for a notification.
+    async fn handle(&mut self, message: Value) -> Option<Value> {
+        let id = message.get("id").cloned()?;
+        let params = message.get("params").cloned().unwrap_or(Value::Null);
+        let result = match message.get("method").and_then(Value::as_str).unwrap_or("") {
+            "initialize" => json!({
+                "protocolVersion": PROTOCOL_VERSION,
+                "capabilities": { "tools": {} },
+                "serverInfo": { "name": "logtrains", "version": env!("CARGO_PKG_VERSION") },
+            }),
+            "ping" => json!({}),
+            "tools/list" => json!({ "tools": tools() }),
+            "tools/call" => {
+                let name = params.get("name").and_then(Value::as_str).unwrap_or("");
+                let arguments = params.get("arguments").cloned().unwrap_or_else(|| json!({}));
+                let outcome = match name {
+                    "analyze_log" => self.analyze_log(&arguments).await,
+                    "analyze_last_command" => self.analyze_last_command(&arguments).await,
+                    "search_history" => self.search_history(&arguments),
+                    _ => return Some(jsonrpc::error(id, INVALID_PARAMS, &format!("Unknown tool: {}", name))),
+                };
+                // Tool failures are results the agent can read, not protocol errors
+                let (text, is_error) = match outcome {
+                    Ok(text) => (text, false),
+                    Err(e) => (format!("{:#}", e), true),
+                };
+                json!({ "content": [{ "type": "text", "text": text }], "isError": is_error })
+            }
+            method => return Some(jsonrpc::method_not_found(id, method)),
+        };
+        Some(jsonrpc::response(id, result))
+    }
+
+    async fn analyze_log(&mut self, arguments: &Value) -> Result<String> {
+        let Some(log) = arguments.get("log").and_then(Value::as_str) else {
+            bail!("`log` is required");
+        };
+        let (explanation, severity) = self.analyze(log, question(arguments)).await?;
+        Ok(format!("{}\n\nSeverity: {}", explanation.trim(), severity.label()))
+    }
+
+    async fn analyze_last_command(&mut self, arguments: &Value) -> Result<String> {
+        let n = arguments.get("n").and_then(Value::as_u64).unwrap_or(1).max(1) as usize;
+        let Some(entry) = self.history.recent(n)?.into_iter().nth(n - 1) else {
+            bail!("There is no recorded command #{} (record commands with `logtrains setup`)", n);
+        };
+        let exit = entry.exit_code.map_or_else(|| "unknown".to_string(), |code| code.to_string());
+        let heading = format!("Command: {} (exit code {})", entry.command, exit);
+
+        // A stored analysis answers the same question without running the model
+        let question = question(arguments);
+        if question.is_none() {
+            if let Some(analysis) = self.history.analysis_with(entry.id, &self.key)? {
+                return Ok(format!(
+                    "{}\n\n{}\n\nSeverity: {}",
+                    heading,
+                    analysis.explanation.trim(),
+                    analysis.severity.label()
+                ));
+            }
+        }
+
+        let log = history::read_log(&entry.log_path)?;
+        let (explanation, severity) = self.analyze(&log, question.clone()).await?;
+        if question.is_none() {
+            self.history.add_analysis(&history::Analysis {
+                id: 0,
+                entry_id: Some(entry.id),
+                source: entry.command.clone(),
+                created_at: chrono::Utc::now().timestamp(),
+                model: self.model.clone(),
+                severity,
+                explanation: explanation.clone(),
+                key: Some(self.key.clone()),
+            })?;
+        }
+        Ok(format!("{}\n\n{}\n\nSeverity: {}", heading, explanation.trim(), severity.label()))
+    }
+
+    fn search_history(&self, arguments: &Value) -> Result<String> {
+        let Some(query) = arguments.get("query").and_then(Value::as_str) else {
+            bail!("`query` is required");
+        };
+        let failed = arguments.get("failed_only").and_then(Value::as_bool).unwrap_or(false);
+        let limit = arguments.get("limit").and_then(Value::as_u64).unwrap_or(DEFAULT_SEARCH_LIMIT) as usize;
+        let results = self.history.search(query, failed, limit)?;
+        if results.is_empty() {
+            return Ok("No matching commands found.".to_string());
+        }
+        let mut text = String::new();
+        for (entry, snippet) in results {
+            let exit = entry.exit_code.map_or_else(|| "?".to_string(), |code| code.to_string());
+            text.push_str(&format!("#{} {} exit {} {}\n", entry.id, entry.started_local(), exit, entry.command));
+            if let Some(cwd) = &entry.cwd {
+                text.push_str(&format!("    in {}\n", cwd));
+            }
+            let snippet = snippet.split_whitespace().collect::<Vec<_>>().join(" ");
+            if !snippet.is_empty() {
+                text.push_str(&format!("    {}\n", snippet));
+            }
+        }
+        Ok(text)
+    }
+
+    async fn analyze(&mut self, log: &str, question: Option<String>) -> Result<(String, crate::severity::Severity)> {
+        if self.engine.is_none() {
+            let engine = self.loader.clone().load().await?;
+            engine.fit_examples(&mut self.request.vars.examples);
+            self.engine = Some(engine);
+        }
+        let engine = self.engine.as_mut().expect("the model was loaded above");
+        let request = AnalysisRequest { log: log.to_string(), question, ..self.request.clone() };
+        let result = analyze(engine, &request, |_| Ok(llm::Flow::Continue))?;
+        Ok((result.explanation, result.severity))
+    }
+}
+
+/// The tools and their input schemas, for `tools/list`.
+fn tools() -> Value {
+    let question = json!({
+        "type": "string",
+        "description": "Answer this question about the log instead of explaining it.",
+    });
+    json!([
+        {
+            "name": "analyze_log",
+            "description": "Explain the errors in a log or command output with a local model: what failed, \
+                the probable cause and a suggested fix, plus a severity (ok, warning, error or fatal).",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "log": { "type": "string", "description": "The log text." },
+                    "question": question,
+                },
+                "required": ["log"],
+            },
+        },
+        {
+            "name": "analyze_last_command",
+            "description": "Explain the output of a command recorded by logtrains' shell integration, \
+                by default the most recent one.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "n": { "type": "integer", "minimum": 1, "description": "Which command, counting back from the latest (1)." },
+                    "question": question,
+                },
+            },
+        },
+        {
+            "name": "search_history",
+            "description": "Search the recorded commands' command lines and output.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "query": { "type": "string", "description": "Words that must all appear." },
+                    "failed_only": { "type": "boolean", "description": "Only commands that exited non-zero." },
+                    "limit": { "type": "integer", "minimum": 1, "description": "Maximum number of results (default 10)." },
+                },
+                "required": ["query"],
+            },
+        },
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_serve() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = History::open(dir.path()).unwrap();
+        let log_path = dir.path().join("build.log");
+        std::fs::write(&log_path, "error: linker `cc` not found\n").unwrap();
+        history
+            .add(&history::Entry {
+                id: 0,
+                command: "cargo build".to_string(),
+                cwd: Some("/src/app".to_string()),
+                started_at: 1_700_000_000,
+                ended_at: None,
+                exit_code: Some(101),
+                log_path,
+            })
+            .unwrap();
+
+        let server = Server::new(
+            history,
+            ModelLoaderBuilder::new("repo", "model.gguf"),
+            "repo/model.gguf".to_string(),
+            "settings".to_string(),
+            AnalysisRequest::new(""),
+        );
+        let input = [
+            r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"protocolVersion":"2024-11-05"}}"#,
+            r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#,
+            r#"{"jsonrpc":"2.0","id":2,"method":"tools/list"}"#,
+            r#"{"jsonrpc":"2.0","id":3,"method":"tools/call","params":{"name":"search_history","arguments":{"query":"linker"}}}"#,
+            r#"{"jsonrpc":"2.0","id":4,"method":"tools/call","params":{"name":"analyze_log","arguments":{}}}"#,
+        ]
+        .join("\n");
+        let mut output = Vec::new();
+        server.serve(input.as_bytes(), &mut output).await.unwrap();
+
+        let responses = jsonrpc::messages(output);
+        assert_eq!(responses.len(), 4, "notifications get no response");
+        assert_eq!(responses[0]["result"]["serverInfo"]["name"], "logtrains");
+        let tools: Vec<&str> = responses[1]["result"]["tools"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|tool| tool["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(tools, ["analyze_log", "analyze_last_command", "search_history"]);
+        let found = responses[2]["result"]["content"][0]["text"].as_str().unwrap();
+        assert!(found.contains("exit 101 cargo build"), "{}", found);
+        assert_eq!(responses[3]["result"]["isError"], true);
+    }
+}
@@ -0,0 +1,258 @@
+//! Versioned migrations for on-disk formats (config file and recorded
+//! history), so upgrades never silently break a config or lose history.
+//!
+//! Each store records its format version: `version = N` in config.toml and a
+//! `VERSION` file in the history directory. Missing means version 0. Before a
+//! store is migrated it is backed up next to the original.
+
+use anyhow::{anyhow, Context, Result};
+use regex::Regex;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+/// A step from `to - 1` to `to`.
+struct Migration<T: ?Sized> {
+    to: u32,
+    description: &'static str,
+    apply: fn(&T) -> Result<()>,
+}
+
+/// Config migrations rewrite the file text (not a parsed table) so user
+/// comments and formatting survive.
+const CONFIG_MIGRATIONS: &[Migration<Path>] = &[Migration {
+    to: 1,
+    description: "record the config format version",
+    apply: |_| Ok(()),
+}];
+
+const HISTORY_MIGRATIONS: &[Migration<Path>] = &[
+    Migration {
+        to: 1,
+        description: "record the history format version",
+        apply: |_| Ok(()),
+    },
+    Migration {
+        to: 2,
+        description: "index recorded logs in history.db",
+        apply: |dir| crate::history::History::upgrade(dir, 2),
+    },
+    Migration {
+        to: 3,
+        description: "index log contents for history search",
+        apply: |dir| crate::history::History::upgrade(dir, 3),
+    },
+    Migration {
+        to: 4,
+        description: "add history tags and notes",
+        apply: |dir| crate::history::History::upgrade(dir, 4),
+    },
+    Migration {
+        to: 5,
+        description: "store generated analyses for recall",
+        apply: |dir| crate::history::History::upgrade(dir, 5),
+    },
+    Migration {
+        to: 6,
+        description: "key stored analyses on the model and prompt settings",
+        apply: |dir| crate::history::History::upgrade(dir, 6),
+    },
+];
+
+static CONFIG_VERSION_LINE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?m)^version\s*=\s*(\d+)\s*$").unwrap());
+
+/// What happened to one store.
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    /// Nothing on disk yet.
+    Missing,
+    UpToDate(u32),
+    /// Migrations that would run (dry run).
+    Pending { from: u32, to: u32, steps: Vec<&'static str> },
+    Migrated { from: u32, to: u32, backup: PathBuf },
+}
+
+pub fn config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("logtrains/config.toml"))
+}
+
+pub fn history_dir() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join("logtrains"))
+}
+
+/// Migrates the config file and history directory if they are outdated.
+/// Returns a line per store that changed (or would change, for a dry run).
+pub fn run(dry_run: bool) -> Result<Vec<(&'static str, Outcome)>> {
+    let mut outcomes = Vec::new();
+    if let Some(path) = config_path() {
+        outcomes.push(("config", migrate_config(&path, dry_run)?));
+    }
+    if let Some(dir) = history_dir() {
+        outcomes.push(("history", migrate_history(&dir, dry_run)?));
+    }
+    Ok(outcomes)
+}
+
+pub fn migrate_config(path: &Path, dry_run: bool) -> Result<Outcome> {
+    if !path.exists() {
+        return Ok(Outcome::Missing);
+    }
+    let text = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let from = CONFIG_VERSION_LINE
+        .captures(&text)
+        .and_then(|c| c[1].parse().ok())
+        .unwrap_or(0);
+    let steps = pending(CONFIG_MIGRATIONS, from, &path.display().to_string())?;
+    let Some(to) = steps.last().map(|m| m.to) else {
+        return Ok(Outcome::UpToDate(from));
+    };
+    if dry_run {
+        return Ok(Outcome::Pending { from, to, steps: steps.iter().map(|m| m.description).collect() });
+    }
+
+    let backup = backup_path(path, from);
+    std::fs::copy(path, &backup).with_context(|| format!("Failed to back up {}", path.display()))?;
+    for step in &steps {
+        (step.apply)(path).with_context(|| format!("Config migration to version {} failed", step.to))?;
+    }
+    let text = std::fs::read_to_string(path)?;
+    std::fs::write(path, set_config_version(&text, to))?;
+    Ok(Outcome::Migrated { from, to, backup })
+}
+
+pub fn migrate_history(dir: &Path, dry_run: bool) -> Result<Outcome> {
+    if !dir.is_dir() {
+        return Ok(Outcome::Missing);
+    }
+    let version_file = dir.join("VERSION");
+    let from = match std::fs::read_to_string(&version_file) {
+        Ok(text) => text
+            .trim()
+            .parse()
+            .with_context(|| format!("Invalid version in {}", version_file.display()))?,
+        Err(_) => 0,
+    };
+    let steps = pending(HISTORY_MIGRATIONS, from, &dir.display().to_string())?;
+    let Some(to) = steps.last().map(|m| m.to) else {
+        return Ok(Outcome::UpToDate(from));
+    };
+    if dry_run {
+        return Ok(Outcome::Pending { from, to, steps: steps.iter().map(|m| m.description).collect() });
+    }
+
+    let backup = backup_path(dir, from);
+    copy_dir(dir, &backup).with_context(|| format!("Failed to back up {}", dir.display()))?;
+    for step in &steps {
+        (step.apply)(dir).with_context(|| format!("History migration to version {} failed", step.to))?;
+    }
+    std::fs::write(&version_file, format!("{}\n", to))?;
+    Ok(Outcome::Migrated { from, to, backup })
+}
+
+/// The migrations newer than `from`, refusing formats newer than we know.
+fn pending<'a, T: ?Sized>(migrations: &'a [Migration<T>], from: u32, what: &str) -> Result<Vec<&'a Migration<T>>> {
+    let latest = migrations.last().map_or(0, |m| m.to);
+    if from > latest {
+        return Err(anyhow!(
+            "{} uses format version {}, but this logtrains only supports up to {}. Please upgrade logtrains.",
+            what,
+            from,
+            latest
+        ));
+    }
+    Ok(migrations.iter().filter(|m| m.to > from).collect())
+}
+
+/// Sets (or adds, at the top) the `version = N` line.
+fn set_config_version(text: &str, version: u32) -> String {
+    let line = format!("version = {}", version);
+    if CONFIG_VERSION_LINE.is_match(text) {
+        CONFIG_VERSION_LINE.replace(text, line.as_str()).into_owned()
+    } else {
+        format!("{}\n{}", line, text)
+    }
+}
+
+/// `config.toml` -> `config.toml.bak-v0`, without clobbering older backups.
+fn backup_path(path: &Path, version: u32) -> PathBuf {
+    let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let mut backup = path.with_file_name(format!("{}.bak-v{}", name, version));
+    let mut n = 1;
+    while backup.exists() {
+        backup = path.with_file_name(format!("{}.bak-v{}.{}", name, version, n));
+        n += 1;
+    }
+    backup
+}
+
+fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    std::fs::create_dir_all(to)?;
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            std::fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_config_migration_keeps_comments_and_backs_up() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let original = "# my settings\nmodel_file = \"x.gguf\"\n";
+        std::fs::write(&path, original).unwrap();
+
+        assert!(matches!(migrate_config(&path, true).unwrap(), Outcome::Pending { from: 0, to: 1, .. }));
+        // A dry run touches nothing
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), original);
+
+        let Outcome::Migrated { from: 0, to: 1, backup } = migrate_config(&path, false).unwrap() else {
+            panic!("expected a migration");
+        };
+        assert_eq!(std::fs::read_to_string(&backup).unwrap(), original);
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "version = 1\n# my settings\nmodel_file = \"x.gguf\"\n"
+        );
+        assert_eq!(migrate_config(&path, false).unwrap(), Outcome::UpToDate(1));
+    }
+
+    #[test]
+    fn test_history_migration() {
+        let dir = tempdir().unwrap();
+        let history = dir.path().join("logtrains");
+        std::fs::create_dir(&history).unwrap();
+        std::fs::write(history.join("log_1700000000_ls.log"), "output").unwrap();
+
+        let Outcome::Migrated { backup, .. } = migrate_history(&history, false).unwrap() else {
+            panic!("expected a migration");
+        };
+        assert!(backup.join("log_1700000000_ls.log").exists());
+        // Each step ran its own change: the old log is listed and searchable
+        let db = rusqlite::Connection::open(history.join(crate::history::DB_FILE)).unwrap();
+        let count = |sql: &str| db.query_row(sql, [], |row| row.get::<_, i64>(0)).unwrap();
+        assert_eq!(count("SELECT count(*) FROM entries"), 1);
+        assert_eq!(count("SELECT count(*) FROM entries_fts WHERE entries_fts MATCH 'output'"), 1);
+        assert_eq!(count("SELECT count(key) FROM analyses"), 0);
+        assert_eq!(std::fs::read_to_string(history.join("VERSION")).unwrap(), "6\n");
+        assert_eq!(migrate_history(&history, false).unwrap(), Outcome::UpToDate(6));
+        assert_eq!(migrate_history(&dir.path().join("missing"), false).unwrap(), Outcome::Missing);
+    }
+
+    #[test]
+    fn test_newer_format_is_refused() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, "version = 99\n").unwrap();
+        assert!(migrate_config(&path, false).is_err());
+    }
+}
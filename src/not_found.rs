@@ -0,0 +1,179 @@
+use std::collections::BTreeSet;
+use std::path::Path;
+
+/// Well-known commands whose package name differs from the binary name (or
+/// that are commonly missing), as `(command, apt, dnf, pacman, brew)`.
+const PACKAGES: &[(&str, &str, &str, &str, &str)] = &[
+    ("rg", "ripgrep", "ripgrep", "ripgrep", "ripgrep"),
+    ("fd", "fd-find", "fd-find", "fd", "fd"),
+    ("bat", "bat", "bat", "bat", "bat"),
+    ("python", "python3", "python3", "python", "python"),
+    ("python3", "python3", "python3", "python", "python"),
+    ("pip", "python3-pip", "python3-pip", "python-pip", "python"),
+    ("pip3", "python3-pip", "python3-pip", "python-pip", "python"),
+    ("node", "nodejs", "nodejs", "nodejs", "node"),
+    ("npm", "npm", "npm", "npm", "node"),
+    ("cargo", "cargo", "cargo", "rust", "rust"),
+    ("rustc", "rustc", "rust", "rust", "rust"),
+    ("go", "golang-go", "golang", "go", "go"),
+    ("java", "default-jre", "java-latest-openjdk", "jre-openjdk", "openjdk"),
+    ("gcc", "gcc", "gcc", "gcc", "gcc"),
+    ("make", "make", "make", "make", "make"),
+    ("cmake", "cmake", "cmake", "cmake", "cmake"),
+    ("git", "git", "git", "git", "git"),
+    ("curl", "curl", "curl", "curl", "curl"),
+    ("wget", "wget", "wget", "wget", "wget"),
+    ("jq", "jq", "jq", "jq", "jq"),
+    ("htop", "htop", "htop", "htop", "htop"),
+    ("tree", "tree", "tree", "tree", "tree"),
+    ("unzip", "unzip", "unzip", "unzip", "unzip"),
+    ("docker", "docker.io", "docker", "docker", "docker"),
+    ("kubectl", "kubectl", "kubernetes-client", "kubectl", "kubernetes-cli"),
+    ("terraform", "terraform", "terraform", "terraform", "terraform"),
+    ("psql", "postgresql-client", "postgresql", "postgresql-libs", "libpq"),
+    ("mysql", "mysql-client", "mysql", "mariadb-clients", "mysql-client"),
+    ("netstat", "net-tools", "net-tools", "net-tools", "net-tools"),
+    ("ifconfig", "net-tools", "net-tools", "net-tools", "net-tools"),
+    ("dig", "dnsutils", "bind-utils", "bind", "bind"),
+    ("nslookup", "dnsutils", "bind-utils", "bind", "bind"),
+    ("xclip", "xclip", "xclip", "xclip", "xclip"),
+    ("tmux", "tmux", "tmux", "tmux", "tmux"),
+    ("vim", "vim", "vim-enhanced", "vim", "vim"),
+];
+
+/// What we could figure out about an unknown command.
+#[derive(Debug, Default, PartialEq)]
+pub struct Suggestion {
+    /// Installed commands that are a likely typo match.
+    pub similar: Vec<String>,
+    /// A ready-to-run install command, if the package is known.
+    pub install: Option<String>,
+}
+
+/// Suggests what the user probably meant when `command` was not found, using
+/// the executables on `$PATH` and a small package table. This runs on every
+/// unknown command, so it never loads a model.
+pub fn suggest(command: &str) -> Suggestion {
+    let candidates = path_executables();
+    Suggestion {
+        similar: similar_commands(command, &candidates),
+        install: install_hint(command),
+    }
+}
+
+/// Returns up to three candidates within a small edit distance of `command`,
+/// closest first.
+pub fn similar_commands(command: &str, candidates: &BTreeSet<String>) -> Vec<String> {
+    let max_distance = if command.len() <= 3 { 1 } else { 2 };
+    let mut scored: Vec<(usize, &String)> = candidates
+        .iter()
+        .filter(|c| c.len().abs_diff(command.len()) <= max_distance && c.as_str() != command)
+        .map(|c| (edit_distance(command, c), c))
+        .filter(|(d, _)| *d <= max_distance)
+        .collect();
+    scored.sort();
+    scored.into_iter().take(3).map(|(_, c)| c.clone()).collect()
+}
+
+fn install_hint(command: &str) -> Option<String> {
+    let (_, apt, dnf, pacman, brew) = PACKAGES.iter().find(|(c, ..)| *c == command)?;
+    let hint = if cfg!(target_os = "macos") {
+        format!("brew install {}", brew)
+    } else if which("apt-get") {
+        format!("sudo apt-get install {}", apt)
+    } else if which("dnf") {
+        format!("sudo dnf install {}", dnf)
+    } else if which("pacman") {
+        format!("sudo pacman -S {}", pacman)
+    } else if which("brew") {
+        format!("brew install {}", brew)
+    } else {
+        return None;
+    };
+    Some(hint)
+}
+
+fn which(program: &str) -> bool {
+    std::env::var_os("PATH")
+        .map(|paths| std::env::split_paths(&paths).any(|dir| dir.join(program).is_file()))
+        .unwrap_or(false)
+}
+
+fn path_executables() -> BTreeSet<String> {
+    let mut names = BTreeSet::new();
+    let Some(paths) = std::env::var_os("PATH") else {
+        return names;
+    };
+    for dir in std::env::split_paths(&paths) {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.filter_map(|e| e.ok()) {
+            if is_executable(&entry.path()) {
+                names.insert(entry.file_name().to_string_lossy().into_owned());
+            }
+        }
+    }
+    names
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// Optimal string alignment distance (Levenshtein plus adjacent transpositions,
+/// which catches the classic `gti` -> `git` typo as a single edit).
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut d = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1).min(d[i][j - 1] + 1).min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("gti", "git"), 1);
+        assert_eq!(edit_distance("carg", "cargo"), 1);
+        assert_eq!(edit_distance("docker", "docker"), 0);
+        assert_eq!(edit_distance("kubectl", "kubeclt"), 1);
+    }
+
+    #[test]
+    fn test_similar_commands() {
+        let candidates: BTreeSet<String> = ["git", "grep", "cargo", "gist", "make"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(similar_commands("gti", &candidates), vec!["git"]);
+        assert_eq!(similar_commands("crago", &candidates), vec!["cargo"]);
+        assert!(similar_commands("zzzzzz", &candidates).is_empty());
+    }
+}
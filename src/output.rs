@@ -0,0 +1,116 @@
+//! Terminal output helpers for the streamed explanation.
+
+use crate::markdown;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+/// Batches stdout flushes while tokens stream in. Flushing per token causes
+/// visible stutter (and lots of syscalls) over SSH/mosh; flushing on a short
+/// interval or at the end of a word keeps the streaming feel.
+pub struct FlushBatcher {
+    interval: Duration,
+    last_flush: Instant,
+    /// When false, tokens are swallowed (the answer is rendered differently).
+    visible: bool,
+    /// Renders Markdown line by line; `None` prints tokens as they come.
+    renderer: Option<markdown::Renderer>,
+}
+
+impl FlushBatcher {
+    /// An interval of zero flushes after every token.
+    pub fn new(interval: Duration, visible: bool) -> Self {
+        Self {
+            interval,
+            last_flush: Instant::now(),
+            visible,
+            renderer: None,
+        }
+    }
+
+    /// Renders the Markdown of the answer for the terminal instead of
+    /// printing it raw.
+    pub fn with_markdown(mut self, enabled: bool) -> Self {
+        self.renderer = enabled.then(markdown::Renderer::default);
+        self
+    }
+
+    /// Prints a token, flushing if the interval elapsed and the token ends a
+    /// word (or the interval elapsed twice over, so long words still appear).
+    pub fn print(&mut self, token: &str) -> io::Result<()> {
+        if !self.visible {
+            return Ok(());
+        }
+        match &mut self.renderer {
+            Some(renderer) => print!("{}", renderer.push(token)),
+            None => print!("{}", token),
+        }
+        if should_flush(self.last_flush.elapsed(), self.interval, token) {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.last_flush = Instant::now();
+        io::stdout().flush()
+    }
+
+    /// Ends an answer: prints any partially rendered line, then flushes.
+    pub fn finish(&mut self) -> io::Result<()> {
+        if let Some(renderer) = &mut self.renderer {
+            if self.visible {
+                print!("{}", renderer.finish());
+            }
+        }
+        self.flush()
+    }
+}
+
+/// A Markdown document for `--output`: a header saying what was analyzed,
+/// when and by which model, then the explanation and any rerun command.
+pub fn markdown_report(source: &str, model: &str, explanation: &str, rerun: Option<&str>) -> String {
+    let mut doc = format!(
+        "# LogTrains Analysis\n\n- **Source:** `{}`\n- **Date:** {}\n- **Model:** {}\n\n## Explanation\n\n{}\n",
+        source,
+        chrono::Local::now().format("%Y-%m-%d %H:%M:%S %:z"),
+        model,
+        explanation.trim()
+    );
+    if let Some(command) = rerun {
+        doc.push_str(&format!("\n## Rerun Failed Tests\n\n```bash\n{}\n```\n", command));
+    }
+    doc
+}
+
+fn should_flush(elapsed: Duration, interval: Duration, token: &str) -> bool {
+    let at_word_boundary = token
+        .chars()
+        .last()
+        .is_some_and(|c| c.is_whitespace() || c.is_ascii_punctuation());
+    elapsed >= interval && (at_word_boundary || elapsed >= interval * 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_markdown_report() {
+        let doc = markdown_report("cargo test", "repo/model.gguf", "It failed.\n", Some("cargo test -- a --exact"));
+        assert!(doc.starts_with("# LogTrains Analysis\n\n- **Source:** `cargo test`\n- **Date:** "));
+        assert!(doc.contains("- **Model:** repo/model.gguf\n\n## Explanation\n\nIt failed.\n"));
+        assert!(doc.ends_with("```bash\ncargo test -- a --exact\n```\n"));
+    }
+
+    #[test]
+    fn test_should_flush() {
+        let ms = Duration::from_millis;
+        // Zero interval keeps the old per-token behaviour
+        assert!(should_flush(ms(0), ms(0), "foo"));
+        assert!(!should_flush(ms(10), ms(50), "word "));
+        assert!(should_flush(ms(60), ms(50), "word "));
+        assert!(should_flush(ms(60), ms(50), "end."));
+        assert!(!should_flush(ms(60), ms(50), "mid"));
+        assert!(should_flush(ms(120), ms(50), "mid"));
+    }
+}
@@ -0,0 +1,148 @@
+//! The prompt library: one file per profile in `~/.config/logtrains/prompts`,
+//! next to the `[prompts]` table of the config (see `logtrains prompts`).
+
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
+
+use crate::llm::Prompt;
+use crate::template;
+
+const EXTENSION: &str = "txt";
+
+/// What `prompts new` starts a profile with.
+pub const STARTER: &str = "You are a {{ROLE}}. Explain the cause of the first real error in the log below \
+and how to fix it. Be brief and use Markdown.
+
+Command: {{COMMAND}} (exit code {{EXIT_CODE}}, on {{OS}})
+
+{{LOG_TEXT}}
+";
+
+/// Used by `prompts test` when no log is given.
+pub const SAMPLE_LOG: &str = "$ npm run build
+> webapp@1.4.0 build
+> tsc -p tsconfig.json
+
+src/api/client.ts(42,17): error TS2339: Property 'retries' does not exist on type 'RequestOptions'.
+src/api/client.ts(57,5): error TS2322: Type 'string | undefined' is not assignable to type 'string'.
+
+Found 2 errors in the same file, starting at: src/api/client.ts:42
+npm ERR! code 2
+";
+
+pub fn dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("logtrains/prompts"))
+}
+
+/// How a profile's text is used: with `{{LOG_TEXT}}` it is a full template,
+/// without it a system prompt used with the default prompt layout.
+pub fn prompt(text: String) -> Prompt {
+    if template::uses(&text, "LOG_TEXT") {
+        Prompt::Template(text)
+    } else {
+        Prompt::System(text)
+    }
+}
+
+pub struct Library {
+    dir: PathBuf,
+}
+
+impl Library {
+    pub fn open(dir: &Path) -> Self {
+        Self { dir: dir.to_path_buf() }
+    }
+
+    /// The profiles in the library, sorted by name.
+    pub fn names(&self) -> Result<Vec<String>> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", self.dir.display())),
+        };
+        let mut names = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == EXTENSION) {
+                if let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) {
+                    names.push(name.to_string());
+                }
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    /// The file of profile `name`, which need not exist yet.
+    pub fn path(&self, name: &str) -> Result<PathBuf> {
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            bail!("Invalid profile name `{}`: use letters, digits, `-` and `_`.", name);
+        }
+        Ok(self.dir.join(format!("{}.{}", name, EXTENSION)))
+    }
+
+    pub fn get(&self, name: &str) -> Result<Option<String>> {
+        let path = self.path(name)?;
+        match std::fs::read_to_string(&path) {
+            Ok(text) => Ok(Some(text)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        }
+    }
+
+    /// Writes a new profile, refusing to overwrite an existing one.
+    pub fn create(&self, name: &str, text: &str) -> Result<PathBuf> {
+        let path = self.path(name)?;
+        if path.exists() {
+            bail!("Profile `{}` already exists: {}", name, path.display());
+        }
+        std::fs::create_dir_all(&self.dir).with_context(|| format!("Failed to create {}", self.dir.display()))?;
+        std::fs::write(&path, text).with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(path)
+    }
+}
+
+/// Opens `path` in `$VISUAL` or `$EDITOR` and waits for it to close.
+pub fn edit(path: &Path) -> Result<()> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .ok()
+        .filter(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| if cfg!(windows) { "notepad" } else { "vi" }.to_string());
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or("vi");
+    let mut args: Vec<std::ffi::OsString> = words.map(Into::into).collect();
+    args.push(path.as_os_str().to_owned());
+    let status = duct::cmd(program, args)
+        .unchecked()
+        .run()
+        .with_context(|| format!("Failed to start the editor `{}`", editor))?
+        .status;
+    if !status.success() {
+        bail!("The editor `{}` exited with {}", editor, status);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_library() {
+        let dir = tempfile::tempdir().unwrap();
+        let library = Library::open(&dir.path().join("prompts"));
+        assert!(library.names().unwrap().is_empty());
+        assert_eq!(library.get("rust").unwrap(), None);
+
+        library.create("rust", "You are a rustc expert.").unwrap();
+        library.create("k8s", STARTER).unwrap();
+        assert_eq!(library.names().unwrap(), ["k8s", "rust"]);
+        assert_eq!(library.get("rust").unwrap().as_deref(), Some("You are a rustc expert."));
+        assert!(library.create("rust", "again").unwrap_err().to_string().contains("already exists"));
+        assert!(library.path("../config").is_err());
+
+        assert_eq!(prompt("Be terse.".to_string()), Prompt::System("Be terse.".to_string()));
+        assert!(matches!(prompt(STARTER.to_string()), Prompt::Template(_)));
+    }
+}
@@ -0,0 +1,594 @@
+//! `logtrains record` and `analyze --run`: run a command under a
+//! pseudo-terminal, so it behaves as if run directly (colors, progress bars,
+//! prompts), and copy its output to the terminal and to a log. This replaces
+//! `script(1)`, whose flags differ between Linux and macOS and which does not
+//! exist on Windows.
+
+use anyhow::{Context, Result};
+use portable_pty::{native_pty_system, CommandBuilder, PtySize};
+use regex::Regex;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::preprocess;
+
+/// Where the command's output is shown while it runs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mirror {
+    Stdout,
+    /// Keeps stdout free for the result, e.g. in JSON mode.
+    Stderr,
+}
+
+/// Runs `command` with its output teed to `log_path` and returns its exit
+/// code. Keystrokes are forwarded to the command while it runs.
+pub fn run(command: &[String], log_path: &Path) -> Result<i32> {
+    let mut cmd = CommandBuilder::new(&command[0]);
+    cmd.args(&command[1..]);
+    let mut log = File::create(log_path).with_context(|| format!("Failed to create {}", log_path.display()))?;
+    Ok(spawn(cmd, &command[0], Mirror::Stdout, &mut log, &Limits::default())?.code)
+}
+
+/// Bounds on an `analyze --run` capture, and who else gets to see it.
+#[derive(Debug, Clone, Default)]
+pub struct Limits {
+    /// Kill the command after this long.
+    pub timeout: Option<Duration>,
+    /// Keep at most this many bytes of output: the first quarter and the
+    /// rest from the end.
+    pub max_capture: Option<usize>,
+    /// Also receives the output as it arrives, for `--live`.
+    pub tee: Option<Sender<Vec<u8>>>,
+    /// Kill the command as soon as a line of output matches.
+    pub abort_on: Option<Regex>,
+}
+
+/// The output of an `analyze --run` command.
+#[derive(Debug)]
+pub struct Captured {
+    pub output: String,
+    /// [`TIMEOUT_EXIT_CODE`] if the command was killed by the timeout.
+    pub exit_code: i32,
+    pub timed_out: bool,
+    /// The line that matched `abort_on`, if the command was killed for it.
+    pub aborted_at: Option<String>,
+    /// Bytes dropped from the middle of the output by `max_capture`.
+    pub dropped: usize,
+}
+
+/// How a command ended.
+struct Exit {
+    code: i32,
+    timed_out: bool,
+    aborted_at: Option<String>,
+}
+
+/// Reported for a command killed by `--timeout`, as by `timeout(1)`.
+pub const TIMEOUT_EXIT_CODE: i32 = 124;
+
+/// How long output is still collected after a command is killed.
+const KILL_GRACE: Duration = Duration::from_secs(1);
+
+/// Runs `argv`, mirroring its output, and returns the output with the
+/// command's exit code.
+pub fn capture(argv: &[String], mirror: Mirror, limits: Limits) -> Result<Captured> {
+    let mut cmd = CommandBuilder::new(&argv[0]);
+    cmd.args(&argv[1..]);
+    let mut output = Bounded::new(limits.max_capture);
+    let exit = spawn(cmd, &argv[0], mirror, &mut output, &limits)?;
+    Ok(output.finish(exit))
+}
+
+/// Starts the lines a command wrote to standard error in a labeled capture.
+pub const STDERR_LABEL: &str = "[stderr] ";
+
+/// Like [`capture`], but with stdout and stderr on separate pipes instead of
+/// a terminal, so stderr lines can be marked with [`STDERR_LABEL`]. Lines are
+/// kept in the order they arrived and mirrored to the stream they came from
+/// (both to stderr with [`Mirror::Stderr`]).
+pub fn capture_labeled(argv: &[String], mirror: Mirror, limits: Limits) -> Result<Captured> {
+    let mut cmd = Command::new(&argv[0]);
+    cmd.args(&argv[1..]).stdout(Stdio::piped()).stderr(Stdio::piped());
+    // A session of its own, as under the PTY, so a kill reaches the whole group
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        // SAFETY: setsid is async-signal-safe and touches no memory
+        unsafe {
+            cmd.pre_exec(|| {
+                if libc::setsid() == -1 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+    }
+    let mut child = cmd.spawn().with_context(|| format!("Failed to run {}", argv[0]))?;
+
+    let (tx, rx) = mpsc::channel();
+    let pipes: [(Box<dyn Read + Send>, bool); 2] = [
+        (Box::new(child.stdout.take().expect("stdout is piped")), false),
+        (Box::new(child.stderr.take().expect("stderr is piped")), true),
+    ];
+    for (pipe, is_stderr) in pipes {
+        let tx = tx.clone();
+        std::thread::spawn(move || {
+            let mut reader = BufReader::new(pipe);
+            let mut line = Vec::new();
+            while matches!(reader.read_until(b'\n', &mut line), Ok(n) if n > 0) {
+                if tx.send((is_stderr, String::from_utf8_lossy(&line).into_owned())).is_err() {
+                    break;
+                }
+                line.clear();
+            }
+        });
+    }
+    drop(tx);
+
+    let mut output = Bounded::new(limits.max_capture);
+    let mut tripwire = Tripwire::new(limits.abort_on.as_ref());
+    let timed_out = pump(
+        &rx,
+        limits.timeout,
+        || {
+            kill_group(Some(child.id()));
+            let _ = child.kill();
+        },
+        |(is_stderr, line)| {
+            let go_on = tripwire.check(line.as_bytes());
+            if is_stderr || mirror == Mirror::Stderr {
+                eprint!("{}", line);
+            } else {
+                print!("{}", line);
+                let _ = std::io::stdout().flush();
+            }
+            let mut labeled = if is_stderr { format!("{}{}", STDERR_LABEL, line) } else { line };
+            if !labeled.ends_with('\n') {
+                labeled.push('\n');
+            }
+            output.write_all(labeled.as_bytes())?;
+            if let Some(tee) = &limits.tee {
+                let _ = tee.send(labeled.into_bytes());
+            }
+            Ok(go_on)
+        },
+    )?;
+    let status = child.wait().context("Failed to wait for the command")?;
+    Ok(output.finish(Exit { code: exit_code(&status.into()), timed_out, aborted_at: tripwire.tripped }))
+}
+
+fn spawn(
+    mut cmd: CommandBuilder,
+    name: &str,
+    mirror: Mirror,
+    sink: &mut dyn Write,
+    limits: &Limits,
+) -> Result<Exit> {
+    let (cols, rows) = crossterm::terminal::size().unwrap_or((80, 24));
+    let pair = native_pty_system()
+        .openpty(PtySize {
+            rows,
+            cols,
+            pixel_width: 0,
+            pixel_height: 0,
+        })
+        .context("Failed to open a pseudo-terminal")?;
+
+    if let Ok(cwd) = std::env::current_dir() {
+        cmd.cwd(cwd);
+    }
+    let mut child = pair
+        .slave
+        .spawn_command(cmd)
+        .with_context(|| format!("Failed to run {}", name))?;
+    // Only the child may hold the terminal open, so reads end when it exits
+    drop(pair.slave);
+
+    let mut reader = pair.master.try_clone_reader()?;
+    let writer = pair.master.take_writer()?;
+
+    let raw = RawMode::enable();
+    let done = Arc::new(AtomicBool::new(false));
+    let input = forward_input(writer, done.clone());
+
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut buf = [0u8; 8192];
+        loop {
+            match reader.read(&mut buf) {
+                // Linux reports EIO instead of EOF once the child has exited
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    if tx.send(buf[..n].to_vec()).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+
+    let mut out: Box<dyn Write> = match mirror {
+        Mirror::Stdout => Box::new(std::io::stdout()),
+        Mirror::Stderr => Box::new(std::io::stderr()),
+    };
+    let mut tripwire = Tripwire::new(limits.abort_on.as_ref());
+    let timed_out = pump(
+        &rx,
+        limits.timeout,
+        || {
+            kill_group(child.process_id());
+            let _ = child.kill();
+        },
+        |chunk| {
+            let go_on = tripwire.check(&chunk);
+            // Keep recording even if our own output is gone (e.g. `| head`)
+            let _ = out.write_all(&chunk).and_then(|_| out.flush());
+            sink.write_all(&chunk)?;
+            if let Some(tee) = &limits.tee {
+                let _ = tee.send(chunk);
+            }
+            Ok(go_on)
+        },
+    )?;
+
+    let status = child.wait().context("Failed to wait for the command")?;
+    done.store(true, Ordering::Relaxed);
+    if let Some(input) = input {
+        let _ = input.join();
+    }
+    drop(raw);
+    Ok(Exit { code: exit_code(&status), timed_out, aborted_at: tripwire.tripped })
+}
+
+/// Hands the command's output from `rx` to `handle` until it ends. The
+/// command is killed after `timeout` or when `handle` returns `false`, and
+/// what arrives within [`KILL_GRACE`] is still handled (children that keep
+/// the output open are not waited for). Returns whether the command timed out.
+fn pump<T>(
+    rx: &Receiver<T>,
+    timeout: Option<Duration>,
+    mut kill: impl FnMut(),
+    mut handle: impl FnMut(T) -> Result<bool>,
+) -> Result<bool> {
+    let mut deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let mut killed = false;
+    let mut timed_out = false;
+    loop {
+        let item = match deadline {
+            None => rx.recv().ok(),
+            Some(at) => match rx.recv_timeout(at.saturating_duration_since(Instant::now())) {
+                Ok(item) => Some(item),
+                Err(RecvTimeoutError::Timeout) if !killed => {
+                    timed_out = true;
+                    killed = true;
+                    kill();
+                    deadline = Some(Instant::now() + KILL_GRACE);
+                    continue;
+                }
+                Err(_) => None,
+            },
+        };
+        match item {
+            Some(item) => {
+                if !handle(item)? && !killed {
+                    killed = true;
+                    kill();
+                    deadline = Some(Instant::now() + KILL_GRACE);
+                }
+            }
+            None => return Ok(timed_out),
+        }
+    }
+}
+
+/// Kills the process group led by `pid`: the command and everything it
+/// started. Commands run in a session of their own (the PTY's, or
+/// [`capture_labeled`]'s), so the group holds nothing else.
+#[cfg(unix)]
+fn kill_group(pid: Option<u32>) {
+    if let Some(pid) = pid.and_then(|pid| i32::try_from(pid).ok()).filter(|&pid| pid > 0) {
+        // SAFETY: sends a signal; a group that is already gone is harmless
+        unsafe {
+            libc::kill(-pid, libc::SIGKILL);
+        }
+    }
+}
+
+/// Only the command itself is killed (by the caller).
+#[cfg(not(unix))]
+fn kill_group(_pid: Option<u32>) {}
+
+/// Watches the output, a line at a time, for the `abort_on` pattern.
+struct Tripwire<'a> {
+    pattern: Option<&'a Regex>,
+    partial: Vec<u8>,
+    tripped: Option<String>,
+}
+
+impl<'a> Tripwire<'a> {
+    fn new(pattern: Option<&'a Regex>) -> Self {
+        Self { pattern, partial: Vec::new(), tripped: None }
+    }
+
+    /// Whether the command may go on after printing `bytes`.
+    fn check(&mut self, bytes: &[u8]) -> bool {
+        let Some(pattern) = self.pattern else {
+            return true;
+        };
+        if self.tripped.is_some() {
+            return false;
+        }
+        self.partial.extend_from_slice(bytes);
+        while let Some(end) = self.partial.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.partial.drain(..=end).collect();
+            let line = preprocess::strip_ansi(&String::from_utf8_lossy(&line));
+            let line = line.trim_end();
+            if pattern.is_match(line) {
+                self.tripped = Some(line.to_string());
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Collects output up to a size: the start, and a window of the end that
+/// moves along as more arrives.
+struct Bounded {
+    head_max: usize,
+    tail_max: usize,
+    head: Vec<u8>,
+    tail: VecDeque<u8>,
+    dropped: usize,
+}
+
+impl Bounded {
+    fn new(max: Option<usize>) -> Self {
+        let max = max.unwrap_or(usize::MAX);
+        Self { head_max: max / 4, tail_max: max - max / 4, head: Vec::new(), tail: VecDeque::new(), dropped: 0 }
+    }
+
+    fn finish(mut self, exit: Exit) -> Captured {
+        let mut output = String::from_utf8_lossy(&self.head).into_owned();
+        if self.dropped > 0 {
+            if !output.is_empty() && !output.ends_with('\n') {
+                output.push('\n');
+            }
+            output.push_str(&format!("[... {} bytes of output dropped by --max-capture ...]\n", self.dropped));
+        }
+        output.push_str(&String::from_utf8_lossy(self.tail.make_contiguous()));
+        Captured {
+            output,
+            exit_code: if exit.timed_out { TIMEOUT_EXIT_CODE } else { exit.code },
+            timed_out: exit.timed_out,
+            aborted_at: exit.aborted_at,
+            dropped: self.dropped,
+        }
+    }
+}
+
+impl Write for Bounded {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let to_head = self.head_max.saturating_sub(self.head.len()).min(buf.len());
+        self.head.extend_from_slice(&buf[..to_head]);
+        self.tail.extend(&buf[to_head..]);
+        if self.tail.len() > self.tail_max {
+            let excess = self.tail.len() - self.tail_max;
+            self.tail.drain(..excess);
+            self.dropped += excess;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Parses a size like `10M`, `512K`, `1G` or a plain number of bytes.
+pub fn parse_size(s: &str) -> Result<usize, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: usize = number.parse().map_err(|_| format!("invalid size: {} (expected e.g. 10M)", s))?;
+    let unit_bytes = match unit.to_ascii_uppercase().trim_end_matches('B') {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        _ => return Err(format!("invalid size unit in {} (use K, M or G)", s)),
+    };
+    if number == 0 {
+        return Err("the size must be greater than 0".to_string());
+    }
+    number.checked_mul(unit_bytes).ok_or_else(|| format!("size too large: {}", s))
+}
+
+/// The exit code a shell would report for `status`, including 128+N for a
+/// command killed by signal N (which the PTY only reports by name).
+fn exit_code(status: &portable_pty::ExitStatus) -> i32 {
+    #[cfg(unix)]
+    if let Some(name) = status.signal() {
+        for signal in 1..32 {
+            // SAFETY: strsignal returns null or a valid C string
+            let known = unsafe { libc::strsignal(signal) };
+            if !known.is_null() && unsafe { std::ffi::CStr::from_ptr(known) }.to_string_lossy() == name {
+                return 128 + signal;
+            }
+        }
+    }
+    status.exit_code() as i32
+}
+
+/// Copies keystrokes to the command until `done` is set. Stdin is polled,
+/// so no keys are swallowed after the command exits (e.g. the first
+/// question of `analyze --run ... -i`).
+#[cfg(unix)]
+fn forward_input(mut writer: Box<dyn Write + Send>, done: Arc<AtomicBool>) -> Option<std::thread::JoinHandle<()>> {
+    Some(std::thread::spawn(move || {
+        let mut buf = [0u8; 1024];
+        while !done.load(Ordering::Relaxed) {
+            let mut fd = libc::pollfd { fd: libc::STDIN_FILENO, events: libc::POLLIN, revents: 0 };
+            // SAFETY: `fd` is a single valid pollfd for the duration of the call
+            let ready = unsafe { libc::poll(&mut fd, 1, 50) };
+            if ready <= 0 {
+                continue;
+            }
+            // SAFETY: reads at most `buf.len()` bytes into `buf`
+            let n = unsafe { libc::read(libc::STDIN_FILENO, buf.as_mut_ptr().cast(), buf.len()) };
+            if n <= 0 || writer.write_all(&buf[..n as usize]).is_err() {
+                break;
+            }
+            let _ = writer.flush();
+        }
+    }))
+}
+
+/// Never joined: it blocks reading stdin and ends with the process.
+#[cfg(not(unix))]
+fn forward_input(mut writer: Box<dyn Write + Send>, _done: Arc<AtomicBool>) -> Option<std::thread::JoinHandle<()>> {
+    std::thread::spawn(move || {
+        let mut stdin = std::io::stdin();
+        let mut buf = [0u8; 1024];
+        while let Ok(n) = stdin.read(&mut buf) {
+            if n == 0 || writer.write_all(&buf[..n]).is_err() {
+                break;
+            }
+            let _ = writer.flush();
+        }
+    });
+    None
+}
+
+/// Puts the terminal in raw mode while the command runs, so keys reach it
+/// unprocessed (the command's own terminal does the echoing).
+struct RawMode(bool);
+
+impl RawMode {
+    fn enable() -> Self {
+        Self(atty::is(atty::Stream::Stdin) && crossterm::terminal::enable_raw_mode().is_ok())
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        if self.0 {
+            let _ = crossterm::terminal::disable_raw_mode();
+        }
+    }
+}
+
+/// The `log_{timestamp}_{slug}.log` file name used for recorded commands:
+/// the command line with non-alphanumerics replaced by `_`, cut to 30 chars.
+pub fn log_file_name(started_at: i64, command: &[String]) -> String {
+    let slug: String = command
+        .join(" ")
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .take(30)
+        .collect();
+    let slug = if slug.is_empty() { "unknown".to_string() } else { slug };
+    format!("log_{}_{}.log", started_at, slug)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_file_name() {
+        let command = vec!["npm".to_string(), "run".to_string(), "build:prod".to_string()];
+        assert_eq!(log_file_name(100, &command), "log_100_npm_run_build_prod.log");
+        let long = vec!["x".repeat(40)];
+        assert_eq!(log_file_name(5, &long), format!("log_5_{}.log", "x".repeat(30)));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_records_exit_code() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("log_1_sh.log");
+        let command = ["sh", "-c", "exit 3"].map(String::from);
+        assert_eq!(run(&command, &log).unwrap(), 3);
+        assert!(log.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_capture_labeled() {
+        let command = ["sh", "-c", "echo compiling; echo 'error: boom' >&2; exit 1"].map(String::from);
+        let captured = capture_labeled(&command, Mirror::Stderr, Limits::default()).unwrap();
+        assert_eq!(captured.exit_code, 1);
+        let mut lines: Vec<&str> = captured.output.lines().collect();
+        lines.sort();
+        assert_eq!(lines, ["[stderr] error: boom", "compiling"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_capture_runs_under_a_terminal() {
+        let command = ["sh", "-c", "test -t 1 && echo tty; exit 4"].map(String::from);
+        let captured = capture(&command, Mirror::Stderr, Limits::default()).unwrap();
+        assert_eq!(captured.exit_code, 4);
+        assert!(captured.output.contains("tty"), "{:?}", captured.output);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_capture_limits() {
+        let limits = Limits { timeout: Some(Duration::from_millis(300)), max_capture: Some(40), ..Limits::default() };
+        let command = ["sh", "-c", "seq 1 100; echo last; sleep 10"].map(String::from);
+        let captured = capture_labeled(&command, Mirror::Stderr, limits).unwrap();
+        assert!(captured.timed_out);
+        assert_eq!(captured.exit_code, TIMEOUT_EXIT_CODE);
+        assert!(captured.dropped > 0);
+        assert!(captured.output.starts_with("1\n2\n"), "{:?}", captured.output);
+        assert!(captured.output.ends_with("100\nlast\n"), "{:?}", captured.output);
+        assert!(captured.output.contains("dropped by --max-capture"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_timeout_kills_what_the_command_started() {
+        let dir = tempfile::tempdir().unwrap();
+        let late = dir.path().join("late");
+        let script = format!("(sleep 1; echo late > {}); true", late.display());
+        let command = ["sh".to_string(), "-c".to_string(), script];
+        for capture in [capture, capture_labeled] {
+            let limits = Limits { timeout: Some(Duration::from_millis(200)), ..Limits::default() };
+            assert!(capture(&command, Mirror::Stderr, limits).unwrap().timed_out);
+        }
+        std::thread::sleep(Duration::from_millis(1500));
+        assert!(!late.exists(), "the subshell outlived the timeout");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_abort_on() {
+        let limits = Limits { abort_on: Some(Regex::new("FATAL").unwrap()), ..Limits::default() };
+        let command = ["sh", "-c", "echo step 1; echo 'FATAL: out of disk'; sleep 10; echo step 2"].map(String::from);
+        let started = Instant::now();
+        let captured = capture(&command, Mirror::Stderr, limits).unwrap();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(captured.aborted_at.as_deref(), Some("FATAL: out of disk"));
+        assert!(!captured.timed_out);
+        assert!(!captured.output.contains("step 2"));
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("10M"), Ok(10 << 20));
+        assert_eq!(parse_size("512kb"), Ok(512 << 10));
+        assert_eq!(parse_size("4096"), Ok(4096));
+        assert!(parse_size("0").is_err());
+        assert!(parse_size("10X").is_err());
+        assert!(parse_size("99999999999G").is_err());
+    }
+}
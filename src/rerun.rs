@@ -0,0 +1,172 @@
+//! Deterministic extraction of failed test identifiers from test-runner
+//! output, and a ready-to-run command that reruns only those tests.
+
+use regex::Regex;
+use std::sync::LazyLock;
+
+static CARGO_FAILED: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?m)^test (\S+) \.\.\. FAILED").unwrap());
+static PYTEST_FAILED: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?m)^(?:FAILED|ERROR) (\S+::\S+)").unwrap());
+static GO_FAILED: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?m)^\s*--- FAIL: (\S+)").unwrap());
+static GO_PACKAGE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?m)^FAIL\s+(\S+)\s+[\d.]+s").unwrap());
+static JEST_FILE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?m)^\s*FAIL\s+(\S+\.(?:test|spec)\.[jt]sx?)").unwrap());
+static JEST_TEST: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?m)^\s*● (.+ › .+)$").unwrap());
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Runner {
+    Cargo,
+    Pytest,
+    Jest,
+    Go,
+}
+
+/// The failed tests found in a log.
+#[derive(Debug, PartialEq)]
+pub struct TestFailures {
+    pub runner: Runner,
+    pub tests: Vec<String>,
+    /// Test files (jest) or packages (go) containing the failures.
+    pub scopes: Vec<String>,
+}
+
+/// Finds failed test identifiers for the first test runner recognized.
+pub fn extract(log: &str) -> Option<TestFailures> {
+    let (runner, tests, scopes) = if GO_FAILED.is_match(log) {
+        let tests = captures(&GO_FAILED, log)
+            .into_iter()
+            // Subtests (TestA/case) are rerun through their parent
+            .map(|t| t.split('/').next().unwrap_or(&t).to_string())
+            .collect();
+        (Runner::Go, dedup(tests), captures(&GO_PACKAGE, log))
+    } else if CARGO_FAILED.is_match(log) {
+        (Runner::Cargo, captures(&CARGO_FAILED, log), Vec::new())
+    } else if PYTEST_FAILED.is_match(log) {
+        (Runner::Pytest, captures(&PYTEST_FAILED, log), Vec::new())
+    } else if JEST_FILE.is_match(log) {
+        let tests = captures(&JEST_TEST, log)
+            .into_iter()
+            .map(|t| t.rsplit(" › ").next().unwrap_or(&t).trim().to_string())
+            .collect();
+        (Runner::Jest, dedup(tests), captures(&JEST_FILE, log))
+    } else {
+        return None;
+    };
+
+    if tests.is_empty() && scopes.is_empty() {
+        return None;
+    }
+    Some(TestFailures { runner, tests, scopes })
+}
+
+impl TestFailures {
+    /// A shell command that reruns only the failed tests.
+    pub fn rerun_command(&self) -> String {
+        let quoted = |items: &[String]| items.iter().map(|t| shell_quote(t)).collect::<Vec<_>>().join(" ");
+        match self.runner {
+            Runner::Cargo => match self.tests.as_slice() {
+                [single] => format!("cargo test {} -- --exact --nocapture", shell_quote(single)),
+                tests => format!("cargo test -- {} --exact --nocapture", quoted(tests)),
+            },
+            Runner::Pytest => format!("pytest {}", quoted(&self.tests)),
+            Runner::Go => {
+                let packages = if self.scopes.is_empty() {
+                    "./...".to_string()
+                } else {
+                    quoted(&self.scopes)
+                };
+                format!(
+                    "go test {} -run {} -v",
+                    packages,
+                    shell_quote(&format!("^({})$", self.tests.join("|")))
+                )
+            }
+            Runner::Jest => {
+                let mut command = format!("npx jest {}", quoted(&self.scopes));
+                if !self.tests.is_empty() {
+                    let pattern = self.tests.iter().map(|t| regex::escape(t)).collect::<Vec<_>>().join("|");
+                    command.push_str(&format!(" -t {}", shell_quote(&pattern)));
+                }
+                command
+            }
+        }
+    }
+}
+
+fn captures(re: &Regex, log: &str) -> Vec<String> {
+    dedup(re.captures_iter(log).map(|c| c[1].to_string()).collect())
+}
+
+fn dedup(items: Vec<String>) -> Vec<String> {
+    let mut unique: Vec<String> = Vec::new();
+    for item in items {
+        if !unique.contains(&item) {
+            unique.push(item);
+        }
+    }
+    unique
+}
+
+/// Quotes a word for POSIX shells when it contains anything but safe characters.
+pub fn shell_quote(word: &str) -> String {
+    let safe = !word.is_empty()
+        && word
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./:=@,+%".contains(c));
+    if safe {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', r"'\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cargo_rerun() {
+        let log = "test tests::ok ... ok\ntest tests::parse_empty ... FAILED\ntest net::retry ... FAILED\n";
+        let failures = extract(log).unwrap();
+        assert_eq!(failures.runner, Runner::Cargo);
+        assert_eq!(
+            failures.rerun_command(),
+            "cargo test -- tests::parse_empty net::retry --exact --nocapture"
+        );
+    }
+
+    #[test]
+    fn test_pytest_rerun() {
+        let log = "=== short test summary info ===\nFAILED tests/test_api.py::test_login[admin] - AssertionError\nFAILED tests/test_db.py::TestConn::test_timeout\n";
+        assert_eq!(
+            extract(log).unwrap().rerun_command(),
+            "pytest 'tests/test_api.py::test_login[admin]' tests/test_db.py::TestConn::test_timeout"
+        );
+    }
+
+    #[test]
+    fn test_go_rerun() {
+        let log = "--- FAIL: TestParse (0.00s)\n    --- FAIL: TestParse/empty (0.00s)\n--- FAIL: TestServe (0.01s)\nFAIL\nFAIL\tgithub.com/acme/app/server\t0.123s\n";
+        assert_eq!(
+            extract(log).unwrap().rerun_command(),
+            "go test github.com/acme/app/server -run '^(TestParse|TestServe)$' -v"
+        );
+    }
+
+    #[test]
+    fn test_jest_rerun() {
+        let log = " FAIL  src/cart.test.js\n  ● Cart › adds items\n\n    expect(received).toBe(expected)\n";
+        assert_eq!(
+            extract(log).unwrap().rerun_command(),
+            "npx jest src/cart.test.js -t 'adds items'"
+        );
+    }
+
+    #[test]
+    fn test_no_failures() {
+        assert_eq!(extract("test a ... ok\ntest result: ok"), None);
+    }
+}
@@ -0,0 +1,225 @@
+//! `logtrains --rpc`: a long-lived JSON-RPC 2.0 session on stdin/stdout for
+//! editor extensions, one message per line. `analyze` streams its answer as
+//! `analyze/partial` notifications and can be cancelled with
+//! `$/cancelRequest`, as in the Language Server Protocol.
+
+use anyhow::Result;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+
+use crate::jsonrpc::{self, send, INTERNAL_ERROR, INVALID_PARAMS};
+use crate::llm::{Flow, Inferencer, ModelLoaderBuilder};
+use logtrains_core::{analyze, AnalysisRequest};
+
+/// The Language Server Protocol's code for a cancelled request.
+const REQUEST_CANCELLED: i64 = -32800;
+
+/// Cancellation flags of the requests that have not been answered yet, keyed
+/// by their JSON-encoded ID. Shared between the reader and the worker.
+#[derive(Default, Clone)]
+struct Pending(Arc<Mutex<HashMap<String, Arc<AtomicBool>>>>);
+
+impl Pending {
+    /// The cancellation flag of request `id`, created on first use.
+    fn register(&self, id: &Value) -> Arc<AtomicBool> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).entry(id.to_string()).or_default().clone()
+    }
+
+    /// Marks request `id` cancelled; unknown or finished IDs are ignored.
+    fn cancel(&self, id: &Value) -> bool {
+        match self.0.lock().unwrap_or_else(|e| e.into_inner()).get(&id.to_string()) {
+            Some(flag) => {
+                flag.store(true, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn finish(&self, id: &Value) {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).remove(&id.to_string());
+    }
+}
+
+pub struct Server {
+    loader: ModelLoaderBuilder,
+    /// Loaded on the first `analyze`.
+    engine: Option<Inferencer>,
+    /// What every analysis starts from: the prompt, role, examples and
+    /// redaction, without a log.
+    request: AnalysisRequest,
+}
+
+impl Server {
+    pub fn new(loader: ModelLoaderBuilder, request: AnalysisRequest) -> Self {
+        Self { loader, engine: None, request }
+    }
+
+    /// Answers requests from `input` on `output` until `input` ends or the
+    /// client sends `exit`. Requests are answered in order; a separate thread
+    /// keeps reading so cancellations arrive while an analysis runs.
+    pub async fn serve(mut self, input: impl BufRead + Send + 'static, mut output: impl Write) -> Result<()> {
+        let pending = Pending::default();
+        let (tx, rx) = mpsc::channel();
+        // The reader ends with the input; after `exit` it is left blocked on it
+        {
+            let pending = pending.clone();
+            std::thread::spawn(move || {
+                for line in input.lines() {
+                    let Ok(line) = line else { break };
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    let message = jsonrpc::parse(&line);
+                    if let Ok(message) = &message {
+                        let method = message.get("method").and_then(Value::as_str);
+                        if method == Some("$/cancelRequest") {
+                            if let Some(id) = message.pointer("/params/id") {
+                                pending.cancel(id);
+                            }
+                            continue;
+                        }
+                        if let Some(id) = message.get("id") {
+                            pending.register(id);
+                        }
+                    }
+                    if tx.send(message).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+
+        for message in rx {
+            let message = match message {
+                Ok(message) => message,
+                Err(error) => {
+                    send(&mut output, &error)?;
+                    continue;
+                }
+            };
+            if message.get("method").and_then(Value::as_str) == Some("exit") {
+                break;
+            }
+            let Some(id) = message.get("id").cloned() else {
+                // Other notifications need no answer
+                continue;
+            };
+            let response = self.handle(&id, &message, &pending, &mut output).await;
+            pending.finish(&id);
+            send(&mut output, &response)?;
+        }
+        Ok(())
+    }
+
+    async fn handle(&mut self, id: &Value, message: &Value, pending: &Pending, output: &mut impl Write) -> Value {
+        let params = message.get("params").cloned().unwrap_or(Value::Null);
+        let result = match message.get("method").and_then(Value::as_str).unwrap_or("") {
+            "initialize" => json!({
+                "serverInfo": { "name": "logtrains", "version": env!("CARGO_PKG_VERSION") },
+                "capabilities": { "analyze": { "partialResults": true, "cancellation": true } },
+            }),
+            "ping" | "shutdown" => Value::Null,
+            "analyze" => {
+                let Some(text) = params.get("text").and_then(Value::as_str) else {
+                    return jsonrpc::error(id.clone(), INVALID_PARAMS, "`text` is required");
+                };
+                let question = jsonrpc::question(&params);
+                let cancelled = pending.register(id);
+                match self.analyze(id, text, question, &cancelled, output).await {
+                    Ok(result) => result,
+                    Err(_) if cancelled.load(Ordering::Relaxed) => {
+                        return jsonrpc::error(id.clone(), REQUEST_CANCELLED, "The request was cancelled")
+                    }
+                    Err(e) => return jsonrpc::error(id.clone(), INTERNAL_ERROR, &format!("{:#}", e)),
+                }
+            }
+            method => return jsonrpc::method_not_found(id.clone(), method),
+        };
+        jsonrpc::response(id.clone(), result)
+    }
+
+    async fn analyze(
+        &mut self,
+        id: &Value,
+        text: &str,
+        question: Option<String>,
+        cancelled: &AtomicBool,
+        output: &mut impl Write,
+    ) -> Result<Value> {
+        if cancelled.load(Ordering::Relaxed) {
+            anyhow::bail!("cancelled");
+        }
+        if self.engine.is_none() {
+            let engine = self.loader.clone().load().await?;
+            engine.fit_examples(&mut self.request.vars.examples);
+            self.engine = Some(engine);
+        }
+        let engine = self.engine.as_mut().expect("the model was loaded above");
+        let request = AnalysisRequest { log: text.to_string(), question, ..self.request.clone() };
+        let result = analyze(engine, &request, |token| {
+            if cancelled.load(Ordering::Relaxed) {
+                return Ok(Flow::Stop);
+            }
+            let partial = json!({
+                "jsonrpc": "2.0",
+                "method": "analyze/partial",
+                "params": { "id": id, "text": token },
+            });
+            send(output, &partial)?;
+            Ok(Flow::Continue)
+        })?;
+        if cancelled.load(Ordering::Relaxed) {
+            anyhow::bail!("cancelled");
+        }
+        Ok(json!({
+            "explanation": result.explanation,
+            "severity": result.severity.label(),
+            "truncated": result.truncated,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pending() {
+        let pending = Pending::default();
+        let flag = pending.register(&json!(7));
+        assert!(Arc::ptr_eq(&flag, &pending.register(&json!(7))));
+        assert!(!pending.cancel(&json!("7")), "string and number IDs differ");
+        assert!(pending.cancel(&json!(7)));
+        assert!(flag.load(Ordering::Relaxed));
+        pending.finish(&json!(7));
+        assert!(!pending.cancel(&json!(7)));
+    }
+
+    #[tokio::test]
+    async fn test_serve() {
+        let server = Server::new(ModelLoaderBuilder::new("repo", "model.gguf"), AnalysisRequest::new(""));
+        let input = [
+            r#"{"jsonrpc":"2.0","id":1,"method":"initialize"}"#,
+            r#"{"jsonrpc":"2.0","id":2,"method":"analyze","params":{}}"#,
+            r#"{"jsonrpc":"2.0","method":"$/cancelRequest","params":{"id":99}}"#,
+            r#"{"jsonrpc":"2.0","id":3,"method":"format"}"#,
+            "not json",
+            r#"{"jsonrpc":"2.0","method":"exit"}"#,
+            r#"{"jsonrpc":"2.0","id":4,"method":"ping"}"#,
+        ]
+        .join("\n");
+        let mut output = Vec::new();
+        server.serve(std::io::Cursor::new(input), &mut output).await.unwrap();
+
+        let responses = jsonrpc::messages(output);
+        assert_eq!(responses.len(), 4, "notifications and messages after `exit` get no response");
+        assert_eq!(responses[0]["result"]["capabilities"]["analyze"]["cancellation"], true);
+        assert_eq!(responses[1]["error"]["code"], INVALID_PARAMS);
+        assert_eq!(responses[2]["error"]["code"], jsonrpc::METHOD_NOT_FOUND);
+        assert_eq!(responses[3]["error"]["code"], jsonrpc::PARSE_ERROR);
+    }
+}
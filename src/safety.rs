@@ -0,0 +1,253 @@
+use regex::Regex;
+use std::sync::LazyLock;
+
+/// A suggested shell command that matched one or more dangerous patterns.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hazard {
+    pub command: String,
+    pub reasons: Vec<&'static str>,
+}
+
+static PIPE_TO_SHELL: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\b(curl|wget)\b[^|]*\|\s*(sudo\s+)?(env\s+)?(ba|z|da|k|fi)?sh\b").unwrap()
+});
+static WORLD_WRITABLE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\bchmod\s+(-\S+\s+)*(0?777|a\+rwx|ugo\+rwx)\b").unwrap());
+static RAW_DEVICE_WRITE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(\bdd\b.*\bof=/dev/(sd|nvme|hd|vd|disk|mmcblk)|>\s*/dev/(sd|nvme|hd|vd|disk|mmcblk))").unwrap()
+});
+static MKFS: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\bmkfs(\.\w+)?\b").unwrap());
+static FORK_BOMB: LazyLock<Regex> = LazyLock::new(|| Regex::new(r":\s*\(\s*\)\s*\{.*:\s*\|\s*:").unwrap());
+
+/// Checks a single shell command line for obviously dangerous constructs.
+/// Returns a human-readable reason for every pattern that matched.
+pub fn check_command(command: &str) -> Vec<&'static str> {
+    let mut reasons = Vec::new();
+
+    for simple in split_simple_commands(command) {
+        if let Some(reason) = check_rm(&simple) {
+            reasons.push(reason);
+        }
+    }
+    if PIPE_TO_SHELL.is_match(command) {
+        reasons.push("pipes a downloaded script straight into a shell");
+    }
+    if WORLD_WRITABLE.is_match(command) {
+        reasons.push("makes files world-writable (chmod 777)");
+    }
+    if RAW_DEVICE_WRITE.is_match(command) {
+        reasons.push("writes directly to a block device");
+    }
+    if MKFS.is_match(command) {
+        reasons.push("formats a filesystem");
+    }
+    if FORK_BOMB.is_match(command) {
+        reasons.push("looks like a fork bomb");
+    }
+
+    reasons.dedup();
+    reasons
+}
+
+/// Scans the generated Markdown for shell commands (fenced code blocks and
+/// inline code spans) and returns the ones that look dangerous.
+pub fn scan_markdown(markdown: &str) -> Vec<Hazard> {
+    extract_commands(markdown)
+        .into_iter()
+        .filter_map(|command| {
+            let reasons = check_command(&command);
+            if reasons.is_empty() {
+                None
+            } else {
+                Some(Hazard { command, reasons })
+            }
+        })
+        .collect()
+}
+
+/// Pulls candidate command lines out of Markdown: every line of a shell-ish
+/// fenced code block, plus inline `code` spans outside of fences.
+pub fn extract_commands(markdown: &str) -> Vec<String> {
+    scan_commands(markdown, true)
+}
+
+/// Only the lines of shell-ish fenced code blocks: the commands the model
+/// actually proposed running, as opposed to identifiers quoted inline.
+pub fn fenced_commands(markdown: &str) -> Vec<String> {
+    let mut commands = scan_commands(markdown, false);
+    let mut seen = std::collections::HashSet::new();
+    commands.retain(|c| seen.insert(c.clone()));
+    commands
+}
+
+fn scan_commands(markdown: &str, include_inline: bool) -> Vec<String> {
+    let mut commands = Vec::new();
+    let mut in_fence = false;
+    let mut fence_is_shell = false;
+
+    for line in markdown.lines() {
+        let trimmed = line.trim();
+        if let Some(lang) = trimmed.strip_prefix("```") {
+            if in_fence {
+                in_fence = false;
+            } else {
+                in_fence = true;
+                fence_is_shell = matches!(
+                    lang.trim(),
+                    "" | "sh" | "bash" | "shell" | "zsh" | "fish" | "console" | "terminal"
+                );
+            }
+            continue;
+        }
+
+        if in_fence {
+            if !fence_is_shell {
+                continue;
+            }
+            let command = trimmed.strip_prefix("$ ").unwrap_or(trimmed);
+            if !command.is_empty() && !command.starts_with('#') {
+                commands.push(command.to_string());
+            }
+        } else if include_inline {
+            // Inline code spans: `like this`
+            let mut parts = line.split('`');
+            parts.next();
+            while let Some(span) = parts.next() {
+                if !span.trim().is_empty() {
+                    commands.push(span.trim().to_string());
+                }
+                parts.next();
+            }
+        }
+    }
+
+    commands
+}
+
+/// Splits a command line on `;`, `&&`, `||` and `|` (outside quotes) and
+/// tokenizes each part, keeping quote characters so we can tell whether a
+/// word was quoted.
+fn split_simple_commands(command: &str) -> Vec<Vec<String>> {
+    let mut commands = Vec::new();
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut quote: Option<char> = None;
+
+    let mut chars = command.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => {
+                quote = None;
+                word.push(c);
+            }
+            (Some(_), c) => word.push(c),
+            (None, '"') | (None, '\'') => {
+                quote = Some(c);
+                word.push(c);
+            }
+            (None, ';') | (None, '|') | (None, '&') => {
+                if matches!(chars.peek(), Some('|') | Some('&')) {
+                    chars.next();
+                }
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+                if !words.is_empty() {
+                    commands.push(std::mem::take(&mut words));
+                }
+            }
+            (None, c) if c.is_whitespace() => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+            }
+            (None, c) => word.push(c),
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    if !words.is_empty() {
+        commands.push(words);
+    }
+    commands
+}
+
+fn check_rm(words: &[String]) -> Option<&'static str> {
+    let mut words = words.iter().map(String::as_str).peekable();
+    // Skip privilege escalation prefixes
+    while let Some(&w) = words.peek() {
+        if w == "sudo" || w == "doas" || w == "command" {
+            words.next();
+        } else {
+            break;
+        }
+    }
+    if words.next() != Some("rm") {
+        return None;
+    }
+
+    let mut recursive = false;
+    let mut targets = Vec::new();
+    for w in words {
+        if w == "--recursive" {
+            recursive = true;
+        } else if let Some(flags) = w.strip_prefix('-').filter(|f| !f.starts_with('-')) {
+            recursive |= flags.contains('r') || flags.contains('R');
+        } else if !w.starts_with("--") {
+            targets.push(w);
+        }
+    }
+
+    if !recursive {
+        return None;
+    }
+    if targets.iter().any(|t| t.starts_with('$')) {
+        return Some("recursively deletes an unquoted variable path (empty variable = wrong directory)");
+    }
+    if targets
+        .iter()
+        .any(|t| matches!(*t, "/" | "/*" | "~" | "~/" | "~/*" | "*" | "." | ".."))
+    {
+        return Some("recursively deletes a root, home, or wildcard path");
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flags_unquoted_rm_rf_variable() {
+        assert!(!check_command("rm -rf $BUILD_DIR").is_empty());
+        assert!(!check_command("sudo rm -fr ${TMP}/cache").is_empty());
+        assert!(check_command("rm -rf \"$BUILD_DIR\"").is_empty());
+        assert!(check_command("rm -rf target").is_empty());
+        assert!(check_command("rm $FILE").is_empty());
+    }
+
+    #[test]
+    fn test_flags_pipe_to_shell_and_chmod() {
+        assert!(!check_command("curl -fsSL https://example.com/install.sh | bash").is_empty());
+        assert!(!check_command("wget -qO- https://x.io | sudo sh").is_empty());
+        assert!(!check_command("chmod -R 777 /var/www").is_empty());
+        assert!(check_command("chmod 644 config.toml").is_empty());
+        assert!(check_command("curl https://example.com | jq .").is_empty());
+    }
+
+    #[test]
+    fn test_extract_commands() {
+        let md = "Try `cargo clean` first.\n```bash\n$ rm -rf $OUT\n# comment\nmake\n```\n```rust\nfn main() {}\n```";
+        assert_eq!(extract_commands(md), vec!["cargo clean", "rm -rf $OUT", "make"]);
+        assert_eq!(fenced_commands(md), vec!["rm -rf $OUT", "make"]);
+    }
+
+    #[test]
+    fn test_scan_markdown() {
+        let md = "```sh\ncurl https://get.example.com | sh\nls -la\n```";
+        let hazards = scan_markdown(md);
+        assert_eq!(hazards.len(), 1);
+        assert_eq!(hazards[0].command, "curl https://get.example.com | sh");
+    }
+}
@@ -0,0 +1,247 @@
+//! `logtrains serve`: an HTTP API for a shared analysis box, e.g. behind CI.
+//! The model is loaded once at startup and kept warm; analyses run one at a
+//! time and stream their tokens as server-sent events.
+
+use anyhow::{Context, Result};
+use axum::extract::{DefaultBodyLimit, FromRequest, Multipart, Query, Request, State};
+use axum::http::{header, StatusCode};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::StreamExt;
+
+use crate::history::History;
+use crate::llm::{Flow, Inferencer};
+use logtrains_core::{analyze, AnalysisRequest};
+
+/// Largest log accepted by `POST /analyze`.
+const MAX_BODY: usize = 64 << 20;
+
+/// Entries returned by `GET /history` unless `limit` says otherwise.
+const DEFAULT_HISTORY_LIMIT: usize = 20;
+
+#[derive(Clone)]
+struct AppState {
+    engine: Arc<Mutex<Inferencer>>,
+    model: String,
+    /// The [`crate::history::analysis_key`] of the model and prompt.
+    key: String,
+    history_dir: PathBuf,
+    /// What every analysis starts from: the prompt, role, examples and
+    /// redaction, without a log.
+    request: AnalysisRequest,
+}
+
+/// Serves the API on `addr` until the process is stopped.
+pub async fn run(
+    addr: SocketAddr,
+    engine: Inferencer,
+    model: String,
+    key: String,
+    history_dir: PathBuf,
+    request: AnalysisRequest,
+) -> Result<()> {
+    let state = AppState { engine: Arc::new(Mutex::new(engine)), model, key, history_dir, request };
+    let app = Router::new()
+        .route("/analyze", post(analyze_log))
+        .route("/history", get(history))
+        .layer(DefaultBodyLimit::max(MAX_BODY))
+        .with_state(state);
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("Failed to listen on {}", addr))?;
+    eprintln!("LogTrains: serving on http://{}", listener.local_addr()?);
+    axum::serve(listener, app).await.context("The server stopped")?;
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct AnalyzeQuery {
+    question: Option<String>,
+}
+
+/// `POST /analyze[?question=...]`: the log is the body, or the first part of
+/// a multipart upload. Streams `token` events with JSON-encoded text, then a
+/// `done` event with the severity and the whole explanation (or `error`).
+async fn analyze_log(State(state): State<AppState>, Query(query): Query<AnalyzeQuery>, request: Request) -> Response {
+    let log = match read_log(request).await {
+        Ok(log) => log,
+        Err(e) => return (StatusCode::BAD_REQUEST, format!("{:#}\n", e)).into_response(),
+    };
+
+    let (tx, rx) = tokio::sync::mpsc::channel::<Event>(64);
+    tokio::task::spawn_blocking(move || {
+        // Requests queue here while another analysis runs
+        let mut engine = state.engine.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let question = query.question.filter(|q| !q.trim().is_empty());
+        let request = AnalysisRequest { log, question, ..state.request.clone() };
+        let result = analyze(&mut engine, &request, |token| {
+            let event = Event::default().event("token").json_data(token)?;
+            // The client went away: stop generating for nobody
+            Ok(if tx.blocking_send(event).is_ok() { Flow::Continue } else { Flow::Stop })
+        });
+        let event = match result {
+            Ok(result) => Event::default().event("done").json_data(json!({
+                "severity": result.severity.label(),
+                "explanation": result.explanation,
+                "truncated": result.truncated,
+                "model": state.model,
+            })),
+            Err(e) => Ok(Event::default().event("error").data(format!("{:#}", e))),
+        };
+        if let Ok(event) = event {
+            let _ = tx.blocking_send(event);
+        }
+    });
+    Sse::new(ReceiverStream::new(rx).map(Ok::<_, Infallible>))
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+/// The log text of an `/analyze` request.
+async fn read_log(request: Request) -> Result<String> {
+    let multipart = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("multipart/form-data"));
+    let log = if multipart {
+        let mut form = Multipart::from_request(request, &()).await.context("Invalid multipart body")?;
+        let field = form.next_field().await.context("Invalid multipart body")?.context("The upload is empty")?;
+        let bytes = field.bytes().await.context("Failed to read the upload")?;
+        String::from_utf8_lossy(&bytes).into_owned()
+    } else {
+        let bytes = axum::body::to_bytes(request.into_body(), MAX_BODY)
+            .await
+            .context("Failed to read the request body")?;
+        String::from_utf8_lossy(&bytes).into_owned()
+    };
+    if log.trim().is_empty() {
+        anyhow::bail!("The log is empty; send it as the request body or a multipart file.");
+    }
+    Ok(log)
+}
+
+#[derive(Deserialize)]
+struct HistoryQuery {
+    query: Option<String>,
+    #[serde(default)]
+    failed: bool,
+    limit: Option<usize>,
+}
+
+/// `GET /history[?query=...&failed=true&limit=N]`: recorded commands, newest
+/// first, with their latest analysis made with the served model and prompt.
+async fn history(State(state): State<AppState>, Query(query): Query<HistoryQuery>) -> Response {
+    let listed = History::open(&state.history_dir).and_then(|history| history_json(&history, &query, &state.key));
+    match listed {
+        Ok(entries) => Json(entries).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, format!("{:#}\n", e)).into_response(),
+    }
+}
+
+fn history_json(history: &History, query: &HistoryQuery, key: &str) -> Result<Value> {
+    let limit = query.limit.unwrap_or(DEFAULT_HISTORY_LIMIT);
+    let entries = match query.query.as_deref().filter(|q| !q.trim().is_empty()) {
+        Some(words) => history.search(words, query.failed, limit)?.into_iter().map(|(entry, _)| entry).collect(),
+        None if query.failed => history.recent_failures(limit)?,
+        None => history.recent(limit)?,
+    };
+    let mut listed = Vec::new();
+    for entry in entries {
+        let analysis = history.analysis_with(entry.id, key)?.map(|analysis| {
+            json!({
+                "severity": analysis.severity.label(),
+                "explanation": analysis.explanation,
+                "created_at": analysis.created_at,
+            })
+        });
+        listed.push(json!({
+            "id": entry.id,
+            "command": entry.command,
+            "cwd": entry.cwd,
+            "started_at": entry.started_at,
+            "ended_at": entry.ended_at,
+            "exit_code": entry.exit_code,
+            "analysis": analysis,
+        }));
+    }
+    Ok(Value::Array(listed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history;
+    use axum::body::Body;
+
+    #[tokio::test]
+    async fn test_read_log() {
+        let plain = Request::builder().body(Body::from("error: boom\n")).unwrap();
+        assert_eq!(read_log(plain).await.unwrap(), "error: boom\n");
+
+        let body = "--XYZ\r\nContent-Disposition: form-data; name=\"file\"; filename=\"ci.log\"\r\n\
+                    Content-Type: text/plain\r\n\r\nFAILED test_auth\r\n--XYZ--\r\n";
+        let upload = Request::builder()
+            .header(header::CONTENT_TYPE, "multipart/form-data; boundary=XYZ")
+            .body(Body::from(body))
+            .unwrap();
+        assert_eq!(read_log(upload).await.unwrap(), "FAILED test_auth");
+
+        let empty = Request::builder().body(Body::from(" \n")).unwrap();
+        assert!(read_log(empty).await.is_err());
+    }
+
+    #[test]
+    fn test_history_json() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = History::open(dir.path()).unwrap();
+        for (command, exit_code) in [("cargo build", 101), ("cargo fmt", 0)] {
+            let log_path = dir.path().join(format!("{}.log", exit_code));
+            std::fs::write(&log_path, command).unwrap();
+            history
+                .add(&history::Entry {
+                    id: 0,
+                    command: command.to_string(),
+                    cwd: None,
+                    started_at: 1_700_000_000 - exit_code as i64,
+                    ended_at: None,
+                    exit_code: Some(exit_code),
+                    log_path,
+                })
+                .unwrap();
+        }
+        let build = history.recent(2).unwrap()[1].id;
+        for (explanation, key) in [("A missing crate.", "served"), ("Eine fehlende Crate.", "german")] {
+            history
+                .add_analysis(&history::Analysis {
+                    id: 0,
+                    entry_id: Some(build),
+                    source: "cargo build".to_string(),
+                    created_at: 1_700_000_200,
+                    model: "repo/model.gguf".to_string(),
+                    severity: crate::severity::Severity::Error,
+                    explanation: explanation.to_string(),
+                    key: Some(key.to_string()),
+                })
+                .unwrap();
+        }
+        // The limit counts failures, not the newer command that succeeded
+        let query = HistoryQuery { query: None, failed: true, limit: Some(1) };
+        let listed = history_json(&history, &query, "served").unwrap();
+        let listed = listed.as_array().unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0]["command"], "cargo build");
+        assert_eq!(listed[0]["analysis"]["explanation"], "A missing crate.");
+        let listed = history_json(&history, &query, "other").unwrap();
+        assert_eq!(listed[0]["analysis"], Value::Null);
+    }
+}
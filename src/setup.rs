@@ -0,0 +1,421 @@
+//! The shell snippets printed by `logtrains setup`: a `logtrains-run`
+//! wrapper around `logtrains record` (or, with `--hooks`, hooks that record
+//! every command), plus the command-not-found hook. With `--auto`, a failed
+//! recorded command is followed by a hint or, with `LOGTRAINS_AUTO=1`, by
+//! its analysis.
+
+use std::path::Path;
+
+/// Called with the exit code after a recorded command fails. Ctrl-C (130)
+/// is not a failure worth explaining.
+const POSIX_ON_FAILURE: &str = r###"
+# Explain failures of recorded commands: with LOGTRAINS_AUTO=1 right away,
+# otherwise with a one-line hint.
+__logtrains_on_failure() {
+    [[ $1 -eq 130 ]] && return
+    if [[ "${LOGTRAINS_AUTO:-0}" != 0 ]]; then
+        logtrains analyze --last
+    else
+        printf '\033[2mlogtrains: exit code %s; run `logtrains analyze --last` to see why\033[0m\n' "$1" >&2
+    fi
+}
+"###;
+
+/// The command-not-found hook of bash and zsh, which spell its name
+/// differently.
+fn posix_not_found(shell: &str) -> String {
+    let name = if shell == "zsh" { "command_not_found_handler" } else { "command_not_found_handle" };
+    r###"
+# Suggest the intended command or package when a command is not found. This
+# replaces any handler already installed, e.g. Ubuntu's command-not-found.
+NAME() {
+    command -v logtrains >/dev/null 2>&1 || { printf '%s: command not found\n' "$1" >&2; return 127; }
+    logtrains not-found "$@"
+    return 127
+}
+"###
+    .replace("NAME", name)
+}
+
+/// The setup script for `shell` (a shell name like `zsh`), or `None` if the
+/// shell is not supported. `auto` adds the failure hook.
+pub fn script(shell: &str, auto: bool) -> Option<String> {
+    match shell {
+        "bash" | "zsh" => Some(posix(shell, auto)),
+        "fish" => Some(fish(auto)),
+        "pwsh" | "powershell" => Some(powershell(auto)),
+        _ => None,
+    }
+}
+
+fn posix(shell: &str, auto: bool) -> String {
+    let run = if auto {
+        r###"    logtrains record -- "$@"
+    local ret=$?
+    [[ $ret -ne 0 ]] && __logtrains_on_failure $ret
+    return $ret"###
+    } else {
+        r###"    logtrains record -- "$@""###
+    };
+
+    format!(
+        r###"# LogTrains Setup Script for {shell}
+# Add the following function to your ~/.{shell}rc or ~/.zshrc file:
+
+logtrains-run() {{
+    # Configuration
+    # You can override these in your environment
+    local exclude_cmds="${{LOGTRAINS_EXCLUDE:-cd ls pwd clear exit history}}"
+    # LOGTRAINS_MAX_FILES (default 50) limits how many commands are kept;
+    # LOGTRAINS_ONLY_FAILURES=1 keeps only the logs of commands that fail
+
+    # Check exclusion
+    local cmd="$1"
+    # Check if cmd is in the space-separated list
+    if [[ " $exclude_cmds " == *" $cmd "* ]] || [[ -z "$cmd" ]]; then
+        "$@"
+        return $?
+    fi
+
+    # Run under a pseudo-terminal, recording the output to the history
+{run}
+}}
+{on_failure}{not_found}
+# Usage:
+# logtrains-run npm install
+# logtrains analyze --last      # Analyze the most recent command
+# logtrains analyze --last 2    # Analyze the 2 most recent commands
+# logtrains analyze --history 7 # Analyze entry 7 of the history listing
+# logtrains history             # See list of recorded commands
+"###,
+        shell = shell,
+        run = run,
+        on_failure = if auto { POSIX_ON_FAILURE } else { "" },
+        not_found = posix_not_found(shell)
+    )
+}
+
+fn fish(auto: bool) -> String {
+    let (run, on_failure) = if auto {
+        (
+            r###"    logtrains record -- $argv
+    set -l ret $status
+    test $ret -ne 0; and __logtrains_on_failure $ret
+    return $ret"###,
+            r###"
+# Explain failures of recorded commands: with LOGTRAINS_AUTO=1 right away,
+# otherwise with a one-line hint.
+function __logtrains_on_failure
+    test $argv[1] -eq 130; and return
+    if set -q LOGTRAINS_AUTO; and test "$LOGTRAINS_AUTO" != 0
+        logtrains analyze --last
+    else
+        printf '\e[2mlogtrains: exit code %s; run `logtrains analyze --last` to see why\e[0m\n' $argv[1] >&2
+    end
+end
+"###,
+        )
+    } else {
+        (r###"    logtrains record -- $argv"###, "")
+    };
+
+    format!(
+        r###"# LogTrains Setup Script for fish
+# Add the following to ~/.config/fish/config.fish:
+
+function logtrains-run
+    # Configuration
+    # You can override these in your environment
+    set -l exclude_cmds cd ls pwd clear exit history
+    set -q LOGTRAINS_EXCLUDE; and set exclude_cmds (string split ' ' -- $LOGTRAINS_EXCLUDE)
+    # LOGTRAINS_MAX_FILES (default 50) limits how many commands are kept;
+    # LOGTRAINS_ONLY_FAILURES=1 keeps only the logs of commands that fail
+
+    # Check exclusion
+    if test (count $argv) -eq 0; or contains -- $argv[1] $exclude_cmds
+        $argv
+        return $status
+    end
+
+    # Run under a pseudo-terminal, recording the output to the history
+{run}
+end
+{on_failure}
+# Suggest the intended command or package when a command is not found. This
+# replaces fish's own handler.
+function fish_command_not_found
+    if not command -q logtrains
+        printf '%s: command not found\n' $argv[1] >&2
+        return 127
+    end
+    logtrains not-found $argv
+end
+
+# Usage:
+# logtrains-run npm install
+# logtrains analyze --last      # Analyze the most recent command
+# logtrains analyze --last 2    # Analyze the 2 most recent commands
+# logtrains analyze --history 7 # Analyze entry 7 of the history listing
+# logtrains history             # See list of recorded commands
+"###,
+        run = run,
+        on_failure = on_failure
+    )
+}
+
+fn powershell(auto: bool) -> String {
+    let (run, on_failure) = if auto {
+        (
+            r###"    logtrains record '--' @args
+    $ret = $LASTEXITCODE
+    if ($ret -ne 0) { __logtrains_on_failure $ret }"###,
+            r###"
+# Explain failures of recorded commands: with LOGTRAINS_AUTO=1 right away,
+# otherwise with a one-line hint.
+function __logtrains_on_failure($code) {
+    if ($code -eq 130) { return }
+    if ($env:LOGTRAINS_AUTO -and $env:LOGTRAINS_AUTO -ne '0') {
+        logtrains analyze --last
+    } else {
+        Write-Host "logtrains: exit code $code; run 'logtrains analyze --last' to see why" -ForegroundColor DarkGray
+    }
+    $global:LASTEXITCODE = $code
+}
+"###,
+        )
+    } else {
+        (r###"    logtrains record '--' @args"###, "")
+    };
+
+    // Filled in with replace: the script is mostly braces
+    r###"# LogTrains Setup Script for PowerShell
+# Add the following function to your $PROFILE:
+
+function logtrains-run {
+    # Configuration
+    # You can override these in your environment
+    $excludeCmds = if ($env:LOGTRAINS_EXCLUDE) { $env:LOGTRAINS_EXCLUDE -split ' ' } else { 'cd', 'ls', 'pwd', 'clear', 'exit', 'history' }
+    # LOGTRAINS_MAX_FILES (default 50) limits how many commands are kept;
+    # LOGTRAINS_ONLY_FAILURES=1 keeps only the logs of commands that fail
+
+    if ($args.Count -eq 0) { return }
+
+    # Check exclusion
+    if ($excludeCmds -contains $args[0]) {
+        & $args[0] @($args | Select-Object -Skip 1)
+        return
+    }
+
+    # Run under a pseudo-terminal, recording the output to the history.
+    # '--' is quoted so PowerShell passes it on.
+{run}
+}
+{on_failure}
+# Usage:
+# logtrains-run npm install
+# logtrains analyze --last      # Analyze the most recent command
+# logtrains analyze --last 2    # Analyze the 2 most recent commands
+# logtrains analyze --history 7 # Analyze entry 7 of the history listing
+# logtrains history             # See list of recorded commands
+"###
+    .replace("{run}", run)
+    .replace("{on_failure}", on_failure)
+}
+
+/// The automatic-recording variant of the bash/zsh script: instead of a
+/// wrapper function, preexec/precmd hooks (a `DEBUG` trap in bash) copy the
+/// output of every command to a log with `tee`. `None` for other shells.
+pub fn hooks(shell: &str, log_dir: &Path, auto: bool) -> Option<String> {
+    let (command_line, install) = match shell {
+        // The DEBUG trap fires for every simple command, including those run
+        // by PROMPT_COMMAND, so only the first one after a prompt is recorded.
+        "bash" => (
+            r###"    [[ -n "$__logtrains_armed" ]] || return 0
+    __logtrains_armed=""
+    local cmdline=$(HISTTIMEFORMAT= history 1 | sed 's/^ *[0-9]* *//')"###,
+            r###"__logtrains_arm() { __logtrains_armed=1; }
+trap '__logtrains_preexec' DEBUG
+PROMPT_COMMAND="__logtrains_precmd${PROMPT_COMMAND:+; $PROMPT_COMMAND}; __logtrains_arm""###,
+        ),
+        "zsh" => (
+            r###"    local cmdline="$1""###,
+            r###"autoload -Uz add-zsh-hook
+add-zsh-hook preexec __logtrains_preexec
+# First, so it sees the command's exit code
+precmd_functions=(__logtrains_precmd ${precmd_functions:#__logtrains_precmd})"###,
+        ),
+        _ => return None,
+    };
+    let index_in_background = r###"( { __logtrains_wait_flushed "$logfile"
+            logtrains history add --log "$logfile" --started "$started" --exit-code "$ret" \
+                --keep "${LOGTRAINS_MAX_FILES:-50}" -- "$cmdline"
+        } >/dev/null 2>&1 & )"###;
+    let index = if auto {
+        // A failure has to be indexed before it can be explained
+        format!(
+            r###"        # Index once tee has flushed the log; in the background unless it failed
+        if [[ $ret -ne 0 ]]; then
+            __logtrains_wait_flushed "$logfile"
+            logtrains history add --log "$logfile" --started "$started" --exit-code "$ret" \
+                --keep "${{LOGTRAINS_MAX_FILES:-50}}" -- "$cmdline" >/dev/null 2>&1
+            __logtrains_on_failure $ret
+        else
+            {}
+        fi"###,
+            index_in_background.replace("\n", "\n    ")
+        )
+    } else {
+        format!(
+            "        # Index in the background, once tee has flushed the log\n        {}",
+            index_in_background
+        )
+    };
+
+    let script = format!(
+        r###"# LogTrains Setup Script for {shell} (automatic recording)
+# Add the following to your ~/.{shell}rc file. Every command is recorded;
+# run logtrains-pause to stop recording and logtrains-resume to start again.
+# You can override LOGTRAINS_MAX_FILES and LOGTRAINS_EXCLUDE in your environment.
+# Output is copied with tee, so full-screen programs belong in LOGTRAINS_EXCLUDE.
+# LOGTRAINS_ONLY_FAILURES=1 keeps only the logs of commands that fail.
+
+__logtrains_log_dir="{log_dir}"
+__logtrains_logfile=""
+
+__logtrains_preexec() {{
+{command_line}
+    [[ -z "$LOGTRAINS_PAUSED" ]] || return 0
+
+    # Check exclusion; logtrains itself is never recorded, so --last stays
+    # on the command you want to analyze
+    local cmd="${{cmdline%% *}}"
+    local exclude_cmds="${{LOGTRAINS_EXCLUDE:-cd ls pwd clear exit history vi vim nano less more man top htop ssh tmux}}"
+    if [[ -z "$cmd" || " $exclude_cmds logtrains logtrains-pause logtrains-resume " == *" $cmd "* ]]; then
+        return 0
+    fi
+
+    mkdir -p "$__logtrains_log_dir"
+    __logtrains_started=$(date +%s)
+    # Sanitize command for filename: replace non-alphanumeric with _, truncate to 30 chars
+    local cmd_slug=$(echo "$cmdline" | sed 's/[^a-zA-Z0-9]/_/g' | cut -c 1-30)
+    [ -z "$cmd_slug" ] && cmd_slug="unknown"
+    __logtrains_logfile="$__logtrains_log_dir/log_${{__logtrains_started}}_${{cmd_slug}}.log"
+    __logtrains_cmdline="$cmdline"
+
+    # Copy the command's output to the log until the next prompt; tee leaves
+    # a marker once it has written everything
+    exec {{__logtrains_out}}>&1 {{__logtrains_err}}>&2
+    exec > >(tee -a "$__logtrains_logfile"; : > "$__logtrains_logfile.flushed") 2>&1
+}}
+
+# Waits until tee has written all of log $1, for at most five seconds in case
+# a background job still holds the output open
+__logtrains_wait_flushed() {{
+    local tries=0
+    while [[ ! -e "$1.flushed" ]] && (( tries++ < 100 )); do
+        sleep 0.05
+    done
+    rm -f "$1.flushed"
+}}
+
+__logtrains_precmd() {{
+    local ret=$?
+    if [[ -n "$__logtrains_logfile" ]]; then
+        exec 1>&$__logtrains_out 2>&$__logtrains_err
+        exec {{__logtrains_out}}>&- {{__logtrains_err}}>&-
+        local logfile="$__logtrains_logfile" started="$__logtrains_started" cmdline="$__logtrains_cmdline"
+        __logtrains_logfile=""
+{index}
+    fi
+    return $ret
+}}
+
+logtrains-pause() {{
+    LOGTRAINS_PAUSED=1
+    echo "LogTrains recording paused; run logtrains-resume to resume."
+}}
+
+logtrains-resume() {{
+    unset LOGTRAINS_PAUSED
+    echo "LogTrains recording resumed."
+}}
+
+{install}
+{on_failure}{not_found}
+# Usage:
+# npm install                   # Recorded automatically
+# logtrains analyze --last      # Analyze the most recent command
+# logtrains analyze --history 7 # Analyze entry 7 of the history listing
+# logtrains history             # See list of recorded commands
+"###,
+        shell = shell,
+        log_dir = log_dir.display(),
+        command_line = command_line,
+        index = index,
+        install = install,
+        on_failure = if auto { POSIX_ON_FAILURE } else { "" },
+        not_found = posix_not_found(shell)
+    );
+    Some(script)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scripts_per_shell() {
+        let zsh = script("zsh", false).unwrap();
+        assert!(zsh.contains("command_not_found_handler() {\n    command -v logtrains >/dev/null 2>&1 || {"));
+        assert!(zsh.contains("logtrains record -- \"$@\""));
+
+        let fish = script("fish", false).unwrap();
+        assert!(fish.contains("function logtrains-run"));
+        assert!(fish.contains("function fish_command_not_found"));
+        // No bash-isms
+        assert!(!fish.contains("local ") && !fish.contains("$?") && !fish.contains("\"$@\""));
+
+        let pwsh = script("pwsh", false).unwrap();
+        assert!(pwsh.contains("function logtrains-run {"));
+        assert!(pwsh.contains("logtrains record '--' @args"));
+
+        assert!(script("tcsh", false).is_none());
+    }
+
+    #[test]
+    fn test_hooks_per_shell() {
+        let dir = Path::new("/home/me/.cache/logtrains");
+        let bash = hooks("bash", dir, false).unwrap();
+        assert!(bash.contains("trap '__logtrains_preexec' DEBUG"));
+        assert!(bash.contains("PROMPT_COMMAND=\"__logtrains_precmd${PROMPT_COMMAND:+; $PROMPT_COMMAND}; __logtrains_arm\""));
+        let zsh = hooks("zsh", dir, false).unwrap();
+        assert!(zsh.contains("add-zsh-hook preexec __logtrains_preexec"));
+        assert!(zsh.contains("logtrains-pause()"));
+        assert!(hooks("fish", dir, false).is_none());
+
+        // Indexing waits for tee's marker rather than a fixed delay
+        for auto in [false, true] {
+            let bash = hooks("bash", dir, auto).unwrap();
+            assert!(bash.contains("tee -a \"$__logtrains_logfile\"; : > \"$__logtrains_logfile.flushed\""));
+            assert!(bash.contains("__logtrains_wait_flushed \"$logfile\""));
+            assert!(!bash.contains("sleep 0.2"));
+        }
+    }
+
+    #[test]
+    fn test_auto_hook_only_when_asked() {
+        let dir = Path::new("/home/me/.cache/logtrains");
+        for (shell, script) in [
+            ("bash", script("bash", true)),
+            ("fish", script("fish", true)),
+            ("pwsh", script("pwsh", true)),
+            ("zsh hooks", hooks("zsh", dir, true)),
+        ] {
+            let script = script.unwrap();
+            assert!(script.contains("__logtrains_on_failure $ret"), "{}", shell);
+            assert!(script.contains("logtrains analyze --last\n"), "{}", shell);
+        }
+        assert!(!script("bash", false).unwrap().contains("__logtrains_on_failure"));
+        assert!(!hooks("bash", dir, false).unwrap().contains("__logtrains_on_failure"));
+    }
+}
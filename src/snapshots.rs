@@ -0,0 +1,84 @@
+//! Snapshot tests over the real-world logs in `fixtures/`. Each `<name>.log`
+//! is run through the deterministic pipeline (cleanup, rule engine, severity, error
+//! splitting, rerun extraction, dependency and network detection) and the
+//! result is compared against `<name>.expected`.
+//!
+//! After an intentional change, regenerate the snapshots with
+//! `UPDATE_FIXTURES=1 cargo test snapshots` and review the diff.
+
+use crate::enrich::{deps, network};
+use crate::severity::Severity;
+use crate::{preprocess, rerun, rules, split};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+fn fixtures_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures")
+}
+
+/// Renders everything the deterministic stages found in a log.
+fn snapshot(log: &str) -> String {
+    let log = preprocess::clean_terminal_output(log);
+    let mut out = String::new();
+
+    let findings = rules::evaluate(&log);
+    writeln!(out, "severity: {}", Severity::from_log(&log, &findings).label()).unwrap();
+    out.push_str("findings:\n");
+    for finding in findings {
+        writeln!(out, "  {:?} @ {}: {}", finding.rule, finding.line_number, finding.line).unwrap();
+    }
+
+    out.push_str("errors:\n");
+    for block in split::split_errors(&log) {
+        writeln!(out, "  {}", split::headline(&block)).unwrap();
+    }
+
+    if let Some(failures) = rerun::extract(&log) {
+        writeln!(out, "rerun: {}", failures.rerun_command()).unwrap();
+    }
+    if let Some(conflict) = deps::detect(&log) {
+        writeln!(out, "dependency conflict: {:?}", conflict).unwrap();
+    }
+    if network::has_network_error(&log) {
+        for target in network::extract_targets(&log) {
+            match target.port {
+                Some(port) => writeln!(out, "network target: {}:{}", target.host, port).unwrap(),
+                None => writeln!(out, "network target: {}", target.host).unwrap(),
+            }
+        }
+    }
+    out
+}
+
+#[test]
+fn test_fixture_snapshots() {
+    let update = std::env::var_os("UPDATE_FIXTURES").is_some();
+    let mut logs: Vec<PathBuf> = std::fs::read_dir(fixtures_dir())
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.extension().is_some_and(|ext| ext == "log"))
+        .collect();
+    logs.sort();
+    assert!(!logs.is_empty(), "no fixtures found");
+
+    let mut mismatches = Vec::new();
+    for log_path in logs {
+        let actual = snapshot(&std::fs::read_to_string(&log_path).unwrap());
+        let expected_path = log_path.with_extension("expected");
+        if update {
+            std::fs::write(&expected_path, &actual).unwrap();
+            continue;
+        }
+        let expected = std::fs::read_to_string(&expected_path)
+            .unwrap_or_else(|_| panic!("missing {} (run with UPDATE_FIXTURES=1)", expected_path.display()));
+        if actual != expected {
+            mismatches.push(format!(
+                "{}\n--- expected\n{}--- actual\n{}",
+                log_path.display(),
+                expected,
+                actual
+            ));
+        }
+    }
+    assert!(mismatches.is_empty(), "snapshot mismatch:\n{}", mismatches.join("\n"));
+}
@@ -0,0 +1,139 @@
+//! Splits a log containing several independent failures into one block per
+//! error, so each can be analyzed on its own.
+
+use regex::Regex;
+use std::sync::LazyLock;
+
+/// Upper bound on the number of errors analyzed separately.
+pub const MAX_ERRORS: usize = 10;
+/// Lines kept per error block.
+const MAX_BLOCK_LINES: usize = 40;
+
+static ERROR_START: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(concat!(
+        r"(?i)^\s*(",
+        r"error(\[E\d+\])?:|",             // rustc, gcc, generic
+        r"\[?error\]?\s|",                 // [ERROR] / ERROR at start
+        r"fatal:|",                        // git
+        r"FAIL(ED)?[\s:]|",                // go test, jest, pytest summary
+        r"---- .+ stdout ----|",           // cargo test failure sections
+        r"_{3,} .+ _{3,}|",                // pytest failure headers
+        r"traceback \(most recent call last\)|",
+        r"thread '.+' panicked|",
+        r"panic:|",
+        r"exception in thread|",
+        r"npm err! code",
+        r")"
+    ))
+    .unwrap()
+});
+/// Headers that open a per-failure section; the error line right after them
+/// belongs to the same block.
+static SECTION_HEADER: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)^\s*(---- .+ stdout ----|_{3,} .+ _{3,}|traceback \(most recent call last\))").unwrap()
+});
+static TRACEBACK_START: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\s*Traceback \(most recent call last\)").unwrap());
+static TIMESTAMP_PREFIX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^\[?\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}:\d{2}\S*\]?\s+").unwrap()
+});
+
+/// Splits `log` at lines that start a new error. Each block runs until the
+/// next error start (or two consecutive blank lines) and is capped in length.
+/// Identical blocks are reported once.
+pub fn split_errors(log: &str) -> Vec<String> {
+    let mut blocks: Vec<Vec<&str>> = Vec::new();
+    let mut current: Option<Vec<&str>> = None;
+    let mut blank_run = 0;
+
+    for line in log.lines() {
+        let unstamped = TIMESTAMP_PREFIX.replace(line, "");
+        // A traceback printed right after the line that logged the error
+        // (`ERROR in app: Exception on /export`) belongs to that error.
+        let continues_section = current.as_ref().is_some_and(|block| {
+            (block.len() <= 2 && SECTION_HEADER.is_match(block[0]))
+                || (block.len() == 1 && TRACEBACK_START.is_match(&unstamped))
+        });
+        if ERROR_START.is_match(&unstamped) && !continues_section {
+            blocks.extend(current.take());
+            current = Some(vec![line]);
+            blank_run = 0;
+            continue;
+        }
+
+        if let Some(block) = current.as_mut() {
+            if line.trim().is_empty() {
+                blank_run += 1;
+                if blank_run >= 2 {
+                    blocks.extend(current.take());
+                    continue;
+                }
+            } else {
+                blank_run = 0;
+            }
+            if block.len() < MAX_BLOCK_LINES {
+                block.push(line);
+            }
+        }
+    }
+    blocks.extend(current);
+
+    let mut unique: Vec<String> = Vec::new();
+    for block in blocks {
+        let text = block.join("\n").trim_end().to_string();
+        if !unique.contains(&text) {
+            unique.push(text);
+        }
+    }
+    unique.truncate(MAX_ERRORS);
+    unique
+}
+
+/// The first line of an error block, used as its heading.
+pub fn headline(block: &str) -> &str {
+    block.lines().next().unwrap_or("").trim()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_cargo_test_failures() {
+        let log = "running 3 tests\n\
+                   test a ... FAILED\n\
+                   \n\
+                   failures:\n\
+                   \n\
+                   ---- tests::a stdout ----\n\
+                   thread 'tests::a' panicked at src/lib.rs:10:5:\n\
+                   assertion failed\n\
+                   ---- tests::b stdout ----\n\
+                   thread 'tests::b' panicked at src/lib.rs:20:5:\n\
+                   index out of bounds\n";
+        let blocks = split_errors(log);
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(
+            blocks[0],
+            "---- tests::a stdout ----\nthread 'tests::a' panicked at src/lib.rs:10:5:\nassertion failed"
+        );
+        assert_eq!(headline(&blocks[1]), "---- tests::b stdout ----");
+    }
+
+    #[test]
+    fn test_split_dedupes_and_ignores_preamble() {
+        let log = "Compiling app\n\
+                   error[E0308]: mismatched types\n --> src/main.rs:3:5\n\
+                   2024-01-01T10:00:00Z ERROR db connection lost\n  retrying\n\
+                   error[E0308]: mismatched types\n --> src/main.rs:3:5\n";
+        let blocks = split_errors(log);
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0], "error[E0308]: mismatched types\n --> src/main.rs:3:5");
+        assert_eq!(blocks[1], "2024-01-01T10:00:00Z ERROR db connection lost\n  retrying");
+    }
+
+    #[test]
+    fn test_split_no_errors() {
+        assert!(split_errors("all good\nfinished").is_empty());
+    }
+}
@@ -0,0 +1,478 @@
+//! `logtrains tui`: the raw log and its streaming analysis side by side, with
+//! the recorded history alongside to switch between entries.
+//!
+//! The model runs on a worker thread so the screen stays responsive while it
+//! generates; starting a new analysis cancels the one in progress.
+
+use crate::history::{self, Entry};
+use crate::llm::{Flow, Inferencer, Prompt};
+use crate::template::Vars;
+use crate::{preprocess, report, rules, truncate};
+use anyhow::Result;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::Frame;
+use regex::Regex;
+use std::collections::BTreeSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, LazyLock};
+use std::time::Duration;
+
+/// How long to wait for a key before checking for new tokens.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+static LINE_REFERENCE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)\blines?\s+(\d+)(?:\s*(?:-|–|to)\s*(\d+))?").unwrap());
+static QUOTED: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"`([^`\n]{4,})`").unwrap());
+
+/// A log to show: its title and contents.
+pub struct Source {
+    pub title: String,
+    pub text: String,
+    /// The history entry it was recorded as, if any.
+    pub entry: Option<i64>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Focus {
+    History,
+    Log,
+    Analysis,
+}
+
+/// Messages from the model thread, tagged with the job they belong to.
+enum Update {
+    Token(u64, String),
+    Done(u64, Option<String>),
+}
+
+struct Job {
+    id: u64,
+    log: String,
+}
+
+struct App {
+    history: history::History,
+    /// The [`history::analysis_key`] of the model and prompt, which stored
+    /// analyses must match to be shown instead of analyzing.
+    key: String,
+    entries: Vec<Entry>,
+    entry_list: ListState,
+    title: String,
+    lines: Vec<String>,
+    errors: BTreeSet<usize>,
+    /// 0-based line under the cursor, and the first line on screen.
+    cursor: usize,
+    offset: usize,
+    /// Where a selection started (`v`); it runs to the cursor.
+    mark: Option<usize>,
+    analysis: String,
+    analysis_scroll: u16,
+    /// The first line of the analyzed text, for resolving references.
+    analyzed_from: usize,
+    analyzed_to: usize,
+    references: Vec<usize>,
+    focus: Focus,
+    status: String,
+    running: Option<u64>,
+    next_job: u64,
+    current: Arc<AtomicU64>,
+    jobs: Sender<Job>,
+}
+
+/// Runs the interface until the user quits, starting on `source`.
+pub fn run(
+    engine: Inferencer,
+    prompt: Option<Prompt>,
+    vars: Vars,
+    history: history::History,
+    key: String,
+    source: Source,
+) -> Result<()> {
+    let entries = history.recent(200)?;
+    let current = Arc::new(AtomicU64::new(0));
+    let (jobs, job_rx) = mpsc::channel();
+    let (update_tx, updates) = mpsc::channel();
+    spawn_worker(engine, prompt, vars, job_rx, update_tx, current.clone());
+
+    let mut app = App {
+        history,
+        key,
+        entries,
+        entry_list: ListState::default(),
+        title: String::new(),
+        lines: Vec::new(),
+        errors: BTreeSet::new(),
+        cursor: 0,
+        offset: 0,
+        mark: None,
+        analysis: String::new(),
+        analysis_scroll: 0,
+        analyzed_from: 0,
+        analyzed_to: 0,
+        references: Vec::new(),
+        focus: Focus::Log,
+        status: String::new(),
+        running: None,
+        next_job: 1,
+        current,
+        jobs,
+    };
+    let index = app.entries.iter().position(|e| Some(e.id) == source.entry);
+    app.entry_list.select(index.or(if app.entries.is_empty() { None } else { Some(0) }));
+    app.open(source)?;
+
+    let mut terminal = ratatui::init();
+    let result = event_loop(&mut terminal, &mut app, &updates);
+    ratatui::restore();
+    result
+}
+
+fn spawn_worker(
+    mut engine: Inferencer,
+    prompt: Option<Prompt>,
+    vars: Vars,
+    jobs: Receiver<Job>,
+    updates: Sender<Update>,
+    current: Arc<AtomicU64>,
+) {
+    std::thread::spawn(move || {
+        for job in jobs {
+            // A newer request superseded this one before it started
+            if current.load(Ordering::SeqCst) != job.id {
+                continue;
+            }
+            let budget = engine.input_budget(prompt.as_ref(), &vars, "");
+            let log = truncate::smart_truncate_by(&job.log, budget, |s| engine.count_tokens(s));
+            let result = engine.explain(&log, prompt.as_ref(), &vars, |token| {
+                if current.load(Ordering::SeqCst) != job.id || updates.send(Update::Token(job.id, token)).is_err() {
+                    return Ok(Flow::Stop);
+                }
+                Ok(Flow::Continue)
+            });
+            let _ = updates.send(Update::Done(job.id, result.err().map(|e| format!("{:#}", e))));
+        }
+    });
+}
+
+fn event_loop(terminal: &mut ratatui::DefaultTerminal, app: &mut App, updates: &Receiver<Update>) -> Result<()> {
+    loop {
+        while let Ok(update) = updates.try_recv() {
+            app.receive(update);
+        }
+        terminal.draw(|frame| app.draw(frame))?;
+
+        if !event::poll(POLL_INTERVAL)? {
+            continue;
+        }
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        let page = terminal.size()?.height.saturating_sub(4).max(1) as usize;
+        match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Ok(()),
+            KeyCode::Char('q') => return Ok(()),
+            KeyCode::Esc if app.mark.is_some() => app.mark = None,
+            KeyCode::Esc => return Ok(()),
+            KeyCode::Tab => {
+                app.focus = match app.focus {
+                    Focus::History => Focus::Log,
+                    Focus::Log => Focus::Analysis,
+                    Focus::Analysis => Focus::History,
+                }
+            }
+            KeyCode::Up | KeyCode::Char('k') => app.move_by(-1),
+            KeyCode::Down | KeyCode::Char('j') => app.move_by(1),
+            KeyCode::PageUp => app.move_by(-(page as isize)),
+            KeyCode::PageDown => app.move_by(page as isize),
+            KeyCode::Home | KeyCode::Char('g') => app.move_by(isize::MIN / 2),
+            KeyCode::End | KeyCode::Char('G') => app.move_by(isize::MAX / 2),
+            KeyCode::Enter if app.focus == Focus::History => app.open_selected()?,
+            KeyCode::Char('v') => {
+                app.mark = match app.mark {
+                    Some(_) => None,
+                    None => Some(app.cursor),
+                }
+            }
+            KeyCode::Char('a') => app.analyze(),
+            KeyCode::Char('n') => app.jump_to_reference(true),
+            KeyCode::Char('N') => app.jump_to_reference(false),
+            _ => {}
+        }
+    }
+}
+
+impl App {
+    /// Shows `source`, with its stored analysis if there is one, or else
+    /// starts analyzing it.
+    fn open(&mut self, source: Source) -> Result<()> {
+        let text = preprocess::clean_terminal_output(&source.text);
+        let findings = rules::evaluate(&text);
+        self.errors = report::error_line_numbers(&text, &findings).into_iter().map(|n| n - 1).collect();
+        self.lines = text.lines().map(str::to_string).collect();
+        self.title = source.title;
+        self.cursor = self.errors.first().copied().unwrap_or(0);
+        self.offset = 0;
+        self.mark = None;
+
+        let stored = match source.entry {
+            Some(id) => self.history.analysis_with(id, &self.key)?,
+            None => None,
+        };
+        match stored {
+            Some(analysis) => {
+                self.cancel();
+                self.status = format!("Stored analysis from {}; press a to run it again.", analysis.created_local());
+                self.analysis = analysis.explanation;
+                self.analysis_scroll = 0;
+                self.analyzed_from = 0;
+                self.analyzed_to = self.lines.len();
+                self.references = referenced_lines(&self.lines, 0, self.lines.len(), &self.analysis);
+            }
+            None => self.analyze(),
+        }
+        Ok(())
+    }
+
+    fn open_selected(&mut self) -> Result<()> {
+        let Some(entry) = self.entry_list.selected().and_then(|i| self.entries.get(i)) else {
+            return Ok(());
+        };
+        let source = Source {
+            title: entry.command.clone(),
+            text: history::read_log(&entry.log_path)?,
+            entry: Some(entry.id),
+        };
+        self.open(source)?;
+        self.focus = Focus::Log;
+        Ok(())
+    }
+
+    /// Analyzes the selected lines, or the whole log without a selection.
+    fn analyze(&mut self) {
+        let (from, to) = match self.mark {
+            Some(mark) => (mark.min(self.cursor), mark.max(self.cursor) + 1),
+            None => (0, self.lines.len()),
+        };
+        let id = self.next_job;
+        self.next_job += 1;
+        self.current.store(id, Ordering::SeqCst);
+        self.running = Some(id);
+        self.analysis.clear();
+        self.analysis_scroll = 0;
+        self.analyzed_from = from;
+        self.analyzed_to = to;
+        self.references.clear();
+        self.status = if self.mark.is_some() {
+            format!("Analyzing lines {}-{}...", from + 1, to)
+        } else {
+            "Analyzing...".to_string()
+        };
+        let log = self.lines[from..to].join("\n");
+        if self.jobs.send(Job { id, log }).is_err() {
+            self.status = "The model stopped; restart logtrains tui.".to_string();
+        }
+    }
+
+    fn cancel(&mut self) {
+        self.current.store(0, Ordering::SeqCst);
+        self.running = None;
+    }
+
+    fn receive(&mut self, update: Update) {
+        match update {
+            Update::Token(id, token) if self.running == Some(id) => {
+                self.analysis.push_str(&token);
+            }
+            Update::Done(id, error) if self.running == Some(id) => {
+                self.running = None;
+                self.references =
+                    referenced_lines(&self.lines, self.analyzed_from, self.analyzed_to, &self.analysis);
+                self.status = match error {
+                    Some(e) => format!("Inference failed: {}", e),
+                    None if self.references.is_empty() => "Done.".to_string(),
+                    None => format!("Done; the analysis refers to {} lines (n/N to jump).", self.references.len()),
+                };
+            }
+            _ => {}
+        }
+    }
+
+    fn move_by(&mut self, delta: isize) {
+        match self.focus {
+            Focus::History => {
+                if !self.entries.is_empty() {
+                    let selected = self.entry_list.selected().unwrap_or(0);
+                    let last = self.entries.len() - 1;
+                    self.entry_list.select(Some(selected.saturating_add_signed(delta).min(last)));
+                }
+            }
+            Focus::Log => {
+                self.cursor = self.cursor.saturating_add_signed(delta).min(self.lines.len().saturating_sub(1));
+            }
+            Focus::Analysis => {
+                let scroll = (self.analysis_scroll as isize).saturating_add(delta).clamp(0, u16::MAX as isize);
+                self.analysis_scroll = scroll as u16;
+            }
+        }
+    }
+
+    fn jump_to_reference(&mut self, forward: bool) {
+        let target = if forward {
+            self.references.iter().find(|&&n| n > self.cursor).or(self.references.first())
+        } else {
+            self.references.iter().rev().find(|&&n| n < self.cursor).or(self.references.last())
+        };
+        if let Some(&line) = target {
+            self.cursor = line;
+            self.focus = Focus::Log;
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, status] = Layout::vertical([Constraint::Min(3), Constraint::Length(1)]).areas(frame.area());
+        let [history, log, analysis] = Layout::horizontal([
+            Constraint::Percentage(22),
+            Constraint::Percentage(43),
+            Constraint::Percentage(35),
+        ])
+        .areas(main);
+
+        self.draw_history(frame, history);
+        self.draw_log(frame, log);
+        self.draw_analysis(frame, analysis);
+
+        let keys = "Tab focus · ↑↓ move · Enter open · v select · a analyze · n/N referenced line · q quit";
+        let line = Line::from(vec![
+            Span::styled(&self.status, Style::new().fg(Color::Yellow)),
+            Span::raw("  "),
+            Span::styled(keys, Style::new().fg(Color::DarkGray)),
+        ]);
+        frame.render_widget(Paragraph::new(line), status);
+    }
+
+    fn block(&self, title: String, focus: Focus) -> Block<'static> {
+        let style = if self.focus == focus { Style::new().fg(Color::Cyan) } else { Style::new() };
+        Block::bordered().title(title).border_style(style)
+    }
+
+    fn draw_history(&mut self, frame: &mut Frame, area: Rect) {
+        let items: Vec<ListItem> = self
+            .entries
+            .iter()
+            .map(|entry| {
+                let style = match entry.exit_code {
+                    Some(0) => Style::new().fg(Color::Green),
+                    Some(_) => Style::new().fg(Color::Red),
+                    None => Style::new(),
+                };
+                ListItem::new(Line::from(vec![Span::styled("● ", style), Span::raw(entry.command.clone())]))
+            })
+            .collect();
+        let list = List::new(items)
+            .block(self.block("History".to_string(), Focus::History))
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, area, &mut self.entry_list);
+    }
+
+    fn draw_log(&mut self, frame: &mut Frame, area: Rect) {
+        let height = area.height.saturating_sub(2) as usize;
+        // Keep the cursor on screen
+        if self.cursor < self.offset {
+            self.offset = self.cursor;
+        } else if height > 0 && self.cursor >= self.offset + height {
+            self.offset = self.cursor + 1 - height;
+        }
+        let selection = self.mark.map(|mark| mark.min(self.cursor)..=mark.max(self.cursor));
+        let width = self.lines.len().to_string().len();
+
+        let lines: Vec<Line> = self
+            .lines
+            .iter()
+            .enumerate()
+            .skip(self.offset)
+            .take(height)
+            .map(|(i, text)| {
+                let mut style = Style::new();
+                if self.errors.contains(&i) {
+                    style = style.fg(Color::Red);
+                }
+                if self.references.binary_search(&i).is_ok() {
+                    style = style.fg(Color::Yellow).add_modifier(Modifier::BOLD);
+                }
+                if selection.as_ref().is_some_and(|s| s.contains(&i)) {
+                    style = style.bg(Color::DarkGray);
+                }
+                if i == self.cursor {
+                    style = style.add_modifier(Modifier::REVERSED);
+                }
+                Line::from(vec![
+                    Span::styled(format!("{:>width$} ", i + 1), Style::new().fg(Color::DarkGray)),
+                    Span::styled(text.as_str(), style),
+                ])
+            })
+            .collect();
+        let title = format!("{} ({}/{})", self.title, self.cursor + 1, self.lines.len());
+        frame.render_widget(Paragraph::new(lines).block(self.block(title, Focus::Log)), area);
+    }
+
+    fn draw_analysis(&self, frame: &mut Frame, area: Rect) {
+        let title = if self.running.is_some() { "Analysis (running)" } else { "Analysis" };
+        let paragraph = Paragraph::new(self.analysis.as_str())
+            .block(self.block(title.to_string(), Focus::Analysis))
+            .wrap(Wrap { trim: false })
+            .scroll((self.analysis_scroll, 0));
+        frame.render_widget(paragraph, area);
+    }
+}
+
+/// 0-based log lines an analysis of `lines[from..to]` points at: lines it
+/// cites by number (counted within the analyzed part) and lines containing
+/// text it quotes in backticks.
+fn referenced_lines(lines: &[String], from: usize, to: usize, analysis: &str) -> Vec<usize> {
+    let mut found = BTreeSet::new();
+    for cap in LINE_REFERENCE.captures_iter(analysis) {
+        let Ok(start) = cap[1].parse::<usize>() else {
+            continue;
+        };
+        let end = cap.get(2).and_then(|m| m.as_str().parse().ok()).unwrap_or(start);
+        for n in start..=end.min(start + 50) {
+            if n >= 1 && from + n <= to {
+                found.insert(from + n - 1);
+            }
+        }
+    }
+    for cap in QUOTED.captures_iter(analysis) {
+        let quote = cap[1].trim();
+        if let Some(i) = lines[from..to].iter().position(|line| line.contains(quote)) {
+            found.insert(from + i);
+        }
+    }
+    found.into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_referenced_lines() {
+        let lines: Vec<String> = ["build started", "compiling app", "error: linker `cc` not found", "done"]
+            .map(String::from)
+            .to_vec();
+        let analysis = "The failure on line 3 happens right after `compiling app`.";
+        assert_eq!(referenced_lines(&lines, 0, 4, analysis), vec![1, 2]);
+
+        // Numbers count from the start of the analyzed selection
+        assert_eq!(referenced_lines(&lines, 1, 4, "See lines 1-2."), vec![1, 2]);
+        // Out of range numbers and quotes outside the selection are ignored
+        assert!(referenced_lines(&lines, 2, 3, "Line 9, `build started`").is_empty());
+    }
+}